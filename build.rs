@@ -0,0 +1,6 @@
+fn main() {
+    //kernel.ld提供代码段的起始地址__text_start，memory::wx用它区分代码和只读数据
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rustc-link-arg=-T{}/kernel.ld", dir);
+    println!("cargo:rerun-if-changed=kernel.ld");
+}
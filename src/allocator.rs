@@ -1,24 +1,52 @@
 use alloc::alloc::{GlobalAlloc, Layout};
+#[cfg(feature = "bump-allocator")]
 use bump::BumpAllocator;
+use core::ops::Range;
 use core::ptr::null_mut;
-use linked_list::LinkedListAllocator;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+use crate::memory;
+#[cfg(not(feature = "bump-allocator"))]
+use linked_list::{FitStrategy, LinkedListAllocator};
+use tracking::TrackingAlloc;
 
 use x86_64::{
     structures::paging::{
-        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
+        mapper::MapToError, FrameAllocator, FrameDeallocator, Mapper, Page, PageSize, PhysFrame,
+        Size2MiB, Size4KiB,
     },
     VirtAddr,
 };
 
-pub const HEAP_START: usize = 0x_4444_4444_0000;
-pub const HEAP_SIZE: usize = 100 * 1024;
+pub const HEAP_SIZE: usize = 100 * 1024; //初始映射的堆大小，也是shrink的下限
+pub const HEAP_MAX_SIZE: usize = 16 * 1024 * 1024; //堆可增长到的最大大小，即vspace中"heap"区域的大小
+const HEAP_GROW_MIN: usize = 64 * 1024; //每次增长的最小字节数
+const PAGE_SIZE: usize = Size4KiB::SIZE as usize;
 pub mod bump;
+pub mod early;
 pub mod linked_list;
+pub mod tag;
+pub mod tracking;
+
+pub use tag::{report_by_tag, with_tag, Tag};
+
+//堆的实际边界，由init_heap设置；HEAP_END为0表示堆尚未初始化
+static HEAP_BOTTOM: AtomicUsize = AtomicUsize::new(0);
+static HEAP_END: AtomicUsize = AtomicUsize::new(0);
+static UNINIT_WARNED: AtomicBool = AtomicBool::new(false);
 
 pub struct Dummy;
 
+//通过`bump-allocator`特性切换全局分配器，默认使用链表分配器
+#[cfg(not(feature = "bump-allocator"))]
 #[global_allocator]
-static ALLOCATOR: Locked<LinkedListAllocator> = Locked::new(LinkedListAllocator::new());
+static ALLOCATOR: TrackingAlloc<Locked<LinkedListAllocator>> =
+    TrackingAlloc::new(Locked::new(LinkedListAllocator::new()));
+
+#[cfg(feature = "bump-allocator")]
+#[global_allocator]
+static ALLOCATOR: TrackingAlloc<Locked<BumpAllocator>> =
+    TrackingAlloc::new(Locked::new(BumpAllocator::new()));
 
 unsafe impl GlobalAlloc for Dummy {
     unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
@@ -30,31 +58,342 @@ unsafe impl GlobalAlloc for Dummy {
     }
 }
 
-pub fn init_heap(
+/// ## 函数说明
+/// 通过全局页表映射初始堆并初始化全局分配器，调用前需要先通过`os::init`安装全局页表
+///
+/// ## 用法
+/// ```rust
+/// allocator::init_heap().expect("heap initialization failed");
+/// ```
+pub fn init_heap() -> Result<(), MapToError<Size4KiB>> {
+    //堆的位置由虚拟地址布局中的"heap"区域决定
+    let heap_start = memory::vspace::find("heap")
+        .expect("heap region missing")
+        .start;
+    let heap_end = heap_start + HEAP_SIZE;
+
+    //对齐且足够大的部分使用2MiB大页，其余部分和大页分配失败时使用4KiB页面
+    memory::with_paging(|paging| -> Result<(), MapToError<Size4KiB>> {
+        let mut addr = heap_start;
+        while addr < heap_end {
+            if addr.is_aligned(Size2MiB::SIZE)
+                && addr + Size2MiB::SIZE <= heap_end
+                && map_heap_huge_page(addr, paging)
+            {
+                addr += Size2MiB::SIZE;
+                continue;
+            }
+            let page = Page::containing_address(addr);
+            map_heap_page(page, &mut paging.mapper, &mut paging.frame_allocator)?;
+            addr += PAGE_SIZE;
+        }
+        Ok(())
+    })
+    .expect("init_heap called before memory::install_paging")?;
+
+    unsafe {
+        ALLOCATOR
+            .inner()
+            .lock()
+            .init(heap_start.as_u64() as usize, HEAP_SIZE);
+    }
+    HEAP_BOTTOM.store(heap_start.as_u64() as usize, Ordering::SeqCst);
+    HEAP_END.store(heap_start.as_u64() as usize + HEAP_SIZE, Ordering::SeqCst);
+    early::EARLY_ARENA.seal(); //主堆可用后不再允许早期分配
+
+    Ok(())
+}
+
+fn map_heap_page(
+    page: Page,
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 ) -> Result<(), MapToError<Size4KiB>> {
-    let page_range = {
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE - 1u64;
-        let heap_start_page = Page::containing_address(heap_start);
-        let heap_end_page = Page::containing_address(heap_end);
-        Page::range_inclusive(heap_start_page, heap_end_page)
+    let frame = frame_allocator
+        .allocate_frame()
+        .ok_or(MapToError::FrameAllocationFailed)?;
+    let flags = memory::wx::data_flags(); //堆页面不可执行
+    unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    Ok(())
+}
+
+//尝试用一个2MiB大页映射`addr`，失败时返回false；映射失败时大帧拆成4KiB帧归还给帧分配器
+fn map_heap_huge_page(addr: VirtAddr, paging: &mut memory::KernelPaging) -> bool {
+    let frame: PhysFrame<Size2MiB> = match paging.frame_allocator.allocate_frame() {
+        Some(frame) => frame,
+        None => return false,
     };
+    let page = Page::<Size2MiB>::containing_address(addr);
+    let mapped = memory::map_huge(
+        page,
+        frame,
+        memory::wx::data_flags(),
+        &mut paging.mapper,
+        &mut paging.frame_allocator,
+    );
+    if mapped.is_err() {
+        let first = PhysFrame::<Size4KiB>::containing_address(frame.start_address());
+        for small in PhysFrame::range(first, first + Size2MiB::SIZE / Size4KiB::SIZE) {
+            unsafe { paging.frame_allocator.deallocate_frame(small) };
+        }
+    }
+    mapped.is_ok()
+}
 
-    for page in page_range {
-        let frame = frame_allocator
-            .allocate_frame()
-            .ok_or(MapToError::FrameAllocationFailed)?;
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+/// ## 说明
+/// 通过全局页表在堆末尾映射至少`min_bytes`字节的新页面
+/// 返回新映射区域的起始地址和大小，由分配器在持有锁时调用
+///
+/// ## 参数
+/// * `min_bytes` - 至少需要的字节数
+fn grow(min_bytes: usize) -> Option<(usize, usize)> {
+    let end = HEAP_END.load(Ordering::SeqCst);
+    if end == 0 {
+        return None;
+    }
+    let limit = HEAP_BOTTOM.load(Ordering::SeqCst) + HEAP_MAX_SIZE;
+    let size = align_up(min_bytes.max(HEAP_GROW_MIN), PAGE_SIZE).min(limit - end);
+    if size < min_bytes {
+        return None;
     }
 
-    unsafe {
-        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+    //逐页映射，中途失败时保留已映射的部分
+    let mapped = memory::with_paging(|paging| {
+        let mut mapped = 0;
+        while mapped < size {
+            let page = Page::containing_address(VirtAddr::new((end + mapped) as u64));
+            if map_heap_page(page, &mut paging.mapper, &mut paging.frame_allocator).is_err() {
+                break;
+            }
+            mapped += PAGE_SIZE;
+        }
+        mapped
+    })?;
+
+    if mapped == 0 {
+        return None;
     }
+    HEAP_END.store(end + mapped, Ordering::SeqCst);
+    Some((end, mapped))
+}
 
-    Ok(())
+/// ## 说明
+/// 将堆末尾完全空闲的页面取消映射，并把物理帧归还全局帧分配器
+/// 堆不会收缩到初始大小`HEAP_SIZE`以下，返回释放的字节数
+///
+/// ## 用法
+/// ```rust
+/// let released = allocator::shrink();
+/// ```
+pub fn shrink() -> usize {
+    //持有分配器锁，防止收缩过程中有新的分配落在将被取消映射的页面
+    let mut allocator = ALLOCATOR.inner().lock();
+    let end = HEAP_END.load(Ordering::SeqCst);
+    if end == 0 {
+        return 0;
+    }
+
+    memory::with_paging(|paging| {
+        let new_end =
+            unsafe { allocator.trim_end(end, HEAP_BOTTOM.load(Ordering::SeqCst) + HEAP_SIZE) };
+        let mut addr = new_end;
+        while addr < end {
+            let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(addr as u64));
+            if let Ok((frame, flush)) = paging.mapper.unmap(page) {
+                flush.flush();
+                unsafe { paging.frame_allocator.deallocate_frame(frame) };
+            }
+            addr += PAGE_SIZE;
+        }
+        HEAP_END.store(new_end, Ordering::SeqCst);
+        end - new_end
+    })
+    .unwrap_or(0)
+}
+
+/// ## 说明
+/// 堆的概况
+///
+/// ## 成员
+/// * `start` - 堆起始地址
+/// * `mapped_bytes` - 当前已映射的字节数
+/// * `free_bytes` - 分配器中的空闲字节数
+#[derive(Debug, Clone, Copy)]
+pub struct HeapInfo {
+    pub start: usize,
+    pub mapped_bytes: usize,
+    pub free_bytes: usize,
+}
+
+/// ## 说明
+/// 返回堆的概况
+pub fn heap_info() -> HeapInfo {
+    let start = HEAP_BOTTOM.load(Ordering::SeqCst);
+    HeapInfo {
+        start,
+        mapped_bytes: HEAP_END.load(Ordering::SeqCst) - start,
+        free_bytes: free_bytes(),
+    }
+}
+
+/// ## 说明
+/// 堆是否已经由`init_heap`初始化
+pub fn is_initialized() -> bool {
+    HEAP_END.load(Ordering::SeqCst) != 0
+}
+
+/// ## 说明
+/// 返回堆所在的虚拟地址范围，堆未初始化时返回`None`
+///
+/// ## 用法
+/// ```rust
+/// if let Some(range) = heap_range() { ... }
+/// ```
+pub fn heap_range() -> Option<Range<VirtAddr>> {
+    let end = HEAP_END.load(Ordering::SeqCst);
+    if end == 0 {
+        return None;
+    }
+    let start = HEAP_BOTTOM.load(Ordering::SeqCst);
+    Some(VirtAddr::new(start as u64)..VirtAddr::new(end as u64))
+}
+
+/// ## 说明
+/// 非法释放的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum InvalidFreePolicy {
+    /// 打印指针和布局后panic
+    Panic,
+    /// 打印警告并忽略这次释放
+    LogAndIgnore,
+}
+
+//调试构建默认panic，发布构建默认忽略
+static INVALID_FREE_POLICY: AtomicU8 = AtomicU8::new(if cfg!(debug_assertions) {
+    InvalidFreePolicy::Panic as u8
+} else {
+    InvalidFreePolicy::LogAndIgnore as u8
+});
+
+/// ## 说明
+/// 设置非法释放的处理策略
+pub fn set_invalid_free_policy(policy: InvalidFreePolicy) {
+    INVALID_FREE_POLICY.store(policy as u8, Ordering::SeqCst);
+}
+
+/// ## 说明
+/// 检查被释放的指针是否位于堆内、满足对齐且不越过堆末尾
+/// 不合法时按`InvalidFreePolicy`处理，返回`false`表示应当忽略这次释放
+///
+/// ## 参数
+/// * `ptr` - 被释放的指针
+/// * `layout` - 分配时的布局
+/// * `min_align` - 分配器返回的指针至少满足的对齐
+fn validate_dealloc(ptr: *mut u8, layout: Layout, min_align: usize) -> bool {
+    let addr = ptr as usize;
+    let bottom = HEAP_BOTTOM.load(Ordering::SeqCst);
+    let end = HEAP_END.load(Ordering::SeqCst);
+    let valid = addr >= bottom
+        && addr.is_multiple_of(min_align.max(layout.align()))
+        && addr
+            .checked_add(layout.size())
+            .is_some_and(|alloc_end| alloc_end <= end);
+    if valid {
+        return true;
+    }
+
+    if INVALID_FREE_POLICY.load(Ordering::SeqCst) == InvalidFreePolicy::Panic as u8 {
+        panic!("invalid dealloc of {:p} with {:?}", ptr, layout);
+    }
+    crate::println!("WARNING: ignored invalid dealloc of {:p} with {:?}", ptr, layout);
+    false
+}
+
+/// ## 说明
+/// 堆初始化前的分配请求调用此函数，仅在第一次时打印警告
+fn warn_uninitialized() {
+    if !UNINIT_WARNED.swap(true, Ordering::SeqCst) {
+        crate::println!("WARNING: heap allocation before init_heap");
+    }
+}
+
+/// ## 说明
+/// 返回全局分配器当前的空闲字节数
+pub fn free_bytes() -> usize {
+    ALLOCATOR.inner().lock().free_bytes()
+}
+
+/// ## 说明
+/// 在持有分配器锁期间访问每个空闲区域，回调中不能进行堆分配
+///
+/// ## 参数
+/// * `f` - 接收区域起始地址和大小的回调
+pub fn for_each_free_region(f: impl FnMut(usize, usize)) {
+    ALLOCATOR
+        .inner()
+        .with_lock(|allocator| allocator.for_each_free_region(f))
+}
+
+/// ## 说明
+/// 返回最大空闲区域的字节数
+pub fn largest_free_block() -> usize {
+    ALLOCATOR.inner().lock().largest_free_block()
+}
+
+/// ## 说明
+/// 返回空闲区域的个数
+pub fn free_region_count() -> usize {
+    ALLOCATOR.inner().lock().free_region_count()
+}
+
+/// ## 说明
+/// 碎片率，即 1 - 最大空闲区域 / 空闲总量，没有空闲内存时为0
+pub fn fragmentation_ratio() -> f32 {
+    let allocator = ALLOCATOR.inner().lock();
+    let total = allocator.free_bytes();
+    if total == 0 {
+        return 0.0;
+    }
+    1.0 - allocator.largest_free_block() as f32 / total as f32
+}
+
+/// ## 说明
+/// 通过`println!`打印空闲链表，用于交互调试
+pub fn dump_free_list() {
+    ALLOCATOR.inner().lock().dump_free_list()
+}
+
+/// ## 说明
+/// 设置全局链表分配器的查找策略
+#[cfg(not(feature = "bump-allocator"))]
+pub fn set_fit_strategy(strategy: FitStrategy) {
+    ALLOCATOR.inner().lock().set_strategy(strategy)
+}
+
+/// ## 说明
+/// 全局链表分配器平均每次查找访问的结点数
+#[cfg(not(feature = "bump-allocator"))]
+pub fn average_scan_length() -> usize {
+    ALLOCATOR.inner().lock().average_scan_length()
+}
+
+/// ## 说明
+/// 当前已分配的堆字节数
+pub fn current_usage() -> usize {
+    ALLOCATOR.current_usage()
+}
+
+/// ## 说明
+/// 已分配堆字节数的历史峰值
+pub fn peak_usage() -> usize {
+    ALLOCATOR.peak_usage()
+}
+
+/// ## 说明
+/// 将峰值重置为当前用量
+pub fn reset_peak() {
+    ALLOCATOR.reset_peak()
 }
 
 // Rust不允许对外部的spin::Mutex实现外部的trait GlobalAlloc
@@ -74,6 +413,17 @@ impl<A> Locked<A> {
     pub fn lock(&self) -> spin::MutexGuard<A> {
         self.inner.lock()
     }
+
+    /// ## 说明
+    /// 在持有锁期间以可变引用访问被包装的值
+    ///
+    /// ## 用法
+    /// ```rust
+    /// ALLOCATOR.with_lock(|allocator| allocator.free_bytes());
+    /// ```
+    pub fn with_lock<R>(&self, f: impl FnOnce(&mut A) -> R) -> R {
+        f(&mut self.inner.lock())
+    }
 }
 
 /// ## 说明
@@ -92,3 +442,15 @@ fn align_up(addr: usize, align: usize) -> usize {
 
     (addr + align - 1) & !(align - 1) //使用位运算提高效率，与上方注释等价
 }
+
+/* ---------------测试------------------ */
+
+#[test_case]
+fn test_alloc_before_init() {
+    //库测试不会初始化堆
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let ptr = unsafe { alloc::alloc::alloc(layout) };
+    assert!(ptr.is_null());
+    assert!(!is_initialized());
+    assert!(heap_range().is_none());
+}
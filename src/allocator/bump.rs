@@ -1,4 +1,8 @@
-use super::{align_up, Locked};
+use super::tracking::CheckedDealloc;
+use super::{
+    align_up, grow, is_initialized, validate_dealloc, warn_uninitialized, Locked, PAGE_SIZE,
+};
+use crate::println;
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::ptr;
 
@@ -34,10 +38,62 @@ impl BumpAllocator {
         self.heap_end = heap_start + heap_size;
         self.next = heap_start;
     }
+
+    /// ## 说明
+    /// 将堆末尾收缩到紧缩指针之后的第一个页边界，返回新的堆末尾
+    ///
+    /// ## 参数
+    /// * `heap_end` - 当前的堆末尾
+    /// * `floor` - 堆末尾的下限，需按页对齐
+    pub unsafe fn trim_end(&mut self, heap_end: usize, floor: usize) -> usize {
+        let new_end = align_up(self.next.max(floor), PAGE_SIZE).min(heap_end);
+        self.heap_end = new_end;
+        new_end
+    }
+
+    /// ## 说明
+    /// 访问空闲区域，紧缩分配器只有紧缩指针到堆末尾这一个区域
+    ///
+    /// ## 参数
+    /// * `f` - 接收区域起始地址和大小的回调
+    pub fn for_each_free_region(&self, mut f: impl FnMut(usize, usize)) {
+        if self.free_bytes() > 0 {
+            f(self.next, self.free_bytes());
+        }
+    }
+
+    /// ## 说明
+    /// 紧缩分配器只有一个空闲区域，即紧缩指针到堆末尾
+    pub fn largest_free_block(&self) -> usize {
+        self.free_bytes()
+    }
+
+    /// ## 说明
+    /// 返回空闲区域的个数，最多为1
+    pub fn free_region_count(&self) -> usize {
+        usize::from(self.free_bytes() > 0)
+    }
+
+    /// ## 说明
+    /// 打印空闲区域的起始地址和大小
+    pub fn dump_free_list(&self) {
+        self.for_each_free_region(|start, size| println!("{:#x} {:>8} bytes", start, size));
+    }
+
+    /// ## 说明
+    /// 返回尚未被紧缩指针越过的空闲字节数
+    pub fn free_bytes(&self) -> usize {
+        self.heap_end - self.next
+    }
 }
 
 unsafe impl GlobalAlloc for Locked<BumpAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if !is_initialized() {
+            warn_uninitialized();
+            return ptr::null_mut();
+        }
+
         let mut bump = self.lock(); //获取一个BumpAllocator可变引用
 
         let alloc_start = align_up(bump.next, layout.align());
@@ -46,6 +102,15 @@ unsafe impl GlobalAlloc for Locked<BumpAllocator> {
             None => return ptr::null_mut(),
         };
 
+        if alloc_end > bump.heap_end {
+            //堆是连续的，增长的页面紧接在heap_end之后
+            if let Some((start, grown)) = grow(alloc_end - bump.heap_end) {
+                if start == bump.heap_end {
+                    bump.heap_end += grown;
+                }
+            }
+        }
+
         if alloc_end > bump.heap_end {
             ptr::null_mut()
         } else {
@@ -55,12 +120,22 @@ unsafe impl GlobalAlloc for Locked<BumpAllocator> {
         }
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.checked_dealloc(ptr, layout);
+    }
+}
+
+impl CheckedDealloc for Locked<BumpAllocator> {
+    unsafe fn checked_dealloc(&self, ptr: *mut u8, layout: Layout) -> bool {
+        if !validate_dealloc(ptr, layout, 1) {
+            return false;
+        }
         let mut bump = self.lock(); //获取BumpAllocator可变引用
 
         bump.allocations -= 1;
         if bump.allocations == 0 {
             bump.next = bump.heap_start;
         }
+        true
     }
 }
@@ -0,0 +1,135 @@
+use alloc::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::align_up;
+
+pub const EARLY_ARENA_SIZE: usize = 64 * 1024;
+
+/// 分页和主堆就绪前使用的全局早期内存区，`init_heap`完成后被封存
+pub static EARLY_ARENA: EarlyArena<EARLY_ARENA_SIZE> = EarlyArena::new();
+
+/// ## 说明
+/// 早期启动阶段的紧缩内存区，只分配不释放，也不实现GlobalAlloc
+///
+/// ## 成员
+/// * `memory` - 静态存储区
+/// * `next` - 下一个未使用字节的偏移
+/// * `sealed` - 封存后再分配会panic，用于发现误用
+#[repr(C, align(4096))]
+pub struct EarlyArena<const N: usize> {
+    memory: UnsafeCell<[u8; N]>,
+    next: AtomicUsize,
+    sealed: AtomicBool,
+}
+
+// 分配通过原子操作划分互不重叠的区域
+unsafe impl<const N: usize> Sync for EarlyArena<N> {}
+
+impl<const N: usize> EarlyArena<N> {
+    pub const fn new() -> Self {
+        EarlyArena {
+            memory: UnsafeCell::new([0; N]),
+            next: AtomicUsize::new(0),
+            sealed: AtomicBool::new(false),
+        }
+    }
+
+    /// ## 说明
+    /// 按`layout`分配一块内存，空间不足时返回`None`，封存后调用会panic
+    ///
+    /// ## 参数
+    /// * `layout` - 大小和对齐方式
+    ///
+    /// ## 用法
+    /// ```rust
+    /// EARLY_ARENA.alloc(Layout::new::<u64>());
+    /// ```
+    pub fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        if self.sealed() {
+            panic!("early arena allocation after init_heap: {:?}", layout);
+        }
+
+        let base = self.memory.get() as usize;
+        let mut next = self.next.load(Ordering::SeqCst);
+        loop {
+            let start = align_up(base + next, layout.align()) - base;
+            let end = start.checked_add(layout.size())?;
+            if end > N {
+                return None;
+            }
+            match self
+                .next
+                .compare_exchange(next, end, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return NonNull::new((base + start) as *mut u8),
+                Err(current) => next = current,
+            }
+        }
+    }
+
+    /// ## 说明
+    /// 封存内存区，之后的分配请求都会panic
+    pub fn seal(&self) {
+        self.sealed.store(true, Ordering::SeqCst);
+    }
+
+    /// ## 说明
+    /// 内存区是否已封存
+    pub fn sealed(&self) -> bool {
+        self.sealed.load(Ordering::SeqCst)
+    }
+
+    /// ## 说明
+    /// 已使用的字节数（包括对齐填充）
+    pub fn used(&self) -> usize {
+        self.next.load(Ordering::SeqCst)
+    }
+}
+
+impl<const N: usize> Default for EarlyArena<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ## 说明
+/// 从全局早期内存区分配`n`个`T`并初始化为默认值
+///
+/// ## 用法
+/// ```rust
+/// let bitmap = early_alloc_slice::<u64>(512).expect("early arena exhausted");
+/// ```
+pub fn early_alloc_slice<T: Default>(n: usize) -> Option<&'static mut [T]> {
+    let layout = Layout::array::<T>(n).ok()?;
+    let ptr = EARLY_ARENA.alloc(layout)?.as_ptr() as *mut T;
+    unsafe {
+        for i in 0..n {
+            ptr.add(i).write(T::default());
+        }
+        Some(core::slice::from_raw_parts_mut(ptr, n))
+    }
+}
+
+/* ---------------测试------------------ */
+
+#[test_case]
+fn test_early_arena_alignment() {
+    static ARENA: EarlyArena<256> = EarlyArena::new();
+
+    let a = ARENA.alloc(Layout::from_size_align(1, 1).unwrap()).unwrap();
+    let b = ARENA.alloc(Layout::from_size_align(8, 64).unwrap()).unwrap();
+    assert_eq!(b.as_ptr() as usize % 64, 0);
+    assert!(b.as_ptr() as usize > a.as_ptr() as usize);
+}
+
+#[test_case]
+fn test_early_arena_exhaustion() {
+    static ARENA: EarlyArena<256> = EarlyArena::new();
+
+    assert!(ARENA.alloc(Layout::from_size_align(200, 8).unwrap()).is_some());
+    assert!(ARENA.alloc(Layout::from_size_align(100, 8).unwrap()).is_none());
+    assert!(ARENA.alloc(Layout::from_size_align(56, 8).unwrap()).is_some());
+    assert_eq!(ARENA.used(), 256);
+}
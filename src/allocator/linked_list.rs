@@ -1,11 +1,17 @@
-use super::align_up;
+use super::tracking::CheckedDealloc;
 use super::Locked;
+use super::{align_up, grow, is_initialized, validate_dealloc, warn_uninitialized, PAGE_SIZE};
 use alloc::alloc::{GlobalAlloc, Layout};
+use crate::println;
 use core::mem;
 use core::ptr;
 
+/// 空闲结点的魔数，释放时写入，分配时清除，用于廉价地检测重复释放
+const FREE_MAGIC: usize = 0xF4EE_B10C_F4EE_B10C;
+
 struct ListNode {
     size: usize,
+    magic: usize,
     next: Option<&'static mut ListNode>,
 }
 
@@ -19,7 +25,11 @@ impl ListNode {
     /// ListNode::new(0);
     /// ```
     const fn new(size: usize) -> Self {
-        ListNode { size, next: None }
+        ListNode {
+            size,
+            magic: FREE_MAGIC,
+            next: None,
+        }
     }
 
     /// ## 说明
@@ -44,8 +54,31 @@ impl ListNode {
     }
 }
 
+/// ## 说明
+/// 空闲区域的查找策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitStrategy {
+    /// 总是从链表头开始查找
+    FirstFit,
+    /// 从上一次成功分配的位置继续查找，到达链表尾部后回绕一次
+    NextFit,
+}
+
+/// ## 说明
+/// 链表分配器
+///
+/// ## 成员
+/// * `head` - 空闲链表的头结点
+/// * `strategy` - 查找策略
+/// * `cursor` - NextFit的游标，为下一轮查找起点的前驱结点地址，0表示头结点
+/// * `scans` - 查找次数
+/// * `scan_steps` - 查找中访问的结点总数
 pub struct LinkedListAllocator {
     head: ListNode,
+    strategy: FitStrategy,
+    cursor: usize,
+    scans: usize,
+    scan_steps: usize,
 }
 
 impl LinkedListAllocator {
@@ -58,9 +91,33 @@ impl LinkedListAllocator {
     pub const fn new() -> Self {
         Self {
             head: ListNode::new(0),
+            strategy: FitStrategy::FirstFit,
+            cursor: 0,
+            scans: 0,
+            scan_steps: 0,
         }
     }
 
+    /// ## 说明
+    /// 设置空闲区域的查找策略，同时清零查找统计
+    ///
+    /// ## 用法
+    /// ```rust
+    /// LinkedListAllocator.set_strategy(FitStrategy::NextFit);
+    /// ```
+    pub fn set_strategy(&mut self, strategy: FitStrategy) {
+        self.strategy = strategy;
+        self.cursor = 0;
+        self.scans = 0;
+        self.scan_steps = 0;
+    }
+
+    /// ## 说明
+    /// 平均每次查找访问的结点数
+    pub fn average_scan_length(&self) -> usize {
+        self.scan_steps.checked_div(self.scans).unwrap_or(0)
+    }
+
     /// ## 说明
     /// 使用给定的堆边界初始化分配器
     ///
@@ -77,7 +134,7 @@ impl LinkedListAllocator {
     }
 
     /// ## 说明
-    /// 内存区域前插至链表
+    /// 把内存区域按地址顺序插入链表，并与相邻的空闲区域合并，防止堆随着分配和释放不断碎片化
     ///
     /// ## 参数
     /// * `heap_start` - 起始边界
@@ -92,12 +149,45 @@ impl LinkedListAllocator {
         assert_eq!(align_up(addr, mem::align_of::<ListNode>()), addr);
         assert!(size >= mem::size_of::<ListNode>());
 
-        //创建一个新的结点并尾插到链表
-        let mut node = ListNode::new(size);
-        node.next = self.head.next.take();
+        //找到最后一个起始地址小于addr的结点，新区域插在它之后
+        let head = &mut self.head as *mut ListNode;
+        let mut prev = head;
+        while let Some(ref mut next) = (*prev).next {
+            if next.start_addr() > addr {
+                break;
+            }
+            prev = &mut **next as *mut ListNode;
+        }
+        let prev = &mut *prev;
+
+        //紧随其后的空闲区域并入新区域
+        let mut size = size;
+        let mut next = prev.next.take();
+        if next
+            .as_ref()
+            .is_some_and(|next| next.start_addr() == addr + size)
+        {
+            let following = next.take().unwrap();
+            size += following.size;
+            next = following.next.take();
+            if self.cursor == following.start_addr() {
+                self.cursor = 0; //游标结点被合并，退回链表头
+            }
+        }
+
+        //被释放的块即使并入前一个区域，起始处也保留魔数，重复释放仍能被发现
         let node_ptr = addr as *mut ListNode;
+        ptr::addr_of_mut!((*node_ptr).magic).write(FREE_MAGIC);
+        if !ptr::eq(prev, head) && prev.end_addr() == addr {
+            prev.size += size;
+            prev.next = next;
+            return;
+        }
+
+        let mut node = ListNode::new(size);
+        node.next = next;
         node_ptr.write(node);
-        self.head.next = Some(&mut *node_ptr)
+        prev.next = Some(&mut *node_ptr)
     }
 
     /// ## 说明
@@ -108,13 +198,79 @@ impl LinkedListAllocator {
     /// * `size` - 空闲区域的指定大小
     /// * `align` - 对齐方式
     fn find_region(&mut self, size: usize, align: usize) -> Option<(&'static mut ListNode, usize)> {
+        let head = &mut self.head as *mut ListNode;
+        let start = match self.strategy {
+            FitStrategy::FirstFit => head,
+            FitStrategy::NextFit if self.cursor == 0 => head,
+            FitStrategy::NextFit => self.cursor as *mut ListNode,
+        };
+
+        self.scans += 1;
+        let mut found = unsafe { Self::find_after(&mut self.scan_steps, start, 0, size, align) };
+        if found.is_none() && start != head {
+            //回绕：从链表头查找到游标结点为止
+            let stop = unsafe { (*start).start_addr() };
+            found = unsafe { Self::find_after(&mut self.scan_steps, head, stop, size, align) };
+        }
+
+        found.map(|(region, alloc_start, prev)| {
+            if self.strategy == FitStrategy::NextFit {
+                self.cursor = if prev == head as usize { 0 } else { prev };
+            }
+            (region, alloc_start)
+        })
+    }
+
+    /// ## 说明
+    /// 从`start`的后继结点开始查找可用区域，检查完起始地址为`stop`的结点后停止
+    /// 返回被摘除的结点、分配起始地址和其前驱结点的地址
+    ///
+    /// ## 参数
+    /// * `scan_steps` - 访问结点的计数
+    /// * `start` - 查找起点的前驱结点，必须位于链表中
+    /// * `stop` - 最后检查的结点地址，0表示查找到链表尾部
+    unsafe fn find_after(
+        scan_steps: &mut usize,
+        start: *mut ListNode,
+        stop: usize,
+        size: usize,
+        align: usize,
+    ) -> Option<(&'static mut ListNode, usize, usize)> {
+        let mut current = &mut *start;
+        while let Some(ref mut region) = current.next {
+            *scan_steps += 1;
+            if let Ok(alloc_start) = Self::alloc_from_region(region, size, align) {
+                let next = region.next.take();
+                let region = current.next.take().unwrap();
+                current.next = next;
+                return Some((region, alloc_start, current as *mut ListNode as usize));
+            } else if region.start_addr() == stop {
+                return None;
+            } else {
+                current = current.next.as_mut().unwrap();
+            }
+        }
+
+        None
+    }
+
+    /// ## 说明
+    /// 从链表中摘除结束于`end`的空闲区域
+    ///
+    /// ## 参数
+    /// * `end` - 区域的结束地址
+    fn take_region_ending_at(&mut self, end: usize) -> Option<&'static mut ListNode> {
         let mut current = &mut self.head;
         while let Some(ref mut region) = current.next {
-            if let Ok(alloc_start) = Self::alloc_from_region(&region, size, align) {
+            if region.end_addr() == end {
                 let next = region.next.take();
-                let ret = Some((current.next.take().unwrap(), alloc_start));
+                let region = current.next.take().unwrap();
                 current.next = next;
-                return ret;
+                region.magic = 0; //结点已不再代表空闲区域
+                if self.cursor == region.start_addr() {
+                    self.cursor = 0; //游标结点被移除，退回链表头
+                }
+                return Some(region);
             } else {
                 current = current.next.as_mut().unwrap();
             }
@@ -123,6 +279,36 @@ impl LinkedListAllocator {
         None
     }
 
+    /// ## 说明
+    /// 摘除堆末尾的连续空闲区域，并把不足整页的部分放回链表
+    /// 返回按页对齐的新堆末尾，`heap_end`到返回值之间的内存可以取消映射
+    ///
+    /// ## 参数
+    /// * `heap_end` - 当前的堆末尾
+    /// * `floor` - 堆末尾的下限，需按页对齐
+    ///
+    /// ## Safety
+    /// `heap_end`必须是管理的堆的末尾，调用者需要在返回后取消映射`[返回值, heap_end)`之前不再访问这段内存
+    pub unsafe fn trim_end(&mut self, heap_end: usize, floor: usize) -> usize {
+        //多个相邻的空闲结点共同组成末尾的空闲区域
+        let mut run_start = heap_end;
+        while let Some(region) = self.take_region_ending_at(run_start) {
+            run_start = region.start_addr();
+        }
+
+        let mut new_end = align_up(run_start.max(floor), PAGE_SIZE);
+        let leading_size = new_end - run_start;
+        if leading_size > 0 && leading_size < mem::size_of::<ListNode>() {
+            new_end += PAGE_SIZE;
+        }
+        let new_end = new_end.min(heap_end);
+        if new_end > run_start {
+            self.add_free_region(run_start, new_end - run_start);
+        }
+
+        new_end
+    }
+
     /// ## 说明
     /// 分配指定大小和对齐方式的区域
     ///
@@ -135,7 +321,12 @@ impl LinkedListAllocator {
     /// LinkedListAllocator.alloc_from_region(100,10);
     /// ```
     fn alloc_from_region(region: &ListNode, size: usize, align: usize) -> Result<usize, ()> {
-        let alloc_start = align_up(region.start_addr(), align);
+        let mut alloc_start = align_up(region.start_addr(), align);
+        let leading_size = alloc_start - region.start_addr();
+        if leading_size > 0 && leading_size < mem::size_of::<ListNode>() {
+            //前部剩余区域容纳不下ListNode，移动到下一个对齐位置
+            alloc_start = align_up(region.start_addr() + mem::size_of::<ListNode>(), align);
+        }
         let alloc_end = alloc_start.checked_add(size).ok_or(())?;
 
         if alloc_end > region.end_addr() {
@@ -150,6 +341,89 @@ impl LinkedListAllocator {
         Ok(alloc_start)
     }
 
+    /// ## 说明
+    /// 检测`addr`处的区域是否已经位于空闲链表中，是则panic
+    ///
+    /// 魔数检查总是开启；启用`debug-heap`特性时还会遍历整个链表检查区域重叠
+    ///
+    /// ## 参数
+    /// * `addr` - 被释放区域的起始地址
+    /// * `size` - 被释放区域的大小
+    unsafe fn check_double_free(&self, addr: usize, size: usize) {
+        let node = &*(addr as *const ListNode);
+        if node.magic == FREE_MAGIC {
+            panic!("double free detected at {:#x}", addr);
+        }
+
+        #[cfg(feature = "debug-heap")]
+        self.for_each_free_region(|start, region_size| {
+            if addr < start + region_size && start < addr + size {
+                panic!(
+                    "double free detected at {:#x}: overlaps free region {:#x}..{:#x}",
+                    addr,
+                    start,
+                    start + region_size
+                );
+            }
+        });
+
+        #[cfg(not(feature = "debug-heap"))]
+        let _ = size;
+    }
+
+    /// ## 说明
+    /// 按地址顺序访问每个空闲区域，不修改链表
+    ///
+    /// ## 参数
+    /// * `f` - 接收区域起始地址和大小的回调
+    ///
+    /// ## 用法
+    /// ```rust
+    /// LinkedListAllocator.for_each_free_region(|start, size| println!("{:#x} {}", start, size));
+    /// ```
+    pub fn for_each_free_region(&self, mut f: impl FnMut(usize, usize)) {
+        let mut current = &self.head;
+        while let Some(ref region) = current.next {
+            f(region.start_addr(), region.size);
+            current = region;
+        }
+    }
+
+    /// ## 说明
+    /// 遍历空闲链表，返回空闲字节总数
+    ///
+    /// ## 用法
+    /// ```rust
+    /// LinkedListAllocator.free_bytes();
+    /// ```
+    pub fn free_bytes(&self) -> usize {
+        let mut total = 0;
+        self.for_each_free_region(|_, size| total += size);
+        total
+    }
+
+    /// ## 说明
+    /// 返回最大空闲区域的字节数
+    pub fn largest_free_block(&self) -> usize {
+        let mut largest = 0;
+        self.for_each_free_region(|_, size| largest = largest.max(size));
+        largest
+    }
+
+    /// ## 说明
+    /// 返回空闲区域的个数
+    pub fn free_region_count(&self) -> usize {
+        let mut count = 0;
+        self.for_each_free_region(|_, _| count += 1);
+        count
+    }
+
+    /// ## 说明
+    /// 打印每个空闲区域的起始地址和大小
+    pub fn dump_free_list(&self) {
+        self.for_each_free_region(|start, size| println!("{:#x} {:>8} bytes", start, size));
+    }
+
     fn size_align(layout: Layout) -> (usize, usize) {
         let layout = layout
             .align_to(mem::align_of::<ListNode>())
@@ -162,13 +436,36 @@ impl LinkedListAllocator {
 
 unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if !is_initialized() {
+            warn_uninitialized();
+            return ptr::null_mut();
+        }
+
         // 执行布局调整
         let (size, align) = LinkedListAllocator::size_align(layout);
         let mut allocator = self.lock();
 
-        if let Some((region, alloc_start)) = allocator.find_region(size, align) {
+        let mut found = allocator.find_region(size, align);
+        if found.is_none() {
+            //空间不足时尝试增长堆
+            if let Some((start, grown)) = grow(size + align) {
+                allocator.add_free_region(start, grown);
+                found = allocator.find_region(size, align);
+            }
+        }
+
+        if let Some((region, alloc_start)) = found {
             let alloc_end = alloc_start.checked_add(size).expect("overflow");
+            let region_start = region.start_addr();
             let excess_size = region.end_addr() - alloc_end;
+            //合并进来的块可能在分配起点留下魔数
+            ptr::addr_of_mut!((*(alloc_start as *mut ListNode)).magic).write(0);
+            region.magic = 0; //该区域不再空闲
+            //大对齐时区域被拆分为前部空闲块、分配块和尾部空闲块
+            let leading_size = alloc_start - region_start;
+            if leading_size > 0 {
+                allocator.add_free_region(region_start, leading_size);
+            }
             if excess_size > 0 {
                 allocator.add_free_region(alloc_end, excess_size);
             }
@@ -180,7 +477,102 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.checked_dealloc(ptr, layout);
+    }
+}
+
+impl CheckedDealloc for Locked<LinkedListAllocator> {
+    unsafe fn checked_dealloc(&self, ptr: *mut u8, layout: Layout) -> bool {
+        if !validate_dealloc(ptr, layout, mem::align_of::<ListNode>()) {
+            return false;
+        }
         let (size, _) = LinkedListAllocator::size_align(layout);
-        self.lock().add_free_region(ptr as usize, size)
+        let mut allocator = self.lock();
+        allocator.check_double_free(ptr as usize, size);
+        allocator.add_free_region(ptr as usize, size);
+        true
+    }
+}
+
+/* ---------------测试------------------ */
+
+#[test_case]
+fn test_next_fit_wraps_around() {
+    #[repr(align(4096))]
+    struct Arena {
+        _memory: [u8; 1024],
     }
+    static mut ARENA: Arena = Arena { _memory: [0; 1024] };
+
+    let base = core::ptr::addr_of_mut!(ARENA) as usize;
+    let mut allocator = LinkedListAllocator::new();
+    allocator.set_strategy(FitStrategy::NextFit);
+    unsafe {
+        allocator.add_free_region(base, 64);
+        allocator.add_free_region(base + 128, 256);
+        allocator.add_free_region(base + 512, 32);
+    }
+
+    //链表为 head -> base -> 128 -> 512，分配256字节后游标停在它的前驱base处
+    let (_, start) = allocator.find_region(256, 8).unwrap();
+    assert_eq!(start, base + 128);
+    assert_eq!(allocator.cursor, base);
+
+    //游标之后只剩下过小的区域，需要回绕才能找到位于游标处的区域
+    let (_, start) = allocator.find_region(64, 8).unwrap();
+    assert_eq!(start, base);
+}
+
+#[test_case]
+fn test_fragmentation_metrics() {
+    #[repr(align(4096))]
+    struct Arena {
+        _memory: [u8; 1024],
+    }
+    static mut ARENA: Arena = Arena { _memory: [0; 1024] };
+
+    let base = core::ptr::addr_of_mut!(ARENA) as usize;
+    let mut allocator = LinkedListAllocator::new();
+    unsafe {
+        allocator.add_free_region(base, 64);
+        allocator.add_free_region(base + 128, 256);
+        allocator.add_free_region(base + 512, 128);
+    }
+
+    assert_eq!(allocator.free_region_count(), 3);
+    assert_eq!(allocator.largest_free_block(), 256);
+    assert_eq!(allocator.free_bytes(), 448);
+}
+
+#[test_case]
+fn test_free_regions_coalesce() {
+    #[repr(align(4096))]
+    struct Arena {
+        _memory: [u8; 1024],
+    }
+    static mut ARENA: Arena = Arena { _memory: [0; 1024] };
+
+    let base = core::ptr::addr_of_mut!(ARENA) as usize;
+    let mut allocator = LinkedListAllocator::new();
+    unsafe {
+        //乱序释放，链表仍按地址排序
+        allocator.add_free_region(base + 512, 64);
+        allocator.add_free_region(base, 64);
+        allocator.add_free_region(base + 128, 64);
+    }
+    let mut starts = [0; 3];
+    let mut count = 0;
+    allocator.for_each_free_region(|start, _| {
+        starts[count] = start;
+        count += 1;
+    });
+    assert_eq!(starts, [base, base + 128, base + 512]);
+
+    //填上两块之间的空隙后三块合并为一块
+    unsafe { allocator.add_free_region(base + 64, 64) };
+    assert_eq!(allocator.free_region_count(), 2);
+    assert_eq!(allocator.largest_free_block(), 192);
+    unsafe { allocator.add_free_region(base + 192, 320) };
+    assert_eq!(allocator.free_region_count(), 1);
+    assert_eq!(allocator.free_bytes(), 576);
 }
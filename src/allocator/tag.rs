@@ -0,0 +1,281 @@
+use crate::println;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use spin::Mutex;
+
+pub const TAG_COUNT: usize = 16;
+
+/// ## 说明
+/// 分配标签，用于把堆用量归属到各个子系统
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Tag {
+    Untagged = 0,
+    Kernel,
+    Memory,
+    Interrupts,
+    Keyboard,
+    Test,
+}
+
+impl Tag {
+    fn name(self) -> &'static str {
+        match self {
+            Tag::Untagged => "untagged",
+            Tag::Kernel => "kernel",
+            Tag::Memory => "memory",
+            Tag::Interrupts => "interrupts",
+            Tag::Keyboard => "keyboard",
+            Tag::Test => "test",
+        }
+    }
+
+    fn from_u8(value: u8) -> Tag {
+        match value {
+            1 => Tag::Kernel,
+            2 => Tag::Memory,
+            3 => Tag::Interrupts,
+            4 => Tag::Keyboard,
+            5 => Tag::Test,
+            _ => Tag::Untagged,
+        }
+    }
+}
+
+//目前只有一个内核执行流，当前标签用全局变量保存；将来可替换为任务结构中的字段
+static CURRENT_TAG: AtomicU8 = AtomicU8::new(Tag::Untagged as u8);
+
+//每个标签下分配和释放的累计字节数
+static ALLOCATED: [AtomicUsize; TAG_COUNT] = [const { AtomicUsize::new(0) }; TAG_COUNT];
+static FREED: [AtomicUsize; TAG_COUNT] = [const { AtomicUsize::new(0) }; TAG_COUNT];
+
+/// ## 说明
+/// 返回当前执行流的分配标签
+pub fn current_tag() -> Tag {
+    Tag::from_u8(CURRENT_TAG.load(Ordering::SeqCst))
+}
+
+/// ## 说明
+/// 设置当前执行流的分配标签，返回之前的标签
+pub fn set_current_tag(tag: Tag) -> Tag {
+    Tag::from_u8(CURRENT_TAG.swap(tag as u8, Ordering::SeqCst))
+}
+
+/// ## 说明
+/// 作用域守卫，析构时恢复之前的标签
+pub struct TagGuard {
+    previous: Tag,
+}
+
+impl TagGuard {
+    pub fn new(tag: Tag) -> Self {
+        TagGuard {
+            previous: set_current_tag(tag),
+        }
+    }
+}
+
+impl Drop for TagGuard {
+    fn drop(&mut self) {
+        set_current_tag(self.previous);
+    }
+}
+
+/// ## 说明
+/// 在`tag`标签下执行`f`，期间的堆分配都归属于该标签
+///
+/// ## 用法
+/// ```rust
+/// with_tag(Tag::Keyboard, || Box::new(0));
+/// ```
+pub fn with_tag<R>(tag: Tag, f: impl FnOnce() -> R) -> R {
+    let _guard = TagGuard::new(tag);
+    f()
+}
+
+//带标签的存活分配表的容量，必须是2的幂
+const OWNER_SLOTS: usize = 512;
+//表中最多保存的分配数，保留空位让线性探测能够结束
+const OWNER_LIMIT: usize = OWNER_SLOTS * 3 / 4;
+
+/// ## 说明
+/// 存活分配的起始地址到分配时标签的散列表（线性探测，删除时向前移动后续表项）
+/// 只记录带标签的分配，查不到的地址属于`Tag::Untagged`；地址0表示空位
+///
+/// ## 成员
+/// * `addrs` - 分配的起始地址
+/// * `tags` - 对应的标签
+/// * `len` - 已使用的表项数
+struct OwnerTable {
+    addrs: [usize; OWNER_SLOTS],
+    tags: [u8; OWNER_SLOTS],
+    len: usize,
+}
+
+impl OwnerTable {
+    const fn new() -> Self {
+        OwnerTable {
+            addrs: [0; OWNER_SLOTS],
+            tags: [0; OWNER_SLOTS],
+            len: 0,
+        }
+    }
+
+    //分配至少按8字节对齐，去掉低位后再散列
+    fn home(addr: usize) -> usize {
+        ((addr >> 3).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) & (OWNER_SLOTS - 1)
+    }
+
+    //表满时返回false，调用者把这次分配记入`Tag::Untagged`
+    fn insert(&mut self, addr: usize, tag: Tag) -> bool {
+        if self.len >= OWNER_LIMIT {
+            return false;
+        }
+        let mut i = Self::home(addr);
+        while self.addrs[i] != 0 && self.addrs[i] != addr {
+            i = (i + 1) & (OWNER_SLOTS - 1);
+        }
+        if self.addrs[i] == 0 {
+            self.len += 1;
+        }
+        self.addrs[i] = addr;
+        self.tags[i] = tag as u8;
+        true
+    }
+
+    fn take(&mut self, addr: usize) -> Option<Tag> {
+        let mut i = Self::home(addr);
+        while self.addrs[i] != addr {
+            if self.addrs[i] == 0 {
+                return None;
+            }
+            i = (i + 1) & (OWNER_SLOTS - 1);
+        }
+        let tag = Tag::from_u8(self.tags[i]);
+        self.addrs[i] = 0;
+        self.len -= 1;
+
+        //把空位之后探测链上的表项前移，保证查找不会提前遇到空位
+        let mut hole = i;
+        let mut j = i;
+        loop {
+            j = (j + 1) & (OWNER_SLOTS - 1);
+            if self.addrs[j] == 0 {
+                break;
+            }
+            let home = Self::home(self.addrs[j]);
+            //home到j的距离不小于hole到j的距离时，表项可以移到空位
+            if (j.wrapping_sub(home) & (OWNER_SLOTS - 1))
+                >= (j.wrapping_sub(hole) & (OWNER_SLOTS - 1))
+            {
+                self.addrs[hole] = self.addrs[j];
+                self.tags[hole] = self.tags[j];
+                self.addrs[j] = 0;
+                hole = j;
+            }
+        }
+        Some(tag)
+    }
+}
+
+static OWNERS: Mutex<OwnerTable> = Mutex::new(OwnerTable::new());
+
+//在当前标签下记录一次分配；带标签的分配同时记住地址，释放时记回同一个标签
+pub(super) fn record_alloc(addr: usize, size: usize) {
+    let mut tag = current_tag();
+    if tag != Tag::Untagged && !OWNERS.lock().insert(addr, tag) {
+        tag = Tag::Untagged; //表满时按未标记的分配统计，分配和释放仍然对得上
+    }
+    ALLOCATED[tag as usize].fetch_add(size, Ordering::SeqCst);
+}
+
+//释放记入分配时的标签，而不是当前标签
+pub(super) fn record_free(addr: usize, size: usize) {
+    let tag = OWNERS.lock().take(addr).unwrap_or(Tag::Untagged);
+    FREED[tag as usize].fetch_add(size, Ordering::SeqCst);
+}
+
+//realloc保持原来的标签，大小的变化量计入该标签
+pub(super) fn record_realloc(old_addr: usize, new_addr: usize, old_size: usize, new_size: usize) {
+    let tag = {
+        let mut owners = OWNERS.lock();
+        match owners.take(old_addr) {
+            Some(tag) if owners.insert(new_addr, tag) => tag,
+            //新地址放不进表时把整块转为未标记
+            Some(tag) => {
+                FREED[tag as usize].fetch_add(old_size, Ordering::SeqCst);
+                ALLOCATED[Tag::Untagged as usize].fetch_add(old_size, Ordering::SeqCst);
+                Tag::Untagged
+            }
+            None => Tag::Untagged,
+        }
+    };
+    if new_size > old_size {
+        ALLOCATED[tag as usize].fetch_add(new_size - old_size, Ordering::SeqCst);
+    } else {
+        FREED[tag as usize].fetch_add(old_size - new_size, Ordering::SeqCst);
+    }
+}
+
+/// ## 说明
+/// 返回`tag`下分配的累计字节数
+pub fn allocated_bytes(tag: Tag) -> usize {
+    ALLOCATED[tag as usize].load(Ordering::SeqCst)
+}
+
+/// ## 说明
+/// 返回`tag`下释放的累计字节数
+pub fn freed_bytes(tag: Tag) -> usize {
+    FREED[tag as usize].load(Ordering::SeqCst)
+}
+
+/// ## 说明
+/// 打印每个标签的分配、释放和净用量
+pub fn report_by_tag() {
+    println!("{:<12}{:>12}{:>12}{:>12}", "tag", "allocated", "freed", "live");
+    for value in 0..TAG_COUNT as u8 {
+        let tag = Tag::from_u8(value);
+        if tag as u8 != value {
+            continue;
+        }
+        let allocated = allocated_bytes(tag);
+        let freed = freed_bytes(tag);
+        println!(
+            "{:<12}{:>12}{:>12}{:>12}",
+            tag.name(),
+            allocated,
+            freed,
+            allocated as isize - freed as isize
+        );
+    }
+}
+
+#[test_case]
+fn test_owner_table_round_trip() {
+    let mut table = OwnerTable::new();
+    assert!(table.insert(0x1000, Tag::Keyboard));
+    assert!(table.insert(0x2000, Tag::Test));
+    assert_eq!(table.take(0x1000), Some(Tag::Keyboard));
+    assert_eq!(table.take(0x1000), None);
+    assert_eq!(table.take(0x3000), None);
+    assert_eq!(table.take(0x2000), Some(Tag::Test));
+    assert_eq!(table.len, 0);
+}
+
+#[test_case]
+fn test_owner_table_collisions() {
+    let mut table = OwnerTable::new();
+    //装满到上限，其中必然有散列冲突；删除一部分后其余表项仍然可以找到
+    let addr = |i: usize| 0x10_0000 + i * 16;
+    for i in 0..OWNER_LIMIT {
+        assert!(table.insert(addr(i), Tag::Memory));
+    }
+    assert!(!table.insert(addr(OWNER_LIMIT), Tag::Memory));
+    for i in (0..OWNER_LIMIT).step_by(3) {
+        assert_eq!(table.take(addr(i)), Some(Tag::Memory));
+    }
+    for i in 0..OWNER_LIMIT {
+        let expected = if i % 3 == 0 { None } else { Some(Tag::Memory) };
+        assert_eq!(table.take(addr(i)), expected);
+    }
+    assert_eq!(table.len, 0);
+}
@@ -0,0 +1,109 @@
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::tag;
+
+/// ## 说明
+/// 能报告释放是否被接受的分配器，`TrackingAlloc`只为被接受的释放更新统计
+pub trait CheckedDealloc: GlobalAlloc {
+    /// ## 函数说明
+    /// 同`GlobalAlloc::dealloc`，非法释放在`InvalidFreePolicy::LogAndIgnore`下被忽略时返回false
+    ///
+    /// ## Safety
+    /// 与`GlobalAlloc::dealloc`相同
+    unsafe fn checked_dealloc(&self, ptr: *mut u8, layout: Layout) -> bool;
+}
+
+/// ## 说明
+/// 统计内存用量的分配器包装类型，记录当前已分配字节数和历史峰值
+///
+/// ## 成员
+/// * `inner` - 被包装的分配器
+/// * `current` - 当前已分配的字节数
+/// * `peak` - 已分配字节数的历史最大值
+pub struct TrackingAlloc<A> {
+    inner: A,
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl<A> TrackingAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        TrackingAlloc {
+            inner,
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// ## 说明
+    /// 获取被包装的分配器
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// ## 说明
+    /// 当前已分配的字节数
+    pub fn current_usage(&self) -> usize {
+        self.current.load(Ordering::SeqCst)
+    }
+
+    /// ## 说明
+    /// 已分配字节数的历史峰值
+    pub fn peak_usage(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+
+    /// ## 说明
+    /// 将峰值重置为当前用量，便于测量某一段代码的峰值
+    pub fn reset_peak(&self) {
+        self.peak.store(self.current_usage(), Ordering::SeqCst);
+    }
+
+    fn add(&self, size: usize) {
+        let current = self.current.fetch_add(size, Ordering::SeqCst) + size;
+        self.peak.fetch_max(current, Ordering::SeqCst);
+    }
+
+    fn sub(&self, size: usize) {
+        self.current.fetch_sub(size, Ordering::SeqCst);
+    }
+}
+
+unsafe impl<A: CheckedDealloc> GlobalAlloc for TrackingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        //被打断的代码可能正持有分配器的锁，在中断处理函数中分配会死锁
+        debug_assert!(
+            !crate::interrupts::in_interrupt_context(),
+            "heap allocation from interrupt context"
+        );
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            tag::record_alloc(ptr as usize, layout.size());
+            self.add(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        //被拒绝的释放没有归还任何内存，计入统计会使用量下溢并弄乱各标签的统计
+        if self.inner.checked_dealloc(ptr, layout) {
+            tag::record_free(ptr as usize, layout.size());
+            self.sub(layout.size());
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            tag::record_realloc(ptr as usize, new_ptr as usize, layout.size(), new_size);
+            //只计入大小的变化量
+            if new_size > layout.size() {
+                self.add(new_size - layout.size());
+            } else {
+                self.sub(layout.size() - new_size);
+            }
+        }
+        new_ptr
+    }
+}
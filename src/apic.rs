@@ -0,0 +1,235 @@
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use x86_64::PhysAddr;
+
+use crate::cpu::msr::{self, Msr};
+use crate::interrupts::InterruptIndex;
+use crate::memory::{self, MapError};
+use crate::time::{self, TimerError};
+
+/// 本地APIC伪中断使用的向量，低4位必须全为1
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+//IA32_APIC_BASE：位11为全局启用，位12起为寄存器页的物理地址
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+//寄存器在MMIO页中的偏移
+const REG_ID: usize = 0x20;
+const REG_VERSION: usize = 0x30;
+const REG_TPR: usize = 0x80; //任务优先级寄存器
+const REG_EOI: usize = 0xb0;
+const REG_SVR: usize = 0xf0;
+//伪中断向量寄存器中的软件启用位
+const SVR_ENABLE: u32 = 1 << 8;
+//计时器的LVT表项、初始计数、当前计数和分频寄存器
+const REG_LVT_TIMER: usize = 0x320;
+const REG_TIMER_INITIAL: usize = 0x380;
+const REG_TIMER_CURRENT: usize = 0x390;
+const REG_TIMER_DIVIDE: usize = 0x3e0;
+//分频寄存器中表示16分频的值
+const DIVIDE_BY_16: u32 = 0b0011;
+//LVT表项的屏蔽位和周期模式位，两者都为0时为未屏蔽的单次模式
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_PERIODIC: u32 = 1 << 17;
+//校准时等待的微秒数
+const CALIBRATE_US: u64 = 10_000;
+
+//寄存器页映射后的虚拟地址，0表示尚未初始化
+static BASE: AtomicU64 = AtomicU64::new(0);
+//校准得到的计时器频率（16分频后每秒的计数），0表示尚未校准
+static TIMER_HZ: AtomicU64 = AtomicU64::new(0);
+//计时器产生时钟中断的频率，0表示计时器未运行；以及切换前PIT的频率
+static TIMER_TARGET_HZ: AtomicU32 = AtomicU32::new(0);
+static PIT_HZ: AtomicU32 = AtomicU32::new(0);
+
+/// ## 说明
+/// 初始化本地APIC时可能出现的错误
+#[derive(Debug)]
+pub enum ApicError {
+    /// CPU没有本地APIC
+    Unsupported,
+    /// 本地APIC尚未通过`init`启用
+    NotEnabled,
+    /// 映射寄存器页失败
+    Map(MapError),
+}
+
+/// ## 函数说明
+/// CPU是否有本地APIC
+pub fn is_supported() -> bool {
+    crate::cpu::features().apic
+}
+
+/// ## 函数说明
+/// 本地APIC是否已经由[`init`]启用，启用后中断结束信号应通过[`eoi`]发送
+pub fn is_enabled() -> bool {
+    BASE.load(Ordering::Acquire) != 0
+}
+
+fn read(offset: usize) -> u32 {
+    let base = BASE.load(Ordering::Acquire);
+    assert_ne!(base, 0, "local APIC not initialized");
+    unsafe { ((base as usize + offset) as *const u32).read_volatile() }
+}
+
+fn write(offset: usize, value: u32) {
+    let base = BASE.load(Ordering::Acquire);
+    assert_ne!(base, 0, "local APIC not initialized");
+    unsafe { ((base as usize + offset) as *mut u32).write_volatile(value) }
+}
+
+/// ## 函数说明
+/// 启用本地APIC：映射IA32_APIC_BASE指出的寄存器页，设置伪中断向量并接受所有优先级的中断，
+/// 然后屏蔽两片8259 PIC的全部管脚。PIC的向量偏移保持不变，之后由`ioapic::init`按相同的向量投递IRQ
+/// 需要在页表安装之后调用，开启`apic`特性时由`os::init`调用
+///
+/// ## 用法
+/// ```rust
+/// apic::init().expect("local APIC initialization failed");
+/// ```
+pub fn init() -> Result<(), ApicError> {
+    if !is_supported() {
+        return Err(ApicError::Unsupported);
+    }
+    let apic_base = msr::apic_base().ok_or(ApicError::Unsupported)?;
+    if apic_base & APIC_BASE_ENABLE == 0 {
+        unsafe { msr::write(Msr::APIC_BASE, apic_base | APIC_BASE_ENABLE) };
+    }
+    let phys = PhysAddr::new(apic_base & APIC_BASE_ADDR_MASK);
+    let virt = memory::with_paging(|paging| {
+        memory::map_physical_region(phys, 4096, &mut paging.mapper, &mut paging.frame_allocator)
+    })
+    .unwrap_or(Err(MapError::NoPaging))
+    .map_err(ApicError::Map)?;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        BASE.store(virt.as_u64(), Ordering::Release);
+        write(REG_TPR, 0);
+        write(REG_SVR, SVR_ENABLE | u32::from(SPURIOUS_VECTOR));
+        unsafe { crate::interrupts::PICS.lock().write_masks(0xff, 0xff) };
+    });
+    Ok(())
+}
+
+/// ## 函数说明
+/// 当前CPU的APIC ID
+pub fn id() -> u8 {
+    (read(REG_ID) >> 24) as u8
+}
+
+/// ## 函数说明
+/// 版本寄存器：低8位为版本号，16~23位为LVT表项数减一
+pub fn version() -> u32 {
+    read(REG_VERSION)
+}
+
+/// ## 函数说明
+/// 向本地APIC发送中断结束信号，伪中断不需要调用
+pub fn eoi() {
+    write(REG_EOI, 0);
+}
+
+/// ## 函数说明
+/// 以16分频的单次模式计数，用TSC计时等待10毫秒，按计数的减少量换算计时器频率
+/// 会停止正在运行的计时器，返回16分频后每秒的计数
+///
+/// ## 用法
+/// ```rust
+/// let hz = apic::calibrate_timer();
+/// ```
+pub fn calibrate_timer() -> u64 {
+    time::tsc_per_ms(); //提前校准TSC，不计入测量
+    let hz = x86_64::instructions::interrupts::without_interrupts(|| {
+        write(REG_TIMER_DIVIDE, DIVIDE_BY_16);
+        write(
+            REG_LVT_TIMER,
+            LVT_MASKED | u32::from(InterruptIndex::Timer.as_u8()),
+        );
+        write(REG_TIMER_INITIAL, u32::MAX);
+        time::sleep_busy_us(CALIBRATE_US);
+        let elapsed = u32::MAX - read(REG_TIMER_CURRENT);
+        write(REG_TIMER_INITIAL, 0);
+        u64::from(elapsed) * 1_000_000 / CALIBRATE_US
+    });
+    TIMER_HZ.store(hz, Ordering::SeqCst);
+    hz
+}
+
+/// ## 函数说明
+/// 校准得到的计时器频率，尚未校准时为0
+pub fn timer_frequency() -> u64 {
+    TIMER_HZ.load(Ordering::SeqCst)
+}
+
+/// ## 函数说明
+/// 用本地APIC计时器代替PIT产生时钟中断：校准后以周期模式按`target_hz`投递到计时器向量，
+/// 并在IOAPIC上屏蔽PIT，`time::ticks`等接口不受影响。超出范围的频率按`time::init`的规则处理
+/// 需要在`os::init`之后调用
+///
+/// ## 参数
+/// * `target_hz` - 时钟中断频率，范围为[time::MIN_FREQUENCY, time::MAX_FREQUENCY]
+///
+/// ## 用法
+/// ```rust
+/// apic::timer_init(1000).expect("invalid timer frequency");
+/// ```
+pub fn timer_init(target_hz: u32) -> Result<(), TimerError> {
+    let applied = target_hz.clamp(time::MIN_FREQUENCY, time::MAX_FREQUENCY);
+    let hz = calibrate_timer();
+    let initial = (hz / u64::from(applied)).clamp(1, u64::from(u32::MAX)) as u32;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        if TIMER_TARGET_HZ.load(Ordering::SeqCst) == 0 {
+            PIT_HZ.store(time::frequency(), Ordering::SeqCst);
+            crate::ioapic::mask(crate::ioapic::isa_irq_to_gsi(0));
+        }
+        write(
+            REG_LVT_TIMER,
+            LVT_PERIODIC | u32::from(InterruptIndex::Timer.as_u8()),
+        );
+        write(REG_TIMER_INITIAL, initial);
+        TIMER_TARGET_HZ.store(applied, Ordering::SeqCst);
+        time::set_tick_source(applied);
+    });
+
+    if applied == target_hz {
+        Ok(())
+    } else {
+        Err(TimerError::OutOfRange {
+            requested: target_hz,
+            applied,
+        })
+    }
+}
+
+/// ## 函数说明
+/// 停止本地APIC计时器，恢复切换前的PIT频率并取消屏蔽PIT；计时器未运行时什么都不做
+pub fn timer_stop() {
+    let pit_hz = x86_64::instructions::interrupts::without_interrupts(|| {
+        if TIMER_TARGET_HZ.swap(0, Ordering::SeqCst) == 0 {
+            return None;
+        }
+        write(
+            REG_LVT_TIMER,
+            LVT_MASKED | u32::from(InterruptIndex::Timer.as_u8()),
+        );
+        write(REG_TIMER_INITIAL, 0);
+        Some(PIT_HZ.load(Ordering::SeqCst))
+    });
+    if let Some(pit_hz) = pit_hz {
+        let _ = time::init(pit_hz); //这个频率之前已被PIT接受
+        crate::ioapic::unmask(crate::ioapic::isa_irq_to_gsi(0));
+    }
+}
+
+/// ## 函数说明
+/// 重新校准计时器，正在运行时以原来的频率重新启动，返回新的计时器频率
+pub fn recalibrate_timer() -> u64 {
+    match TIMER_TARGET_HZ.load(Ordering::SeqCst) {
+        0 => calibrate_timer(),
+        target_hz => {
+            let _ = timer_init(target_hz); //频率在上次启动时已经限制在范围内
+            timer_frequency()
+        }
+    }
+}
@@ -0,0 +1,118 @@
+pub mod cpuid;
+pub mod msr;
+
+use core::fmt;
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::registers::mxcsr::MxCsr;
+
+pub use cpuid::{features, CpuFeatures};
+
+/// ## 函数说明
+/// 按SSE的要求设置控制寄存器：清除CR0.EM、设置CR0.MP，设置CR4.OSFXSR和CR4.OSXMMEXCPT
+/// 此后SSE指令可以执行，未屏蔽的SIMD浮点异常以#XM（19号向量）报告，而不是#UD
+/// 目标规格禁用了SSE（soft-float），编译器不会生成SSE指令，中断处理函数因此不需要保存XMM寄存器
+///
+/// ## 用法
+/// ```rust
+/// cpu::enable_sse();
+/// ```
+pub fn enable_sse() {
+    unsafe {
+        Cr0::update(|flags| {
+            flags.remove(Cr0Flags::EMULATE_COPROCESSOR);
+            flags.insert(Cr0Flags::MONITOR_COPROCESSOR);
+        });
+        Cr4::update(|flags| flags.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE));
+    }
+}
+
+/// ## 函数说明
+/// SSE是否已经按`enable_sse`的方式启用
+pub fn sse_enabled() -> bool {
+    let cr0 = Cr0::read();
+    let cr4 = Cr4::read();
+    !cr0.contains(Cr0Flags::EMULATE_COPROCESSOR)
+        && cr0.contains(Cr0Flags::MONITOR_COPROCESSOR)
+        && cr4.contains(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE)
+}
+
+/// ## 函数说明
+/// CPU支持时在EFER中启用NO_EXECUTE，返回是否已启用
+/// 此后页表项中的NX位才有效，未启用时设置NX位会引发保留位页错误，因此需要在建立任何映射之前调用
+///
+/// ## 用法
+/// ```rust
+/// assert!(cpu::enable_nx(), "CPU has no NX support");
+/// ```
+pub fn enable_nx() -> bool {
+    if !features().nx {
+        return false;
+    }
+    unsafe { Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE)) };
+    true
+}
+
+/// ## 函数说明
+/// EFER中的NO_EXECUTE是否已经启用
+pub fn nx_enabled() -> bool {
+    msr::efer().contains(EferFlags::NO_EXECUTE_ENABLE)
+}
+
+//MXCSR的6个异常标志及其名称，按位从低到高
+const EXCEPTION_FLAGS: [(MxCsr, &str); 6] = [
+    (MxCsr::INVALID_OPERATION, "invalid"),
+    (MxCsr::DENORMAL, "denormal"),
+    (MxCsr::DIVIDE_BY_ZERO, "divide-by-zero"),
+    (MxCsr::OVERFLOW, "overflow"),
+    (MxCsr::UNDERFLOW, "underflow"),
+    (MxCsr::PRECISION, "precision"),
+];
+
+/// ## 说明
+/// 打印MXCSR中置位的异常标志，如"invalid, divide-by-zero"，没有标志时为"none"
+#[derive(Debug, Clone, Copy)]
+pub struct SimdExceptions(pub MxCsr);
+
+impl fmt::Display for SimdExceptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut set = EXCEPTION_FLAGS
+            .iter()
+            .filter(|(flag, _)| self.0.contains(*flag))
+            .map(|(_, name)| name);
+        match set.next() {
+            Some(first) => write!(f, "{}", first)?,
+            None => return write!(f, "none"),
+        }
+        for name in set {
+            write!(f, ", {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+use crate::memory::debug::StrBuf;
+
+#[test_case]
+fn test_simd_exceptions_display() {
+    let print = |mxcsr| StrBuf::format(format_args!("{}", SimdExceptions(mxcsr)));
+    assert_eq!(print(MxCsr::default()).as_str(), "none");
+    assert_eq!(print(MxCsr::DIVIDE_BY_ZERO).as_str(), "divide-by-zero");
+    let flags =
+        MxCsr::INVALID_OPERATION | MxCsr::OVERFLOW | MxCsr::PRECISION | MxCsr::OVERFLOW_MASK;
+    assert_eq!(print(flags).as_str(), "invalid, overflow, precision");
+}
+
+#[test_case]
+fn test_sse_enabled_after_init() {
+    assert!(sse_enabled());
+}
+
+#[test_case]
+fn test_nx_enabled_after_init() {
+    assert_eq!(nx_enabled(), features().nx);
+    //重复调用不改变状态
+    assert_eq!(enable_nx(), features().nx);
+    assert_eq!(nx_enabled(), features().nx);
+}
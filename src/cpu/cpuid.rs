@@ -0,0 +1,304 @@
+use core::arch::x86_64::{__cpuid_count, CpuidResult};
+use core::fmt;
+use lazy_static::lazy_static;
+
+//扩展叶的起始编号，0x8000_0000号叶返回最大扩展叶
+const EXTENDED_BASE: u32 = 0x8000_0000;
+//处理器名称字符串所在的三个扩展叶
+const BRAND_LEAVES: [u32; 3] = [0x8000_0002, 0x8000_0003, 0x8000_0004];
+
+/// ## 函数说明
+/// 支持的最大标准叶
+pub fn max_standard_leaf() -> u32 {
+    __cpuid_count(0, 0).eax
+}
+
+/// ## 函数说明
+/// 支持的最大扩展叶，不支持扩展叶时为0
+pub fn max_extended_leaf() -> u32 {
+    let max = __cpuid_count(EXTENDED_BASE, 0).eax;
+    if max >= EXTENDED_BASE {
+        max
+    } else {
+        0
+    }
+}
+
+/// ## 函数说明
+/// 执行CPUID，超出最大叶时返回None
+/// 超出范围的叶在Intel处理器上返回最大标准叶的内容，而不是全0，因此不能直接执行
+///
+/// ## 参数
+/// * `leaf` - EAX中的叶编号
+/// * `subleaf` - ECX中的子叶编号
+///
+/// ## 用法
+/// ```rust
+/// let leaf7 = cpuid::query(7, 0);
+/// ```
+pub fn query(leaf: u32, subleaf: u32) -> Option<CpuidResult> {
+    query_limited(leaf, subleaf, max_standard_leaf(), max_extended_leaf())
+}
+
+fn query_limited(leaf: u32, subleaf: u32, max_std: u32, max_ext: u32) -> Option<CpuidResult> {
+    let max = if leaf >= EXTENDED_BASE {
+        max_ext
+    } else {
+        max_std
+    };
+    if leaf > max {
+        return None;
+    }
+    Some(__cpuid_count(leaf, subleaf))
+}
+
+/// ## 说明
+/// CPUID返回的定长ASCII字符串，去掉了首尾的空格和结尾的NUL
+pub struct CpuString {
+    bytes: [u8; 48],
+    len: usize,
+}
+
+impl CpuString {
+    fn from_registers(registers: &[u32]) -> Self {
+        let mut bytes = [0u8; 48];
+        for (chunk, register) in bytes.chunks_mut(4).zip(registers) {
+            chunk.copy_from_slice(&register.to_le_bytes());
+        }
+        let len = bytes
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(registers.len() * 4);
+        CpuString { bytes, len }
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len])
+            .unwrap_or("?")
+            .trim()
+    }
+}
+
+impl fmt::Display for CpuString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// ## 函数说明
+/// 厂商字符串，如"GenuineIntel"、"AuthenticAMD"
+pub fn vendor_string() -> CpuString {
+    let leaf0 = __cpuid_count(0, 0);
+    //顺序为EBX、EDX、ECX
+    CpuString::from_registers(&[leaf0.ebx, leaf0.edx, leaf0.ecx])
+}
+
+/// ## 函数说明
+/// 处理器名称字符串，CPU不支持0x8000_0004号叶时返回None
+pub fn brand_string() -> Option<CpuString> {
+    let mut registers = [0u32; 12];
+    for (i, &leaf) in BRAND_LEAVES.iter().enumerate() {
+        let result = query(leaf, 0)?;
+        registers[i * 4..i * 4 + 4]
+            .copy_from_slice(&[result.eax, result.ebx, result.ecx, result.edx]);
+    }
+    Some(CpuString::from_registers(&registers))
+}
+
+/// ## 说明
+/// 内核关心的CPU特性，由[`features`]在第一次调用时检测一次
+/// 所在的叶超出最大叶时对应的特性为false
+///
+/// ## 成员
+/// * `max_standard_leaf` - 支持的最大标准叶
+/// * `max_extended_leaf` - 支持的最大扩展叶，不支持扩展叶时为0
+/// * `tsc` - 时间戳计数器
+/// * `tsc_deadline` - 本地APIC定时器的TSC-deadline模式
+/// * `invariant_tsc` - TSC频率不随电源状态变化
+/// * `apic` - 本地APIC
+/// * `x2apic` - x2APIC模式
+/// * `nx` - 页表项的NX位
+/// * `rdrand` - RDRAND指令
+/// * `rdseed` - RDSEED指令
+/// * `pse` - 4KiB分页下的2MiB大页
+/// * `huge_pages_1g` - 1GiB大页
+/// * `pge` - 全局页
+/// * `mca` - 机器检查架构，MCG_*和MCi_*寄存器
+/// * `sse`至`sse4_2` - 各级SSE指令
+/// * `xsave` - XSAVE系列指令
+/// * `hypervisor` - 运行在虚拟机中（CPUID.1:ECX的第31位由虚拟机监视器设置）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuFeatures {
+    pub max_standard_leaf: u32,
+    pub max_extended_leaf: u32,
+    pub tsc: bool,
+    pub tsc_deadline: bool,
+    pub invariant_tsc: bool,
+    pub apic: bool,
+    pub x2apic: bool,
+    pub nx: bool,
+    pub rdrand: bool,
+    pub rdseed: bool,
+    pub pse: bool,
+    pub huge_pages_1g: bool,
+    pub pge: bool,
+    pub mca: bool,
+    pub sse: bool,
+    pub sse2: bool,
+    pub sse3: bool,
+    pub ssse3: bool,
+    pub sse4_1: bool,
+    pub sse4_2: bool,
+    pub xsave: bool,
+    pub hypervisor: bool,
+}
+
+impl CpuFeatures {
+    fn detect() -> Self {
+        Self::detect_limited(max_standard_leaf(), max_extended_leaf())
+    }
+
+    //只查询不超过给出的最大叶的叶，测试通过它模拟较旧的CPU
+    fn detect_limited(max_std: u32, max_ext: u32) -> Self {
+        let bit = |value: u32, bit: u32| value & (1 << bit) != 0;
+        let mut features = CpuFeatures {
+            max_standard_leaf: max_std,
+            max_extended_leaf: max_ext,
+            ..CpuFeatures::default()
+        };
+        if let Some(leaf1) = query_limited(1, 0, max_std, max_ext) {
+            features.tsc = bit(leaf1.edx, 4);
+            features.pse = bit(leaf1.edx, 3);
+            features.apic = bit(leaf1.edx, 9);
+            features.pge = bit(leaf1.edx, 13);
+            features.mca = bit(leaf1.edx, 14);
+            features.sse = bit(leaf1.edx, 25);
+            features.sse2 = bit(leaf1.edx, 26);
+            features.sse3 = bit(leaf1.ecx, 0);
+            features.ssse3 = bit(leaf1.ecx, 9);
+            features.sse4_1 = bit(leaf1.ecx, 19);
+            features.sse4_2 = bit(leaf1.ecx, 20);
+            features.x2apic = bit(leaf1.ecx, 21);
+            features.tsc_deadline = bit(leaf1.ecx, 24);
+            features.xsave = bit(leaf1.ecx, 26);
+            features.rdrand = bit(leaf1.ecx, 30);
+            features.hypervisor = bit(leaf1.ecx, 31);
+        }
+        if let Some(leaf7) = query_limited(7, 0, max_std, max_ext) {
+            features.rdseed = bit(leaf7.ebx, 18);
+        }
+        if let Some(ext1) = query_limited(0x8000_0001, 0, max_std, max_ext) {
+            features.nx = bit(ext1.edx, 20);
+            features.huge_pages_1g = bit(ext1.edx, 26);
+        }
+        if let Some(ext7) = query_limited(0x8000_0007, 0, max_std, max_ext) {
+            features.invariant_tsc = bit(ext7.edx, 8);
+        }
+        features
+    }
+}
+
+impl fmt::Display for CpuFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            (self.tsc, "tsc"),
+            (self.tsc_deadline, "tsc-deadline"),
+            (self.invariant_tsc, "invariant-tsc"),
+            (self.apic, "apic"),
+            (self.x2apic, "x2apic"),
+            (self.nx, "nx"),
+            (self.rdrand, "rdrand"),
+            (self.rdseed, "rdseed"),
+            (self.pse, "pse"),
+            (self.huge_pages_1g, "1g-pages"),
+            (self.pge, "pge"),
+            (self.mca, "mca"),
+            (self.sse, "sse"),
+            (self.sse2, "sse2"),
+            (self.sse3, "sse3"),
+            (self.ssse3, "ssse3"),
+            (self.sse4_1, "sse4.1"),
+            (self.sse4_2, "sse4.2"),
+            (self.xsave, "xsave"),
+            (self.hypervisor, "hypervisor"),
+        ];
+        write!(
+            f,
+            "leaves {:#x}/{:#x}:",
+            self.max_standard_leaf, self.max_extended_leaf
+        )?;
+        for (_, name) in flags.iter().filter(|(set, _)| *set) {
+            write!(f, " {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref FEATURES: CpuFeatures = CpuFeatures::detect();
+}
+
+/// ## 函数说明
+/// 当前CPU支持的特性，第一次调用时检测
+///
+/// ## 用法
+/// ```rust
+/// if cpu::features().rdrand {
+///     println!("RDRAND available");
+/// }
+/// ```
+pub fn features() -> &'static CpuFeatures {
+    &FEATURES
+}
+
+/// ## 函数说明
+/// 打印一行CPU厂商、名称和特性的摘要
+pub fn print_summary() {
+    match brand_string() {
+        Some(brand) => crate::println!("CPU: {} ({}) {}", vendor_string(), brand, features()),
+        None => crate::println!("CPU: {} {}", vendor_string(), features()),
+    }
+}
+
+#[test_case]
+fn test_vendor_string_known() {
+    let known = [
+        "GenuineIntel",
+        "AuthenticAMD",
+        "HygonGenuine",
+        "TCGTCGTCGTCG",
+        "KVMKVMKVM",
+    ];
+    let vendor = vendor_string();
+    assert!(known.contains(&vendor.as_str()));
+}
+
+#[test_case]
+fn test_query_beyond_max_leaf() {
+    let max_std = max_standard_leaf();
+    assert!(query(max_std + 1, 0).is_none());
+    assert!(query(EXTENDED_BASE + 0x1000, 0).is_none());
+    assert!(query(0, 0).is_some());
+
+    //只有0号叶的CPU上，所有特性都应为false
+    let oldest = CpuFeatures::detect_limited(0, 0);
+    assert_eq!(
+        oldest,
+        CpuFeatures {
+            max_standard_leaf: 0,
+            ..CpuFeatures::default()
+        }
+    );
+    //不支持扩展叶时，只有来自扩展叶的特性为false
+    let no_ext = CpuFeatures::detect_limited(max_std, 0);
+    assert!(!no_ext.nx && !no_ext.huge_pages_1g && !no_ext.invariant_tsc);
+    assert_eq!(no_ext.sse2, features().sse2);
+    assert_eq!(*features(), CpuFeatures::detect());
+}
+
+#[test_case]
+fn test_features_match_running_state() {
+    //引导程序已经启用了长模式，x86_64要求SSE2和PAE所在的叶存在
+    assert!(features().sse && features().sse2);
+    assert!(features().max_extended_leaf >= 0x8000_0001);
+}
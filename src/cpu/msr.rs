@@ -0,0 +1,185 @@
+use core::arch::asm;
+use x86_64::registers::model_specific::{Efer, EferFlags, GsBase, KernelGsBase};
+use x86_64::VirtAddr;
+
+use super::cpuid;
+
+/// ## 说明
+/// 模型特定寄存器的编号，内核用到的寄存器以关联常量给出
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Msr(pub u32);
+
+impl Msr {
+    /// 本地APIC的基址和全局启用位
+    pub const APIC_BASE: Msr = Msr(0x1b);
+    /// 机器检查的能力，低8位为bank的个数
+    pub const MCG_CAP: Msr = Msr(0x179);
+    /// 机器检查的全局状态
+    pub const MCG_STATUS: Msr = Msr(0x17a);
+    /// 长模式、NX和syscall的开关
+    pub const EFER: Msr = Msr(0xc000_0080);
+    /// syscall/sysret使用的段选择子
+    pub const STAR: Msr = Msr(0xc000_0081);
+    /// syscall的入口地址
+    pub const LSTAR: Msr = Msr(0xc000_0082);
+    /// syscall时清除的RFLAGS位
+    pub const FMASK: Msr = Msr(0xc000_0084);
+    /// GS段的基址
+    pub const GS_BASE: Msr = Msr(0xc000_0101);
+    /// swapgs时与GS_BASE交换的基址
+    pub const KERNEL_GS_BASE: Msr = Msr(0xc000_0102);
+
+    //第i个错误报告bank的状态和地址寄存器为0x401 + 4i、0x402 + 4i
+    const MC0_STATUS: u32 = 0x401;
+    const MC0_ADDR: u32 = 0x402;
+
+    /// ## 函数说明
+    /// 第`bank`个机器检查bank的状态寄存器
+    pub const fn mc_status(bank: u32) -> Msr {
+        Msr(Self::MC0_STATUS + 4 * bank)
+    }
+
+    /// ## 函数说明
+    /// 第`bank`个机器检查bank的地址寄存器
+    pub const fn mc_addr(bank: u32) -> Msr {
+        Msr(Self::MC0_ADDR + 4 * bank)
+    }
+}
+
+/// ## 函数说明
+/// 读取MSR
+///
+/// ## Safety
+/// 读取CPU没有实现的MSR会引发#GP，不确定时使用[`try_read`]
+pub unsafe fn read(msr: Msr) -> u64 {
+    let (high, low): (u32, u32);
+    asm!("rdmsr", in("ecx") msr.0, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+/// ## 函数说明
+/// 写入MSR
+///
+/// ## Safety
+/// 写入没有实现的MSR或保留位会引发#GP，
+/// EFER、GS_BASE等寄存器的值还决定了内核能否继续运行，调用者需要保证写入的值有效
+pub unsafe fn write(msr: Msr, value: u64) {
+    let low = value as u32;
+    let high = (value >> 32) as u32;
+    asm!("wrmsr", in("ecx") msr.0, in("eax") low, in("edx") high, options(nostack, preserves_flags));
+}
+
+/// ## 函数说明
+/// 根据CPUID判断CPU是否实现了这个MSR，只认识[`Msr`]中给出的寄存器，其他编号返回false
+///
+/// ## 参数
+/// * `msr` - MSR的编号
+pub fn is_implemented(msr: Msr) -> bool {
+    let features = cpuid::features();
+    match msr {
+        //内核运行在长模式下，这些寄存器必然存在
+        Msr::EFER | Msr::STAR | Msr::LSTAR | Msr::FMASK | Msr::GS_BASE | Msr::KERNEL_GS_BASE => {
+            true
+        }
+        Msr::APIC_BASE => features.apic,
+        Msr::MCG_CAP | Msr::MCG_STATUS => features.mca,
+        Msr(number) if features.mca && number >= Msr::MC0_STATUS => {
+            let bank = (number - Msr::MC0_STATUS) / 4;
+            let register = (number - Msr::MC0_STATUS) % 4;
+            //只有状态和地址寄存器，且bank需要在MCG_CAP报告的个数之内
+            register <= 1 && u64::from(bank) < unsafe { read(Msr::MCG_CAP) } & 0xff
+        }
+        _ => false,
+    }
+}
+
+/// ## 函数说明
+/// 读取MSR，CPU没有实现或不认识这个编号时返回None
+///
+/// ## 参数
+/// * `msr` - MSR的编号
+///
+/// ## 用法
+/// ```rust
+/// if let Some(cap) = msr::try_read(Msr::MCG_CAP) {
+///     println!("{} banks", cap & 0xff);
+/// }
+/// ```
+pub fn try_read(msr: Msr) -> Option<u64> {
+    if is_implemented(msr) {
+        Some(unsafe { read(msr) })
+    } else {
+        None
+    }
+}
+
+/// ## 函数说明
+/// EFER中的标志
+pub fn efer() -> EferFlags {
+    Efer::read()
+}
+
+/// ## 函数说明
+/// 写入EFER
+///
+/// ## Safety
+/// 清除LONG_MODE_ENABLE或在页表项使用NX位时清除NO_EXECUTE_ENABLE都会使内核崩溃
+pub unsafe fn set_efer(flags: EferFlags) {
+    Efer::write(flags);
+}
+
+/// ## 函数说明
+/// IA32_APIC_BASE的值，CPU没有本地APIC时返回None
+pub fn apic_base() -> Option<u64> {
+    try_read(Msr::APIC_BASE)
+}
+
+/// ## 函数说明
+/// 当前的GS基址
+pub fn gs_base() -> VirtAddr {
+    GsBase::read()
+}
+
+/// ## 函数说明
+/// swapgs时换入的GS基址
+pub fn kernel_gs_base() -> VirtAddr {
+    KernelGsBase::read()
+}
+
+#[test_case]
+fn test_read_efer() {
+    let efer = efer();
+    assert!(efer.contains(EferFlags::LONG_MODE_ENABLE | EferFlags::LONG_MODE_ACTIVE));
+    //`os::init`在支持时启用了NX
+    assert_eq!(
+        efer.contains(EferFlags::NO_EXECUTE_ENABLE),
+        cpuid::features().nx
+    );
+    assert_eq!(unsafe { read(Msr::EFER) }, efer.bits());
+}
+
+#[test_case]
+fn test_read_apic_base() {
+    //位8表示引导处理器，寄存器页4KiB对齐
+    const BSP: u64 = 1 << 8;
+    match apic_base() {
+        Some(base) => {
+            assert!(cpuid::features().apic);
+            assert_ne!(base & BSP, 0);
+            assert_ne!(base & !0xfff, 0);
+        }
+        None => assert!(!cpuid::features().apic),
+    }
+}
+
+#[test_case]
+fn test_try_read_unknown() {
+    assert!(try_read(Msr(0x1234)).is_none());
+    assert_eq!(try_read(Msr::GS_BASE), Some(gs_base().as_u64()));
+    assert_eq!(
+        try_read(Msr::KERNEL_GS_BASE),
+        Some(kernel_gs_base().as_u64())
+    );
+    //MCi的第三、四个寄存器不在认识的范围内
+    assert!(try_read(Msr(Msr::mc_status(0).0 + 2)).is_none());
+}
@@ -0,0 +1,240 @@
+use core::arch::asm;
+use core::fmt;
+use x86_64::registers::control::{Cr2, Cr3};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+//打印的栈上四字的个数
+const STACK_DUMP_QWORDS: usize = 32;
+//RFLAGS中的中断允许位
+const RFLAGS_IF: u64 = 1 << 9;
+
+/// ## 说明
+/// 某一时刻通用寄存器和RFLAGS的快照，由[`snapshot`]获取
+/// 保存快照的代码需要一个寄存器存放目标地址，这个寄存器记录的是快照自身的地址
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct Registers {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rflags: u64,
+}
+
+/// ## 函数说明
+/// 保存当前的通用寄存器和RFLAGS，总是内联到调用处，RSP为调用者栈帧中的值
+/// 应在panic处理函数的最开始调用，此后的代码会覆盖寄存器中的值
+///
+/// ## 用法
+/// ```rust
+/// let regs = crash::snapshot();
+/// ```
+#[inline(always)]
+pub fn snapshot() -> Registers {
+    let mut regs = Registers::default();
+    unsafe {
+        //偏移与Registers的字段顺序一致；先保存RSP，pushfq会移动栈顶
+        asm!(
+            "mov [{r} + 0x00], rax",
+            "mov [{r} + 0x08], rbx",
+            "mov [{r} + 0x10], rcx",
+            "mov [{r} + 0x18], rdx",
+            "mov [{r} + 0x20], rsi",
+            "mov [{r} + 0x28], rdi",
+            "mov [{r} + 0x30], rbp",
+            "mov [{r} + 0x38], rsp",
+            "mov [{r} + 0x40], r8",
+            "mov [{r} + 0x48], r9",
+            "mov [{r} + 0x50], r10",
+            "mov [{r} + 0x58], r11",
+            "mov [{r} + 0x60], r12",
+            "mov [{r} + 0x68], r13",
+            "mov [{r} + 0x70], r14",
+            "mov [{r} + 0x78], r15",
+            "pushfq",
+            "pop qword ptr [{r} + 0x80]",
+            r = in(reg) &mut regs as *mut Registers,
+        );
+    }
+    regs
+}
+
+/// ## 函数说明
+/// 输出寄存器、RFLAGS、CR2、CR3和RSP之上32个四字的栈内容
+/// 落在内核代码区域中的值标记为可能的返回地址
+/// 不使用堆，也不获取任何锁，输出目标是否安全由调用者决定
+///
+/// ## 参数
+/// * `out` - 输出目标
+/// * `regs` - [`snapshot`]保存的寄存器
+pub fn write_dump(out: &mut dyn fmt::Write, regs: &Registers) -> fmt::Result {
+    let rows = [
+        [
+            ("RAX", regs.rax),
+            ("RBX", regs.rbx),
+            ("RCX", regs.rcx),
+            ("RDX", regs.rdx),
+        ],
+        [
+            ("RSI", regs.rsi),
+            ("RDI", regs.rdi),
+            ("RBP", regs.rbp),
+            ("RSP", regs.rsp),
+        ],
+        [
+            ("R8", regs.r8),
+            ("R9", regs.r9),
+            ("R10", regs.r10),
+            ("R11", regs.r11),
+        ],
+        [
+            ("R12", regs.r12),
+            ("R13", regs.r13),
+            ("R14", regs.r14),
+            ("R15", regs.r15),
+        ],
+    ];
+    for row in rows.iter() {
+        for (name, value) in row.iter() {
+            write!(out, "{:>3}={:#018x} ", name, value)?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(
+        out,
+        "RFLAGS={:#x} (interrupts {})",
+        regs.rflags,
+        if regs.rflags & RFLAGS_IF != 0 {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    )?;
+    writeln!(out, "CR2: {:#x}", Cr2::read().as_u64())?;
+    let (frame, flags) = Cr3::read();
+    writeln!(
+        out,
+        "CR3: {:#x} {:?}",
+        frame.start_address().as_u64(),
+        flags
+    )?;
+    write_stack(out, regs.rsp)
+}
+
+//地址所在的页面是否已映射，直接遍历CR3指向的页表而不获取页表锁
+fn is_mapped(addr: VirtAddr) -> bool {
+    let offset = match crate::memory::phys::try_phys_offset() {
+        Some(offset) => offset,
+        None => return false,
+    };
+    let flags = crate::memory::entry_flags(addr, offset);
+    flags.iter().enumerate().any(|(level, flags)| match flags {
+        Some(flags) => level == 3 || (level > 0 && flags.contains(PageTableFlags::HUGE_PAGE)),
+        None => false,
+    })
+}
+
+fn write_stack(out: &mut dyn fmt::Write, rsp: u64) -> fmt::Result {
+    writeln!(out, "stack at {:#x}:", rsp)?;
+    let base = rsp as *const u64;
+    for i in 0..STACK_DUMP_QWORDS {
+        //栈向低地址增长，RSP之上是调用者的栈帧；栈顶之上通常是未映射的保护页，越过已映射的页面时停止
+        let end = rsp
+            .checked_add(i as u64 * 8 + 7)
+            .and_then(|end| VirtAddr::try_new(end).ok());
+        let readable = match end {
+            Some(end) if i == 0 || end.as_u64() % 4096 < 8 => is_mapped(end),
+            Some(_) => true,
+            None => false,
+        };
+        if !readable {
+            writeln!(out, "  [rsp+{:#04x}] <unmapped>", i * 8)?;
+            break;
+        }
+        let value = unsafe { base.add(i).read_volatile() };
+        let code = VirtAddr::try_new(value)
+            .map(crate::memory::wx::is_kernel_code)
+            .unwrap_or(false);
+        if code {
+            writeln!(out, "  [rsp+{:#04x}] {:#018x} <- kernel code", i * 8, value)?;
+        } else {
+            writeln!(out, "  [rsp+{:#04x}] {:#018x}", i * 8, value)?;
+        }
+    }
+    Ok(())
+}
+
+//把fmt::Write的输出转发给打印函数
+struct Sink(fn(fmt::Arguments));
+
+impl fmt::Write for Sink {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        (self.0)(format_args!("{}", s));
+        Ok(())
+    }
+}
+
+/// ## 函数说明
+/// 通过串口的紧急打印路径输出[`write_dump`]的内容，串口被锁定时强制解锁
+///
+/// ## 参数
+/// * `regs` - [`snapshot`]保存的寄存器
+pub fn dump_to_serial(regs: &Registers) {
+    let _ = write_dump(&mut Sink(crate::serial::_emergency_print), regs);
+}
+
+/// ## 函数说明
+/// 向VGA控制台输出[`write_dump`]的内容，WRITER被锁定时放弃输出
+///
+/// ## 参数
+/// * `regs` - [`snapshot`]保存的寄存器
+pub fn dump_to_screen(regs: &Registers) {
+    fn print(args: fmt::Arguments) {
+        crate::vga_buffer::_try_print(args);
+    }
+    let _ = write_dump(&mut Sink(print), regs);
+}
+
+#[test_case]
+fn test_snapshot_registers() {
+    let regs = snapshot();
+    //快照中的RSP位于当前栈帧内
+    let local = 0u64;
+    let here = &local as *const u64 as u64;
+    assert!(regs.rsp.abs_diff(here) < 4096);
+    //RFLAGS的第1位恒为1
+    assert_ne!(regs.rflags & 0b10, 0);
+}
+
+#[test_case]
+fn test_stack_dump_marks_code() {
+    //统计被标记为代码地址的行数
+    struct CountMarks(usize);
+    impl fmt::Write for CountMarks {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0 += s.matches("<- kernel code").count();
+            Ok(())
+        }
+    }
+
+    //构造一个只有第一项是代码地址的“栈”
+    let mut stack = [0u64; STACK_DUMP_QWORDS];
+    stack[0] = snapshot as *const () as u64;
+    stack[1] = &stack as *const _ as u64;
+    let mut out = CountMarks(0);
+    write_stack(&mut out, stack.as_ptr() as u64).unwrap();
+    assert_eq!(out.0, 1);
+}
@@ -0,0 +1,4 @@
+pub mod ata;
+pub mod cmos;
+pub mod ps2;
+pub mod speaker;
@@ -0,0 +1,589 @@
+use core::fmt;
+use spin::Mutex;
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+
+use crate::storage::{self, BlockDevice, BlockError};
+use crate::time::tsc;
+
+/// 扇区大小（字节）
+pub const SECTOR_SIZE: usize = 512;
+/// 28位LBA能寻址的扇区数
+pub const MAX_LBA28: u32 = 1 << 28;
+
+//主通道的命令块寄存器和控制块寄存器
+const PRIMARY_IO: u16 = 0x1f0;
+const PRIMARY_CONTROL: u16 = 0x3f6;
+//命令块寄存器相对于基址的偏移
+const REG_DATA: u16 = 0;
+const REG_ERROR: u16 = 1;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DRIVE: u16 = 6;
+const REG_STATUS: u16 = 7; //写入时为命令寄存器
+
+//状态寄存器
+const STATUS_ERR: u8 = 0x01;
+const STATUS_DRQ: u8 = 0x08;
+const STATUS_DF: u8 = 0x20;
+const STATUS_BSY: u8 = 0x80;
+//设备控制寄存器：禁止设备产生中断，驱动只轮询
+const CONTROL_NIEN: u8 = 0x02;
+//驱动器选择寄存器：主盘，位6为LBA模式，位7和位5恒为1
+const DRIVE_MASTER: u8 = 0xa0;
+const DRIVE_LBA: u8 = 0x40;
+
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+const CMD_CACHE_FLUSH: u8 = 0xe7;
+const CMD_IDENTIFY: u8 = 0xec;
+
+//等待BSY清除、DRQ置位的时间上限
+const TIMEOUT_NS: u64 = 1_000_000_000;
+//IDENTIFY数据中型号字符串所在的字（40个字符）和LBA28扇区数所在的字
+const IDENTIFY_MODEL: core::ops::Range<usize> = 27..47;
+const IDENTIFY_LBA28_SECTORS: usize = 60;
+const MODEL_LEN: usize = 40;
+
+/// ## 说明
+/// ATA驱动的错误，设备报告的错误来自错误寄存器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtaError {
+    /// 通道上没有设备（状态寄存器为0或浮空）
+    NoDevice,
+    /// 设备不是ATA硬盘，如ATAPI光驱或SATA，`signature`为LBA_MID和LBA_HIGH
+    NotAta { signature: (u8, u8) },
+    /// 尚未调用[`init`]或`init`失败
+    NotInitialized,
+    /// 等待设备超时，`status`为最后读到的状态
+    Timeout { status: u8 },
+    /// 设备故障（状态寄存器的DF位）
+    DeviceFault,
+    /// 请求的扇区超出28位LBA或硬盘的大小
+    OutOfRange { lba: u32, count: u8 },
+    /// 读取时缓冲区小于`count * SECTOR_SIZE`
+    BufferTooSmall { needed: usize, len: usize },
+    /// 写入时缓冲区的长度不等于`count * SECTOR_SIZE`
+    BufferSizeMismatch { expected: usize, len: usize },
+    /// 坏块标记
+    BadBlock,
+    /// 数据中有无法纠正的错误
+    Uncorrectable,
+    /// 介质已更换
+    MediaChanged,
+    /// 找不到请求的扇区
+    IdNotFound,
+    /// 命令被中止，通常是不支持的命令或参数
+    Aborted,
+    /// 找不到0号磁道
+    Track0NotFound,
+    /// 找不到地址标记
+    AddressMarkNotFound,
+    /// 错误寄存器中没有已知的位
+    Unknown(u8),
+}
+
+impl AtaError {
+    /// ## 函数说明
+    /// 把状态寄存器ERR位置位时的错误寄存器解码为错误，多个位置位时报告最严重的一个
+    ///
+    /// ## 参数
+    /// * `error` - 错误寄存器的值
+    pub fn from_error_register(error: u8) -> Self {
+        const BITS: [(u8, AtaError); 7] = [
+            (0x80, AtaError::BadBlock),
+            (0x40, AtaError::Uncorrectable),
+            (0x20, AtaError::MediaChanged),
+            (0x10, AtaError::IdNotFound),
+            (0x04, AtaError::Aborted),
+            (0x02, AtaError::Track0NotFound),
+            (0x01, AtaError::AddressMarkNotFound),
+        ];
+        BITS.iter()
+            .find(|(bit, _)| error & bit != 0)
+            .map(|&(_, err)| err)
+            .unwrap_or(AtaError::Unknown(error))
+    }
+}
+
+//块设备层只看到通用的错误，ATA特有的细节在这里折叠
+impl From<AtaError> for BlockError {
+    fn from(err: AtaError) -> Self {
+        match err {
+            AtaError::NoDevice | AtaError::NotAta { .. } | AtaError::NotInitialized => {
+                BlockError::NoDevice
+            }
+            AtaError::Timeout { .. } => BlockError::Timeout,
+            AtaError::OutOfRange { lba, count } => BlockError::OutOfRange {
+                lba: u64::from(lba),
+                blocks: u64::from(count),
+            },
+            AtaError::BufferTooSmall { len, .. } | AtaError::BufferSizeMismatch { len, .. } => {
+                BlockError::BadBufferLength {
+                    len,
+                    block_size: SECTOR_SIZE,
+                }
+            }
+            AtaError::DeviceFault
+            | AtaError::BadBlock
+            | AtaError::Uncorrectable
+            | AtaError::MediaChanged
+            | AtaError::IdNotFound
+            | AtaError::Aborted
+            | AtaError::Track0NotFound
+            | AtaError::AddressMarkNotFound
+            | AtaError::Unknown(_) => BlockError::Io,
+        }
+    }
+}
+
+/// ## 说明
+/// IDENTIFY得到的硬盘信息
+#[derive(Clone, Copy)]
+pub struct DriveInfo {
+    /// 28位LBA可寻址的扇区数
+    pub sectors: u32,
+    model: [u8; MODEL_LEN],
+    model_len: usize,
+}
+
+impl DriveInfo {
+    /// ## 函数说明
+    /// 型号字符串，已去掉末尾的空格
+    pub fn model(&self) -> &str {
+        core::str::from_utf8(&self.model[..self.model_len]).unwrap_or("?")
+    }
+
+    /// ## 函数说明
+    /// 容量（字节）
+    pub fn size_bytes(&self) -> u64 {
+        u64::from(self.sectors) * SECTOR_SIZE as u64
+    }
+
+    fn from_identify(words: &[u16; 256]) -> Self {
+        let mut model = [0u8; MODEL_LEN];
+        let model_len = decode_identify_string(&words[IDENTIFY_MODEL], &mut model);
+        let sectors = u32::from(words[IDENTIFY_LBA28_SECTORS])
+            | (u32::from(words[IDENTIFY_LBA28_SECTORS + 1]) << 16);
+        DriveInfo {
+            sectors,
+            model,
+            model_len,
+        }
+    }
+}
+
+impl fmt::Debug for DriveInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DriveInfo")
+            .field("model", &self.model())
+            .field("sectors", &self.sectors)
+            .finish()
+    }
+}
+
+/// ## 函数说明
+/// 解码IDENTIFY数据中的字符串：每个字的高字节是前一个字符，末尾用空格填充
+/// 返回去掉末尾空格和NUL后的长度
+///
+/// ## 参数
+/// * `words` - IDENTIFY数据中字符串所在的字
+/// * `out` - 输出缓冲区，至少为`words.len() * 2`字节
+pub fn decode_identify_string(words: &[u16], out: &mut [u8]) -> usize {
+    for (chunk, word) in out.chunks_exact_mut(2).zip(words) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    let len = (words.len() * 2).min(out.len());
+    out[..len]
+        .iter()
+        .rposition(|&b| b != b' ' && b != 0)
+        .map_or(0, |last| last + 1)
+}
+
+//一个ATA通道的寄存器
+struct Channel {
+    data: Port<u16>,
+    error: PortReadOnly<u8>,
+    sector_count: PortWriteOnly<u8>,
+    lba_low: PortWriteOnly<u8>,
+    lba_mid: Port<u8>,
+    lba_high: Port<u8>,
+    drive: PortWriteOnly<u8>,
+    status: PortReadOnly<u8>,
+    command: PortWriteOnly<u8>,
+    alt_status: PortReadOnly<u8>,
+    control: PortWriteOnly<u8>,
+}
+
+impl Channel {
+    const fn new(io: u16, control: u16) -> Self {
+        Channel {
+            data: Port::new(io + REG_DATA),
+            error: PortReadOnly::new(io + REG_ERROR),
+            sector_count: PortWriteOnly::new(io + REG_SECTOR_COUNT),
+            lba_low: PortWriteOnly::new(io + REG_LBA_LOW),
+            lba_mid: Port::new(io + REG_LBA_MID),
+            lba_high: Port::new(io + REG_LBA_HIGH),
+            drive: PortWriteOnly::new(io + REG_DRIVE),
+            status: PortReadOnly::new(io + REG_STATUS),
+            command: PortWriteOnly::new(io + REG_STATUS),
+            alt_status: PortReadOnly::new(control),
+            control: PortWriteOnly::new(control),
+        }
+    }
+
+    //选择驱动器后设备需要约400ns才会更新状态，读取备用状态寄存器不会清除挂起的中断
+    fn delay_400ns(&mut self) {
+        for _ in 0..4 {
+            unsafe { self.alt_status.read() };
+        }
+    }
+
+    //等待BSY清除，之后检查DF和ERR
+    fn wait_not_busy(&mut self) -> Result<u8, AtaError> {
+        let deadline = tsc::now_cycles() + tsc::ns_to_cycles(TIMEOUT_NS);
+        loop {
+            let status = unsafe { self.status.read() };
+            if status & STATUS_BSY == 0 {
+                return self.check_status(status);
+            }
+            if tsc::now_cycles() > deadline {
+                return Err(AtaError::Timeout { status });
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    //等待设备准备好数据（DRQ）
+    fn wait_drq(&mut self) -> Result<(), AtaError> {
+        let deadline = tsc::now_cycles() + tsc::ns_to_cycles(TIMEOUT_NS);
+        loop {
+            let status = self.wait_not_busy()?;
+            if status & STATUS_DRQ != 0 {
+                return Ok(());
+            }
+            if tsc::now_cycles() > deadline {
+                return Err(AtaError::Timeout { status });
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn check_status(&mut self, status: u8) -> Result<u8, AtaError> {
+        if status & STATUS_DF != 0 {
+            Err(AtaError::DeviceFault)
+        } else if status & STATUS_ERR != 0 {
+            Err(AtaError::from_error_register(unsafe { self.error.read() }))
+        } else {
+            Ok(status)
+        }
+    }
+
+    fn read_words(&mut self, words: &mut [u16]) {
+        for word in words {
+            *word = unsafe { self.data.read() };
+        }
+    }
+
+    fn identify(&mut self) -> Result<DriveInfo, AtaError> {
+        unsafe {
+            self.control.write(CONTROL_NIEN);
+            self.drive.write(DRIVE_MASTER);
+        }
+        self.delay_400ns();
+        unsafe {
+            self.sector_count.write(0);
+            self.lba_low.write(0);
+            self.lba_mid.write(0);
+            self.lba_high.write(0);
+            self.command.write(CMD_IDENTIFY);
+        }
+        //没有设备时状态为0，总线浮空时为0xff
+        match unsafe { self.status.read() } {
+            0 | 0xff => return Err(AtaError::NoDevice),
+            _ => {}
+        }
+        let busy = self.wait_not_busy();
+        //ATAPI和SATA设备中止IDENTIFY，并在LBA_MID、LBA_HIGH中留下签名
+        let signature = unsafe { (self.lba_mid.read(), self.lba_high.read()) };
+        if signature != (0, 0) {
+            return Err(AtaError::NotAta { signature });
+        }
+        busy?;
+        self.wait_drq()?;
+        let mut words = [0u16; 256];
+        self.read_words(&mut words);
+        Ok(DriveInfo::from_identify(&words))
+    }
+
+    fn read_sectors(&mut self, lba: u32, count: u8, buf: &mut [u8]) -> Result<(), AtaError> {
+        unsafe {
+            self.drive
+                .write(DRIVE_MASTER | DRIVE_LBA | ((lba >> 24) as u8 & 0x0f));
+        }
+        self.delay_400ns();
+        self.wait_not_busy()?;
+        unsafe {
+            self.sector_count.write(count);
+            self.lba_low.write(lba as u8);
+            self.lba_mid.write((lba >> 8) as u8);
+            self.lba_high.write((lba >> 16) as u8);
+            self.command.write(CMD_READ_SECTORS);
+        }
+        for sector in buf.chunks_exact_mut(SECTOR_SIZE).take(usize::from(count)) {
+            self.delay_400ns();
+            self.wait_drq()?;
+            let mut words = [0u16; SECTOR_SIZE / 2];
+            self.read_words(&mut words);
+            for (bytes, word) in sector.chunks_exact_mut(2).zip(words.iter()) {
+                bytes.copy_from_slice(&word.to_le_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    fn write_sectors(&mut self, lba: u32, count: u8, buf: &[u8]) -> Result<(), AtaError> {
+        unsafe {
+            self.drive
+                .write(DRIVE_MASTER | DRIVE_LBA | ((lba >> 24) as u8 & 0x0f));
+        }
+        self.delay_400ns();
+        self.wait_not_busy()?;
+        unsafe {
+            self.sector_count.write(count);
+            self.lba_low.write(lba as u8);
+            self.lba_mid.write((lba >> 8) as u8);
+            self.lba_high.write((lba >> 16) as u8);
+            self.command.write(CMD_WRITE_SECTORS);
+        }
+        for sector in buf.chunks_exact(SECTOR_SIZE) {
+            self.delay_400ns();
+            self.wait_drq()?;
+            //逐个字写入而不使用rep outsw，每次out之间的间隔足够较慢的控制器取走数据
+            for bytes in sector.chunks_exact(2) {
+                unsafe { self.data.write(u16::from_le_bytes([bytes[0], bytes[1]])) };
+            }
+        }
+        self.delay_400ns();
+        self.wait_not_busy()?;
+        self.flush()
+    }
+
+    //把设备写缓存中的数据写入介质
+    fn flush(&mut self) -> Result<(), AtaError> {
+        unsafe {
+            self.drive.write(DRIVE_MASTER | DRIVE_LBA);
+        }
+        self.delay_400ns();
+        unsafe { self.command.write(CMD_CACHE_FLUSH) };
+        self.delay_400ns();
+        self.wait_not_busy().map(|_| ())
+    }
+}
+
+struct Primary {
+    channel: Channel,
+    info: Option<DriveInfo>,
+}
+
+static PRIMARY: Mutex<Primary> = Mutex::new(Primary {
+    channel: Channel::new(PRIMARY_IO, PRIMARY_CONTROL),
+    info: None,
+});
+
+/// ## 函数说明
+/// 对主通道的主盘执行IDENTIFY，确认它是ATA硬盘并记录容量和型号
+/// 设备的中断被禁止（nIEN），之后的读取都通过轮询完成
+///
+/// ## 用法
+/// ```rust
+/// let info = drivers::ata::init()?;
+/// println!("disk: {} ({} sectors)", info.model(), info.sectors);
+/// ```
+pub fn init() -> Result<DriveInfo, AtaError> {
+    let mut primary = PRIMARY.lock();
+    let info = primary.channel.identify()?;
+    primary.info = Some(info);
+    Ok(info)
+}
+
+/// ## 函数说明
+/// `init`得到的硬盘信息
+pub fn info() -> Option<DriveInfo> {
+    PRIMARY.lock().info
+}
+
+/// ## 函数说明
+/// 从主盘读取`count`个扇区到`buf`，`count`为0时不读取
+///
+/// ## 参数
+/// * `lba` - 第一个扇区的28位LBA
+/// * `count` - 扇区数
+/// * `buf` - 缓冲区，至少为`count * SECTOR_SIZE`字节
+///
+/// ## 用法
+/// ```rust
+/// let mut mbr = [0u8; SECTOR_SIZE];
+/// drivers::ata::read_sectors(0, 1, &mut mbr)?;
+/// ```
+pub fn read_sectors(lba: u32, count: u8, buf: &mut [u8]) -> Result<(), AtaError> {
+    let needed = usize::from(count) * SECTOR_SIZE;
+    if buf.len() < needed {
+        return Err(AtaError::BufferTooSmall {
+            needed,
+            len: buf.len(),
+        });
+    }
+    if count == 0 {
+        return Ok(());
+    }
+    let mut primary = PRIMARY.lock();
+    check_range(&primary, lba, count)?;
+    primary.channel.read_sectors(lba, count, buf)
+}
+
+//请求的扇区需要在28位LBA和IDENTIFY报告的容量之内
+fn check_range(primary: &Primary, lba: u32, count: u8) -> Result<(), AtaError> {
+    let info = primary.info.ok_or(AtaError::NotInitialized)?;
+    let end = lba.checked_add(u32::from(count));
+    if end.is_none_or(|end| end > info.sectors.min(MAX_LBA28)) {
+        return Err(AtaError::OutOfRange { lba, count });
+    }
+    Ok(())
+}
+
+/// ## 函数说明
+/// 把`buf`写入主盘从`lba`开始的`count`个扇区，写入完成后刷新设备的写缓存，`count`为0时不写入
+///
+/// ## 参数
+/// * `lba` - 第一个扇区的28位LBA
+/// * `count` - 扇区数
+/// * `buf` - 数据，长度必须等于`count * SECTOR_SIZE`
+///
+/// ## 用法
+/// ```rust
+/// drivers::ata::write_sectors(100, 1, &[0u8; SECTOR_SIZE])?;
+/// ```
+pub fn write_sectors(lba: u32, count: u8, buf: &[u8]) -> Result<(), AtaError> {
+    let expected = usize::from(count) * SECTOR_SIZE;
+    if buf.len() != expected {
+        return Err(AtaError::BufferSizeMismatch {
+            expected,
+            len: buf.len(),
+        });
+    }
+    if count == 0 {
+        return Ok(());
+    }
+    let mut primary = PRIMARY.lock();
+    check_range(&primary, lba, count)?;
+    primary.channel.write_sectors(lba, count, buf)
+}
+
+/// ## 函数说明
+/// 刷新主盘的写缓存，返回后之前写入的数据已经到达介质
+pub fn flush() -> Result<(), AtaError> {
+    let mut primary = PRIMARY.lock();
+    primary.info.ok_or(AtaError::NotInitialized)?;
+    primary.channel.flush()
+}
+
+/// ## 说明
+/// 把主盘作为[`BlockDevice`]，需要先调用[`init`]；每次最多传输255个扇区
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AtaDisk;
+
+//单条命令最多传输的扇区数，0在ATA中表示256，这里不使用
+const MAX_TRANSFER: usize = 255;
+
+impl BlockDevice for AtaDisk {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        info().map_or(0, |info| u64::from(info.sectors.min(MAX_LBA28)))
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        storage::check_request(self, lba, buf.len())?;
+        for (i, chunk) in buf.chunks_mut(MAX_TRANSFER * SECTOR_SIZE).enumerate() {
+            let start = lba as u32 + (i * MAX_TRANSFER) as u32;
+            read_sectors(start, (chunk.len() / SECTOR_SIZE) as u8, chunk)?;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        storage::check_request(self, lba, buf.len())?;
+        for (i, chunk) in buf.chunks(MAX_TRANSFER * SECTOR_SIZE).enumerate() {
+            let start = lba as u32 + (i * MAX_TRANSFER) as u32;
+            write_sectors(start, (chunk.len() / SECTOR_SIZE) as u8, chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_decode_identify_string() {
+    //"QEMU HARDDISK"按字交换字节后的样子，末尾用空格填充
+    let words = [
+        0x5145, 0x4d55, 0x2048, 0x4152, 0x4444, 0x4953, 0x4b20, 0x2020,
+    ];
+    let mut out = [0u8; 16];
+    let len = decode_identify_string(&words, &mut out);
+    assert_eq!(&out[..len], b"QEMU HARDDISK");
+
+    //奇数长度的字符串，最后一个字的低字节是空格
+    let words = [0x4142, 0x4320];
+    let mut out = [0u8; 4];
+    assert_eq!(decode_identify_string(&words, &mut out), 3);
+    assert_eq!(&out[..3], b"ABC");
+
+    //全为空格或NUL
+    let mut out = [0u8; 4];
+    assert_eq!(decode_identify_string(&[0x2020, 0], &mut out), 0);
+}
+
+#[test_case]
+fn test_identify_info() {
+    let mut words = [0u16; 256];
+    words[27] = 0x5145; //"QE"
+    words[28] = 0x4d55; //"MU"
+    for word in &mut words[29..47] {
+        *word = 0x2020;
+    }
+    words[60] = 0x5678;
+    words[61] = 0x0012;
+    let info = DriveInfo::from_identify(&words);
+    assert_eq!(info.model(), "QEMU");
+    assert_eq!(info.sectors, 0x0012_5678);
+    assert_eq!(info.size_bytes(), 0x0012_5678 * 512);
+}
+
+#[test_case]
+fn test_error_register_decoding() {
+    assert_eq!(AtaError::from_error_register(0x04), AtaError::Aborted);
+    assert_eq!(AtaError::from_error_register(0x10), AtaError::IdNotFound);
+    //同时置位时报告更严重的错误
+    assert_eq!(AtaError::from_error_register(0x44), AtaError::Uncorrectable);
+    assert_eq!(AtaError::from_error_register(0), AtaError::Unknown(0));
+}
+
+#[test_case]
+fn test_block_error_mapping() {
+    assert_eq!(
+        BlockError::from(AtaError::NotInitialized),
+        BlockError::NoDevice
+    );
+    assert_eq!(
+        BlockError::from(AtaError::Timeout { status: 0x80 }),
+        BlockError::Timeout
+    );
+    assert_eq!(
+        BlockError::from(AtaError::OutOfRange { lba: 10, count: 2 }),
+        BlockError::OutOfRange { lba: 10, blocks: 2 }
+    );
+    assert_eq!(BlockError::from(AtaError::Uncorrectable), BlockError::Io);
+}
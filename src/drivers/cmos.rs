@@ -0,0 +1,300 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Once;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+//CMOS的索引端口和数据端口，索引端口的位7为1时屏蔽NMI
+const CMOS_INDEX: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+const NMI_DISABLE: u8 = 0x80;
+const INDEX_MASK: u8 = 0x7f;
+/// RTC之后第一个通用存储寄存器
+pub const FIRST_NVRAM: u8 = 0x0e;
+/// 最后一个寄存器
+pub const LAST_REGISTER: u8 = 0x7f;
+
+//启动记录的位置：魔数、启动次数（低字节在前）、标志、校验和，放在BIOS通常不使用的末尾
+const RECORD_BASE: u8 = 0x78;
+const RECORD_LEN: usize = 5;
+const RECORD_MAGIC: u8 = 0xb7;
+const FLAG_LAST_BOOT_OK: u8 = 0x01;
+
+//每次选择寄存器时写入的NMI屏蔽位，所有访问共用，避免某次访问意外地打开或关闭NMI
+static NMI_DISABLED: AtomicBool = AtomicBool::new(false);
+//本次启动时读到的上一次启动的记录，由`begin_boot`设置一次
+static PREVIOUS_BOOT: Once<Option<BootRecord>> = Once::new();
+
+/// ## 说明
+/// CMOS寄存器的读写，真实的CMOS为[`Cmos`]，测试中可以用内存中的数组代替
+pub trait Registers {
+    fn read(&mut self, reg: u8) -> u8;
+    fn write(&mut self, reg: u8, value: u8);
+}
+
+/// ## 说明
+/// 通过端口0x70/0x71访问的CMOS
+pub struct Cmos;
+
+impl Registers for Cmos {
+    fn read(&mut self, reg: u8) -> u8 {
+        read(reg)
+    }
+
+    fn write(&mut self, reg: u8, value: u8) {
+        write(reg, value)
+    }
+}
+
+fn select(reg: u8) -> u8 {
+    let nmi = if NMI_DISABLED.load(Ordering::SeqCst) {
+        NMI_DISABLE
+    } else {
+        0
+    };
+    (reg & INDEX_MASK) | nmi
+}
+
+/// ## 函数说明
+/// 读取CMOS寄存器，索引和数据端口的访问之间关闭中断
+///
+/// ## 参数
+/// * `reg` - 寄存器号，范围为0~0x7f，位7被忽略
+pub fn read(reg: u8) -> u8 {
+    let mut index = Port::<u8>::new(CMOS_INDEX);
+    let mut data = Port::<u8>::new(CMOS_DATA);
+    interrupts::without_interrupts(|| unsafe {
+        index.write(select(reg));
+        data.read()
+    })
+}
+
+/// ## 函数说明
+/// 写入CMOS寄存器。0x00~0x0d为RTC的时间和状态寄存器，0x10~0x2f由BIOS设置并有自己的校验和，
+/// 写入它们会改变时钟或BIOS配置
+///
+/// ## 参数
+/// * `reg` - 寄存器号，范围为0~0x7f，位7被忽略
+/// * `value` - 写入的值
+pub fn write(reg: u8, value: u8) {
+    let mut index = Port::<u8>::new(CMOS_INDEX);
+    let mut data = Port::<u8>::new(CMOS_DATA);
+    interrupts::without_interrupts(|| unsafe {
+        index.write(select(reg));
+        data.write(value);
+    })
+}
+
+/// ## 函数说明
+/// 设置之后每次访问CMOS时写入的NMI屏蔽位，并立即生效
+///
+/// ## 参数
+/// * `disabled` - 是否屏蔽NMI
+pub fn set_nmi_disabled(disabled: bool) {
+    NMI_DISABLED.store(disabled, Ordering::SeqCst);
+    //重新选择一个无害的寄存器，让屏蔽位立即写入索引端口
+    read(FIRST_NVRAM);
+}
+
+/// ## 函数说明
+/// 当前是否屏蔽NMI
+pub fn nmi_disabled() -> bool {
+    NMI_DISABLED.load(Ordering::SeqCst)
+}
+
+/// ## 说明
+/// 保存在CMOS中的启动记录，用于发现反复崩溃重启
+///
+/// ## 成员
+/// * `boot_count` - 启动次数，溢出后从0重新开始
+/// * `last_boot_ok` - 上一次启动是否通过`power::shutdown`正常关机
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BootRecord {
+    pub boot_count: u16,
+    pub last_boot_ok: bool,
+}
+
+//使全部字节（包括校验和）之和为0
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0u8, |sum, &b| sum.wrapping_add(b))
+        .wrapping_neg()
+}
+
+impl BootRecord {
+    fn encode(&self) -> [u8; RECORD_LEN] {
+        let [low, high] = self.boot_count.to_le_bytes();
+        let flags = if self.last_boot_ok {
+            FLAG_LAST_BOOT_OK
+        } else {
+            0
+        };
+        let mut bytes = [RECORD_MAGIC, low, high, flags, 0];
+        bytes[RECORD_LEN - 1] = checksum(&bytes[..RECORD_LEN - 1]);
+        bytes
+    }
+
+    fn decode(bytes: &[u8; RECORD_LEN]) -> Option<Self> {
+        if bytes[0] != RECORD_MAGIC || checksum(&bytes[..RECORD_LEN - 1]) != bytes[RECORD_LEN - 1] {
+            return None;
+        }
+        Some(BootRecord {
+            boot_count: u16::from_le_bytes([bytes[1], bytes[2]]),
+            last_boot_ok: bytes[3] & FLAG_LAST_BOOT_OK != 0,
+        })
+    }
+
+    /// ## 函数说明
+    /// 从寄存器中读取记录，魔数或校验和不对（例如CMOS从未写入过或电池没电）时返回None
+    ///
+    /// ## 参数
+    /// * `regs` - CMOS寄存器
+    pub fn load_from(regs: &mut impl Registers) -> Option<Self> {
+        let mut bytes = [0u8; RECORD_LEN];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = regs.read(RECORD_BASE + i as u8);
+        }
+        Self::decode(&bytes)
+    }
+
+    /// ## 函数说明
+    /// 把记录连同校验和写入寄存器
+    ///
+    /// ## 参数
+    /// * `regs` - CMOS寄存器
+    pub fn store_to(&self, regs: &mut impl Registers) {
+        for (i, &b) in self.encode().iter().enumerate() {
+            regs.write(RECORD_BASE + i as u8, b);
+        }
+    }
+
+    /// ## 函数说明
+    /// 从CMOS读取记录
+    ///
+    /// ## 用法
+    /// ```rust
+    /// let record = BootRecord::load().unwrap_or_default();
+    /// ```
+    pub fn load() -> Option<Self> {
+        interrupts::without_interrupts(|| Self::load_from(&mut Cmos))
+    }
+
+    /// ## 函数说明
+    /// 把记录写入CMOS
+    pub fn store(&self) {
+        interrupts::without_interrupts(|| self.store_to(&mut Cmos))
+    }
+}
+
+/// ## 函数说明
+/// 由`os::init`调用：记下上一次启动的记录，启动次数加一，并在正常关机之前把本次启动标记为未完成
+pub fn begin_boot() {
+    let previous = BootRecord::load();
+    PREVIOUS_BOOT.call_once(|| previous);
+    BootRecord {
+        boot_count: previous.map_or(0, |r| r.boot_count).wrapping_add(1),
+        last_boot_ok: false,
+    }
+    .store();
+}
+
+/// ## 函数说明
+/// 由`power::shutdown`调用，标记本次启动正常结束
+pub fn mark_clean_shutdown() {
+    let record = BootRecord::load().unwrap_or_default();
+    BootRecord {
+        last_boot_ok: true,
+        ..record
+    }
+    .store();
+}
+
+/// ## 函数说明
+/// 本次启动时读到的记录：`last_boot_ok`为false说明上一次启动没有正常关机；
+/// CMOS中没有有效记录或者还没有调用`begin_boot`时返回None
+///
+/// ## 用法
+/// ```rust
+/// if cmos::previous_boot().is_some_and(|r| !r.last_boot_ok) {
+///     println!("previous boot did not shut down cleanly");
+/// }
+/// ```
+pub fn previous_boot() -> Option<BootRecord> {
+    PREVIOUS_BOOT.r#try().copied().flatten()
+}
+
+#[cfg(test)]
+struct MockCmos([u8; 128]);
+
+#[cfg(test)]
+impl Registers for MockCmos {
+    fn read(&mut self, reg: u8) -> u8 {
+        self.0[usize::from(reg & INDEX_MASK)]
+    }
+
+    fn write(&mut self, reg: u8, value: u8) {
+        self.0[usize::from(reg & INDEX_MASK)] = value;
+    }
+}
+
+#[test_case]
+fn test_record_round_trip() {
+    let mut cmos = MockCmos([0x5a; 128]);
+    let record = BootRecord {
+        boot_count: 0x1234,
+        last_boot_ok: true,
+    };
+    record.store_to(&mut cmos);
+    assert_eq!(BootRecord::load_from(&mut cmos), Some(record));
+    //记录之外的寄存器不受影响
+    assert!(cmos.0[..usize::from(RECORD_BASE)]
+        .iter()
+        .all(|&b| b == 0x5a));
+
+    let record = BootRecord {
+        boot_count: u16::MAX,
+        last_boot_ok: false,
+    };
+    record.store_to(&mut cmos);
+    assert_eq!(BootRecord::load_from(&mut cmos), Some(record));
+}
+
+#[test_case]
+fn test_record_rejects_garbage() {
+    //从未写入过和全部为1的CMOS
+    assert_eq!(BootRecord::load_from(&mut MockCmos([0; 128])), None);
+    assert_eq!(BootRecord::load_from(&mut MockCmos([0xff; 128])), None);
+
+    //任一字节损坏都会被校验和发现
+    let mut cmos = MockCmos([0; 128]);
+    BootRecord {
+        boot_count: 7,
+        last_boot_ok: true,
+    }
+    .store_to(&mut cmos);
+    for i in 0..RECORD_LEN {
+        let reg = usize::from(RECORD_BASE) + i;
+        cmos.0[reg] ^= 0x10;
+        assert_eq!(BootRecord::load_from(&mut cmos), None);
+        cmos.0[reg] ^= 0x10;
+    }
+    assert!(BootRecord::load_from(&mut cmos).is_some());
+}
+
+#[test_case]
+fn test_select_keeps_nmi_bit() {
+    assert!(!nmi_disabled());
+    assert_eq!(select(0x8e), 0x0e);
+    NMI_DISABLED.store(true, Ordering::SeqCst);
+    assert_eq!(select(0x0e), 0x8e);
+    NMI_DISABLED.store(false, Ordering::SeqCst);
+}
+
+#[test_case]
+fn test_boot_counted() {
+    //`os::init`已经调用过`begin_boot`
+    let record = BootRecord::load().expect("boot record missing after os::init");
+    assert!(!record.last_boot_ok);
+    let expected = previous_boot().map_or(0, |r| r.boot_count).wrapping_add(1);
+    assert_eq!(record.boot_count, expected);
+}
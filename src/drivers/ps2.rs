@@ -0,0 +1,668 @@
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+//PS/2控制器的数据端口和状态/命令端口
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+//状态寄存器：输出缓冲区有数据、输入缓冲区未被控制器取走、输出缓冲区中的字节来自第二个端口
+const OUTPUT_FULL: u8 = 0x01;
+const INPUT_FULL: u8 = 0x02;
+const AUX_DATA: u8 = 0x20;
+//控制器命令
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_PORT2: u8 = 0xa7;
+const ENABLE_PORT2: u8 = 0xa8;
+const TEST_PORT2: u8 = 0xa9;
+const SELF_TEST: u8 = 0xaa;
+const TEST_PORT1: u8 = 0xab;
+const DISABLE_PORT1: u8 = 0xad;
+const ENABLE_PORT1: u8 = 0xae;
+const WRITE_PORT2: u8 = 0xd4;
+//控制器自检和端口测试通过时的应答
+const SELF_TEST_OK: u8 = 0x55;
+const PORT_TEST_OK: u8 = 0x00;
+//配置字节：两个端口的IRQ、两个端口的时钟禁止位、第一个端口的扫描码转换
+const CONFIG_PORT1_IRQ: u8 = 0x01;
+const CONFIG_PORT2_IRQ: u8 = 0x02;
+const CONFIG_PORT1_CLOCK_OFF: u8 = 0x10;
+const CONFIG_PORT2_CLOCK_OFF: u8 = 0x20;
+const CONFIG_TRANSLATION: u8 = 0x40;
+//设备命令及其应答
+const RESET: u8 = 0xff;
+const IDENTIFY: u8 = 0xf2;
+const DISABLE_SCANNING: u8 = 0xf5;
+const ENABLE_SCANNING: u8 = 0xf4;
+const ACK: u8 = 0xfa;
+const RESEND: u8 = 0xfe;
+const RESET_OK: u8 = 0xaa;
+//收到0xFE时重发命令的次数上限
+const RESEND_LIMIT: usize = 3;
+//等待控制器时轮询状态寄存器的次数上限；设备复位自检较慢，单独放宽
+const TIMEOUT: usize = 100_000;
+const RESET_TIMEOUT: usize = 1_000_000;
+//清空输出缓冲区时最多丢弃的字节数，防止坏掉的控制器一直报告有数据
+const FLUSH_LIMIT: usize = 32;
+
+/// ## 说明
+/// 初始化PS/2控制器或与设备通信时可能出现的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    /// 还没有调用`init`，或者初始化失败
+    NotInitialized,
+    /// 控制器或设备在限定时间内没有响应
+    Timeout,
+    /// 控制器自检没有返回0x55，`code`为实际收到的字节
+    SelfTestFailed(u8),
+    /// 设备没有用0xFA应答命令，`reply`为实际收到的字节
+    NoAck { command: u8, reply: u8 },
+    /// 设备复位后没有报告自检通过，`reply`为实际收到的字节（通常为0xFC）
+    ResetFailed { reply: u8 },
+}
+
+/// ## 说明
+/// 控制器的两个端口，第一个端口通常接键盘，第二个（辅助）端口通常接鼠标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Port {
+    Port1,
+    Port2,
+}
+
+/// ## 说明
+/// 根据设备对0xF2命令的应答判断出的设备类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    /// 不返回标识字节的AT键盘
+    AtKeyboard,
+    /// MF2键盘，开启转换时第二个字节为0x41或0xC1，否则为0x83
+    Mf2Keyboard,
+    /// 标准PS/2鼠标
+    Mouse,
+    /// 带滚轮的鼠标
+    ScrollMouse,
+    /// 五键鼠标
+    FiveButtonMouse,
+    /// 无法识别的设备，`bytes`的前`len`个字节为收到的标识
+    Unknown { bytes: [u8; 2], len: u8 },
+}
+
+impl DeviceType {
+    /// ## 函数说明
+    /// 按标识字节判断设备类型
+    ///
+    /// ## 参数
+    /// * `bytes` - 0xF2命令应答之后收到的0~2个字节
+    ///
+    /// ## 用法
+    /// ```rust
+    /// assert_eq!(DeviceType::from_identify(&[0x03]), DeviceType::ScrollMouse);
+    /// ```
+    pub fn from_identify(bytes: &[u8]) -> DeviceType {
+        match bytes {
+            [] => DeviceType::AtKeyboard,
+            [0x00] => DeviceType::Mouse,
+            [0x03] => DeviceType::ScrollMouse,
+            [0x04] => DeviceType::FiveButtonMouse,
+            [0xab, 0x41 | 0xc1 | 0x83] => DeviceType::Mf2Keyboard,
+            _ => {
+                let mut id = [0; 2];
+                let len = bytes.len().min(id.len());
+                id[..len].copy_from_slice(&bytes[..len]);
+                DeviceType::Unknown {
+                    bytes: id,
+                    len: len as u8,
+                }
+            }
+        }
+    }
+
+    /// ## 函数说明
+    /// 是否为键盘
+    pub fn is_keyboard(&self) -> bool {
+        matches!(self, DeviceType::AtKeyboard | DeviceType::Mf2Keyboard)
+    }
+
+    /// ## 函数说明
+    /// 是否为鼠标
+    pub fn is_mouse(&self) -> bool {
+        matches!(
+            self,
+            DeviceType::Mouse | DeviceType::ScrollMouse | DeviceType::FiveButtonMouse
+        )
+    }
+}
+
+/// ## 说明
+/// 初始化时在一个端口上发现的情况
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    /// 控制器只有一个端口，只会出现在第二个端口上
+    NotPresent,
+    /// 端口接口测试失败，附带测试返回的错误码
+    InterfaceFailed(u8),
+    /// 端口正常，但复位时没有设备应答
+    NoDevice,
+    /// 设备应答了复位但出错
+    DeviceFailed(Ps2Error),
+    /// 设备已复位并识别
+    Device(DeviceType),
+}
+
+impl PortState {
+    /// ## 函数说明
+    /// 端口上识别出的设备，没有可用设备时返回`None`
+    pub fn device(&self) -> Option<DeviceType> {
+        match *self {
+            PortState::Device(device) => Some(device),
+            _ => None,
+        }
+    }
+}
+
+/// ## 说明
+/// `init`的结果
+///
+/// ## 成员
+/// * `dual_channel` - 控制器是否有第二个端口
+/// * `translation` - 第一个端口是否开启了扫描码转换（把第2套扫描码转换为第1套）
+/// * `port1` - 第一个端口的情况
+/// * `port2` - 第二个端口的情况
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ps2Status {
+    pub dual_channel: bool,
+    pub translation: bool,
+    pub port1: PortState,
+    pub port2: PortState,
+}
+
+impl Ps2Status {
+    /// ## 函数说明
+    /// 第一个端口上是否有键盘
+    pub fn has_keyboard(&self) -> bool {
+        self.port1
+            .device()
+            .is_some_and(|device| device.is_keyboard())
+    }
+
+    /// ## 函数说明
+    /// 第二个端口上是否有鼠标
+    pub fn has_mouse(&self) -> bool {
+        self.port2.device().is_some_and(|device| device.is_mouse())
+    }
+}
+
+static STATUS: Mutex<Option<Ps2Status>> = Mutex::new(None);
+
+//轮询状态寄存器直到`mask`位变为`set`，超过`spins`次返回false
+fn wait_status(mut read_status: impl FnMut() -> u8, mask: u8, set: bool, spins: usize) -> bool {
+    for _ in 0..spins {
+        if (read_status() & mask != 0) == set {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+fn read_status() -> u8 {
+    unsafe { Port::<u8>::new(STATUS_PORT).read() }
+}
+
+fn wait(mask: u8, set: bool, spins: usize) -> Result<(), Ps2Error> {
+    if wait_status(read_status, mask, set, spins) {
+        Ok(())
+    } else {
+        Err(Ps2Error::Timeout)
+    }
+}
+
+fn command(command: u8) -> Result<(), Ps2Error> {
+    wait(INPUT_FULL, false, TIMEOUT)?;
+    unsafe { Port::<u8>::new(STATUS_PORT).write(command) };
+    Ok(())
+}
+
+fn write_data(byte: u8) -> Result<(), Ps2Error> {
+    wait(INPUT_FULL, false, TIMEOUT)?;
+    unsafe { Port::<u8>::new(DATA_PORT).write(byte) };
+    Ok(())
+}
+
+fn read_data(spins: usize) -> Result<u8, Ps2Error> {
+    wait(OUTPUT_FULL, true, spins)?;
+    Ok(unsafe { Port::<u8>::new(DATA_PORT).read() })
+}
+
+fn command_reply(cmd: u8) -> Result<u8, Ps2Error> {
+    command(cmd)?;
+    read_data(TIMEOUT)
+}
+
+fn read_config() -> Result<u8, Ps2Error> {
+    command_reply(READ_CONFIG)
+}
+
+fn write_config(config: u8) -> Result<(), Ps2Error> {
+    command(WRITE_CONFIG)?;
+    write_data(config)
+}
+
+//丢弃输出缓冲区中残留的字节
+fn flush() {
+    let mut data = Port::<u8>::new(DATA_PORT);
+    for _ in 0..FLUSH_LIMIT {
+        if read_status() & OUTPUT_FULL == 0 {
+            break;
+        }
+        unsafe { data.read() };
+    }
+}
+
+/// ## 函数说明
+/// 向端口上的设备写入一个字节，不等待应答。需要自己处理应答的协议（如键盘LED命令）使用
+///
+/// ## 参数
+/// * `port` - 设备所在的端口
+/// * `byte` - 要写入的字节
+pub(crate) fn send_byte(port: Ps2Port, byte: u8) -> Result<(), Ps2Error> {
+    if port == Ps2Port::Port2 {
+        command(WRITE_PORT2)?;
+    }
+    write_data(byte)
+}
+
+/// ## 函数说明
+/// 向端口上的设备发送命令并等待0xFA应答，收到0xFE时重发
+/// 调用方需要关闭中断，否则应答会被IRQ处理函数读走
+///
+/// ## 参数
+/// * `port` - 设备所在的端口
+/// * `cmd` - 设备命令
+///
+/// ## 用法
+/// ```rust
+/// interrupts::without_interrupts(|| ps2::send(Ps2Port::Port2, 0xf4))?;
+/// ```
+pub fn send(port: Ps2Port, cmd: u8) -> Result<(), Ps2Error> {
+    let mut reply = RESEND;
+    for _ in 0..RESEND_LIMIT {
+        send_byte(port, cmd)?;
+        reply = read_data(TIMEOUT)?;
+        if reply != RESEND {
+            break;
+        }
+    }
+    match reply {
+        ACK => Ok(()),
+        reply => Err(Ps2Error::NoAck {
+            command: cmd,
+            reply,
+        }),
+    }
+}
+
+/// ## 函数说明
+/// 读取设备发来的下一个字节及其来源端口，超时返回`Ps2Error::Timeout`
+/// 两个端口共用输出缓冲区，等待键盘应答时也可能读到鼠标的数据
+pub(crate) fn read_reply() -> Result<(Ps2Port, u8), Ps2Error> {
+    let mut status = 0;
+    let ready = wait_status(
+        || {
+            status = read_status();
+            status
+        },
+        OUTPUT_FULL,
+        true,
+        TIMEOUT,
+    );
+    if !ready {
+        return Err(Ps2Error::Timeout);
+    }
+    let byte = unsafe { Port::<u8>::new(DATA_PORT).read() };
+    Ok((source_port(status), byte))
+}
+
+//输出缓冲区中的字节来自哪个端口
+fn source_port(status: u8) -> Ps2Port {
+    if status & AUX_DATA != 0 {
+        Ps2Port::Port2
+    } else {
+        Ps2Port::Port1
+    }
+}
+
+//复位设备：应答0xFA和自检结果0xAA的顺序因设备而异；端口上没有设备时超时
+fn reset(port: Ps2Port) -> Result<(), Ps2Error> {
+    reset_with(port, || send_byte(port, RESET), read_data)
+}
+
+//复位的应答流程，`send_reset`发送复位命令，`read`按给定的轮询次数读取一个字节
+//收到0xFE时重发，与`send`一样最多发送RESEND_LIMIT次
+fn reset_with(
+    port: Ps2Port,
+    mut send_reset: impl FnMut() -> Result<(), Ps2Error>,
+    mut read: impl FnMut(usize) -> Result<u8, Ps2Error>,
+) -> Result<(), Ps2Error> {
+    send_reset()?;
+    let mut sends = 1;
+    let (mut acked, mut passed) = (false, false);
+    while !(acked && passed) {
+        match read(RESET_TIMEOUT)? {
+            ACK => acked = true,
+            RESET_OK => passed = true,
+            RESEND if sends < RESEND_LIMIT => {
+                send_reset()?;
+                sends += 1;
+            }
+            reply => return Err(Ps2Error::ResetFailed { reply }),
+        }
+    }
+    //鼠标在0xAA之后还会发送设备号0x00，此时可能还没有到达，等待它而不是直接清空缓冲区
+    //第二个端口上接键盘时没有设备号，超时不算失败
+    if port == Ps2Port::Port2 {
+        let _ = read(TIMEOUT);
+    }
+    Ok(())
+}
+
+//停止扫描后读取标识字节，键盘随后重新开始扫描，鼠标保持停止等待驱动启用
+fn identify(port: Ps2Port) -> Result<DeviceType, Ps2Error> {
+    send(port, DISABLE_SCANNING)?;
+    send(port, IDENTIFY)?;
+    let mut bytes = [0; 2];
+    let mut len = 0;
+    while len < bytes.len() {
+        match read_data(TIMEOUT) {
+            Ok(byte) => {
+                bytes[len] = byte;
+                len += 1;
+            }
+            Err(_) => break, //AT键盘不返回标识字节
+        }
+    }
+    let device = DeviceType::from_identify(&bytes[..len]);
+    if device.is_keyboard() {
+        send(port, ENABLE_SCANNING)?;
+    }
+    Ok(device)
+}
+
+fn probe_device(port: Ps2Port) -> PortState {
+    match reset(port).and_then(|()| identify(port)) {
+        Ok(device) => PortState::Device(device),
+        Err(Ps2Error::Timeout) => PortState::NoDevice,
+        Err(err) => PortState::DeviceFailed(err),
+    }
+}
+
+fn probe(translation: bool) -> Result<Ps2Status, Ps2Error> {
+    command(DISABLE_PORT1)?;
+    command(DISABLE_PORT2)?;
+    flush();
+
+    //初始化期间关闭两个端口的IRQ，改为轮询
+    let mut config = read_config()? & !(CONFIG_PORT1_IRQ | CONFIG_PORT2_IRQ | CONFIG_TRANSLATION);
+    if translation {
+        config |= CONFIG_TRANSLATION;
+    }
+    //禁止第二个端口后时钟禁止位仍为0说明控制器只有一个端口
+    let maybe_dual = config & CONFIG_PORT2_CLOCK_OFF != 0;
+    write_config(config)?;
+
+    match command_reply(SELF_TEST)? {
+        SELF_TEST_OK => {}
+        code => return Err(Ps2Error::SelfTestFailed(code)),
+    }
+    write_config(config)?; //部分控制器自检时会复位配置字节
+
+    let dual_channel = maybe_dual && {
+        command(ENABLE_PORT2)?;
+        let enabled = read_config()? & CONFIG_PORT2_CLOCK_OFF == 0;
+        command(DISABLE_PORT2)?;
+        enabled
+    };
+
+    let port1_test = command_reply(TEST_PORT1)?;
+    let port2_test = if dual_channel {
+        Some(command_reply(TEST_PORT2)?)
+    } else {
+        None
+    };
+    if port1_test == PORT_TEST_OK {
+        command(ENABLE_PORT1)?;
+    }
+    if port2_test == Some(PORT_TEST_OK) {
+        command(ENABLE_PORT2)?;
+    }
+
+    let port1 = match port1_test {
+        PORT_TEST_OK => probe_device(Ps2Port::Port1),
+        code => PortState::InterfaceFailed(code),
+    };
+    let port2 = match port2_test {
+        None => PortState::NotPresent,
+        Some(PORT_TEST_OK) => probe_device(Ps2Port::Port2),
+        Some(code) => PortState::InterfaceFailed(code),
+    };
+
+    //只为找到设备的端口打开IRQ，并确保启用的端口的时钟禁止位已经清除
+    let mut config = read_config()?;
+    if port1_test == PORT_TEST_OK {
+        config &= !CONFIG_PORT1_CLOCK_OFF;
+    }
+    if port2_test == Some(PORT_TEST_OK) {
+        config &= !CONFIG_PORT2_CLOCK_OFF;
+    }
+    if port1.device().is_some() {
+        config |= CONFIG_PORT1_IRQ;
+    }
+    if port2.device().is_some() {
+        config |= CONFIG_PORT2_IRQ;
+    }
+    write_config(config)?;
+
+    Ok(Ps2Status {
+        dual_channel,
+        translation: config & CONFIG_TRANSLATION != 0,
+        port1,
+        port2,
+    })
+}
+
+/// ## 函数说明
+/// 按标准流程初始化PS/2控制器：禁止两个端口并清空输出缓冲区，设置配置字节，
+/// 控制器自检，检测第二个端口并测试两个端口的接口，重新启用端口后复位并识别设备，
+/// 最后为找到设备的端口打开IRQ。每次等待都有时间上限
+/// 保留扫描码转换，键盘驱动按第1套扫描码解码。需要在PIC初始化之后、开中断之前调用
+///
+/// ## 用法
+/// ```rust
+/// let status = drivers::ps2::init()?;
+/// if status.has_mouse() {
+///     mouse::init()?;
+/// }
+/// ```
+pub fn init() -> Result<Ps2Status, Ps2Error> {
+    init_with(true)
+}
+
+/// ## 函数说明
+/// 同`init`，可以选择是否开启第一个端口的扫描码转换
+///
+/// ## 参数
+/// * `translation` - 是否开启扫描码转换，关闭后键盘发送第2套扫描码
+pub fn init_with(translation: bool) -> Result<Ps2Status, Ps2Error> {
+    let result = interrupts::without_interrupts(|| probe(translation));
+    *STATUS.lock() = result.ok();
+    result
+}
+
+/// ## 函数说明
+/// 最近一次`init`的结果，尚未初始化或初始化失败时返回`None`
+pub fn status() -> Option<Ps2Status> {
+    *STATUS.lock()
+}
+
+#[test_case]
+fn test_wait_status_succeeds() {
+    //第三次读取时输入缓冲区变空
+    let mut reads = [INPUT_FULL, INPUT_FULL, 0].into_iter();
+    assert!(wait_status(
+        || reads.next().unwrap_or(0),
+        INPUT_FULL,
+        false,
+        10
+    ));
+    let mut reads = [0, 0, OUTPUT_FULL].into_iter();
+    assert!(wait_status(
+        || reads.next().unwrap_or(0),
+        OUTPUT_FULL,
+        true,
+        3
+    ));
+}
+
+#[test_case]
+fn test_wait_status_times_out() {
+    let mut count = 0;
+    let ready = wait_status(
+        || {
+            count += 1;
+            INPUT_FULL
+        },
+        INPUT_FULL,
+        false,
+        5,
+    );
+    assert!(!ready);
+    assert_eq!(count, 5);
+    //其他位不影响判断
+    assert!(!wait_status(|| !OUTPUT_FULL, OUTPUT_FULL, true, 5));
+}
+
+#[test_case]
+fn test_source_port() {
+    assert_eq!(source_port(OUTPUT_FULL), Ps2Port::Port1);
+    assert_eq!(source_port(OUTPUT_FULL | AUX_DATA), Ps2Port::Port2);
+    //其他位不影响判断
+    assert_eq!(source_port(!AUX_DATA), Ps2Port::Port1);
+}
+
+#[test_case]
+fn test_reset_replies() {
+    //应答和自检结果的顺序不影响结果，鼠标随后发送的设备号被读走
+    let mut replies = [RESET_OK, ACK, 0x00].into_iter();
+    let mut sends = 0;
+    let result = reset_with(
+        Ps2Port::Port2,
+        || {
+            sends += 1;
+            Ok(())
+        },
+        |_| replies.next().ok_or(Ps2Error::Timeout),
+    );
+    assert_eq!(result, Ok(()));
+    assert_eq!(sends, 1);
+    assert_eq!(replies.next(), None);
+
+    //设备号迟迟不到（如第二个端口上的键盘）时仍然成功；第一个端口不等待设备号
+    let mut replies = [ACK, RESET_OK].into_iter();
+    let result = reset_with(
+        Ps2Port::Port2,
+        || Ok(()),
+        |_| replies.next().ok_or(Ps2Error::Timeout),
+    );
+    assert_eq!(result, Ok(()));
+    let mut replies = [ACK, RESET_OK, 0x00].into_iter();
+    let result = reset_with(
+        Ps2Port::Port1,
+        || Ok(()),
+        |_| replies.next().ok_or(Ps2Error::Timeout),
+    );
+    assert_eq!(result, Ok(()));
+    assert_eq!(replies.next(), Some(0x00));
+}
+
+#[test_case]
+fn test_reset_resend_limit() {
+    //一直要求重发时，发送RESEND_LIMIT次后放弃
+    let mut sends = 0;
+    let result = reset_with(
+        Ps2Port::Port1,
+        || {
+            sends += 1;
+            Ok(())
+        },
+        |_| Ok(RESEND),
+    );
+    assert_eq!(result, Err(Ps2Error::ResetFailed { reply: RESEND }));
+    assert_eq!(sends, RESEND_LIMIT);
+
+    //重发之后成功
+    let mut replies = [RESEND, ACK, RESET_OK].into_iter();
+    let mut sends = 0;
+    let result = reset_with(
+        Ps2Port::Port1,
+        || {
+            sends += 1;
+            Ok(())
+        },
+        |_| replies.next().ok_or(Ps2Error::Timeout),
+    );
+    assert_eq!(result, Ok(()));
+    assert_eq!(sends, 2);
+}
+
+#[test_case]
+fn test_identify_decoding() {
+    assert_eq!(DeviceType::from_identify(&[]), DeviceType::AtKeyboard);
+    assert_eq!(DeviceType::from_identify(&[0x00]), DeviceType::Mouse);
+    assert_eq!(DeviceType::from_identify(&[0x03]), DeviceType::ScrollMouse);
+    assert_eq!(
+        DeviceType::from_identify(&[0x04]),
+        DeviceType::FiveButtonMouse
+    );
+    for second in [0x41, 0xc1, 0x83] {
+        assert_eq!(
+            DeviceType::from_identify(&[0xab, second]),
+            DeviceType::Mf2Keyboard
+        );
+    }
+    assert_eq!(
+        DeviceType::from_identify(&[0xab, 0x84]),
+        DeviceType::Unknown {
+            bytes: [0xab, 0x84],
+            len: 2
+        }
+    );
+    assert_eq!(
+        DeviceType::from_identify(&[0x05]),
+        DeviceType::Unknown {
+            bytes: [0x05, 0],
+            len: 1
+        }
+    );
+    assert!(DeviceType::Mf2Keyboard.is_keyboard());
+    assert!(!DeviceType::ScrollMouse.is_keyboard());
+    assert!(DeviceType::FiveButtonMouse.is_mouse());
+}
+
+#[test_case]
+fn test_status_devices() {
+    let status = Ps2Status {
+        dual_channel: false,
+        translation: true,
+        port1: PortState::Device(DeviceType::Mf2Keyboard),
+        port2: PortState::NotPresent,
+    };
+    assert!(status.has_keyboard());
+    assert!(!status.has_mouse());
+    let status = Ps2Status {
+        port1: PortState::DeviceFailed(Ps2Error::ResetFailed { reply: 0xfc }),
+        port2: PortState::Device(DeviceType::Mouse),
+        ..status
+    };
+    assert!(!status.has_keyboard());
+    assert!(status.has_mouse());
+}
@@ -0,0 +1,174 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+use crate::time::{self, PIT_FREQUENCY};
+
+//PIT通道2的数据端口和模式/命令端口
+const CHANNEL_2: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+//通道2，先低字节后高字节，模式3（方波）
+const SQUARE_WAVE_2: u8 = 0xb6;
+//键盘控制器端口B：位0为通道2的门控，位1把通道2的输出接到扬声器
+const PORT_B: u16 = 0x61;
+const SPEAKER_BITS: u8 = 0x03;
+
+/// 可以发出的最低频率，对应16位分频系数的上限
+pub const MIN_FREQUENCY: u32 = PIT_FREQUENCY.div_ceil(u16::MAX as u32);
+/// 可以发出的最高频率，模式3的分频系数至少为2
+pub const MAX_FREQUENCY: u32 = PIT_FREQUENCY / 2;
+
+//panic时先发出的提示音：(频率, 毫秒)
+const PANIC_PATTERN: [(u32, u64); 3] = [(880, 150), (440, 150), (880, 300)];
+
+//panic时是否鸣响，默认关闭
+static BEEP_ON_PANIC: AtomicBool = AtomicBool::new(false);
+
+/// ## 函数说明
+/// 频率对应的PIT分频系数，超出[MIN_FREQUENCY, MAX_FREQUENCY]的频率会被限制到最近的边界
+///
+/// ## 参数
+/// * `frequency_hz` - 频率（Hz）
+pub fn divisor(frequency_hz: u32) -> u16 {
+    (PIT_FREQUENCY / frequency_hz.clamp(MIN_FREQUENCY, MAX_FREQUENCY)) as u16
+}
+
+//端口B的新值，只修改门控和扬声器两位
+fn gate_bits(port_b: u8, on: bool) -> u8 {
+    if on {
+        port_b | SPEAKER_BITS
+    } else {
+        port_b & !SPEAKER_BITS
+    }
+}
+
+fn set_gate(on: bool) {
+    let mut port_b: Port<u8> = Port::new(PORT_B);
+    interrupts::without_interrupts(|| unsafe {
+        let value = port_b.read();
+        port_b.write(gate_bits(value, on));
+    });
+}
+
+//离开作用域时关闭扬声器，`wait`提前返回时也不会一直鸣响
+struct Gate;
+
+impl Gate {
+    fn open(frequency_hz: u32) -> Gate {
+        let divisor = divisor(frequency_hz);
+        let mut command: Port<u8> = Port::new(COMMAND);
+        let mut channel_2: Port<u8> = Port::new(CHANNEL_2);
+        interrupts::without_interrupts(|| unsafe {
+            command.write(SQUARE_WAVE_2);
+            channel_2.write(divisor as u8);
+            channel_2.write((divisor >> 8) as u8);
+        });
+        set_gate(true);
+        Gate
+    }
+}
+
+impl Drop for Gate {
+    fn drop(&mut self) {
+        silence();
+    }
+}
+
+/// ## 函数说明
+/// 关闭扬声器
+pub fn silence() {
+    set_gate(false);
+}
+
+/// ## 函数说明
+/// 以`frequency_hz`鸣响`duration_ms`毫秒，之后关闭扬声器
+/// 使用`time::sleep_ms`等待，因此需要开启中断，并且不能在中断处理函数中调用
+///
+/// ## 参数
+/// * `frequency_hz` - 频率（Hz），超出范围时限制到[MIN_FREQUENCY, MAX_FREQUENCY]
+/// * `duration_ms` - 持续的毫秒数
+///
+/// ## 用法
+/// ```rust
+/// speaker::beep(1000, 100);
+/// ```
+pub fn beep(frequency_hz: u32, duration_ms: u64) {
+    let _gate = Gate::open(frequency_hz);
+    time::sleep_ms(duration_ms);
+}
+
+/// ## 函数说明
+/// 依次发出一串音，每个音之后关闭扬声器
+///
+/// ## 参数
+/// * `pattern` - (频率, 毫秒)的列表
+///
+/// ## 用法
+/// ```rust
+/// speaker::beep_pattern(&[(440, 100), (880, 100)]);
+/// ```
+pub fn beep_pattern(pattern: &[(u32, u64)]) {
+    for &(frequency_hz, duration_ms) in pattern {
+        beep(frequency_hz, duration_ms);
+    }
+}
+
+/// ## 函数说明
+/// 设置panic时是否鸣响
+///
+/// ## 参数
+/// * `enabled` - 是否鸣响
+pub fn set_beep_on_panic(enabled: bool) {
+    BEEP_ON_PANIC.store(enabled, Ordering::SeqCst);
+}
+
+/// ## 函数说明
+/// panic时是否鸣响
+pub fn beep_on_panic() -> bool {
+    BEEP_ON_PANIC.load(Ordering::SeqCst)
+}
+
+/// ## 函数说明
+/// 由panic处理函数调用：panic不会展开栈，正在进行的`beep`留下的门控需要在这里关闭；
+/// 开启了[`set_beep_on_panic`]时再发出提示音。中断可能已经关闭，因此使用`sleep_busy_us`等待
+pub fn on_panic() {
+    silence();
+    if !beep_on_panic() {
+        return;
+    }
+    for (frequency_hz, duration_ms) in PANIC_PATTERN {
+        let _gate = Gate::open(frequency_hz);
+        time::sleep_busy_us(duration_ms * 1000);
+    }
+}
+
+#[test_case]
+fn test_divisor() {
+    assert_eq!(divisor(1000), 1193);
+    assert_eq!(divisor(440), 2711);
+    //超出范围的频率被限制到边界
+    assert_eq!(divisor(0), divisor(MIN_FREQUENCY));
+    assert_eq!(divisor(1), divisor(MIN_FREQUENCY));
+    assert_eq!(divisor(MIN_FREQUENCY), 62799);
+    assert_eq!(divisor(u32::MAX), 2);
+    assert!(PIT_FREQUENCY / MIN_FREQUENCY <= u32::from(u16::MAX));
+    assert!(PIT_FREQUENCY / (MIN_FREQUENCY - 1) > u32::from(u16::MAX));
+}
+
+#[test_case]
+fn test_gate_bits_preserve_others() {
+    for value in [0x00u8, 0x0c, 0xf0, 0xfc, 0xff, 0x21] {
+        assert_eq!(gate_bits(value, true), value | 0x03);
+        assert_eq!(gate_bits(value, false), value & !0x03);
+        assert_eq!(gate_bits(value, true) & !0x03, value & !0x03);
+        assert_eq!(gate_bits(value, false) & !0x03, value & !0x03);
+    }
+}
+
+#[test_case]
+fn test_beep_clears_gate() {
+    let mut port_b: Port<u8> = Port::new(PORT_B);
+    beep(1000, 1);
+    assert_eq!(unsafe { port_b.read() } & SPEAKER_BITS, 0);
+    assert!(!beep_on_panic());
+}
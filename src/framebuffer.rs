@@ -0,0 +1,421 @@
+pub mod font;
+
+use bootloader::BootInfo;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use crate::vga_buffer::{ColorCode, Console, DEFAULT_COLOR};
+use font::{GLYPH_HEIGHT, GLYPH_WIDTH};
+
+/// ## 说明
+/// 像素中颜色分量的排列方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 依次为红、绿、蓝，之后的字节（如果有）保留
+    Rgb,
+    /// 依次为蓝、绿、红，GOP最常见的格式
+    Bgr,
+    /// 每像素1字节的灰度
+    U8,
+}
+
+/// ## 说明
+/// 帧缓冲区的布局
+///
+/// ## 成员
+/// * `width`, `height` - 可见区域的分辨率（像素）
+/// * `stride` - 每行的像素数，可能大于`width`
+/// * `bytes_per_pixel` - 每像素的字节数
+/// * `format` - 像素格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameBufferInfo {
+    pub width: usize,
+    pub height: usize,
+    pub stride: usize,
+    pub bytes_per_pixel: usize,
+    pub format: PixelFormat,
+}
+
+impl FrameBufferInfo {
+    /// ## 函数说明
+    /// 帧缓冲区至少需要的字节数
+    pub fn byte_len(&self) -> usize {
+        self.stride * self.height * self.bytes_per_pixel
+    }
+}
+
+/// ## 说明
+/// 设置帧缓冲区控制台时可能出现的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FbError {
+    /// 引导程序没有提供帧缓冲区
+    NotProvided,
+    /// 缓冲区比`FrameBufferInfo::byte_len`小
+    BufferTooSmall { len: usize, needed: usize },
+    /// 不支持的像素大小，RGB/BGR需要3或4字节，灰度需要1字节
+    UnsupportedFormat {
+        format: PixelFormat,
+        bytes_per_pixel: usize,
+    },
+    /// 分辨率放不下一个字符，或`stride`小于`width`
+    BadGeometry,
+}
+
+/// ## 说明
+/// 在帧缓冲区上用8x16点阵字体显示文字，行为与VGA文本模式的Writer相同：
+/// 总是写最后一行，换行时把整个屏幕上移一行
+///
+/// ## 成员
+/// * `info` - 帧缓冲区的布局
+/// * `buffer` - 帧缓冲区
+/// * `columns`, `rows` - 字符的列数和行数
+/// * `column_position` - 最后一行的写入位置
+/// * `color_code` - 前景色和背景色，与VGA文本模式使用相同的调色板
+pub struct FbWriter<'a> {
+    info: FrameBufferInfo,
+    buffer: &'a mut [u8],
+    columns: usize,
+    rows: usize,
+    column_position: usize,
+    color_code: ColorCode,
+}
+
+impl<'a> FbWriter<'a> {
+    /// ## 函数说明
+    /// 检查帧缓冲区的布局并清屏
+    ///
+    /// ## 参数
+    /// * `info` - 帧缓冲区的布局
+    /// * `buffer` - 帧缓冲区，长度至少为`info.byte_len()`
+    ///
+    /// ## 用法
+    /// ```rust
+    /// let mut writer = FbWriter::new(info, buffer)?;
+    /// writer.write_string("hello");
+    /// ```
+    pub fn new(info: FrameBufferInfo, buffer: &'a mut [u8]) -> Result<Self, FbError> {
+        let supported = match info.format {
+            PixelFormat::Rgb | PixelFormat::Bgr => matches!(info.bytes_per_pixel, 3 | 4),
+            PixelFormat::U8 => info.bytes_per_pixel == 1,
+        };
+        if !supported {
+            return Err(FbError::UnsupportedFormat {
+                format: info.format,
+                bytes_per_pixel: info.bytes_per_pixel,
+            });
+        }
+        let columns = info.width / GLYPH_WIDTH;
+        let rows = info.height / GLYPH_HEIGHT;
+        if columns == 0 || rows == 0 || info.stride < info.width {
+            return Err(FbError::BadGeometry);
+        }
+        if buffer.len() < info.byte_len() {
+            return Err(FbError::BufferTooSmall {
+                len: buffer.len(),
+                needed: info.byte_len(),
+            });
+        }
+        let mut writer = FbWriter {
+            info,
+            buffer,
+            columns,
+            rows,
+            column_position: 0,
+            color_code: DEFAULT_COLOR,
+        };
+        for row in 0..rows {
+            writer.clear_row(row);
+        }
+        Ok(writer)
+    }
+
+    /// ## 函数说明
+    /// 字符的列数
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// ## 函数说明
+    /// 字符的行数
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// ## 函数说明
+    /// 像素(`x`, `y`)在帧缓冲区中的字节偏移
+    pub fn pixel_offset(&self, x: usize, y: usize) -> usize {
+        (y * self.info.stride + x) * self.info.bytes_per_pixel
+    }
+
+    //按像素格式编码颜色，只使用前`bytes_per_pixel`个字节
+    fn encode(&self, (r, g, b): (u8, u8, u8)) -> [u8; 4] {
+        match self.info.format {
+            PixelFormat::Rgb => [r, g, b, 0],
+            PixelFormat::Bgr => [b, g, r, 0],
+            //ITU-R BT.601的亮度权重
+            PixelFormat::U8 => {
+                let y = (u32::from(r) * 299 + u32::from(g) * 587 + u32::from(b) * 114) / 1000;
+                [y as u8, 0, 0, 0]
+            }
+        }
+    }
+
+    /// ## 函数说明
+    /// 在第`row`行第`col`列画一个字符，字形中置位的点为前景色，其他为背景色
+    ///
+    /// ## 参数
+    /// * `col` - 列号，小于`columns()`
+    /// * `row` - 行号，小于`rows()`
+    /// * `byte` - 字符
+    pub fn draw_glyph(&mut self, col: usize, row: usize, byte: u8) {
+        let (foreground, background) = self.color_code.rgb();
+        let foreground = self.encode(foreground);
+        let background = self.encode(background);
+        let bpp = self.info.bytes_per_pixel;
+        for (dy, bits) in font::glyph(byte).iter().enumerate() {
+            let start = self.pixel_offset(col * GLYPH_WIDTH, row * GLYPH_HEIGHT + dy);
+            let line = &mut self.buffer[start..start + GLYPH_WIDTH * bpp];
+            for (dx, pixel) in line.chunks_exact_mut(bpp).enumerate() {
+                let color = if bits & (0x80 >> dx) != 0 {
+                    &foreground
+                } else {
+                    &background
+                };
+                pixel.copy_from_slice(&color[..bpp]);
+            }
+        }
+    }
+
+    //一行字符在帧缓冲区中占用的字节数
+    fn row_bytes(&self) -> usize {
+        GLYPH_HEIGHT * self.info.stride * self.info.bytes_per_pixel
+    }
+
+    //把字符区域整体上移一行，再清除最后一行
+    fn new_line(&mut self) {
+        let row_bytes = self.row_bytes();
+        self.buffer.copy_within(row_bytes..self.rows * row_bytes, 0);
+        self.clear_row(self.rows - 1);
+        self.column_position = 0;
+    }
+
+    //用背景色填充一行字符，不修改每行`width`之后的像素
+    fn clear_row(&mut self, row: usize) {
+        let (_, background) = self.color_code.rgb();
+        let background = self.encode(background);
+        let bpp = self.info.bytes_per_pixel;
+        for y in row * GLYPH_HEIGHT..(row + 1) * GLYPH_HEIGHT {
+            let start = self.pixel_offset(0, y);
+            let line = &mut self.buffer[start..start + self.columns * GLYPH_WIDTH * bpp];
+            for pixel in line.chunks_exact_mut(bpp) {
+                pixel.copy_from_slice(&background[..bpp]);
+            }
+        }
+    }
+}
+
+impl Console for FbWriter<'_> {
+    fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            byte => {
+                if self.column_position >= self.columns {
+                    self.new_line();
+                }
+                self.draw_glyph(self.column_position, self.rows - 1, byte);
+                self.column_position += 1;
+            }
+        }
+    }
+
+    fn set_color(&mut self, color_code: ColorCode) {
+        self.color_code = color_code;
+    }
+}
+
+impl fmt::Write for FbWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        Ok(())
+    }
+}
+
+//启动时检测到的帧缓冲区控制台，设置后`print!`改为输出到这里
+static FB_WRITER: Mutex<Option<FbWriter<'static>>> = Mutex::new(None);
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// ## 函数说明
+/// 使用引导程序提供的帧缓冲区作为控制台
+///
+/// ## 说明
+/// 这是bootloader 0.9下的占位实现：它通过BIOS启动并设置VGA文本模式，BootInfo中没有帧缓冲区信息，
+/// 因此总是返回`NotProvided`，控制台继续使用VGA文本缓冲区。换用提供帧缓冲区的引导程序后，
+/// 在这里把它的信息交给[`install`]
+///
+/// ## 参数
+/// * `boot_info` - bootloader提供的启动信息
+pub fn init(_boot_info: &'static BootInfo) -> Result<(), FbError> {
+    Err(FbError::NotProvided)
+}
+
+/// ## 函数说明
+/// 把`buffer`设置为控制台，之后`print!`等宏输出到帧缓冲区而不是VGA文本缓冲区
+///
+/// ## 参数
+/// * `info` - 帧缓冲区的布局
+/// * `buffer` - 已映射的帧缓冲区
+///
+/// ## 用法
+/// ```rust
+/// framebuffer::install(info, unsafe { slice::from_raw_parts_mut(ptr, info.byte_len()) })?;
+/// ```
+pub fn install(info: FrameBufferInfo, buffer: &'static mut [u8]) -> Result<(), FbError> {
+    let writer = FbWriter::new(info, buffer)?;
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        *FB_WRITER.lock() = Some(writer);
+        ACTIVE.store(true, Ordering::SeqCst);
+    });
+    Ok(())
+}
+
+/// ## 函数说明
+/// 控制台是否为帧缓冲区
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+//由`vga_buffer::_print`和`_try_print`调用，没有帧缓冲区控制台时返回None，
+//`wait`为false且控制台已被锁定时放弃输出并返回Some(false)
+pub(crate) fn print(args: fmt::Arguments, wait: bool) -> Option<bool> {
+    use core::fmt::Write;
+
+    if !is_active() {
+        return None;
+    }
+    let mut guard = if wait {
+        FB_WRITER.lock()
+    } else {
+        match FB_WRITER.try_lock() {
+            Some(guard) => guard,
+            None => return Some(false),
+        }
+    };
+    Some(guard.as_mut().is_some_and(|w| w.write_fmt(args).is_ok()))
+}
+
+/* ---------------测试------------------ */
+
+#[cfg(test)]
+const TEST_INFO: FrameBufferInfo = FrameBufferInfo {
+    //两列两行字符，右边和下面各多出4和8个像素，每行还有4个像素的填充
+    width: 20,
+    height: 40,
+    stride: 24,
+    bytes_per_pixel: 3,
+    format: PixelFormat::Rgb,
+};
+
+#[cfg(test)]
+const TEST_LEN: usize = 24 * 40 * 3;
+
+//检查一个字符格中的像素是否与字形一致
+#[cfg(test)]
+fn cell_shows(writer: &FbWriter, col: usize, row: usize, byte: u8) -> bool {
+    let (foreground, background) = writer.color_code.rgb();
+    let foreground = writer.encode(foreground);
+    let background = writer.encode(background);
+    let bpp = writer.info.bytes_per_pixel;
+    font::glyph(byte).iter().enumerate().all(|(dy, bits)| {
+        (0..GLYPH_WIDTH).all(|dx| {
+            let offset = writer.pixel_offset(col * GLYPH_WIDTH + dx, row * GLYPH_HEIGHT + dy);
+            let expected = if bits & (0x80 >> dx) != 0 {
+                &foreground
+            } else {
+                &background
+            };
+            writer.buffer[offset..offset + bpp] == expected[..bpp]
+        })
+    })
+}
+
+#[test_case]
+fn test_glyph_blit() {
+    let mut buffer = [0x11u8; TEST_LEN];
+    let mut writer = FbWriter::new(TEST_INFO, &mut buffer).unwrap();
+    assert_eq!((writer.columns(), writer.rows()), (2, 2));
+    assert_eq!(writer.pixel_offset(1, 2), (2 * 24 + 1) * 3);
+
+    writer.draw_glyph(1, 0, b'A');
+    assert!(cell_shows(&writer, 1, 0, b'A'));
+    //'A'的第一个置位的点
+    let glyph = font::glyph(b'A');
+    let (dy, bits) = glyph.iter().enumerate().find(|(_, b)| **b != 0).unwrap();
+    let dx = bits.leading_zeros() as usize;
+    let offset = writer.pixel_offset(GLYPH_WIDTH + dx, dy);
+    assert_eq!(writer.buffer[offset..offset + 3], [0xff, 0xff, 0x55]);
+    //不可打印的字节显示为方块
+    writer.draw_glyph(0, 1, 0xfe);
+    assert!(cell_shows(&writer, 0, 1, 0xfe));
+}
+
+#[test_case]
+fn test_pixel_formats() {
+    let mut buffer = [0u8; TEST_LEN];
+    let mut info = TEST_INFO;
+    info.format = PixelFormat::Bgr;
+    let writer = FbWriter::new(info, &mut buffer).unwrap();
+    assert_eq!(writer.encode((1, 2, 3))[..3], [3, 2, 1]);
+
+    let mut buffer = [0u8; TEST_LEN];
+    info.format = PixelFormat::U8;
+    info.bytes_per_pixel = 1;
+    let writer = FbWriter::new(info, &mut buffer).unwrap();
+    assert_eq!(writer.encode((255, 255, 255))[0], 255);
+    assert_eq!(writer.encode((0, 0, 0))[0], 0);
+
+    let mut buffer = [0u8; TEST_LEN];
+    info.bytes_per_pixel = 2;
+    assert_eq!(
+        FbWriter::new(info, &mut buffer).err(),
+        Some(FbError::UnsupportedFormat {
+            format: PixelFormat::U8,
+            bytes_per_pixel: 2
+        })
+    );
+    assert_eq!(
+        FbWriter::new(TEST_INFO, &mut buffer[..100]).err(),
+        Some(FbError::BufferTooSmall {
+            len: 100,
+            needed: TEST_LEN
+        })
+    );
+}
+
+#[test_case]
+fn test_scroll() {
+    let mut buffer = [0x55u8; TEST_LEN];
+    let mut writer = FbWriter::new(TEST_INFO, &mut buffer).unwrap();
+    writer.write_string("ab");
+    assert!(cell_shows(&writer, 0, 1, b'a'));
+    assert!(cell_shows(&writer, 1, 1, b'b'));
+    //行满后自动换行，上一行整体上移
+    writer.write_string("c");
+    assert!(cell_shows(&writer, 0, 0, b'a'));
+    assert!(cell_shows(&writer, 1, 0, b'b'));
+    assert!(cell_shows(&writer, 0, 1, b'c'));
+    assert!(cell_shows(&writer, 1, 1, b' '));
+    writer.write_string("\nd");
+    assert!(cell_shows(&writer, 0, 0, b'c'));
+    assert!(cell_shows(&writer, 0, 1, b'd'));
+
+    //字符区域之外的像素没有被修改
+    for y in 0..TEST_INFO.height {
+        for x in 0..TEST_INFO.stride {
+            if x >= 16 || y >= 32 {
+                let offset = (y * TEST_INFO.stride + x) * 3;
+                assert_eq!(buffer[offset..offset + 3], [0x55; 3]);
+            }
+        }
+    }
+}
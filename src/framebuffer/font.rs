@@ -0,0 +1,549 @@
+/// 第一个和最后一个有字形的字符
+pub const FIRST: u8 = 0x20;
+pub const LAST: u8 = 0x7e;
+/// 字形的宽度和高度（像素）
+pub const GLYPH_WIDTH: usize = 8;
+pub const GLYPH_HEIGHT: usize = 16;
+
+//可打印ASCII字符（0x20~0x7e）的8x16字形，每字节为一行，最高位在最左边
+//由DejaVu Sans Mono的轮廓栅格化得到，按其许可证保留以下声明：
+//
+//Fonts are (c) Bitstream (see below). DejaVu changes are in public domain.
+//
+//Bitstream Vera Fonts Copyright
+//Copyright (c) 2003 by Bitstream, Inc. All Rights Reserved. Bitstream Vera is
+//a trademark of Bitstream, Inc.
+//
+//Permission is hereby granted, free of charge, to any person obtaining a copy
+//of the fonts accompanying this license ("Fonts") and associated
+//documentation files (the "Font Software"), to reproduce and distribute the
+//Font Software, including without limitation the rights to use, copy, merge,
+//publish, distribute, and/or sell copies of the Font Software, and to permit
+//persons to whom the Font Software is furnished to do so, subject to the
+//following conditions:
+//
+//The above copyright and trademark notices and this permission notice shall
+//be included in all copies of one or more of the Font Software typefaces.
+//
+//The Font Software may be modified, altered, or added to, and in particular
+//the designs of glyphs or characters in the Fonts may be modified and
+//additional glyphs or characters may be added to the Fonts, only if the fonts
+//are renamed to names not containing either the words "Bitstream" or the word
+//"Vera".
+//
+//This License becomes null and void to the extent applicable to Fonts or Font
+//Software that has been modified and is distributed under the "Bitstream
+//Vera" names.
+//
+//The Font Software may be sold as part of a larger software package but no
+//copy of one or more of the Font Software typefaces may be sold by itself.
+//
+//THE FONT SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+//OR IMPLIED, INCLUDING BUT NOT LIMITED TO ANY WARRANTIES OF MERCHANTABILITY,
+//FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT OF COPYRIGHT, PATENT,
+//TRADEMARK, OR OTHER RIGHT. IN NO EVENT SHALL BITSTREAM OR THE GNOME
+//FOUNDATION BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, INCLUDING
+//ANY GENERAL, SPECIAL, INDIRECT, INCIDENTAL, OR CONSEQUENTIAL DAMAGES,
+//WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF
+//THE USE OR INABILITY TO USE THE FONT SOFTWARE OR FROM OTHER DEALINGS IN THE
+//FONT SOFTWARE.
+//
+//Except as contained in this notice, the names of Gnome, the Gnome
+//Foundation, and Bitstream Inc., shall not be used in advertising or
+//otherwise to promote the sale, use or other dealings in this Font Software
+//without prior written authorization from the Gnome Foundation or Bitstream
+//Inc., respectively. For further information, contact: fonts at gnome dot
+//org.
+pub static GLYPHS: [[u8; GLYPH_HEIGHT]; (LAST - FIRST + 1) as usize] = [
+    //空格
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'!'
+    [
+        0x00, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'"'
+    [
+        0x00, 0x24, 0x24, 0x24, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'#'
+    [
+        0x00, 0x02, 0x12, 0x12, 0x16, 0x7f, 0x24, 0x24, 0xfe, 0xfe, 0x68, 0x48, 0x40, 0x00, 0x00,
+        0x00,
+    ],
+    //'$'
+    [
+        0x00, 0x08, 0x08, 0x3e, 0x68, 0x68, 0x78, 0x3c, 0x0e, 0x0a, 0x0a, 0x7e, 0x18, 0x08, 0x00,
+        0x00,
+    ],
+    //'%'
+    [
+        0x00, 0x00, 0x70, 0x90, 0x90, 0xf0, 0x2c, 0x30, 0x4e, 0x09, 0x09, 0x0e, 0x04, 0x00, 0x00,
+        0x00,
+    ],
+    //'&'
+    [
+        0x00, 0x18, 0x24, 0x60, 0x20, 0x20, 0x70, 0x59, 0xc9, 0xc6, 0x46, 0x7e, 0x38, 0x00, 0x00,
+        0x00,
+    ],
+    //'''
+    [
+        0x00, 0x00, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'('
+    [
+        0x00, 0x0c, 0x08, 0x08, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x18, 0x08, 0x08, 0x00,
+        0x00,
+    ],
+    //')'
+    [
+        0x00, 0x30, 0x10, 0x10, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x18, 0x10, 0x10, 0x00,
+        0x00,
+    ],
+    //'*'
+    [
+        0x00, 0x00, 0x00, 0x66, 0x18, 0x3c, 0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'+'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18, 0xff, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //','
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18, 0x10, 0x10,
+        0x00,
+    ],
+    //'-'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'.'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'/'
+    [
+        0x00, 0x02, 0x06, 0x04, 0x0c, 0x08, 0x08, 0x18, 0x10, 0x30, 0x20, 0x20, 0x60, 0x40, 0x00,
+        0x00,
+    ],
+    //'0'
+    [
+        0x00, 0x18, 0x3c, 0x66, 0x42, 0x42, 0x5a, 0x5a, 0x42, 0x42, 0x66, 0x3c, 0x18, 0x00, 0x00,
+        0x00,
+    ],
+    //'1'
+    [
+        0x00, 0x18, 0x78, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x3e, 0x3e, 0x00, 0x00,
+        0x00,
+    ],
+    //'2'
+    [
+        0x00, 0x38, 0x7c, 0x06, 0x06, 0x06, 0x04, 0x08, 0x18, 0x30, 0x20, 0x7e, 0x7c, 0x00, 0x00,
+        0x00,
+    ],
+    //'3'
+    [
+        0x00, 0x38, 0x7c, 0x06, 0x06, 0x04, 0x3c, 0x04, 0x02, 0x02, 0x06, 0x7c, 0x38, 0x00, 0x00,
+        0x00,
+    ],
+    //'4'
+    [
+        0x00, 0x04, 0x0c, 0x1c, 0x14, 0x24, 0x24, 0x44, 0x7e, 0x7e, 0x04, 0x04, 0x04, 0x00, 0x00,
+        0x00,
+    ],
+    //'5'
+    [
+        0x00, 0x7c, 0x7c, 0x60, 0x60, 0x78, 0x7c, 0x06, 0x06, 0x06, 0x06, 0x7c, 0x38, 0x00, 0x00,
+        0x00,
+    ],
+    //'6'
+    [
+        0x00, 0x1c, 0x3c, 0x60, 0x40, 0x5c, 0x76, 0x62, 0x42, 0x42, 0x62, 0x3e, 0x18, 0x00, 0x00,
+        0x00,
+    ],
+    //'7'
+    [
+        0x00, 0x7e, 0x7e, 0x06, 0x04, 0x04, 0x0c, 0x08, 0x08, 0x18, 0x10, 0x10, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'8'
+    [
+        0x00, 0x3c, 0x66, 0x66, 0x66, 0x66, 0x3c, 0x66, 0x42, 0x42, 0x42, 0x7e, 0x18, 0x00, 0x00,
+        0x00,
+    ],
+    //'9'
+    [
+        0x00, 0x38, 0x6c, 0x46, 0x42, 0x42, 0x46, 0x7e, 0x1a, 0x02, 0x06, 0x7c, 0x38, 0x00, 0x00,
+        0x00,
+    ],
+    //':'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //';'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18, 0x10, 0x10,
+        0x00,
+    ],
+    //'<'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x03, 0x0e, 0x38, 0xe0, 0x70, 0x1c, 0x07, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'='
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x7e, 0x00, 0x7e, 0x7e, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'>'
+    [
+        0x00, 0x00, 0x00, 0x00, 0xc0, 0x70, 0x1c, 0x07, 0x0e, 0x38, 0xe0, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'?'
+    [
+        0x00, 0x3c, 0x7c, 0x06, 0x06, 0x04, 0x08, 0x18, 0x18, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'@'
+    [
+        0x00, 0x00, 0x1c, 0x22, 0x43, 0x4d, 0x9b, 0x91, 0x91, 0x91, 0x9f, 0x4c, 0x40, 0x30, 0x1e,
+        0x00,
+    ],
+    //'A'
+    [
+        0x00, 0x18, 0x18, 0x18, 0x3c, 0x24, 0x24, 0x24, 0x7e, 0x7e, 0x42, 0xc3, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'B'
+    [
+        0x00, 0x78, 0x7e, 0x62, 0x62, 0x66, 0x7c, 0x66, 0x62, 0x62, 0x62, 0x7e, 0x78, 0x00, 0x00,
+        0x00,
+    ],
+    //'C'
+    [
+        0x00, 0x1e, 0x3e, 0x60, 0x60, 0x40, 0x40, 0x40, 0x40, 0x60, 0x20, 0x3e, 0x0c, 0x00, 0x00,
+        0x00,
+    ],
+    //'D'
+    [
+        0x00, 0x70, 0x7c, 0x46, 0x42, 0x42, 0x42, 0x42, 0x42, 0x46, 0x46, 0x7c, 0x70, 0x00, 0x00,
+        0x00,
+    ],
+    //'E'
+    [
+        0x00, 0x7e, 0x7e, 0x60, 0x60, 0x60, 0x7e, 0x60, 0x60, 0x60, 0x60, 0x7e, 0x3e, 0x00, 0x00,
+        0x00,
+    ],
+    //'F'
+    [
+        0x00, 0x3e, 0x7e, 0x60, 0x60, 0x60, 0x7e, 0x60, 0x60, 0x60, 0x60, 0x60, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'G'
+    [
+        0x00, 0x1c, 0x3e, 0x60, 0x40, 0x40, 0x40, 0x4e, 0x42, 0x42, 0x62, 0x3e, 0x1c, 0x00, 0x00,
+        0x00,
+    ],
+    //'H'
+    [
+        0x00, 0x42, 0x42, 0x42, 0x42, 0x42, 0x7e, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x00, 0x00,
+        0x00,
+    ],
+    //'I'
+    [
+        0x00, 0x7e, 0x7e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x7e, 0x3c, 0x00, 0x00,
+        0x00,
+    ],
+    //'J'
+    [
+        0x00, 0x1c, 0x1c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x7c, 0x38, 0x00, 0x00,
+        0x00,
+    ],
+    //'K'
+    [
+        0x00, 0x42, 0x46, 0x44, 0x48, 0x50, 0x70, 0x78, 0x4c, 0x44, 0x46, 0x42, 0x40, 0x00, 0x00,
+        0x00,
+    ],
+    //'L'
+    [
+        0x00, 0x20, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x7e, 0x3e, 0x00, 0x00,
+        0x00,
+    ],
+    //'M'
+    [
+        0x00, 0x42, 0xe7, 0xe7, 0xe7, 0xdb, 0xdb, 0xdb, 0xc3, 0xc3, 0xc3, 0xc3, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'N'
+    [
+        0x00, 0x62, 0x62, 0x62, 0x72, 0x52, 0x52, 0x4a, 0x4a, 0x4e, 0x46, 0x46, 0x42, 0x00, 0x00,
+        0x00,
+    ],
+    //'O'
+    [
+        0x00, 0x18, 0x3c, 0x66, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x66, 0x3c, 0x18, 0x00, 0x00,
+        0x00,
+    ],
+    //'P'
+    [
+        0x00, 0x78, 0x7e, 0x62, 0x62, 0x62, 0x66, 0x7c, 0x60, 0x60, 0x60, 0x60, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'Q'
+    [
+        0x00, 0x18, 0x3c, 0x66, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x66, 0x3c, 0x1c, 0x04, 0x00,
+        0x00,
+    ],
+    //'R'
+    [
+        0x00, 0x78, 0x7c, 0x46, 0x46, 0x46, 0x7c, 0x7c, 0x44, 0x46, 0x42, 0x43, 0x40, 0x00, 0x00,
+        0x00,
+    ],
+    //'S'
+    [
+        0x00, 0x3c, 0x7e, 0x40, 0x40, 0x60, 0x3c, 0x0e, 0x02, 0x02, 0x02, 0x7e, 0x38, 0x00, 0x00,
+        0x00,
+    ],
+    //'T'
+    [
+        0x00, 0xff, 0xff, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'U'
+    [
+        0x00, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x7e, 0x18, 0x00, 0x00,
+        0x00,
+    ],
+    //'V'
+    [
+        0x00, 0x42, 0x42, 0x42, 0x42, 0x66, 0x24, 0x24, 0x24, 0x3c, 0x18, 0x18, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'W'
+    [
+        0x00, 0x81, 0x81, 0xc3, 0xc3, 0xdb, 0x5a, 0x5a, 0x5a, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'X'
+    [
+        0x00, 0x42, 0x62, 0x26, 0x34, 0x18, 0x18, 0x18, 0x3c, 0x24, 0x66, 0x42, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'Y'
+    [
+        0x00, 0xc3, 0x42, 0x66, 0x24, 0x3c, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'Z'
+    [
+        0x00, 0x7e, 0x7e, 0x06, 0x04, 0x0c, 0x08, 0x18, 0x10, 0x20, 0x60, 0x7e, 0x7e, 0x00, 0x00,
+        0x00,
+    ],
+    //'['
+    [
+        0x00, 0x1c, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x18, 0x18,
+        0x00,
+    ],
+    //'\'
+    [
+        0x00, 0x40, 0x40, 0x20, 0x20, 0x30, 0x10, 0x10, 0x08, 0x08, 0x0c, 0x04, 0x04, 0x02, 0x00,
+        0x00,
+    ],
+    //']'
+    [
+        0x00, 0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x18, 0x18,
+        0x00,
+    ],
+    //'^'
+    [
+        0x00, 0x18, 0x18, 0x24, 0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'_'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xff,
+    ],
+    //'`'
+    [
+        0x20, 0x10, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'a'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x3c, 0x06, 0x02, 0x3e, 0x62, 0x42, 0x46, 0x7e, 0x30, 0x00, 0x00,
+        0x00,
+    ],
+    //'b'
+    [
+        0x00, 0x60, 0x60, 0x60, 0x7c, 0x66, 0x62, 0x62, 0x62, 0x62, 0x62, 0x7e, 0x18, 0x00, 0x00,
+        0x00,
+    ],
+    //'c'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x1e, 0x32, 0x20, 0x60, 0x60, 0x60, 0x20, 0x3e, 0x0c, 0x00, 0x00,
+        0x00,
+    ],
+    //'d'
+    [
+        0x00, 0x06, 0x06, 0x06, 0x3e, 0x66, 0x46, 0x46, 0x46, 0x46, 0x46, 0x7e, 0x18, 0x00, 0x00,
+        0x00,
+    ],
+    //'e'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x3c, 0x66, 0x42, 0x7e, 0x7e, 0x40, 0x60, 0x3e, 0x1c, 0x00, 0x00,
+        0x00,
+    ],
+    //'f'
+    [
+        0x00, 0x0e, 0x18, 0x18, 0x7e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'g'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x3e, 0x66, 0x46, 0x46, 0x46, 0x46, 0x66, 0x3e, 0x16, 0x06, 0x2c,
+        0x38,
+    ],
+    //'h'
+    [
+        0x00, 0x60, 0x60, 0x60, 0x7c, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'i'
+    [
+        0x00, 0x18, 0x08, 0x00, 0x38, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3e, 0x3e, 0x00, 0x00,
+        0x00,
+    ],
+    //'j'
+    [
+        0x00, 0x08, 0x08, 0x00, 0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x38,
+        0x70,
+    ],
+    //'k'
+    [
+        0x00, 0x60, 0x60, 0x60, 0x62, 0x64, 0x68, 0x78, 0x78, 0x6c, 0x66, 0x62, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'l'
+    [
+        0x00, 0x70, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1e, 0x04, 0x00, 0x00,
+        0x00,
+    ],
+    //'m'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x76, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x5a, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'n'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x5c, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'o'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x3c, 0x66, 0x42, 0x42, 0x42, 0x42, 0x66, 0x3c, 0x18, 0x00, 0x00,
+        0x00,
+    ],
+    //'p'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x7c, 0x66, 0x62, 0x62, 0x62, 0x62, 0x62, 0x7e, 0x78, 0x60, 0x60,
+        0x40,
+    ],
+    //'q'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x3a, 0x66, 0x46, 0x42, 0x42, 0x46, 0x66, 0x3e, 0x1a, 0x02, 0x02,
+        0x02,
+    ],
+    //'r'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x2e, 0x38, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'s'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x3c, 0x20, 0x60, 0x30, 0x1c, 0x06, 0x06, 0x7c, 0x38, 0x00, 0x00,
+        0x00,
+    ],
+    //'t'
+    [
+        0x00, 0x00, 0x10, 0x10, 0x7e, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1c, 0x04, 0x00, 0x00,
+        0x00,
+    ],
+    //'u'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x42, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x3e, 0x38, 0x00, 0x00,
+        0x00,
+    ],
+    //'v'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x42, 0x42, 0x66, 0x24, 0x24, 0x3c, 0x18, 0x18, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'w'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x81, 0x81, 0xc3, 0x5a, 0x5a, 0x5a, 0x66, 0x66, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+    //'x'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x42, 0x24, 0x3c, 0x18, 0x18, 0x3c, 0x24, 0x66, 0x42, 0x00, 0x00,
+        0x00,
+    ],
+    //'y'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x42, 0x42, 0x66, 0x24, 0x24, 0x1c, 0x18, 0x18, 0x18, 0x10, 0x30,
+        0x60,
+    ],
+    //'z'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x3e, 0x06, 0x04, 0x08, 0x18, 0x30, 0x20, 0x7c, 0x3c, 0x00, 0x00,
+        0x00,
+    ],
+    //'{'
+    [
+        0x00, 0x0e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x30, 0x30, 0x18, 0x18, 0x18, 0x18, 0x08, 0x0e,
+        0x00,
+    ],
+    //'|'
+    [
+        0x00, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18,
+        0x18,
+    ],
+    //'}'
+    [
+        0x00, 0x70, 0x10, 0x18, 0x18, 0x18, 0x18, 0x0c, 0x0c, 0x18, 0x18, 0x18, 0x18, 0x10, 0x70,
+        0x00,
+    ],
+    //'~'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7f, 0x0e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ],
+];
+
+/// ## 函数说明
+/// 字符的字形，没有字形的字节显示为实心方块，与VGA文本模式中0xfe的显示一致
+///
+/// ## 参数
+/// * `byte` - 字符
+pub fn glyph(byte: u8) -> &'static [u8; GLYPH_HEIGHT] {
+    const BLOCK: [u8; GLYPH_HEIGHT] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x3c, 0x3c, 0x3c, 0x3c, 0x3c, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+    match byte {
+        FIRST..=LAST => &GLYPHS[usize::from(byte - FIRST)],
+        _ => &BLOCK,
+    }
+}
@@ -0,0 +1,32 @@
+pub mod fat;
+pub mod initrd;
+
+use crate::storage::BlockError;
+
+/// ## 说明
+/// 文件系统操作的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    /// 读取块设备失败
+    Io(BlockError),
+    /// 引导扇区不是有效的BPB
+    BadBootSector,
+    /// 不支持的文件系统变体，如FAT12、FAT32
+    Unsupported(&'static str),
+    /// 路径中的某一部分不存在
+    NotFound,
+    /// 路径中间的一部分不是目录
+    NotADirectory,
+    /// 打开的路径是目录
+    IsADirectory,
+    /// 路径的某一部分不是有效的8.3文件名
+    InvalidName,
+    /// 簇链指向保留或超出范围的簇，或簇链成环（长度超过总簇数）
+    CorruptChain { cluster: u32 },
+}
+
+impl From<BlockError> for FsError {
+    fn from(err: BlockError) -> Self {
+        FsError::Io(err)
+    }
+}
@@ -0,0 +1,505 @@
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+
+use super::FsError;
+use crate::storage::BlockDevice;
+
+//FAT只使用512字节的扇区
+const SECTOR_SIZE: usize = 512;
+const DIR_ENTRY_SIZE: usize = 32;
+//按簇数区分FAT12/16/32
+const FAT16_MIN_CLUSTERS: u32 = 4085;
+const FAT16_MAX_CLUSTERS: u32 = 65525;
+//FAT16表项：大于等于0xfff8表示链结束，0xfff7为坏簇
+const FAT16_END: u16 = 0xfff8;
+const FAT16_BAD: u16 = 0xfff7;
+//目录项第一个字节：0表示目录结束，0xe5表示已删除
+const ENTRY_END: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xe5;
+
+/// ## 说明
+/// 目录项的属性字节
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attributes(pub u8);
+
+impl Attributes {
+    pub const READ_ONLY: u8 = 0x01;
+    pub const HIDDEN: u8 = 0x02;
+    pub const SYSTEM: u8 = 0x04;
+    pub const VOLUME_ID: u8 = 0x08;
+    pub const DIRECTORY: u8 = 0x10;
+    pub const ARCHIVE: u8 = 0x20;
+    /// 长文件名项的属性为前四位全部置位
+    pub const LONG_NAME: u8 = 0x0f;
+
+    /// 是否为目录
+    pub fn is_dir(self) -> bool {
+        self.0 & Self::DIRECTORY != 0
+    }
+
+    /// 是否为长文件名项
+    pub fn is_long_name(self) -> bool {
+        self.0 & 0x3f == Self::LONG_NAME
+    }
+}
+
+/// ## 说明
+/// 目录中的一项，名字为"NAME.EXT"形式的8.3名
+#[derive(Clone, Copy)]
+pub struct DirEntry {
+    name: [u8; 12],
+    name_len: usize,
+    pub size: u32,
+    pub attributes: Attributes,
+    first_cluster: u32,
+}
+
+impl DirEntry {
+    /// ## 函数说明
+    /// 文件名，如"README.TXT"，没有扩展名时不带点
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("?")
+    }
+
+    /// ## 函数说明
+    /// 是否为目录
+    pub fn is_dir(&self) -> bool {
+        self.attributes.is_dir()
+    }
+
+    fn parse(raw: &[u8]) -> Self {
+        let mut name = [0u8; 12];
+        let mut len = 0;
+        for &b in raw[..8].iter().take_while(|&&b| b != b' ') {
+            name[len] = b;
+            len += 1;
+        }
+        if raw[8] != b' ' {
+            name[len] = b'.';
+            len += 1;
+            for &b in raw[8..11].iter().take_while(|&&b| b != b' ') {
+                name[len] = b;
+                len += 1;
+            }
+        }
+        DirEntry {
+            name,
+            name_len: len,
+            size: u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]),
+            attributes: Attributes(raw[11]),
+            first_cluster: u32::from(u16::from_le_bytes([raw[26], raw[27]])),
+        }
+    }
+}
+
+impl fmt::Debug for DirEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DirEntry")
+            .field("name", &self.name())
+            .field("size", &self.size)
+            .field("attributes", &self.attributes)
+            .finish()
+    }
+}
+
+/// ## 函数说明
+/// 把路径中的一部分转换为目录项中的11字节名字（8字节主名和3字节扩展名，大写并用空格填充）
+/// 主名超过8个字符、扩展名超过3个字符或包含多个点时返回None
+///
+/// ## 参数
+/// * `component` - 文件名，如"readme.txt"
+pub fn short_name(component: &str) -> Option<[u8; 11]> {
+    let (base, ext) = match component.rsplit_once('.') {
+        Some((base, ext)) => (base, ext),
+        None => (component, ""),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || base.contains('.') {
+        return None;
+    }
+    let mut name = [b' '; 11];
+    for (dst, src) in name[..8].iter_mut().zip(base.bytes()) {
+        *dst = src.to_ascii_uppercase();
+    }
+    for (dst, src) in name[8..].iter_mut().zip(ext.bytes()) {
+        *dst = src.to_ascii_uppercase();
+    }
+    Some(name)
+}
+
+//目录的位置：FAT16的根目录是固定区域，子目录是普通的簇链
+#[derive(Clone, Copy)]
+enum Dir {
+    Root,
+    Cluster(u32),
+}
+
+//BPB中用到的字段，扇区号都是相对于设备（或分区）开头的
+struct Layout {
+    sectors_per_cluster: u32,
+    fat_start: u64,
+    root_start: u64,
+    root_sectors: u32,
+    data_start: u64,
+    clusters: u32,
+}
+
+impl Layout {
+    fn parse(boot: &[u8; SECTOR_SIZE]) -> Result<Self, FsError> {
+        let u16_at =
+            |offset: usize| u32::from(u16::from_le_bytes([boot[offset], boot[offset + 1]]));
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                boot[offset],
+                boot[offset + 1],
+                boot[offset + 2],
+                boot[offset + 3],
+            ])
+        };
+        if boot[510..] != [0x55, 0xaa] {
+            return Err(FsError::BadBootSector);
+        }
+        let bytes_per_sector = u16_at(11);
+        let sectors_per_cluster = u32::from(boot[13]);
+        let reserved = u16_at(14);
+        let fats = u32::from(boot[16]);
+        let root_entries = u16_at(17);
+        let total = match u16_at(19) {
+            0 => u32_at(32),
+            total => total,
+        };
+        let fat_size = u16_at(22);
+        if bytes_per_sector != SECTOR_SIZE as u32
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved == 0
+            || fats == 0
+        {
+            return Err(FsError::BadBootSector);
+        }
+        //FAT32的16位FAT大小为0
+        if fat_size == 0 {
+            return Err(FsError::Unsupported("FAT32"));
+        }
+        let root_sectors = (root_entries * DIR_ENTRY_SIZE as u32).div_ceil(SECTOR_SIZE as u32);
+        let data_start = reserved + fats * fat_size + root_sectors;
+        let clusters = total
+            .checked_sub(data_start)
+            .ok_or(FsError::BadBootSector)?
+            / sectors_per_cluster;
+        if clusters < FAT16_MIN_CLUSTERS {
+            return Err(FsError::Unsupported("FAT12"));
+        }
+        if clusters >= FAT16_MAX_CLUSTERS {
+            return Err(FsError::Unsupported("FAT32"));
+        }
+        Ok(Layout {
+            sectors_per_cluster,
+            fat_start: u64::from(reserved),
+            root_start: u64::from(reserved + fats * fat_size),
+            root_sectors,
+            data_start: u64::from(data_start),
+            clusters,
+        })
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u64 {
+        self.data_start + u64::from(cluster - 2) * u64::from(self.sectors_per_cluster)
+    }
+
+    fn cluster_bytes(&self) -> usize {
+        self.sectors_per_cluster as usize * SECTOR_SIZE
+    }
+}
+
+/// ## 说明
+/// 只读的FAT16文件系统，设备可以是整个硬盘或`PartitionDevice`
+pub struct FatFs<D: BlockDevice> {
+    device: Mutex<D>,
+    layout: Layout,
+}
+
+impl<D: BlockDevice> FatFs<D> {
+    /// ## 函数说明
+    /// 读取引导扇区中的BPB并挂载文件系统，不是FAT16时返回错误
+    ///
+    /// ## 参数
+    /// * `device` - 块大小为512字节的设备
+    ///
+    /// ## 用法
+    /// ```rust
+    /// let fs = FatFs::mount(partition)?;
+    /// let mut file = fs.open("/ETC/CONFIG.TXT")?;
+    /// ```
+    pub fn mount(mut device: D) -> Result<Self, FsError> {
+        if device.block_size() != SECTOR_SIZE {
+            return Err(FsError::BadBootSector);
+        }
+        let mut boot = [0u8; SECTOR_SIZE];
+        device.read_blocks(0, &mut boot)?;
+        let layout = Layout::parse(&boot)?;
+        Ok(FatFs {
+            device: Mutex::new(device),
+            layout,
+        })
+    }
+
+    fn read_sector(&self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), FsError> {
+        self.device.lock().read_blocks(lba, buf)?;
+        Ok(())
+    }
+
+    //簇链中的下一个簇，链结束时返回None
+    fn next_cluster(&self, cluster: u32) -> Result<Option<u32>, FsError> {
+        let offset = cluster as usize * 2;
+        let mut sector = [0u8; SECTOR_SIZE];
+        self.read_sector(
+            self.layout.fat_start + (offset / SECTOR_SIZE) as u64,
+            &mut sector,
+        )?;
+        let entry = u16::from_le_bytes([
+            sector[offset % SECTOR_SIZE],
+            sector[offset % SECTOR_SIZE + 1],
+        ]);
+        match entry {
+            FAT16_END..=0xffff => Ok(None),
+            FAT16_BAD => Err(FsError::CorruptChain { cluster }),
+            next => self.check_cluster(u32::from(next)).map(Some),
+        }
+    }
+
+    //沿簇链前进一步，`steps`记录已走的步数；合法的簇链不会长于总簇数，超过时说明簇链成环
+    fn follow_chain(&self, cluster: u32, steps: &mut u32) -> Result<Option<u32>, FsError> {
+        *steps += 1;
+        if *steps >= self.layout.clusters {
+            return Err(FsError::CorruptChain { cluster });
+        }
+        self.next_cluster(cluster)
+    }
+
+    fn check_cluster(&self, cluster: u32) -> Result<u32, FsError> {
+        if cluster < 2 || cluster >= self.layout.clusters + 2 {
+            Err(FsError::CorruptChain { cluster })
+        } else {
+            Ok(cluster)
+        }
+    }
+
+    //依次对目录中的每个有效项调用`f`，`f`返回true时停止；跳过已删除、长文件名和卷标项
+    fn scan_dir(
+        &self,
+        dir: Dir,
+        mut f: impl FnMut(&[u8], DirEntry) -> bool,
+    ) -> Result<(), FsError> {
+        let mut sector = [0u8; SECTOR_SIZE];
+        let mut visit = |lba: u64, sector: &mut [u8; SECTOR_SIZE]| -> Result<bool, FsError> {
+            self.read_sector(lba, sector)?;
+            for raw in sector.chunks_exact(DIR_ENTRY_SIZE) {
+                match raw[0] {
+                    ENTRY_END => return Ok(true),
+                    ENTRY_DELETED => continue,
+                    _ => {}
+                }
+                let attributes = Attributes(raw[11]);
+                if attributes.is_long_name() || attributes.0 & Attributes::VOLUME_ID != 0 {
+                    continue;
+                }
+                if f(&raw[..11], DirEntry::parse(raw)) {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        };
+        match dir {
+            Dir::Root => {
+                for i in 0..u64::from(self.layout.root_sectors) {
+                    if visit(self.layout.root_start + i, &mut sector)? {
+                        break;
+                    }
+                }
+            }
+            Dir::Cluster(first) => {
+                let mut cluster = Some(self.check_cluster(first)?);
+                let mut steps = 0;
+                while let Some(current) = cluster {
+                    let start = self.layout.cluster_sector(current);
+                    for i in 0..u64::from(self.layout.sectors_per_cluster) {
+                        if visit(start + i, &mut sector)? {
+                            return Ok(());
+                        }
+                    }
+                    cluster = self.follow_chain(current, &mut steps)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //在目录中按8.3名查找
+    fn find(&self, dir: Dir, component: &str) -> Result<DirEntry, FsError> {
+        let name = short_name(component).ok_or(FsError::InvalidName)?;
+        let mut found = None;
+        self.scan_dir(dir, |raw, entry| {
+            if raw == name {
+                found = Some(entry);
+            }
+            found.is_some()
+        })?;
+        found.ok_or(FsError::NotFound)
+    }
+
+    //沿路径查找，返回最后一部分的目录项，路径为根目录时返回None
+    fn lookup(&self, path: &str) -> Result<Option<DirEntry>, FsError> {
+        let mut dir = Dir::Root;
+        let mut entry = None;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            if let Some(parent) = entry {
+                dir = Self::as_dir(&parent)?;
+            }
+            entry = Some(self.find(dir, component)?);
+        }
+        Ok(entry)
+    }
+
+    fn as_dir(entry: &DirEntry) -> Result<Dir, FsError> {
+        if !entry.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+        //".."指向根目录时簇号为0
+        Ok(match entry.first_cluster {
+            0 => Dir::Root,
+            cluster => Dir::Cluster(cluster),
+        })
+    }
+
+    /// ## 函数说明
+    /// 打开文件，路径以'/'分隔，每一部分按8.3名不区分大小写地匹配
+    ///
+    /// ## 参数
+    /// * `path` - 如"/DOCS/README.TXT"
+    pub fn open(&self, path: &str) -> Result<File<'_, D>, FsError> {
+        let entry = self.lookup(path)?.ok_or(FsError::IsADirectory)?;
+        if entry.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        Ok(File {
+            fs: self,
+            size: entry.size,
+            position: 0,
+            cluster: entry.first_cluster,
+            steps: 0,
+        })
+    }
+
+    /// ## 函数说明
+    /// 列出目录中的文件和子目录，不包括"."和".."
+    ///
+    /// ## 参数
+    /// * `path` - 目录路径，"/"为根目录
+    ///
+    /// ## 用法
+    /// ```rust
+    /// for entry in fs.read_dir("/")? {
+    ///     println!("{} {}", entry.name(), entry.size);
+    /// }
+    /// ```
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>, FsError> {
+        let dir = match self.lookup(path)? {
+            Some(entry) => Self::as_dir(&entry)?,
+            None => Dir::Root,
+        };
+        let mut entries = Vec::new();
+        self.scan_dir(dir, |raw, entry| {
+            if raw[0] != b'.' {
+                entries.push(entry);
+            }
+            false
+        })?;
+        Ok(entries)
+    }
+}
+
+/// ## 说明
+/// 打开的文件，从头开始顺序读取
+pub struct File<'a, D: BlockDevice> {
+    fs: &'a FatFs<D>,
+    size: u32,
+    position: u32,
+    //`position`所在的簇
+    cluster: u32,
+    //从第一个簇起沿簇链走过的步数
+    steps: u32,
+}
+
+impl<D: BlockDevice> File<'_, D> {
+    /// ## 函数说明
+    /// 文件的大小（字节）
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// ## 函数说明
+    /// 从当前位置读取到`buf`，返回读取的字节数，到达文件末尾时返回0
+    ///
+    /// ## 参数
+    /// * `buf` - 缓冲区
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let layout = &self.fs.layout;
+        let cluster_bytes = layout.cluster_bytes();
+        let mut sector = [0u8; SECTOR_SIZE];
+        let mut read = 0;
+        while read < buf.len() && self.position < self.size {
+            let offset = self.position as usize % cluster_bytes;
+            //每进入一个新的簇（文件开头除外）都沿簇链前进
+            if offset == 0 && self.position != 0 {
+                self.cluster = self.fs.follow_chain(self.cluster, &mut self.steps)?.ok_or(
+                    FsError::CorruptChain {
+                        cluster: self.cluster,
+                    },
+                )?;
+            }
+            self.fs.check_cluster(self.cluster)?;
+            let lba = layout.cluster_sector(self.cluster) + (offset / SECTOR_SIZE) as u64;
+            self.fs.read_sector(lba, &mut sector)?;
+
+            let in_sector = offset % SECTOR_SIZE;
+            let len = (SECTOR_SIZE - in_sector)
+                .min(buf.len() - read)
+                .min((self.size - self.position) as usize);
+            buf[read..read + len].copy_from_slice(&sector[in_sector..in_sector + len]);
+            read += len;
+            self.position += len as u32;
+        }
+        Ok(read)
+    }
+}
+
+#[test_case]
+fn test_short_name() {
+    assert_eq!(short_name("readme.txt"), Some(*b"README  TXT"));
+    assert_eq!(short_name("KERNEL"), Some(*b"KERNEL     "));
+    assert_eq!(short_name("a.b"), Some(*b"A       B  "));
+    assert_eq!(short_name("toolongname.txt"), None);
+    assert_eq!(short_name("file.text"), None);
+    assert_eq!(short_name("a.b.c"), None);
+    assert_eq!(short_name(".txt"), None);
+}
+
+#[test_case]
+fn test_parse_dir_entry() {
+    let mut raw = [0u8; DIR_ENTRY_SIZE];
+    raw[..11].copy_from_slice(b"HELLO   TXT");
+    raw[11] = Attributes::ARCHIVE;
+    raw[26..28].copy_from_slice(&5u16.to_le_bytes());
+    raw[28..32].copy_from_slice(&1234u32.to_le_bytes());
+    let entry = DirEntry::parse(&raw);
+    assert_eq!(entry.name(), "HELLO.TXT");
+    assert_eq!(entry.size, 1234);
+    assert_eq!(entry.first_cluster, 5);
+    assert!(!entry.is_dir());
+
+    raw[..11].copy_from_slice(b"DOCS       ");
+    raw[11] = Attributes::DIRECTORY;
+    assert_eq!(DirEntry::parse(&raw).name(), "DOCS");
+    assert!(Attributes(0x0f).is_long_name());
+    assert!(!Attributes(Attributes::DIRECTORY).is_long_name());
+}
@@ -0,0 +1,278 @@
+use core::fmt;
+use spin::Once;
+use x86_64::VirtAddr;
+
+//newc格式的cpio：固定110字节的ASCII头部，之后是以NUL结尾的文件名和文件内容，两者都按4字节对齐
+const HEADER_SIZE: usize = 110;
+const MAGIC_NEWC: &[u8; 6] = b"070701";
+const MAGIC_CRC: &[u8; 6] = b"070702";
+//头部中magic之后的13个8位十六进制字段的序号
+const FIELD_MODE: usize = 1;
+const FIELD_FILESIZE: usize = 6;
+const FIELD_NAMESIZE: usize = 11;
+//归档由名为TRAILER!!!的项结束
+const TRAILER: &str = "TRAILER!!!";
+//mode中的文件类型
+const MODE_TYPE_MASK: u32 = 0o170000;
+const MODE_DIR: u32 = 0o040000;
+
+//由`init`设置一次
+static ARCHIVE: Once<Archive<'static>> = Once::new();
+
+/// ## 说明
+/// 解析或访问initrd时的错误，`offset`为出错的项在归档中的偏移
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitrdError {
+    /// 还没有调用`init`
+    NotInitialized,
+    /// 已经调用过`init`
+    AlreadyInitialized,
+    /// 头部的magic不是070701或070702
+    BadMagic { offset: usize },
+    /// 头部的字段不是十六进制数
+    BadField { offset: usize },
+    /// 文件名为空、不以NUL结尾或不是UTF-8
+    BadName { offset: usize },
+    /// 头部、文件名或内容超出了归档的末尾，或者没有结束项
+    Truncated { offset: usize },
+    /// 没有这个文件
+    NotFound,
+    /// 打开的是目录
+    IsADirectory,
+}
+
+/// ## 说明
+/// 归档中的一项，名字和内容都直接引用归档的内存
+#[derive(Clone, Copy)]
+pub struct Entry<'a> {
+    pub name: &'a str,
+    pub data: &'a [u8],
+    pub mode: u32,
+}
+
+impl Entry<'_> {
+    /// ## 函数说明
+    /// 是否为目录
+    pub fn is_dir(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_DIR
+    }
+}
+
+impl fmt::Debug for Entry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Entry")
+            .field("name", &self.name)
+            .field("size", &self.data.len())
+            .field("mode", &format_args!("{:#o}", self.mode))
+            .finish()
+    }
+}
+
+/// ## 说明
+/// 已经校验过的newc cpio归档
+#[derive(Clone, Copy)]
+pub struct Archive<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Archive<'a> {
+    /// ## 函数说明
+    /// 检查从头到结束项的每一个头部，任何读取都不会越过`data`的末尾
+    ///
+    /// ## 参数
+    /// * `data` - 归档的全部字节，结束项之后的填充会被忽略
+    ///
+    /// ## 用法
+    /// ```rust
+    /// let archive = Archive::parse(include_bytes!("initrd.cpio"))?;
+    /// ```
+    pub fn parse(data: &'a [u8]) -> Result<Self, InitrdError> {
+        let archive = Archive { data };
+        let mut offset = 0;
+        while let Some((_, next)) = archive.entry_at(offset)? {
+            offset = next;
+        }
+        Ok(archive)
+    }
+
+    //解析`offset`处的项，返回它和下一项的偏移，结束项返回None
+    fn entry_at(&self, offset: usize) -> Result<Option<(Entry<'a>, usize)>, InitrdError> {
+        let truncated = InitrdError::Truncated { offset };
+        let header = self
+            .data
+            .get(offset..offset + HEADER_SIZE)
+            .ok_or(truncated)?;
+        if &header[..6] != MAGIC_NEWC && &header[..6] != MAGIC_CRC {
+            return Err(InitrdError::BadMagic { offset });
+        }
+        let field = |index: usize| -> Result<usize, InitrdError> {
+            let digits = &header[6 + index * 8..][..8];
+            let digits =
+                core::str::from_utf8(digits).map_err(|_| InitrdError::BadField { offset })?;
+            u32::from_str_radix(digits, 16)
+                .map(|value| value as usize)
+                .map_err(|_| InitrdError::BadField { offset })
+        };
+        let mode = field(FIELD_MODE)? as u32;
+        let file_size = field(FIELD_FILESIZE)?;
+        let name_size = field(FIELD_NAMESIZE)?;
+
+        let name_start = offset + HEADER_SIZE;
+        let name = name_start
+            .checked_add(name_size)
+            .and_then(|end| self.data.get(name_start..end))
+            .ok_or(truncated)?;
+        let name = match name.split_last() {
+            Some((0, name)) if !name.is_empty() => {
+                core::str::from_utf8(name).map_err(|_| InitrdError::BadName { offset })?
+            }
+            _ => return Err(InitrdError::BadName { offset }),
+        };
+        if name == TRAILER {
+            return Ok(None);
+        }
+
+        let data_start = align4(name_start + name_size);
+        let data = data_start
+            .checked_add(file_size)
+            .and_then(|end| self.data.get(data_start..end))
+            .ok_or(truncated)?;
+        let entry = Entry { name, data, mode };
+        //最后一项的内容之后可能没有填充，越界由下一次读取头部时报告
+        Ok(Some((entry, align4(data_start + file_size))))
+    }
+
+    /// ## 函数说明
+    /// 依次返回归档中的每一项，名字去掉了开头的"./"，跳过"."本身
+    pub fn entries(&self) -> Entries<'a> {
+        Entries {
+            archive: *self,
+            offset: 0,
+        }
+    }
+
+    /// ## 函数说明
+    /// 按名字查找文件，返回它的内容；名字开头的'/'或"./"会被忽略
+    ///
+    /// ## 参数
+    /// * `name` - 如"keymaps/us.map"
+    pub fn open(&self, name: &str) -> Result<&'a [u8], InitrdError> {
+        let name = normalize(name);
+        let entry = self
+            .entries()
+            .find(|entry| entry.name == name)
+            .ok_or(InitrdError::NotFound)?;
+        if entry.is_dir() {
+            return Err(InitrdError::IsADirectory);
+        }
+        Ok(entry.data)
+    }
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn normalize(name: &str) -> &str {
+    let name = name.trim_start_matches('/');
+    name.strip_prefix("./").unwrap_or(name)
+}
+
+/// ## 说明
+/// `Archive::entries`返回的迭代器
+pub struct Entries<'a> {
+    archive: Archive<'a>,
+    offset: usize,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Entry<'a>> {
+        loop {
+            //`Archive::parse`已经检查过所有的项，这里不会出错
+            let (mut entry, next) = self.archive.entry_at(self.offset).ok()??;
+            self.offset = next;
+            entry.name = normalize(entry.name);
+            if entry.name != "." && !entry.name.is_empty() {
+                return Some(entry);
+            }
+        }
+    }
+}
+
+/// ## 函数说明
+/// 解析位于`addr`的initrd并设置为全局归档，之后可以通过[`open`]和[`read_dir`]访问
+/// 返回归档中的项数
+///
+/// ## 参数
+/// * `addr` - 归档的虚拟地址，例如物理地址加上`memory::phys_offset()`
+/// * `len` - 归档的字节数
+///
+/// ## Safety
+/// `addr`开始的`len`字节必须已经映射，并且在内核运行期间保持有效且不被修改
+///
+/// ## 用法
+/// ```rust
+/// unsafe { initrd::init(memory::phys_offset() + initrd_phys, initrd_len)? };
+/// ```
+pub unsafe fn init(addr: VirtAddr, len: usize) -> Result<usize, InitrdError> {
+    init_from_slice(core::slice::from_raw_parts(addr.as_ptr(), len))
+}
+
+/// ## 函数说明
+/// 同[`init`]，归档来自静态数据，例如通过`include_bytes!`链接进内核的文件
+///
+/// ## 参数
+/// * `data` - 归档的全部字节
+///
+/// ## 用法
+/// ```rust
+/// initrd::init_from_slice(include_bytes!("../initrd.cpio"))?;
+/// ```
+pub fn init_from_slice(data: &'static [u8]) -> Result<usize, InitrdError> {
+    if ARCHIVE.r#try().is_some() {
+        return Err(InitrdError::AlreadyInitialized);
+    }
+    let archive = Archive::parse(data)?;
+    ARCHIVE.call_once(|| archive);
+    Ok(archive.entries().count())
+}
+
+/// ## 函数说明
+/// 全局归档，尚未初始化时返回错误
+pub fn archive() -> Result<Archive<'static>, InitrdError> {
+    ARCHIVE.r#try().copied().ok_or(InitrdError::NotInitialized)
+}
+
+/// ## 函数说明
+/// 在全局归档中打开文件，返回的切片直接指向归档
+///
+/// ## 参数
+/// * `name` - 文件名
+pub fn open(name: &str) -> Result<&'static [u8], InitrdError> {
+    archive()?.open(name)
+}
+
+/// ## 函数说明
+/// 列出全局归档中的所有项
+///
+/// ## 用法
+/// ```rust
+/// for entry in initrd::read_dir()? {
+///     println!("{} {}", entry.name, entry.data.len());
+/// }
+/// ```
+pub fn read_dir() -> Result<Entries<'static>, InitrdError> {
+    Ok(archive()?.entries())
+}
+
+#[test_case]
+fn test_align4() {
+    assert_eq!(align4(0), 0);
+    assert_eq!(align4(1), 4);
+    assert_eq!(align4(112), 112);
+    assert_eq!(align4(113), 116);
+    assert_eq!(normalize("/./a/b"), "a/b");
+    assert_eq!(normalize("a"), "a");
+}
@@ -1,60 +1,216 @@
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
 use lazy_static::lazy_static;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
+use crate::memory;
+
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+const DOUBLE_FAULT_STACK_PAGES: u64 = 5;
+//NMI可能在任何时刻到达，包括内核栈已经不可用的时候，因此使用独立的IST栈
+pub const NMI_IST_INDEX: u16 = 1;
+const NMI_STACK_PAGES: u64 = 2;
+//机器检查发生时内核栈的状态同样不可信
+pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+const MACHINE_CHECK_STACK_PAGES: u64 = 2;
+//栈溢出时页错误无法在原来的栈上压栈，使用独立的IST栈才能报告溢出，而不是直接升级为double fault
+//页错误处理函数中再次发生页错误会从同一个栈顶重新开始，覆盖外层的栈帧，因此处理函数中的致命路径只做打印
+pub const PAGE_FAULT_IST_INDEX: u16 = 3;
+const PAGE_FAULT_STACK_PAGES: u64 = 5;
+
+//CPL为3时发生中断，CPU从TSS的RSP0加载内核栈
+const PRIVILEGE_STACK_PAGES: u64 = 4;
+
+//TSS只由硬件读取，切换任务时需要修改RSP0，因此放在UnsafeCell中，只通过`set_kernel_stack`写入
+struct TssCell(UnsafeCell<TaskStateSegment>);
+
+unsafe impl Sync for TssCell {}
 
+//IST栈从已映射的页面中分配，下方有未映射的保护页，因此TSS必须在安装全局页表之后初始化
 lazy_static! {
-    static ref TSS: TaskStateSegment = {
+    static ref TSS: TssCell = {
         let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
-        tss
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            memory::guard::alloc_guarded_stack("double fault", DOUBLE_FAULT_STACK_PAGES)
+                .expect("failed to map double fault stack");
+        tss.interrupt_stack_table[NMI_IST_INDEX as usize] =
+            memory::guard::alloc_guarded_stack("nmi", NMI_STACK_PAGES)
+                .expect("failed to map NMI stack");
+        tss.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] =
+            memory::guard::alloc_guarded_stack("machine check", MACHINE_CHECK_STACK_PAGES)
+                .expect("failed to map machine check stack");
+        tss.interrupt_stack_table[PAGE_FAULT_IST_INDEX as usize] =
+            memory::guard::alloc_guarded_stack("page fault", PAGE_FAULT_STACK_PAGES)
+                .expect("failed to map page fault stack");
+        tss.privilege_stack_table[0] =
+            memory::guard::alloc_guarded_stack("privilege", PRIVILEGE_STACK_PAGES)
+                .expect("failed to map privilege stack");
+        TssCell(UnsafeCell::new(tss))
     };
 }
 
-//全局描述符表中添加描述符来让ltr指令加上GDT序号来加载TSS
+/*
+    GDT的布局，顺序由syscall/sysret决定：
+      0     空描述符
+      1     0x08  内核代码段   syscall加载的CS为STAR[47:32]
+      2     0x10  内核数据段   syscall加载的SS为STAR[47:32] + 8
+      3     0x1b  用户数据段   sysret加载的SS为STAR[63:48] + 8
+      4     0x23  用户代码段   sysret加载的CS为STAR[63:48] + 16（64位模式）
+      5~6   0x28  TSS，在长模式下占用两个表项
+    因此STAR[47:32] = 0x08，STAR[63:48] = 0x10
+*/
 lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
-        let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+        let kernel_code = gdt.add_entry(Descriptor::kernel_code_segment());
+        let kernel_data = gdt.add_entry(Descriptor::kernel_data_segment());
+        let user_data = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code = gdt.add_entry(Descriptor::user_code_segment());
+        //描述符只记录TSS的地址，之后对RSP0的写入不经过这个引用
+        let tss = gdt.add_entry(Descriptor::tss_segment(unsafe { &*TSS.0.get() }));
         (
             gdt,
             Selectors {
-                code_selector,
-                tss_selector,
+                kernel_code,
+                kernel_data,
+                user_code,
+                user_data,
+                tss,
             },
         )
     };
 }
 
-struct Selectors {
-    code_selector: SegmentSelector,
-    tss_selector: SegmentSelector,
+/// ## 说明
+/// GDT中各描述符的段选择子，用户段的RPL为3
+///
+/// ## 成员
+/// * `kernel_code` - 内核代码段，0x08
+/// * `kernel_data` - 内核数据段，0x10
+/// * `user_code` - 64位用户代码段，0x23
+/// * `user_data` - 用户数据段，0x1b
+/// * `tss` - 任务状态段，0x28
+#[derive(Debug, Clone, Copy)]
+pub struct Selectors {
+    pub kernel_code: SegmentSelector,
+    pub kernel_data: SegmentSelector,
+    pub user_code: SegmentSelector,
+    pub user_data: SegmentSelector,
+    pub tss: SegmentSelector,
+}
+
+/// ## 函数说明
+/// GDT中各描述符的段选择子，供进入用户态和设置syscall相关的MSR使用
+///
+/// ## 用法
+/// ```rust
+/// let cs = gdt::selectors().user_code;
+/// ```
+pub fn selectors() -> &'static Selectors {
+    &GDT.1
 }
 
 /// ## 函数说明
-/// 加载GDT
+/// 加载GDT，并登记启动栈和IST栈的保护页，调用前需要先通过`memory::install_paging`安装全局页表
 /// ## 用法
 /// ```rust
 /// init();
 /// ```
 pub fn init() {
-    use x86_64::instructions::segmentation::{Segment, CS};
+    use x86_64::instructions::segmentation::{Segment, CS, SS};
     use x86_64::instructions::tables::load_tss;
 
+    memory::guard::register_boot_stack_guard();
     GDT.0.load();
-    //重载代码段寄存器和TSS,unsafe的两个函数如果加载无效指针会破坏内存安全性
+    //重载代码段、栈段寄存器和TSS,unsafe的函数如果加载无效指针会破坏内存安全性
+    //引导程序留下的SS指向它自己的GDT，在新表中可能是别的描述符
     unsafe {
-        CS::set_reg(GDT.1.code_selector);
-        load_tss(GDT.1.tss_selector);
+        CS::set_reg(GDT.1.kernel_code);
+        SS::set_reg(GDT.1.kernel_data);
+        load_tss(GDT.1.tss);
+    }
+}
+
+/// ## 函数说明
+/// 设置TSS中的RSP0，即在用户态发生中断时CPU切换到的内核栈顶，调度器在每次切换任务时调用
+///
+/// ## 参数
+/// * `stack_top` - 内核栈顶，需要16字节对齐
+///
+/// ## 用法
+/// ```rust
+/// gdt::set_kernel_stack(next_task.kernel_stack_top);
+/// ```
+pub fn set_kernel_stack(stack_top: VirtAddr) {
+    //TSS按4字节紧凑排列，RSP0没有8字节对齐
+    unsafe {
+        let rsp0 = ptr::addr_of_mut!((*TSS.0.get()).privilege_stack_table[0]);
+        rsp0.write_unaligned(stack_top);
+    }
+    //保证写入在之后可能进入用户态的指令之前完成
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// ## 函数说明
+/// TSS中当前的RSP0
+pub fn kernel_stack() -> VirtAddr {
+    unsafe { ptr::addr_of!((*TSS.0.get()).privilege_stack_table[0]).read_unaligned() }
+}
+
+#[test_case]
+fn test_set_kernel_stack() {
+    let original = kernel_stack();
+    //初始的RSP0来自带保护页的栈
+    assert!(memory::guard::find_guard(original - 1u64).is_none());
+    assert!(memory::guard::find_guard(original - PRIVILEGE_STACK_PAGES * 4096 - 1u64).is_some());
+
+    let stack_top = VirtAddr::new(0xffff_8000_1234_0000);
+    set_kernel_stack(stack_top);
+    assert_eq!(kernel_stack(), stack_top);
+    set_kernel_stack(original);
+    assert_eq!(kernel_stack(), original);
+}
+
+#[test_case]
+fn test_ist_stacks_distinct() {
+    let indexes = [
+        DOUBLE_FAULT_IST_INDEX,
+        NMI_IST_INDEX,
+        MACHINE_CHECK_IST_INDEX,
+        PAGE_FAULT_IST_INDEX,
+    ];
+    let stacks = indexes.map(|index| unsafe {
+        ptr::addr_of!((*TSS.0.get()).interrupt_stack_table[index as usize]).read_unaligned()
+    });
+    for (i, stack) in stacks.iter().enumerate() {
+        assert_ne!(stack.as_u64(), 0);
+        assert!(stacks[..i].iter().all(|other| other != stack));
+        assert!(indexes[..i].iter().all(|&other| other != indexes[i]));
+    }
+}
+
+#[test_case]
+fn test_selector_layout() {
+    use x86_64::PrivilegeLevel;
+
+    let selectors = selectors();
+    let layout = [
+        (selectors.kernel_code, 1, PrivilegeLevel::Ring0, 0x08),
+        (selectors.kernel_data, 2, PrivilegeLevel::Ring0, 0x10),
+        (selectors.user_data, 3, PrivilegeLevel::Ring3, 0x1b),
+        (selectors.user_code, 4, PrivilegeLevel::Ring3, 0x23),
+        (selectors.tss, 5, PrivilegeLevel::Ring0, 0x28),
+    ];
+    for (selector, index, rpl, value) in layout {
+        assert_eq!(selector.index(), index);
+        assert_eq!(selector.rpl(), rpl);
+        assert_eq!(selector.0, value);
     }
+    //sysret根据STAR[63:48]依次取得用户数据段和用户代码段
+    let sysret_base = selectors.kernel_data.0;
+    assert_eq!(sysret_base + 8, selectors.user_data.0 & !3);
+    assert_eq!(sysret_base + 16, selectors.user_code.0 & !3);
 }
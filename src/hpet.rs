@@ -0,0 +1,167 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::PhysAddr;
+
+use crate::memory::{self, MapError};
+
+/// HPET寄存器的惯用物理地址，尚未解析ACPI表时使用
+pub const DEFAULT_BASE: u64 = 0xfed0_0000;
+
+//寄存器偏移：能力与ID、通用配置、主计数器
+const REG_CAPABILITIES: usize = 0x000;
+const REG_CONFIG: usize = 0x010;
+const REG_COUNTER: usize = 0x0f0;
+//能力寄存器：位13表示主计数器为64位，高32位为计数周期（飞秒）
+const CAP_COUNTER_64: u64 = 1 << 13;
+//通用配置寄存器：位0启动主计数器，位1为传统替换路由（保持关闭，不接管中断）
+const CONFIG_ENABLE: u64 = 1 << 0;
+const CONFIG_LEGACY_ROUTE: u64 = 1 << 1;
+//规范规定的最大计数周期：100纳秒
+const MAX_PERIOD_FS: u64 = 100_000_000;
+const FS_PER_NS: u64 = 1_000_000;
+
+//寄存器映射后的虚拟地址，0表示未启用
+static BASE: AtomicU64 = AtomicU64::new(0);
+//计数周期（飞秒）
+static PERIOD_FS: AtomicU64 = AtomicU64::new(0);
+//启用时已经过的纳秒数，使`now_ns`从启动开始计时
+static OFFSET_NS: AtomicU64 = AtomicU64::new(0);
+
+/// ## 说明
+/// 初始化HPET时可能出现的错误
+#[derive(Debug)]
+pub enum HpetError {
+    /// 地址处没有HPET，或者计数周期不合法
+    NotPresent,
+    /// 主计数器只有32位，直接读取会回绕
+    Counter32Bit,
+    /// 映射寄存器失败
+    Map(MapError),
+}
+
+/// ## 函数说明
+/// 把计数值按飞秒周期换算为纳秒，中间结果使用128位，不会溢出
+///
+/// ## 参数
+/// * `ticks` - 主计数器的计数
+/// * `period_fs` - 计数周期（飞秒）
+pub fn ticks_to_ns(ticks: u64, period_fs: u64) -> u64 {
+    (u128::from(ticks) * u128::from(period_fs) / u128::from(FS_PER_NS)) as u64
+}
+
+fn read(offset: usize) -> u64 {
+    let base = BASE.load(Ordering::Acquire);
+    unsafe { ((base as usize + offset) as *const u64).read_volatile() }
+}
+
+fn write(offset: usize, value: u64) {
+    let base = BASE.load(Ordering::Acquire);
+    unsafe { ((base as usize + offset) as *mut u64).write_volatile(value) }
+}
+
+/// ## 函数说明
+/// 映射`base`处的HPET寄存器，检查计数周期后从0开始运行主计数器
+/// 只作为单调时钟使用，不配置任何比较器，也不接管中断投递
+///
+/// ## 参数
+/// * `base` - 寄存器的物理地址，来自ACPI的HPET表或`DEFAULT_BASE`
+///
+/// ## 用法
+/// ```rust
+/// hpet::init(PhysAddr::new(hpet::DEFAULT_BASE))?;
+/// ```
+pub fn init(base: PhysAddr) -> Result<(), HpetError> {
+    let virt = memory::with_paging(|paging| {
+        memory::map_physical_region(base, 1024, &mut paging.mapper, &mut paging.frame_allocator)
+    })
+    .unwrap_or(Err(MapError::NoPaging))
+    .map_err(HpetError::Map)?;
+
+    let caps = unsafe {
+        (virt.as_u64() as *const u64)
+            .add(REG_CAPABILITIES / 8)
+            .read_volatile()
+    };
+    let period = caps >> 32;
+    if period == 0 || period > MAX_PERIOD_FS {
+        return Err(HpetError::NotPresent);
+    }
+    if caps & CAP_COUNTER_64 == 0 {
+        return Err(HpetError::Counter32Bit);
+    }
+
+    BASE.store(virt.as_u64(), Ordering::Release);
+    let config = read(REG_CONFIG) & !(CONFIG_ENABLE | CONFIG_LEGACY_ROUTE);
+    write(REG_CONFIG, config);
+    write(REG_COUNTER, 0);
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        OFFSET_NS.store(crate::time::uptime_ms() * 1_000_000, Ordering::SeqCst);
+        PERIOD_FS.store(period, Ordering::SeqCst);
+        write(REG_CONFIG, config | CONFIG_ENABLE);
+    });
+    Ok(())
+}
+
+/// ## 函数说明
+/// HPET是否已经启用
+pub fn is_enabled() -> bool {
+    PERIOD_FS.load(Ordering::SeqCst) != 0
+}
+
+/// ## 函数说明
+/// 计数周期（飞秒），未启用时为0
+pub fn period_fs() -> u64 {
+    PERIOD_FS.load(Ordering::SeqCst)
+}
+
+/// ## 函数说明
+/// 启动以来经过的纳秒数，单调递增；HPET未启用时返回`None`
+///
+/// ## 用法
+/// ```rust
+/// let start = hpet::now_ns().unwrap();
+/// ```
+pub fn now_ns() -> Option<u64> {
+    match period_fs() {
+        0 => None,
+        period => Some(OFFSET_NS.load(Ordering::SeqCst) + ticks_to_ns(read(REG_COUNTER), period)),
+    }
+}
+
+/// ## 函数说明
+/// 通过轮询主计数器等待至少`ns`纳秒，可以在中断处理函数中使用
+/// HPET未启用时退回到`time::sleep_busy_us`
+///
+/// ## 参数
+/// * `ns` - 纳秒数
+pub fn busy_wait_ns(ns: u64) {
+    let start = match now_ns() {
+        Some(start) => start,
+        None => return crate::time::sleep_busy_us(ns.div_ceil(1000)),
+    };
+    while now_ns().unwrap_or(u64::MAX) - start < ns {
+        core::hint::spin_loop();
+    }
+}
+
+#[test_case]
+fn test_ticks_to_ns() {
+    //QEMU的HPET周期为10纳秒
+    assert_eq!(ticks_to_ns(1, 10_000_000), 10);
+    assert_eq!(ticks_to_ns(1_000, 10_000_000), 10_000);
+    //常见硬件的14.31818MHz时钟，周期约69.84纳秒
+    assert_eq!(ticks_to_ns(14_318_180, 69_841_279), 1_000_000_004);
+    assert_eq!(ticks_to_ns(3, 69_841_279), 209);
+    //计数很大时不溢出
+    assert_eq!(ticks_to_ns(u64::MAX / 2, 2_000_000), u64::MAX - 1);
+}
+
+#[test_case]
+fn test_now_ns_monotonic() {
+    let before = now_ns().expect("HPET not enabled");
+    x86_64::instructions::hlt();
+    let after = now_ns().unwrap();
+    assert!(after > before);
+    let start = now_ns().unwrap();
+    busy_wait_ns(50_000);
+    assert!(now_ns().unwrap() - start >= 50_000);
+}
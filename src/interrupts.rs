@@ -1,24 +1,410 @@
 use crate::{gdt, hlt_loop, print, println};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 use pic8259::ChainedPics; //映射主副PIC映射布局
 use spin;
+use x86_64::instructions::port::Port;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode}; //引入中断描述表
 
+mod machine_check;
+
+pub use machine_check::McStatus;
+
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
 pub static PICS: spin::Mutex<ChainedPics> =
     spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
+//每个向量已经触发的次数，由各处理函数在入口处递增
+static INTERRUPT_COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+
+//架构定义的0~31号异常的名称，保留的向量为None
+const EXCEPTION_NAMES: [Option<&str>; 32] = [
+    Some("Divide Error"),
+    Some("Debug"),
+    Some("Non-Maskable Interrupt"),
+    Some("Breakpoint"),
+    Some("Overflow"),
+    Some("Bound Range Exceeded"),
+    Some("Invalid Opcode"),
+    Some("Device Not Available"),
+    Some("Double Fault"),
+    Some("Coprocessor Segment Overrun"),
+    Some("Invalid TSS"),
+    Some("Segment Not Present"),
+    Some("Stack Segment Fault"),
+    Some("General Protection Fault"),
+    Some("Page Fault"),
+    None,
+    Some("x87 Floating Point"),
+    Some("Alignment Check"),
+    Some("Machine Check"),
+    Some("SIMD Floating Point"),
+    Some("Virtualization"),
+    Some("Control Protection"),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    Some("Hypervisor Injection"),
+    Some("VMM Communication"),
+    Some("Security Exception"),
+    None,
+];
+
+//中断嵌套深度保存在当前CPU的PerCpu中，`percpu::init`之前（例如引导早期的异常）使用这个全局计数
+static EARLY_NESTING: AtomicUsize = AtomicUsize::new(0);
+//中断嵌套深度的历史最大值
+static MAX_NESTING: AtomicUsize = AtomicUsize::new(0);
+//PIC中断重入的次数，以及是否已经打印过警告
+static REENTRANT_IRQS: AtomicU64 = AtomicU64::new(0);
+static REENTRANT_WARNED: AtomicBool = AtomicBool::new(false);
+
+//当前CPU的中断嵌套计数
+fn nesting() -> &'static AtomicUsize {
+    if crate::percpu::is_initialized() {
+        &crate::percpu::current().nesting
+    } else {
+        &EARLY_NESTING
+    }
+}
+
+//处理函数返回时减少进入时增加的计数；停机的处理函数不会返回，深度随之保持
+#[must_use]
+struct InterruptContext(&'static AtomicUsize);
+
+impl Drop for InterruptContext {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+//每个处理函数在入口处调用，记录触发次数并进入中断上下文，返回值需要保存到处理函数结束
+#[inline]
+fn count_interrupt(vector: u8) -> InterruptContext {
+    INTERRUPT_COUNTS[usize::from(vector)].fetch_add(1, Ordering::Relaxed);
+    let nesting = nesting();
+    let depth = nesting.fetch_add(1, Ordering::Relaxed) + 1;
+    MAX_NESTING.fetch_max(depth, Ordering::Relaxed);
+    //中断门会清除IF，PIC中断只有在某个处理函数重新开启中断时才会嵌套
+    if depth > 1 && (PIC_1_OFFSET..PIC_2_OFFSET + 8).contains(&vector) {
+        REENTRANT_IRQS.fetch_add(1, Ordering::Relaxed);
+        if !REENTRANT_WARNED.swap(true, Ordering::Relaxed) {
+            crate::try_println!(
+                "WARNING: IRQ {} entered at nesting depth {}, interrupts were enabled inside a handler",
+                vector - PIC_1_OFFSET,
+                depth
+            );
+        }
+    }
+    InterruptContext(nesting)
+}
+
+/// ## 函数说明
+/// 当前CPU的中断嵌套深度，正常代码中为0
+pub fn current_nesting() -> usize {
+    nesting().load(Ordering::Relaxed)
+}
+
+/// ## 函数说明
+/// 启动以来中断嵌套深度的最大值
+pub fn max_nesting() -> usize {
+    MAX_NESTING.load(Ordering::Relaxed)
+}
+
+/// ## 函数说明
+/// 嵌套在其他处理函数中的PIC中断次数，当前的设计下应当始终为0
+pub fn reentrant_irq_count() -> u64 {
+    REENTRANT_IRQS.load(Ordering::Relaxed)
+}
+
+/// ## 函数说明
+/// 当前是否在中断或异常处理函数中执行，用于检查不能在中断上下文中调用的函数
+///
+/// ## 用法
+/// ```rust
+/// debug_assert!(!interrupts::in_interrupt_context());
+/// ```
+pub fn in_interrupt_context() -> bool {
+    current_nesting() > 0
+}
+
+/// ## 函数说明
+/// 已知向量的名称：CPU异常、计时器、键盘和PIC伪中断向量，其余返回`None`
+///
+/// ## 参数
+/// * `vector` - 中断向量号
+pub fn vector_name(vector: u8) -> Option<&'static str> {
+    match vector {
+        0..=31 => EXCEPTION_NAMES[usize::from(vector)],
+        v if v == InterruptIndex::Timer as u8 => Some("Timer"),
+        v if v == InterruptIndex::Keyboard as u8 => Some("Keyboard"),
+        IRQ7_VECTOR => Some("IRQ7"),
+        IRQ15_VECTOR => Some("IRQ15"),
+        _ => None,
+    }
+}
+
+/// ## 函数说明
+/// 向量`vector`已经触发的次数
+///
+/// ## 参数
+/// * `vector` - 中断向量号
+pub fn interrupt_count(vector: u8) -> u64 {
+    INTERRUPT_COUNTS[usize::from(vector)].load(Ordering::Relaxed)
+}
+
+/// ## 函数说明
+/// 遍历每个向量及其触发次数，包括从未触发的向量
+///
+/// ## 用法
+/// ```rust
+/// let total: u64 = interrupts::stats().map(|(_, count)| count).sum();
+/// ```
+pub fn stats() -> impl Iterator<Item = (u8, u64)> {
+    INTERRUPT_COUNTS
+        .iter()
+        .enumerate()
+        .map(|(vector, count)| (vector as u8, count.load(Ordering::Relaxed)))
+}
+
+/// ## 函数说明
+/// 打印所有触发过的向量及其次数
+///
+/// ## 用法
+/// ```rust
+/// print_interrupt_stats();
+/// ```
+pub fn print_interrupt_stats() {
+    println!("interrupt statistics:");
+    for (vector, count) in stats().filter(|&(_, count)| count != 0) {
+        println!(
+            "  {:3} {:<28} {}",
+            vector,
+            vector_name(vector).unwrap_or("-"),
+            count
+        );
+    }
+}
+
 /*
     注册breakpoint异常处理函数
     在执行INT3指令时会出现断点异常。一些调试软件用INT3指令替换指令。当断点被捕获时，它会用原始指令替换INT3指令，并将指令指针递减一。
     保存的指令指针指向INT3指令之后的字节。
 */
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+    let _ctx = count_interrupt(3);
+    #[cfg(test)]
+    BREAKPOINT_NESTING.store(current_nesting(), Ordering::Relaxed);
     println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
 
+//断点处理函数看到的嵌套深度，供测试检查
+#[cfg(test)]
+static BREAKPOINT_NESTING: AtomicUsize = AtomicUsize::new(0);
+
+/*
+    生成只打印异常名称、错误码（如果有）和栈帧的异常处理函数
+      make_exception_handler!(name, vector, "NAME")             故障，打印后停机，测试时以失败退出QEMU
+      make_exception_handler!(name, vector, "NAME", error_code) 带错误码的故障
+      make_exception_handler!(name, vector, "NAME", abort)      不可恢复的中止类异常，使用`-> !`签名
+      make_exception_handler!(name, vector, "NAME", trap)       陷阱，打印后返回继续执行
+*/
+macro_rules! make_exception_handler {
+    ($name:ident, $vector:expr, $exception:expr) => {
+        extern "x86-interrupt" fn $name(stack_frame: InterruptStackFrame) {
+            let _ctx = count_interrupt($vector);
+            println!("EXCEPTION: {}", $exception);
+            println!("RIP: {:?}", stack_frame.instruction_pointer);
+            println!("{:#?}", stack_frame);
+            #[cfg(test)]
+            crate::exit_qemu(crate::QemuExitCode::Failed);
+            hlt_loop();
+        }
+    };
+    ($name:ident, $vector:expr, $exception:expr, error_code) => {
+        extern "x86-interrupt" fn $name(stack_frame: InterruptStackFrame, error_code: u64) {
+            let _ctx = count_interrupt($vector);
+            println!("EXCEPTION: {}", $exception);
+            println!("Error Code: {:#x}", error_code);
+            println!("RIP: {:?}", stack_frame.instruction_pointer);
+            println!("{:#?}", stack_frame);
+            #[cfg(test)]
+            crate::exit_qemu(crate::QemuExitCode::Failed);
+            hlt_loop();
+        }
+    };
+    ($name:ident, $vector:expr, $exception:expr, abort) => {
+        extern "x86-interrupt" fn $name(stack_frame: InterruptStackFrame) -> ! {
+            let _ctx = count_interrupt($vector);
+            println!("EXCEPTION: {}", $exception);
+            println!("{:#?}", stack_frame);
+            #[cfg(test)]
+            crate::exit_qemu(crate::QemuExitCode::Failed);
+            hlt_loop();
+        }
+    };
+    ($name:ident, $vector:expr, $exception:expr, trap) => {
+        extern "x86-interrupt" fn $name(stack_frame: InterruptStackFrame) {
+            let _ctx = count_interrupt($vector);
+            println!("EXCEPTION: {}\n{:#?}", $exception, stack_frame);
+        }
+    };
+}
+
+//算术异常：除以零或商溢出（#DE）、INTO检测到溢出（#OF）、BOUND越界（#BR）
+make_exception_handler!(divide_error_handler, 0, "DIVIDE ERROR");
+make_exception_handler!(overflow_handler, 4, "OVERFLOW");
+make_exception_handler!(bound_range_exceeded_handler, 5, "BOUND RANGE EXCEEDED");
+
+//其余没有专门处理函数的异常，保证任何异常都不会悄悄升级为double fault
+make_exception_handler!(debug_handler, 1, "DEBUG", trap);
+make_exception_handler!(invalid_tss_handler, 10, "INVALID TSS", error_code);
+make_exception_handler!(
+    segment_not_present_handler,
+    11,
+    "SEGMENT NOT PRESENT",
+    error_code
+);
+make_exception_handler!(
+    stack_segment_fault_handler,
+    12,
+    "STACK SEGMENT FAULT",
+    error_code
+);
+make_exception_handler!(x87_floating_point_handler, 16, "X87 FLOATING POINT");
+make_exception_handler!(virtualization_handler, 20, "VIRTUALIZATION");
+make_exception_handler!(cp_protection_handler, 21, "CONTROL PROTECTION", error_code);
+make_exception_handler!(hv_injection_handler, 28, "HYPERVISOR INJECTION");
+make_exception_handler!(
+    vmm_communication_handler,
+    29,
+    "VMM COMMUNICATION",
+    error_code
+);
+make_exception_handler!(
+    security_exception_handler,
+    30,
+    "SECURITY EXCEPTION",
+    error_code
+);
+
+static NMI_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// ## 函数说明
+/// 目前为止收到的不可屏蔽中断数量
+pub fn nmi_count() -> u64 {
+    NMI_COUNT.load(Ordering::Relaxed)
+}
+
+/*
+    注册NMI处理函数
+    NMI可能打断持有WRITER锁的代码，这里只能用try_println，锁被占用时放弃输出，计数仍然有效
+*/
+extern "x86-interrupt" fn nmi_handler(_stack_frame: InterruptStackFrame) {
+    let _ctx = count_interrupt(2);
+    let count = NMI_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+    crate::try_println!("NMI received (#{})", count);
+}
+
+/*
+    注册alignment check处理函数
+    CR0.AM和RFLAGS.AC都置位时，ring 3的非对齐访问触发。CPU不报告访问的数据地址，只能打印出错指令的RIP
+*/
+extern "x86-interrupt" fn alignment_check_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    let _ctx = count_interrupt(17);
+    println!("EXCEPTION: ALIGNMENT CHECK");
+    println!(
+        "unaligned access by instruction at {:?} (error code {:#x})",
+        stack_frame.instruction_pointer, error_code
+    );
+    println!("{:#?}", stack_frame);
+    #[cfg(test)]
+    crate::exit_qemu(crate::QemuExitCode::Failed);
+    hlt_loop();
+}
+
+/*
+    注册invalid opcode处理函数
+    执行未定义的指令（如ud2）时触发，打印RIP处的字节以便查看是哪条指令
+*/
+extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    let _ctx = count_interrupt(6);
+    //只读取RIP所在页面中的字节，下一页可能没有映射
+    let rip = stack_frame.instruction_pointer;
+    let len = (4096 - usize::from(u16::from(rip.page_offset()))).min(16);
+    println!("EXCEPTION: INVALID OPCODE at {:?}", rip);
+    print!("bytes:");
+    for i in 0..len {
+        let byte = unsafe { (rip + i as u64).as_ptr::<u8>().read_volatile() };
+        print!(" {:02x}", byte);
+    }
+    println!();
+    println!("{:#?}", stack_frame);
+    #[cfg(test)]
+    crate::exit_qemu(crate::QemuExitCode::Failed);
+    hlt_loop();
+}
+
+/*
+    注册SIMD floating point处理函数
+    SSE指令产生MXCSR中未屏蔽的异常时触发（需要CR4.OSXMMEXCPT），打印置位的异常标志后停机
+*/
+extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: InterruptStackFrame) {
+    let _ctx = count_interrupt(19);
+    use x86_64::registers::mxcsr::{self, MxCsr};
+
+    let mxcsr: MxCsr = mxcsr::read();
+    println!("EXCEPTION: SIMD FLOATING POINT");
+    println!(
+        "MXCSR: {:#x} ({})",
+        mxcsr.bits(),
+        crate::cpu::SimdExceptions(mxcsr)
+    );
+    println!("{:#?}", stack_frame);
+    #[cfg(test)]
+    crate::exit_qemu(crate::QemuExitCode::Failed);
+    hlt_loop();
+}
+
+/*
+    注册device not available处理函数
+    CR0.TS或CR0.EM置位时执行FPU/SIMD指令触发，之后可以在这里实现FPU状态的延迟保存
+*/
+extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
+    let _ctx = count_interrupt(7);
+    use x86_64::registers::control::{Cr0, Cr0Flags};
+
+    let cr0 = Cr0::read();
+    println!("EXCEPTION: DEVICE NOT AVAILABLE");
+    println!(
+        "FPU/SIMD instruction executed with CR0.TS={} CR0.EM={}",
+        cr0.contains(Cr0Flags::TASK_SWITCHED) as u8,
+        cr0.contains(Cr0Flags::EMULATE_COPROCESSOR) as u8
+    );
+    println!("{:#?}", stack_frame);
+    #[cfg(test)]
+    crate::exit_qemu(crate::QemuExitCode::Failed);
+    hlt_loop();
+}
+
+//调用`set_double_fault_hook`或`set_stack_overflow_hook`设置的钩子，没有设置时返回
+fn run_fatal_hook(hook: &AtomicPtr<()>, stack_frame: &InterruptStackFrame) {
+    let hook = hook.load(Ordering::Acquire);
+    if !hook.is_null() {
+        //非空指针只可能由钩子函数转换而来
+        let hook = unsafe { core::mem::transmute::<*mut (), DoubleFaultHook>(hook) };
+        hook(stack_frame);
+    }
+}
+
 /*
     注册double fault处理函数
     当错误发生时，CPU会尝试调用错误处理函数，但如果 在调用错误处理函数过程中 再次发生错误，CPU就会触发该错误。
@@ -26,17 +412,159 @@ extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
 */
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
-    _error_fault_handler: u64,
+    error_code: u64,
 ) -> ! {
+    let _ctx = count_interrupt(8);
+    use x86_64::registers::control::Cr2;
+
+    //先写串口，无显示器运行时也能看到原因；出错时可能有锁被持有，只能用强制解锁的输出
+    let guard = crate::memory::guard::find_guard(Cr2::read());
+    crate::serial_emergency_println!("EXCEPTION: DOUBLE FAULT (error code {:#x})", error_code);
+    if let Some(name) = guard {
+        crate::serial_emergency_println!("kernel stack overflow on stack {}", name);
+    }
+    crate::serial_emergency_println!("CR2: {:?}\n{:#?}", Cr2::read(), stack_frame);
+
+    run_fatal_hook(&DOUBLE_FAULT_HOOK, &stack_frame);
+
+    //页错误的IST栈也不可用时才会走到这里，CR2仍指向保护页
+    if let Some(name) = guard {
+        panic!(
+            "EXCEPTION: DOUBLE FAULT\nkernel stack overflow on stack {}\n{:#?}",
+            name, stack_frame
+        );
+    }
     panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
 }
 
+/// double fault钩子的类型，钩子不能返回
+pub type DoubleFaultHook = fn(&InterruptStackFrame) -> !;
+
+//double fault时调用的钩子，空指针表示没有设置；发生错误时才读取，可以在`init_idt`之前设置
+static DOUBLE_FAULT_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// ## 函数说明
+/// 设置double fault处理函数在输出诊断信息之后调用的钩子，替换之前的钩子
+/// 测试可以用它退出QEMU，而不必自己构造IDT
+///
+/// ## 参数
+/// * `hook` - 钩子，不能返回
+///
+/// ## 用法
+/// ```rust
+/// fn exit_on_double_fault(_frame: &InterruptStackFrame) -> ! {
+///     exit_qemu(QemuExitCode::Success);
+///     loop {}
+/// }
+/// interrupts::set_double_fault_hook(exit_on_double_fault);
+/// ```
+pub fn set_double_fault_hook(hook: DoubleFaultHook) {
+    DOUBLE_FAULT_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+//页错误处理函数发现栈溢出时调用的钩子，与double fault钩子分开，测试可以区分两条路径
+static STACK_OVERFLOW_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// ## 函数说明
+/// 设置页错误处理函数在报告栈溢出（CR2落在保护页内）之后调用的钩子，替换之前的钩子
+/// 只有页错误无法在自己的IST栈上处理、升级为double fault时才会调用double fault钩子
+///
+/// ## 参数
+/// * `hook` - 钩子，不能返回
+///
+/// ## 用法
+/// ```rust
+/// interrupts::set_stack_overflow_hook(exit_on_stack_overflow);
+/// ```
+pub fn set_stack_overflow_hook(hook: DoubleFaultHook) {
+    STACK_OVERFLOW_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
 //中断测试
 #[test_case]
 fn test_breakpoint_exception() {
     x86_64::instructions::interrupts::int3();
 }
 
+#[test_case]
+fn test_breakpoint_counted() {
+    let count = || stats().nth(3).map(|(_, count)| count).unwrap();
+    let before = count();
+    for _ in 0..3 {
+        x86_64::instructions::interrupts::int3();
+    }
+    assert_eq!(count(), before + 3);
+    assert_eq!(vector_name(3), Some("Breakpoint"));
+    assert_eq!(vector_name(InterruptIndex::Timer as u8), Some("Timer"));
+}
+
+#[test_case]
+fn test_page_fault_uses_own_stack() {
+    let entry = &IDT.page_fault as *const _ as *const u16;
+    let options = unsafe { entry.add(2).read() };
+    assert_eq!(options & 0x7, gdt::PAGE_FAULT_IST_INDEX + 1);
+    assert_ne!(gdt::PAGE_FAULT_IST_INDEX, gdt::DOUBLE_FAULT_IST_INDEX);
+}
+
+#[test_case]
+fn test_interrupt_context() {
+    assert!(!in_interrupt_context());
+    assert_eq!(current_nesting(), 0);
+    x86_64::instructions::interrupts::int3();
+    assert_eq!(BREAKPOINT_NESTING.load(Ordering::Relaxed), 1);
+    assert!(!in_interrupt_context());
+    assert!(max_nesting() >= 1);
+    assert_eq!(reentrant_irq_count(), 0);
+}
+
+//int 1与调试陷阱使用同一个处理函数，返回后继续执行
+#[test_case]
+fn test_debug_exception() {
+    unsafe { core::arch::asm!("int 1") };
+}
+
+//x86_64没有提供读取IDT表项选项的方法，按表项布局直接读取：第2个u16是选项，低3位为IST序号加1
+#[test_case]
+fn test_nmi_uses_own_stack() {
+    let entry = &IDT.non_maskable_interrupt as *const _ as *const u16;
+    let options = unsafe { entry.add(2).read() };
+    assert_eq!(options & 0x7, gdt::NMI_IST_INDEX + 1);
+    assert_eq!(nmi_count(), 0);
+}
+
+#[test_case]
+fn test_all_exceptions_have_handlers() {
+    let idt = &*IDT;
+    let handlers = [
+        ("divide_error", idt.divide_error.handler_addr()),
+        ("debug", idt.debug.handler_addr()),
+        ("nmi", idt.non_maskable_interrupt.handler_addr()),
+        ("breakpoint", idt.breakpoint.handler_addr()),
+        ("overflow", idt.overflow.handler_addr()),
+        ("bound_range", idt.bound_range_exceeded.handler_addr()),
+        ("invalid_opcode", idt.invalid_opcode.handler_addr()),
+        ("device_na", idt.device_not_available.handler_addr()),
+        ("double_fault", idt.double_fault.handler_addr()),
+        ("invalid_tss", idt.invalid_tss.handler_addr()),
+        ("segment_np", idt.segment_not_present.handler_addr()),
+        ("stack_segment", idt.stack_segment_fault.handler_addr()),
+        ("gpf", idt.general_protection_fault.handler_addr()),
+        ("page_fault", idt.page_fault.handler_addr()),
+        ("x87", idt.x87_floating_point.handler_addr()),
+        ("alignment", idt.alignment_check.handler_addr()),
+        ("machine_check", idt.machine_check.handler_addr()),
+        ("simd", idt.simd_floating_point.handler_addr()),
+        ("virtualization", idt.virtualization.handler_addr()),
+        ("cp", idt.cp_protection_exception.handler_addr()),
+        ("hv_injection", idt.hv_injection_exception.handler_addr()),
+        ("vmm", idt.vmm_communication_exception.handler_addr()),
+        ("security", idt.security_exception.handler_addr()),
+    ];
+    for (name, addr) in handlers {
+        assert_ne!(addr.as_u64(), 0, "no handler for {}", name);
+    }
+}
+
 //C风格枚举
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
@@ -46,68 +574,580 @@ pub enum InterruptIndex {
 }
 
 impl InterruptIndex {
-    fn as_u8(self) -> u8 {
+    pub fn as_u8(self) -> u8 {
         self as u8
     }
 
-    fn as_usize(self) -> usize {
+    pub fn as_usize(self) -> usize {
         usize::from(self.as_u8())
     }
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    print!(".");
-    //PIC还在等待处理函数返回中断结束信号否则始终认为一直在处理第一个计时器中断
-    unsafe {
-        //判读中断信号发送源头
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
+//EOI时等待PICS锁的最大尝试次数
+const EOI_LOCK_SPINS: usize = 1000;
+
+//PICS锁被占用、直接写端口发送EOI的次数
+static EOI_FALLBACKS: AtomicU64 = AtomicU64::new(0);
+
+/// ## 说明
+/// 在中断处理函数开头创建，离开作用域时向PIC发送中断结束信号
+/// 处理函数中途返回时也会发送EOI，对应的IRQ线不会因为遗漏EOI而再也收不到中断
+///
+/// ## 用法
+/// ```rust
+/// let _eoi = EoiGuard::new(InterruptIndex::Keyboard);
+/// ```
+pub struct EoiGuard {
+    vector: u8,
+}
+
+impl EoiGuard {
+    /// ## 函数说明
+    /// 为`index`对应的中断创建EOI守卫
+    pub fn new(index: InterruptIndex) -> Self {
+        EoiGuard {
+            vector: index.as_u8(),
+        }
+    }
+
+    /// ## 函数说明
+    /// 为PIC管脚创建EOI守卫
+    ///
+    /// ## 参数
+    /// * `irq` - PIC管脚号，0~15
+    pub fn for_irq(irq: u8) -> Self {
+        assert!(irq < 16, "IRQ {} out of range", irq);
+        EoiGuard {
+            vector: PIC_1_OFFSET + irq,
+        }
     }
 }
 
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
-    use spin::Mutex;
-    use x86_64::instructions::port::Port;
+impl Drop for EoiGuard {
+    fn drop(&mut self) {
+        //启用本地APIC后PIC被屏蔽，中断由IOAPIC投递，EOI发给本地APIC
+        #[cfg(feature = "apic")]
+        if crate::apic::is_enabled() {
+            crate::apic::eoi();
+            return;
+        }
+        for _ in 0..EOI_LOCK_SPINS {
+            if let Some(mut pics) = PICS.try_lock() {
+                unsafe { pics.notify_end_of_interrupt(self.vector) };
+                return;
+            }
+            core::hint::spin_loop();
+        }
+        //持有锁的代码可能正在被这个中断打断，继续等待会在中断上下文中死锁
+        //EOI只写命令端口，即使与锁内的IMR读写交错也只是顺序上的竞争
+        EOI_FALLBACKS.fetch_add(1, Ordering::Relaxed);
+        for &port in eoi_ports(self.vector - PIC_1_OFFSET) {
+            unsafe { Port::<u8>::new(port).write(PIC_EOI) };
+        }
+    }
+}
 
-    lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore)
-        );
+//发送EOI需要写入的命令端口：副PIC上的管脚需要先通知副PIC，再通知主PIC
+fn eoi_ports(irq: u8) -> &'static [u16] {
+    if irq >= 8 {
+        &[PIC_2_COMMAND, PIC_1_COMMAND]
+    } else {
+        &[PIC_1_COMMAND]
     }
+}
 
-    let mut keyboard = KEYBOARD.lock();
-    let mut port = Port::new(0x60);
+/// ## 函数说明
+/// 因PICS锁被占用而直接写端口发送EOI的次数
+pub fn eoi_fallback_count() -> u64 {
+    EOI_FALLBACKS.load(Ordering::Relaxed)
+}
+
+//计时器中断：推进系统时钟
+fn timer_irq(_irq: u8) {
+    crate::time::tick();
+}
 
+//键盘中断：只读取扫描码放入队列，解码和输出由`keyboard::poll_event`的调用者完成
+fn keyboard_irq(_irq: u8) {
+    let mut port = Port::new(0x60);
     let scancode: u8 = unsafe { port.read() };
-    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-        if let Some(key) = keyboard.process_keyevent(key_event) {
-            match key {
-                DecodedKey::Unicode(character) => print!("{}", character),
-                DecodedKey::RawKey(key) => print!("{:?}", key),
-            }
+    crate::keyboard::push_scancode(scancode);
+}
+
+/// ## 说明
+/// 该IRQ已经注册了处理函数
+///
+/// ## 成员
+/// * `irq` - PIC管脚号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlreadyRegistered {
+    pub irq: u8,
+}
+
+//16条PIC管脚的处理函数，空指针表示未注册；用原子指针而不是锁，中断处理函数读取时不会死锁
+static IRQ_HANDLERS: [AtomicPtr<()>; 16] = [const { AtomicPtr::new(ptr::null_mut()) }; 16];
+//没有注册处理函数的IRQ触发次数
+static UNEXPECTED_IRQS: AtomicU64 = AtomicU64::new(0);
+
+/// ## 函数说明
+/// 为PIC管脚注册处理函数。处理函数在中断上下文中运行，不能阻塞，返回后由分发代码发送EOI
+///
+/// ## 参数
+/// * `irq` - PIC管脚号，0~15
+/// * `handler` - 处理函数，参数为触发的管脚号
+///
+/// ## 用法
+/// ```rust
+/// fn serial_irq(_irq: u8) {
+///     //...
+/// }
+/// interrupts::register_irq_handler(4, serial_irq).expect("IRQ4 already taken");
+/// ```
+pub fn register_irq_handler(irq: u8, handler: fn(u8)) -> Result<(), AlreadyRegistered> {
+    assert!(irq < 16, "IRQ {} out of range", irq);
+    IRQ_HANDLERS[usize::from(irq)]
+        .compare_exchange(
+            ptr::null_mut(),
+            handler as *mut (),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .map(|_| ())
+        .map_err(|_| AlreadyRegistered { irq })
+}
+
+/// ## 函数说明
+/// 注销PIC管脚的处理函数，之后该管脚上的中断只会被记录为意外中断
+///
+/// ## 参数
+/// * `irq` - PIC管脚号，0~15
+pub fn unregister_irq_handler(irq: u8) {
+    assert!(irq < 16, "IRQ {} out of range", irq);
+    IRQ_HANDLERS[usize::from(irq)].store(ptr::null_mut(), Ordering::Release);
+}
+
+//副PIC级联到主PIC的管脚
+const CASCADE_IRQ: u8 = 2;
+
+//在PICS锁下读改写两片PIC的IMR（数据端口0x21/0xa1），置位表示屏蔽
+//关中断后再加锁，否则中断处理函数发送EOI时会在同一把锁上死锁
+fn update_irq_mask(update: impl FnOnce(u16) -> u16) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut pics = PICS.lock();
+        unsafe {
+            let [master, slave] = pics.read_masks();
+            let mask = update(u16::from_le_bytes([master, slave]));
+            let [master, slave] = mask.to_le_bytes();
+            pics.write_masks(master, slave);
         }
+    });
+}
+
+/// ## 函数说明
+/// 取消屏蔽PIC管脚，副PIC上的管脚（8~15）同时取消屏蔽级联管脚IRQ2
+/// 启用IOAPIC后改为取消屏蔽IRQ对应的GSI
+///
+/// ## 参数
+/// * `irq` - PIC管脚号，0~15
+///
+/// ## 用法
+/// ```rust
+/// interrupts::register_irq_handler(12, mouse_irq)?;
+/// interrupts::enable_irq(12);
+/// ```
+pub fn enable_irq(irq: u8) {
+    assert!(irq < 16, "IRQ {} out of range", irq);
+    #[cfg(feature = "apic")]
+    if crate::ioapic::is_enabled() {
+        crate::ioapic::unmask(crate::ioapic::isa_irq_to_gsi(irq));
+        return;
     }
+    update_irq_mask(|mask| {
+        let mut mask = mask & !(1 << irq);
+        if irq >= 8 {
+            mask &= !(1 << CASCADE_IRQ);
+        }
+        mask
+    });
+}
 
+/// ## 函数说明
+/// 屏蔽PIC管脚。只修改IMR，正在处理的中断不受影响，处理函数返回后照常发送EOI
+/// 级联管脚保持原样，副PIC上的其他管脚不会因此被屏蔽；启用IOAPIC后改为屏蔽IRQ对应的GSI
+///
+/// ## 参数
+/// * `irq` - PIC管脚号，0~15
+pub fn disable_irq(irq: u8) {
+    assert!(irq < 16, "IRQ {} out of range", irq);
+    #[cfg(feature = "apic")]
+    if crate::ioapic::is_enabled() {
+        crate::ioapic::mask(crate::ioapic::isa_irq_to_gsi(irq));
+        return;
+    }
+    update_irq_mask(|mask| mask | (1 << irq));
+}
+
+/// ## 函数说明
+/// 当前的PIC屏蔽字，低8位为主PIC，高8位为副PIC，置位表示屏蔽
+/// 启用IOAPIC后PIC被完全屏蔽，屏蔽状态需要通过`ioapic::is_masked`查询
+pub fn irq_mask() -> u16 {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let [master, slave] = unsafe { PICS.lock().read_masks() };
+        u16::from_le_bytes([master, slave])
+    })
+}
+
+fn irq_handler(irq: u8) -> Option<fn(u8)> {
+    let handler = IRQ_HANDLERS[usize::from(irq)].load(Ordering::Acquire);
+    //非空指针只可能由`register_irq_handler`从`fn(u8)`转换而来
+    (!handler.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), fn(u8)>(handler) })
+}
+
+//调用注册的处理函数，然后发送EOI
+fn dispatch_irq(irq: u8) {
+    //PIC还在等待处理函数返回中断结束信号否则始终认为一直在处理同一个中断
+    let _eoi = EoiGuard::for_irq(irq);
+    match irq_handler(irq) {
+        Some(handler) => handler(irq),
+        None => {
+            UNEXPECTED_IRQS.fetch_add(1, Ordering::Relaxed);
+            println!("unexpected IRQ {}", irq);
+        }
+    }
+}
+
+//为每条PIC管脚生成只负责计数和分发的中断处理函数
+macro_rules! irq_trampoline {
+    ($name:ident, $irq:expr) => {
+        extern "x86-interrupt" fn $name(_stack_frame: InterruptStackFrame) {
+            let _ctx = count_interrupt(PIC_1_OFFSET + $irq);
+            dispatch_irq($irq);
+        }
+    };
+}
+
+irq_trampoline!(irq0_trampoline, 0);
+irq_trampoline!(irq1_trampoline, 1);
+irq_trampoline!(irq2_trampoline, 2);
+irq_trampoline!(irq3_trampoline, 3);
+irq_trampoline!(irq4_trampoline, 4);
+irq_trampoline!(irq5_trampoline, 5);
+irq_trampoline!(irq6_trampoline, 6);
+irq_trampoline!(irq8_trampoline, 8);
+irq_trampoline!(irq9_trampoline, 9);
+irq_trampoline!(irq10_trampoline, 10);
+irq_trampoline!(irq11_trampoline, 11);
+irq_trampoline!(irq12_trampoline, 12);
+irq_trampoline!(irq13_trampoline, 13);
+irq_trampoline!(irq14_trampoline, 14);
+
+//IRQ7和IRQ15同时是两片PIC各自的伪中断向量
+const IRQ7_VECTOR: u8 = PIC_1_OFFSET + 7;
+const IRQ15_VECTOR: u8 = PIC_2_OFFSET + 7;
+//主副PIC的命令端口
+const PIC_1_COMMAND: u16 = 0x20;
+const PIC_2_COMMAND: u16 = 0xa0;
+//OCW3：下一次读命令端口返回ISR寄存器
+const OCW3_READ_ISR: u8 = 0x0b;
+const PIC_EOI: u8 = 0x20;
+
+static SPURIOUS_COUNT: AtomicU64 = AtomicU64::new(0);
+
+//读取PIC的ISR寄存器，每一位表示对应管脚的中断正在被处理
+fn read_pic_isr(command_port: u16) -> u8 {
+    let mut port = Port::<u8>::new(command_port);
     unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
+        port.write(OCW3_READ_ISR);
+        port.read()
+    }
+}
+
+/// ## 函数说明
+/// 根据ISR寄存器判断7号管脚上的中断是否为伪中断：真正的中断在ISR中置位，伪中断则没有
+///
+/// ## 参数
+/// * `isr` - 通过OCW3读到的ISR寄存器
+pub fn is_spurious(isr: u8) -> bool {
+    isr & (1 << 7) == 0
+}
+
+/// ## 函数说明
+/// 目前为止收到的PIC伪中断数量（IRQ7和IRQ15合计）
+pub fn spurious_count() -> u64 {
+    SPURIOUS_COUNT.load(Ordering::Relaxed)
+}
+
+extern "x86-interrupt" fn spurious_irq7_handler(_stack_frame: InterruptStackFrame) {
+    let _ctx = count_interrupt(IRQ7_VECTOR);
+    if is_spurious(read_pic_isr(PIC_1_COMMAND)) {
+        //伪中断不能发送EOI，否则会结束掉主PIC上另一个正在处理的中断
+        SPURIOUS_COUNT.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    dispatch_irq(7);
+}
+
+extern "x86-interrupt" fn spurious_irq15_handler(_stack_frame: InterruptStackFrame) {
+    let _ctx = count_interrupt(IRQ15_VECTOR);
+    if is_spurious(read_pic_isr(PIC_2_COMMAND)) {
+        //副PIC的伪中断经过主PIC的2号管脚，主PIC仍在等待EOI，副PIC则不能收到EOI
+        SPURIOUS_COUNT.fetch_add(1, Ordering::Relaxed);
+        unsafe {
+            Port::<u8>::new(PIC_1_COMMAND).write(PIC_EOI);
+        }
+        return;
     }
+    dispatch_irq(15);
+}
+
+//启用APIC时IRQ由IOAPIC投递，EOI和屏蔽不经过PIC
+#[cfg(not(feature = "apic"))]
+#[test_case]
+fn test_eoi_fallback() {
+    assert_eq!(eoi_ports(1), &[PIC_1_COMMAND]);
+    assert_eq!(eoi_ports(12), &[PIC_2_COMMAND, PIC_1_COMMAND]);
+
+    //没有中断正在处理时多余的EOI没有影响
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let before = eoi_fallback_count();
+        drop(EoiGuard::for_irq(3));
+        assert_eq!(eoi_fallback_count(), before);
+
+        let pics = PICS.lock();
+        drop(EoiGuard::new(InterruptIndex::Keyboard));
+        drop(pics);
+        assert_eq!(eoi_fallback_count(), before + 1);
+    });
+}
+
+//启用APIC时IRQ由IOAPIC投递，EOI和屏蔽不经过PIC
+#[cfg(not(feature = "apic"))]
+#[test_case]
+fn test_irq_mask() {
+    //IRQ3和IRQ10在QEMU中没有设备使用，修改屏蔽不会引发中断
+    let saved = irq_mask();
+
+    disable_irq(3);
+    assert_ne!(irq_mask() & (1 << 3), 0);
+    enable_irq(3);
+    assert_eq!(irq_mask() & (1 << 3), 0);
+
+    //启用副PIC的管脚时同时取消屏蔽级联管脚，屏蔽时级联管脚不变
+    disable_irq(10);
+    disable_irq(CASCADE_IRQ);
+    enable_irq(10);
+    assert_eq!(irq_mask() & (1 << 10 | 1 << CASCADE_IRQ), 0);
+    disable_irq(10);
+    assert_ne!(irq_mask() & (1 << 10), 0);
+    assert_eq!(irq_mask() & (1 << CASCADE_IRQ), 0);
+
+    //恢复原来的屏蔽字
+    update_irq_mask(|_| saved);
+    assert_eq!(irq_mask(), saved);
+}
+
+/*
+    注册本地APIC伪中断处理函数
+    中断在提升任务优先级时被撤回会投递伪中断向量，ISR中没有对应的位，因此不能发送EOI
+*/
+#[cfg(feature = "apic")]
+extern "x86-interrupt" fn apic_spurious_handler(_stack_frame: InterruptStackFrame) {
+    let _ctx = count_interrupt(crate::apic::SPURIOUS_VECTOR);
+}
+
+#[test_case]
+fn test_spurious_irq_handlers() {
+    assert_ne!(IDT[usize::from(IRQ7_VECTOR)].handler_addr().as_u64(), 0);
+    assert_ne!(IDT[usize::from(IRQ15_VECTOR)].handler_addr().as_u64(), 0);
+    assert_eq!(spurious_count(), 0);
+
+    //只有第7位决定7号管脚上的中断是否真实
+    assert!(is_spurious(0x00));
+    assert!(is_spurious(0x7f));
+    assert!(!is_spurious(0x80));
+    assert!(!is_spurious(0x81));
+}
+
+#[cfg(test)]
+static TEST_IRQ_HITS: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(test)]
+fn counting_irq(irq: u8) {
+    assert_eq!(irq, 5);
+    TEST_IRQ_HITS.fetch_add(1, Ordering::Relaxed);
+}
+
+//IRQ5没有设备，用int指令直接进入它的处理函数；PIC中没有正在处理的中断，多余的EOI不产生影响
+#[test_case]
+fn test_registered_irq_dispatch() {
+    assert_eq!(
+        register_irq_handler(0, counting_irq),
+        Err(AlreadyRegistered { irq: 0 })
+    );
+    register_irq_handler(5, counting_irq).unwrap();
+    assert_eq!(
+        register_irq_handler(5, counting_irq),
+        Err(AlreadyRegistered { irq: 5 })
+    );
+    unsafe { core::arch::asm!("int 37") };
+    assert_eq!(TEST_IRQ_HITS.load(Ordering::Relaxed), 1);
+
+    unregister_irq_handler(5);
+    let unexpected = UNEXPECTED_IRQS.load(Ordering::Relaxed);
+    unsafe { core::arch::asm!("int 37") };
+    assert_eq!(TEST_IRQ_HITS.load(Ordering::Relaxed), 1);
+    assert_eq!(UNEXPECTED_IRQS.load(Ordering::Relaxed), unexpected + 1);
 }
 
 extern "x86-interrupt" fn page_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: PageFaultErrorCode,
 ) {
+    let _ctx = count_interrupt(14);
     use x86_64::registers::control::Cr2;
 
+    //写时复制页面上的写错误在复制后返回，重新执行写指令
+    let cow_fault = PageFaultErrorCode::CAUSED_BY_WRITE | PageFaultErrorCode::PROTECTION_VIOLATION;
+    if error_code.contains(cow_fault) && crate::memory::cow::handle_write_fault(Cr2::read()) {
+        return;
+    }
+    //按需分配区域中的缺页在映射新帧后返回
+    if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+        && crate::memory::lazy::handle_fault(Cr2::read())
+    {
+        return;
+    }
+
+    let addr = Cr2::read();
+    //处理函数运行在独立的IST栈上，栈溢出时也能报告；溢出的代码可能持有WRITER锁，只写串口
+    //之后调用栈溢出钩子，然后panic
+    if let crate::memory::FaultRegion::StackGuard(name) = crate::memory::classify_fault(addr) {
+        crate::serial_emergency_println!("EXCEPTION: PAGE FAULT");
+        crate::serial_emergency_println!("{}", crate::memory::FaultSummary { addr, error_code });
+        crate::serial_emergency_println!("kernel stack overflow on stack {}", name);
+        crate::serial_emergency_println!("{:#?}", stack_frame);
+        run_fatal_hook(&STACK_OVERFLOW_HOOK, &stack_frame);
+        panic!(
+            "EXCEPTION: PAGE FAULT\nkernel stack overflow on stack {}\n{:#?}",
+            name, stack_frame
+        );
+    }
     println!("EXCEPTION: PAGE FAULT");
-    println!("Access Address: {:?}", Cr2::read());
+    println!("{}", crate::memory::FaultSummary { addr, error_code });
+    println!("region: {}", crate::memory::classify_fault(addr));
+    if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        if error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+            println!("W^X violation: instruction fetch from non-executable page");
+        } else if error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE)
+            && crate::memory::wx::is_kernel_read_only(addr)
+        {
+            println!("W^X violation: write to read-only kernel image");
+        }
+    }
+    println!("Access Address: {:?}", addr);
     println!("Error Code: {:?}", error_code);
+    crate::memory::print_fault_entries(addr);
     println!("{:#?}", stack_frame);
     hlt_loop();
 }
 
+/// ## 说明
+/// 描述符表的类型，来自选择子错误码的TBL位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorTable {
+    Gdt,
+    Idt,
+    Ldt,
+}
+
+/// ## 说明
+/// 与段选择子相关的异常（#GP、#NP、#SS、#TS）的错误码，按SDM解码
+/// 位0为外部事件标志，位1-2为描述符表，位3-15为选择子索引；错误码为0表示异常与段无关
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectorErrorCode(pub u64);
+
+impl SelectorErrorCode {
+    /// ## 函数说明
+    /// 异常是否由外部事件（硬件中断等）引起
+    pub fn external(&self) -> bool {
+        self.0 & 1 != 0
+    }
+
+    /// ## 函数说明
+    /// 选择子所在的描述符表
+    pub fn table(&self) -> DescriptorTable {
+        match (self.0 >> 1) & 0b11 {
+            0b00 => DescriptorTable::Gdt,
+            0b10 => DescriptorTable::Ldt,
+            _ => DescriptorTable::Idt,
+        }
+    }
+
+    /// ## 函数说明
+    /// 选择子在描述符表中的索引
+    pub fn index(&self) -> u16 {
+        ((self.0 >> 3) & 0x1fff) as u16
+    }
+
+    /// ## 函数说明
+    /// 异常是否与段选择子有关，为`false`时通常是特权指令、非规范地址等原因
+    pub fn is_segment_related(&self) -> bool {
+        self.0 != 0
+    }
+}
+
+impl core::fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "{:#x} (external: {}, table: {:?}, index: {})",
+            self.0,
+            self.external(),
+            self.table(),
+            self.index()
+        )
+    }
+}
+
+/*
+    注册general protection fault处理函数
+    以前GPF没有处理函数，会升级为double fault，报告的位置与真正的原因无关
+*/
+extern "x86-interrupt" fn general_protection_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    let _ctx = count_interrupt(13);
+    let code = SelectorErrorCode(error_code);
+    println!("EXCEPTION: GENERAL PROTECTION FAULT");
+    if code.is_segment_related() {
+        println!("segment selector: {}", code);
+    } else {
+        println!("not segment related: privileged instruction, non-canonical address or similar");
+    }
+    println!("RIP: {:?}", stack_frame.instruction_pointer);
+    println!("{:#?}", stack_frame);
+    #[cfg(test)]
+    crate::exit_qemu(crate::QemuExitCode::Failed);
+    hlt_loop();
+}
+
+#[test_case]
+fn test_selector_error_code_decoding() {
+    let code = SelectorErrorCode(0xfff8);
+    assert!(!code.external());
+    assert_eq!(code.table(), DescriptorTable::Gdt);
+    assert_eq!(code.index(), 0x1fff);
+
+    //IDT中第13项，由外部事件引起
+    let code = SelectorErrorCode((13 << 3) | 0b011);
+    assert!(code.external());
+    assert_eq!(code.table(), DescriptorTable::Idt);
+    assert_eq!(code.index(), 13);
+    assert_eq!(SelectorErrorCode(0b100).table(), DescriptorTable::Ldt);
+    assert!(!SelectorErrorCode(0).is_segment_related());
+}
+
 /*
     由于是操作系统不存在堆区概念，所以不能用Box申请内存转化为'static指针
     我们直接将其定义为'static变量，但很容易形成数据竞争，需要unsafe
@@ -117,18 +1157,71 @@ lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
         idt.breakpoint.set_handler_fn(breakpoint_handler);
+        idt.divide_error.set_handler_fn(divide_error_handler);
+        idt.overflow.set_handler_fn(overflow_handler);
+        idt.bound_range_exceeded.set_handler_fn(bound_range_exceeded_handler);
+        idt.debug.set_handler_fn(debug_handler);
+        idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+        idt.segment_not_present.set_handler_fn(segment_not_present_handler);
+        idt.stack_segment_fault.set_handler_fn(stack_segment_fault_handler);
+        idt.x87_floating_point.set_handler_fn(x87_floating_point_handler);
+        idt.alignment_check.set_handler_fn(alignment_check_handler);
+        idt.simd_floating_point.set_handler_fn(simd_floating_point_handler);
+        idt.virtualization.set_handler_fn(virtualization_handler);
+        idt.cp_protection_exception.set_handler_fn(cp_protection_handler);
+        idt.hv_injection_exception.set_handler_fn(hv_injection_handler);
+        idt.vmm_communication_exception.set_handler_fn(vmm_communication_handler);
+        idt.security_exception.set_handler_fn(security_exception_handler);
+        idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+        idt.device_not_available.set_handler_fn(device_not_available_handler);
 
         unsafe{
             idt.double_fault.set_handler_fn(double_fault_handler)  //捕获double fault异常
                 .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+            idt.non_maskable_interrupt.set_handler_fn(nmi_handler)
+                .set_stack_index(gdt::NMI_IST_INDEX);
+            idt.machine_check.set_handler_fn(machine_check::machine_check_handler)
+                .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+        }
+        //PIC的16条管脚都进入分发表，设备驱动通过`register_irq_handler`注册处理函数
+        let trampolines = [
+            irq0_trampoline,
+            irq1_trampoline,
+            irq2_trampoline,
+            irq3_trampoline,
+            irq4_trampoline,
+            irq5_trampoline,
+            irq6_trampoline,
+            spurious_irq7_handler,
+            irq8_trampoline,
+            irq9_trampoline,
+            irq10_trampoline,
+            irq11_trampoline,
+            irq12_trampoline,
+            irq13_trampoline,
+            irq14_trampoline,
+            spurious_irq15_handler,
+        ];
+        for (irq, trampoline) in trampolines.into_iter().enumerate() {
+            idt[usize::from(PIC_1_OFFSET) + irq].set_handler_fn(trampoline);
+        }
+
+        //系统调用入口是手写的汇编，DPL设为3，以后用户态代码也可以通过int 0x80进入
+        unsafe {
+            idt[usize::from(crate::syscall::SYSCALL_VECTOR)]
+                .set_handler_addr(crate::syscall::entry_addr())
+                .set_privilege_level(x86_64::PrivilegeLevel::Ring3);
         }
-        idt[InterruptIndex::Timer.as_usize()]
-        .set_handler_fn(timer_interrupt_handler);
 
-        idt[InterruptIndex::Keyboard.as_usize()]
-            .set_handler_fn(keyboard_interrupt_handler);
+        #[cfg(feature = "apic")]
+        idt[usize::from(crate::apic::SPURIOUS_VECTOR)].set_handler_fn(apic_spurious_handler);
 
-        idt.page_fault.set_handler_fn(page_fault_handler);  //处理页错误
+        unsafe {
+            idt.page_fault
+                .set_handler_fn(page_fault_handler)
+                .set_stack_index(gdt::PAGE_FAULT_IST_INDEX); //处理页错误，栈溢出时原来的栈不可用
+        }
+        idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
 
         idt
     };
@@ -143,4 +1236,7 @@ lazy_static! {
 /// ```
 pub fn init_idt() {
     IDT.load();
+    //重复初始化时处理函数已经注册过
+    let _ = register_irq_handler(0, timer_irq);
+    let _ = register_irq_handler(1, keyboard_irq);
 }
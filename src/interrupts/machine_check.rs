@@ -0,0 +1,139 @@
+use core::fmt;
+use x86_64::structures::idt::InterruptStackFrame;
+
+use crate::cpu::msr::{self, Msr};
+use crate::{hlt_loop, println};
+
+//MCG_STATUS：可以从RIP处重新开始执行、错误与RIP处的指令直接相关、异常正在处理中
+const MCG_RIPV: u64 = 1 << 0;
+const MCG_EIPV: u64 = 1 << 1;
+const MCG_MCIP: u64 = 1 << 2;
+
+/// ## 说明
+/// 错误报告bank的MCi_STATUS寄存器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct McStatus(pub u64);
+
+impl McStatus {
+    /// ## 函数说明
+    /// 寄存器中记录了一个错误
+    pub fn valid(self) -> bool {
+        self.0 & (1 << 63) != 0
+    }
+
+    /// ## 函数说明
+    /// 在上一个错误被读取之前又发生了错误，有错误信息丢失
+    pub fn overflow(self) -> bool {
+        self.0 & (1 << 62) != 0
+    }
+
+    /// ## 函数说明
+    /// 错误没有被硬件纠正
+    pub fn uncorrected(self) -> bool {
+        self.0 & (1 << 61) != 0
+    }
+
+    /// ## 函数说明
+    /// 处理器的状态已被破坏，不能安全地继续执行
+    pub fn processor_context_corrupt(self) -> bool {
+        self.0 & (1 << 57) != 0
+    }
+
+    /// ## 函数说明
+    /// MCi_ADDR中保存了出错的地址
+    pub fn addr_valid(self) -> bool {
+        self.0 & (1 << 58) != 0
+    }
+
+    /// ## 函数说明
+    /// 架构定义的MCA错误码，位于低16位
+    pub fn error_code(self) -> u16 {
+        self.0 as u16
+    }
+}
+
+impl fmt::Display for McStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#018x}", self.0)?;
+        if !self.valid() {
+            return write!(f, " (no error)");
+        }
+        write!(f, " code {:#06x}", self.error_code())?;
+        let flags = [
+            (self.uncorrected(), "UC"),
+            (self.overflow(), "OVER"),
+            (self.processor_context_corrupt(), "PCC"),
+            (self.addr_valid(), "ADDRV"),
+        ];
+        for (_, name) in flags.iter().filter(|(set, _)| *set) {
+            write!(f, " {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+//打印MCG_STATUS和每个bank中记录了错误的MCi_STATUS，只读取MCG_CAP报告存在的bank
+fn dump_banks() {
+    //CPU不支持机器检查架构时读取这些MSR会引发#GP
+    let (Some(cap), Some(status)) = (msr::try_read(Msr::MCG_CAP), msr::try_read(Msr::MCG_STATUS))
+    else {
+        println!("machine check architecture not supported, no banks to read");
+        return;
+    };
+    println!(
+        "MCG_STATUS: {:#x} RIPV={} EIPV={} MCIP={}",
+        status,
+        (status & MCG_RIPV != 0) as u8,
+        (status & MCG_EIPV != 0) as u8,
+        (status & MCG_MCIP != 0) as u8
+    );
+    let banks = (cap & 0xff) as u32;
+    for bank in 0..banks {
+        let status = McStatus(unsafe { msr::read(Msr::mc_status(bank)) });
+        if !status.valid() {
+            continue;
+        }
+        print_bank(bank, status);
+    }
+}
+
+fn print_bank(bank: u32, status: McStatus) {
+    if status.addr_valid() {
+        let addr = unsafe { msr::read(Msr::mc_addr(bank)) };
+        println!("MC{}_STATUS: {} addr {:#x}", bank, status, addr);
+    } else {
+        println!("MC{}_STATUS: {}", bank, status);
+    }
+}
+
+/*
+    注册machine check处理函数
+    硬件检测到无法纠正的错误时触发，属于abort，打印各个bank的状态后停机
+    使用独立的IST栈，出错时内核栈未必可用
+*/
+pub(super) extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    let _ctx = super::count_interrupt(18);
+    println!("EXCEPTION: MACHINE CHECK");
+    dump_banks();
+    println!("{:#?}", stack_frame);
+    #[cfg(test)]
+    crate::exit_qemu(crate::QemuExitCode::Failed);
+    hlt_loop();
+}
+
+#[test_case]
+fn test_mc_status_decoding() {
+    let none = McStatus(0);
+    assert!(!none.valid());
+
+    //有效、未纠正、溢出，错误码0x0150
+    let status = McStatus((1 << 63) | (1 << 62) | (1 << 61) | 0x0150);
+    assert!(status.valid() && status.overflow() && status.uncorrected());
+    assert!(!status.processor_context_corrupt() && !status.addr_valid());
+    assert_eq!(status.error_code(), 0x0150);
+
+    //已纠正的错误
+    let corrected = McStatus((1 << 63) | (1 << 58) | 0x0005);
+    assert!(corrected.valid() && corrected.addr_valid());
+    assert!(!corrected.uncorrected() && !corrected.overflow());
+}
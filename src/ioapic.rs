@@ -0,0 +1,216 @@
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::PhysAddr;
+
+use crate::apic::{self, ApicError};
+use crate::interrupts::{InterruptIndex, PIC_1_OFFSET};
+use crate::memory::{self, MapError};
+
+/// IOAPIC寄存器窗口的默认物理地址，之后可以改为从ACPI的MADT中读取
+pub const IOAPIC_BASE: u64 = 0xfec0_0000;
+
+//间接访问：先向IOREGSEL写入寄存器号，再通过IOWIN读写
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+const REG_VERSION: u32 = 0x01;
+//第n个重定向表项占用0x10 + 2n和0x11 + 2n两个32位寄存器
+const REG_REDTBL: u32 = 0x10;
+//重定向表项中的屏蔽位
+const ENTRY_MASKED: u64 = 1 << 16;
+
+//寄存器窗口的虚拟地址和重定向表项数；IOREGSEL和IOWIN之间不能被打断，因此整体加锁
+struct IoApic {
+    base: u64,
+    entries: u8,
+}
+
+impl IoApic {
+    fn read(&self, reg: u32) -> u32 {
+        unsafe {
+            ((self.base as usize + IOREGSEL) as *mut u32).write_volatile(reg);
+            ((self.base as usize + IOWIN) as *const u32).read_volatile()
+        }
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        unsafe {
+            ((self.base as usize + IOREGSEL) as *mut u32).write_volatile(reg);
+            ((self.base as usize + IOWIN) as *mut u32).write_volatile(value);
+        }
+    }
+
+    fn read_entry(&self, gsi: u8) -> u64 {
+        let reg = REG_REDTBL + 2 * u32::from(gsi);
+        u64::from(self.read(reg)) | u64::from(self.read(reg + 1)) << 32
+    }
+
+    //先写入带屏蔽位的低32位，改写目标之后再写入最终的低32位，避免中途以旧目标投递
+    fn write_entry(&self, gsi: u8, entry: u64) {
+        let reg = REG_REDTBL + 2 * u32::from(gsi);
+        self.write(reg, entry as u32 | ENTRY_MASKED as u32);
+        self.write(reg + 1, (entry >> 32) as u32);
+        self.write(reg, entry as u32);
+    }
+}
+
+static IOAPIC: Mutex<Option<IoApic>> = Mutex::new(None);
+
+//在关中断时访问已初始化的IOAPIC
+fn with_ioapic<R>(f: impl FnOnce(&IoApic) -> R) -> R {
+    interrupts::without_interrupts(|| {
+        let ioapic = IOAPIC.lock();
+        f(ioapic.as_ref().expect("IOAPIC not initialized"))
+    })
+}
+
+/// ## 函数说明
+/// 按ISA的标准中断源覆盖把IRQ换算为GSI：PIT接在IOAPIC的2号管脚，其余IRQ与GSI相同
+///
+/// ## 参数
+/// * `irq` - ISA IRQ号，0~15
+pub fn isa_irq_to_gsi(irq: u8) -> u8 {
+    match irq {
+        0 => 2,
+        irq => irq,
+    }
+}
+
+/// ## 函数说明
+/// 构造重定向表项：固定投递、物理目标模式、边沿触发、高电平有效
+///
+/// ## 参数
+/// * `vector` - 中断向量
+/// * `masked` - 是否屏蔽
+/// * `apic_id` - 接收中断的本地APIC
+pub fn redirection_entry(vector: u8, masked: bool, apic_id: u8) -> u64 {
+    let mut entry = u64::from(vector) | u64::from(apic_id) << 56;
+    if masked {
+        entry |= ENTRY_MASKED;
+    }
+    entry
+}
+
+/// ## 函数说明
+/// 启用IOAPIC：映射寄存器窗口，把16个ISA IRQ按PIC原有的偏移路由到同样的向量，
+/// 其中计时器和键盘取消屏蔽，其余保持屏蔽，直到通过`interrupts::enable_irq`启用
+/// 需要在`apic::init`之后调用，PIC此时已被完全屏蔽
+///
+/// ## 用法
+/// ```rust
+/// apic::init()?;
+/// ioapic::init()?;
+/// ```
+pub fn init() -> Result<(), ApicError> {
+    if !apic::is_enabled() {
+        return Err(ApicError::NotEnabled);
+    }
+    let base = memory::with_paging(|paging| {
+        memory::map_physical_region(
+            PhysAddr::new(IOAPIC_BASE),
+            IOWIN + 4,
+            &mut paging.mapper,
+            &mut paging.frame_allocator,
+        )
+    })
+    .unwrap_or(Err(MapError::NoPaging))
+    .map_err(ApicError::Map)?;
+
+    let mut ioapic = IoApic {
+        base: base.as_u64(),
+        entries: 0,
+    };
+    ioapic.entries = ((ioapic.read(REG_VERSION) >> 16) & 0xff) as u8 + 1;
+    let apic_id = apic::id();
+    interrupts::without_interrupts(|| {
+        for gsi in 0..ioapic.entries {
+            ioapic.write_entry(gsi, ENTRY_MASKED);
+        }
+        for irq in (0..16).filter(|&irq| irq != 2) {
+            let unmasked = irq == InterruptIndex::Timer.as_u8() - PIC_1_OFFSET
+                || irq == InterruptIndex::Keyboard.as_u8() - PIC_1_OFFSET;
+            let entry = redirection_entry(PIC_1_OFFSET + irq, !unmasked, apic_id);
+            ioapic.write_entry(isa_irq_to_gsi(irq), entry);
+        }
+        *IOAPIC.lock() = Some(ioapic);
+    });
+    Ok(())
+}
+
+/// ## 函数说明
+/// IOAPIC是否已经由[`init`]启用
+pub fn is_enabled() -> bool {
+    interrupts::without_interrupts(|| IOAPIC.lock().is_some())
+}
+
+/// ## 函数说明
+/// 重定向表项数，即IOAPIC的管脚数
+pub fn entries() -> u8 {
+    with_ioapic(|ioapic| ioapic.entries)
+}
+
+/// ## 函数说明
+/// 把GSI路由到当前CPU上的`vector`
+///
+/// ## 参数
+/// * `gsi` - IOAPIC管脚号
+/// * `vector` - 中断向量
+/// * `masked` - 是否屏蔽
+pub fn route(gsi: u8, vector: u8, masked: bool) {
+    let entry = redirection_entry(vector, masked, apic::id());
+    with_ioapic(|ioapic| {
+        assert!(gsi < ioapic.entries, "GSI {} out of range", gsi);
+        ioapic.write_entry(gsi, entry);
+    });
+}
+
+/// ## 函数说明
+/// 读取GSI的64位重定向表项
+///
+/// ## 参数
+/// * `gsi` - IOAPIC管脚号
+pub fn read_entry(gsi: u8) -> u64 {
+    with_ioapic(|ioapic| {
+        assert!(gsi < ioapic.entries, "GSI {} out of range", gsi);
+        ioapic.read_entry(gsi)
+    })
+}
+
+fn set_masked(gsi: u8, masked: bool) {
+    with_ioapic(|ioapic| {
+        assert!(gsi < ioapic.entries, "GSI {} out of range", gsi);
+        let entry = ioapic.read_entry(gsi);
+        let entry = if masked {
+            entry | ENTRY_MASKED
+        } else {
+            entry & !ENTRY_MASKED
+        };
+        ioapic.write_entry(gsi, entry);
+    });
+}
+
+/// ## 函数说明
+/// 取消屏蔽GSI，路由保持不变
+///
+/// ## 参数
+/// * `gsi` - IOAPIC管脚号
+pub fn unmask(gsi: u8) {
+    set_masked(gsi, false);
+}
+
+/// ## 函数说明
+/// 屏蔽GSI，正在处理的中断不受影响
+///
+/// ## 参数
+/// * `gsi` - IOAPIC管脚号
+pub fn mask(gsi: u8) {
+    set_masked(gsi, true);
+}
+
+/// ## 函数说明
+/// GSI当前是否被屏蔽
+///
+/// ## 参数
+/// * `gsi` - IOAPIC管脚号
+pub fn is_masked(gsi: u8) -> bool {
+    read_entry(gsi) & ENTRY_MASKED != 0
+}
@@ -0,0 +1,882 @@
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use pc_keyboard::{layouts, DecodedKey, HandleControl, KeyCode, KeyState, Keyboard, ScancodeSet1};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::drivers::ps2::{self, Ps2Error, Ps2Port};
+
+/// ## 说明
+/// pc-keyboard支持的键盘布局
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// 美式104键
+    Us104,
+    /// 英式105键
+    Uk105,
+    /// Dvorak 104键
+    Dvorak104,
+    /// 日式109键
+    Jis109,
+    /// 法式AZERTY
+    Azerty,
+}
+
+impl Layout {
+    /// ## 函数说明
+    /// 按名称查找布局（不区分大小写），供解析命令行参数等配置使用
+    ///
+    /// ## 参数
+    /// * `name` - 布局名称，如"us"、"uk"、"dvorak"、"jis"、"azerty"
+    ///
+    /// ## 用法
+    /// ```rust
+    /// if let Some(layout) = Layout::from_name("uk") {
+    ///     keyboard::set_layout(layout);
+    /// }
+    /// ```
+    pub fn from_name(name: &str) -> Option<Layout> {
+        let layouts = [
+            ("us", Layout::Us104),
+            ("uk", Layout::Uk105),
+            ("dvorak", Layout::Dvorak104),
+            ("jis", Layout::Jis109),
+            ("azerty", Layout::Azerty),
+        ];
+        layouts
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, layout)| layout)
+    }
+}
+
+/// 最多可以通过`on_key`注册的回调函数数量
+pub const MAX_KEY_CALLBACKS: usize = 8;
+
+/// ## 说明
+/// 带修饰键状态的按键事件，按下和释放都会产生
+///
+/// ## 成员
+/// * `key` - 按下时为按布局解码的结果；释放、修饰键和无法解码的按键为`RawKey`
+/// * `ctrl` - 事件发生时是否按住了Ctrl
+/// * `alt` - 事件发生时是否按住了Alt
+/// * `shift` - 事件发生时是否按住了Shift
+/// * `pressed` - 按下为`true`，释放为`false`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEventExt {
+    pub key: DecodedKey,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub pressed: bool,
+}
+
+impl KeyEventExt {
+    /// ## 函数说明
+    /// 是否是Ctrl、Alt或Shift自身的按下或释放事件
+    pub fn is_modifier(&self) -> bool {
+        matches!(
+            self.key,
+            DecodedKey::RawKey(
+                KeyCode::ControlLeft
+                    | KeyCode::ControlRight
+                    | KeyCode::AltLeft
+                    | KeyCode::AltRight
+                    | KeyCode::ShiftLeft
+                    | KeyCode::ShiftRight
+            )
+        )
+    }
+}
+
+//目前按住的修饰键，左右两侧分开记录，松开一侧时另一侧仍然有效
+#[derive(Debug, Default, Clone, Copy)]
+struct Modifiers {
+    lctrl: bool,
+    rctrl: bool,
+    lalt: bool,
+    ralt: bool,
+    lshift: bool,
+    rshift: bool,
+}
+
+impl Modifiers {
+    //更新修饰键状态，`code`不是修饰键时返回false
+    fn update(&mut self, code: KeyCode, pressed: bool) -> bool {
+        let state = match code {
+            KeyCode::ControlLeft => &mut self.lctrl,
+            KeyCode::ControlRight => &mut self.rctrl,
+            KeyCode::AltLeft => &mut self.lalt,
+            KeyCode::AltRight => &mut self.ralt,
+            KeyCode::ShiftLeft => &mut self.lshift,
+            KeyCode::ShiftRight => &mut self.rshift,
+            _ => return false,
+        };
+        *state = pressed;
+        true
+    }
+}
+
+/// ## 说明
+/// 三个锁定键的状态，与键盘上的指示灯一一对应
+///
+/// ## 成员
+/// * `scroll` - Scroll Lock
+/// * `num` - Num Lock
+/// * `caps` - Caps Lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockKeys {
+    pub scroll: bool,
+    pub num: bool,
+    pub caps: bool,
+}
+
+impl LockKeys {
+    /// ## 函数说明
+    /// 初始状态，与pc-keyboard相同，只有Num Lock开启
+    pub const fn new() -> Self {
+        LockKeys {
+            scroll: false,
+            num: true,
+            caps: false,
+        }
+    }
+
+    /// ## 函数说明
+    /// LED命令的参数字节：位0为Scroll Lock，位1为Num Lock，位2为Caps Lock
+    pub fn led_byte(&self) -> u8 {
+        u8::from(self.scroll) | u8::from(self.num) << 1 | u8::from(self.caps) << 2
+    }
+
+    //按下锁定键时切换状态；与pc-keyboard一样，按住不放产生的重复按下也会切换
+    fn update(&mut self, code: KeyCode, pressed: bool) {
+        let state = match code {
+            KeyCode::ScrollLock => &mut self.scroll,
+            KeyCode::NumpadLock => &mut self.num,
+            KeyCode::CapsLock => &mut self.caps,
+            _ => return,
+        };
+        if pressed {
+            *state = !*state;
+        }
+    }
+}
+
+impl Default for LockKeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//pc-keyboard的布局是类型参数，按布局分派到不同的实例
+enum Inner {
+    Us104(Keyboard<layouts::Us104Key, ScancodeSet1>),
+    Uk105(Keyboard<layouts::Uk105Key, ScancodeSet1>),
+    Dvorak104(Keyboard<layouts::Dvorak104Key, ScancodeSet1>),
+    Jis109(Keyboard<layouts::Jis109Key, ScancodeSet1>),
+    Azerty(Keyboard<layouts::Azerty, ScancodeSet1>),
+}
+
+/// ## 说明
+/// 将扫描码集1的扫描码按运行时选择的布局解码为按键
+///
+/// ## 成员
+/// * `layout` - 当前布局
+/// * `inner` - 对应布局的pc-keyboard解码器，保存修饰键和多字节扫描码的状态
+/// * `modifiers` - 按下的修饰键，pc-keyboard不对外提供这部分状态
+/// * `locks` - 锁定键状态，同样需要自己记录
+pub struct Decoder {
+    layout: Layout,
+    inner: Inner,
+    modifiers: Modifiers,
+    locks: LockKeys,
+}
+
+impl Decoder {
+    /// ## 函数说明
+    /// 创建使用`layout`布局的解码器，修饰键均未按下
+    ///
+    /// ## 参数
+    /// * `layout` - 键盘布局
+    pub fn new(layout: Layout) -> Self {
+        let control = HandleControl::Ignore;
+        let inner = match layout {
+            Layout::Us104 => Inner::Us104(Keyboard::new(layouts::Us104Key, ScancodeSet1, control)),
+            Layout::Uk105 => Inner::Uk105(Keyboard::new(layouts::Uk105Key, ScancodeSet1, control)),
+            Layout::Dvorak104 => {
+                Inner::Dvorak104(Keyboard::new(layouts::Dvorak104Key, ScancodeSet1, control))
+            }
+            Layout::Jis109 => {
+                Inner::Jis109(Keyboard::new(layouts::Jis109Key, ScancodeSet1, control))
+            }
+            Layout::Azerty => Inner::Azerty(Keyboard::new(layouts::Azerty, ScancodeSet1, control)),
+        };
+        Decoder {
+            layout,
+            inner,
+            modifiers: Modifiers::default(),
+            locks: LockKeys::new(),
+        }
+    }
+
+    /// ## 函数说明
+    /// 当前使用的布局
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// ## 函数说明
+    /// 当前的锁定键状态
+    pub fn locks(&self) -> LockKeys {
+        self.locks
+    }
+
+    /// ## 函数说明
+    /// 输入一个扫描码，组成完整的按下或释放事件时返回该事件，多字节扫描码的前缀不产生事件
+    ///
+    /// ## 参数
+    /// * `scancode` - 从0x60端口读到的扫描码
+    pub fn add_event(&mut self, scancode: u8) -> Option<KeyEventExt> {
+        macro_rules! decode {
+            ($keyboard:expr) => {{
+                let event = $keyboard.add_byte(scancode).ok()??;
+                let (code, state) = (event.code, event.state);
+                (code, state, $keyboard.process_keyevent(event))
+            }};
+        }
+
+        let (code, state, decoded) = match &mut self.inner {
+            Inner::Us104(keyboard) => decode!(keyboard),
+            Inner::Uk105(keyboard) => decode!(keyboard),
+            Inner::Dvorak104(keyboard) => decode!(keyboard),
+            Inner::Jis109(keyboard) => decode!(keyboard),
+            Inner::Azerty(keyboard) => decode!(keyboard),
+        };
+        let pressed = state == KeyState::Down;
+        //修饰键自身的事件带有更新之后的状态，例如按下Ctrl的事件中ctrl为true
+        self.modifiers.update(code, pressed);
+        self.locks.update(code, pressed);
+        let m = self.modifiers;
+        Some(KeyEventExt {
+            key: decoded.unwrap_or(DecodedKey::RawKey(code)),
+            ctrl: m.lctrl || m.rctrl,
+            alt: m.lalt || m.ralt,
+            shift: m.lshift || m.rshift,
+            pressed,
+        })
+    }
+
+    /// ## 函数说明
+    /// 输入一个扫描码，组成按下按键的事件时返回解码结果。修饰键和按键释放不产生结果
+    ///
+    /// ## 参数
+    /// * `scancode` - 从0x60端口读到的扫描码
+    pub fn add_byte(&mut self, scancode: u8) -> Option<DecodedKey> {
+        self.add_event(scancode)
+            .filter(|event| event.pressed && !event.is_modifier())
+            .map(|event| event.key)
+    }
+}
+
+lazy_static! {
+    static ref DECODER: Mutex<Decoder> = Mutex::new(Decoder::new(Layout::Us104));
+}
+
+/// ## 函数说明
+/// 切换键盘布局。解码器会被重置，切换时按住的修饰键需要重新按下，锁定键和指示灯恢复初始状态
+///
+/// ## 参数
+/// * `layout` - 新的布局
+///
+/// ## 用法
+/// ```rust
+/// keyboard::set_layout(Layout::Uk105);
+/// ```
+pub fn set_layout(layout: Layout) {
+    //键盘中断处理函数也会获取这个锁
+    interrupts::without_interrupts(|| *DECODER.lock() = Decoder::new(layout));
+    let _ = sync_leds(); //没有键盘时忽略
+}
+
+/// ## 函数说明
+/// 当前使用的键盘布局
+pub fn layout() -> Layout {
+    interrupts::without_interrupts(|| DECODER.lock().layout())
+}
+
+//通过`on_key`注册的回调函数，空指针表示空位
+static KEY_CALLBACKS: [AtomicPtr<()>; MAX_KEY_CALLBACKS] =
+    [const { AtomicPtr::new(ptr::null_mut()) }; MAX_KEY_CALLBACKS];
+
+/// ## 说明
+/// 已经注册了`MAX_KEY_CALLBACKS`个回调函数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallbacksFull;
+
+/// ## 函数说明
+/// 注册按键回调函数，`poll_event`解码出的每个按下和释放事件都会依次调用所有回调
+///
+/// ## 参数
+/// * `callback` - 回调函数
+///
+/// ## 用法
+/// ```rust
+/// fn on_ctrl_c(event: KeyEventExt) {
+///     if event.ctrl && event.pressed && event.key == DecodedKey::Unicode('c') {
+///         //取消当前输入
+///     }
+/// }
+/// keyboard::on_key(on_ctrl_c).expect("too many key callbacks");
+/// ```
+pub fn on_key(callback: fn(KeyEventExt)) -> Result<(), CallbacksFull> {
+    KEY_CALLBACKS
+        .iter()
+        .find(|slot| {
+            slot.compare_exchange(
+                ptr::null_mut(),
+                callback as *mut (),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+        })
+        .map(|_| ())
+        .ok_or(CallbacksFull)
+}
+
+fn dispatch(event: KeyEventExt) {
+    for slot in KEY_CALLBACKS.iter() {
+        let callback = slot.load(Ordering::Acquire);
+        if !callback.is_null() {
+            //非空指针只可能由`on_key`从`fn(KeyEventExt)`转换而来
+            let callback = unsafe { core::mem::transmute::<*mut (), fn(KeyEventExt)>(callback) };
+            callback(event);
+        }
+    }
+}
+
+//解码后通知所有回调；回调和LED更新在解码器的锁释放之后进行
+fn handle_scancode(scancode: u8) -> Option<KeyEventExt> {
+    let (event, before, after) = {
+        let mut decoder = DECODER.lock();
+        let before = decoder.locks();
+        let event = decoder.add_event(scancode)?;
+        (event, before, decoder.locks())
+    };
+    if after != before {
+        let _ = set_leds(after.scroll, after.num, after.caps); //没有键盘（例如扫描码来自测试）时忽略
+    }
+    dispatch(event);
+    Some(event)
+}
+
+/// ## 函数说明
+/// 当前的锁定键状态
+pub fn lock_keys() -> LockKeys {
+    interrupts::without_interrupts(|| DECODER.lock().locks())
+}
+
+//键盘命令及其应答
+const SET_LEDS: u8 = 0xed;
+const ACK: u8 = 0xfa;
+const RESEND: u8 = 0xfe;
+//收到0xFE时重发同一个字节的次数上限
+const LED_RESEND_LIMIT: usize = 3;
+//等待应答期间最多转交给扫描码队列或鼠标的字节数，超过后视为键盘不应答
+const LED_MAX_FORWARDED: usize = 16;
+
+/// ## 说明
+/// 设置键盘指示灯时可能出现的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedError {
+    /// `drivers::ps2::init`没有在第一个端口上发现键盘
+    NoKeyboard,
+    /// 与控制器通信失败或等待应答超时；一直收不到应答时为`Ps2Error::NoAck`
+    Ps2(Ps2Error),
+    /// 键盘连续要求重发，`byte`为没有被接受的字节
+    TooManyResends { byte: u8 },
+}
+
+impl From<Ps2Error> for LedError {
+    fn from(err: Ps2Error) -> Self {
+        LedError::Ps2(err)
+    }
+}
+
+//依次发送0xED和参数字节，每个字节都要等到键盘的0xFA，收到0xFE时重发当前字节
+//应答之间夹杂的其他字节是扫描码或鼠标数据，连同来源端口交给`forward`
+fn exchange_leds(
+    leds: u8,
+    mut send: impl FnMut(u8) -> Result<(), Ps2Error>,
+    mut recv: impl FnMut() -> Result<(Ps2Port, u8), Ps2Error>,
+    mut forward: impl FnMut(Ps2Port, u8),
+) -> Result<(), LedError> {
+    let mut forwarded = 0;
+    for byte in [SET_LEDS, leds] {
+        let mut sends = 0;
+        loop {
+            send(byte)?;
+            sends += 1;
+            let reply = loop {
+                match recv()? {
+                    (Ps2Port::Port1, reply @ (ACK | RESEND)) => break reply,
+                    (port, code) if forwarded < LED_MAX_FORWARDED => {
+                        forward(port, code);
+                        forwarded += 1;
+                    }
+                    (Ps2Port::Port1, reply) => {
+                        return Err(LedError::Ps2(Ps2Error::NoAck {
+                            command: byte,
+                            reply,
+                        }))
+                    }
+                    //鼠标一直在发送数据，键盘的应答没有到达
+                    (Ps2Port::Port2, _) => return Err(LedError::Ps2(Ps2Error::Timeout)),
+                }
+            };
+            if reply == ACK {
+                break;
+            }
+            if sends > LED_RESEND_LIMIT {
+                return Err(LedError::TooManyResends { byte });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// ## 函数说明
+/// 设置键盘上的三个指示灯。不修改锁定键状态，按下锁定键时`poll_event`会自动更新指示灯
+/// 交换期间关闭中断，键盘中断处理函数不会读走应答；其间收到的扫描码放回扫描码队列，鼠标数据交给鼠标驱动
+///
+/// ## 参数
+/// * `scroll` - Scroll Lock指示灯
+/// * `num` - Num Lock指示灯
+/// * `caps` - Caps Lock指示灯
+///
+/// ## 用法
+/// ```rust
+/// keyboard::set_leds(false, true, true)?;
+/// ```
+pub fn set_leds(scroll: bool, num: bool, caps: bool) -> Result<(), LedError> {
+    if !ps2::status().is_some_and(|status| status.has_keyboard()) {
+        return Err(LedError::NoKeyboard);
+    }
+    let leds = LockKeys { scroll, num, caps }.led_byte();
+    //关中断时键盘中断处理函数不会运行，这里是扫描码队列唯一的生产者
+    interrupts::without_interrupts(|| {
+        exchange_leds(
+            leds,
+            |byte| ps2::send_byte(Ps2Port::Port1, byte),
+            ps2::read_reply,
+            |port, code| match port {
+                Ps2Port::Port1 => push_scancode(code),
+                Ps2Port::Port2 => crate::mouse::feed_byte(code),
+            },
+        )
+    })
+}
+
+//让指示灯与解码器记录的锁定键状态一致
+fn sync_leds() -> Result<(), LedError> {
+    let locks = lock_keys();
+    set_leds(locks.scroll, locks.num, locks.caps)
+}
+
+/// 扫描码队列的容量
+pub const SCANCODE_QUEUE_SIZE: usize = 128;
+
+/// ## 说明
+/// 单生产者单消费者的无锁扫描码环形队列：键盘中断处理函数写入，内核主循环读取
+///
+/// ## 成员
+/// * `codes` - 扫描码
+/// * `head` - 下一个要读取的位置，只由消费者修改
+/// * `tail` - 下一个要写入的位置，只由生产者修改
+/// * `dropped` - 队列已满时丢弃的扫描码数量
+pub struct ScancodeQueue {
+    codes: [AtomicU8; SCANCODE_QUEUE_SIZE],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+impl ScancodeQueue {
+    /// ## 函数说明
+    /// 创建一个空队列
+    pub const fn new() -> Self {
+        ScancodeQueue {
+            codes: [const { AtomicU8::new(0) }; SCANCODE_QUEUE_SIZE],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// ## 函数说明
+    /// 写入一个扫描码，队列已满时丢弃并计数。同一时刻只能有一个生产者
+    ///
+    /// ## 参数
+    /// * `code` - 扫描码
+    pub fn push(&self, code: u8) -> bool {
+        //位置单调递增，取模得到下标，head和tail之差就是队列长度
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) == SCANCODE_QUEUE_SIZE {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        self.codes[tail % SCANCODE_QUEUE_SIZE].store(code, Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// ## 函数说明
+    /// 取出最早的扫描码。同一时刻只能有一个消费者
+    pub fn pop(&self) -> Option<u8> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let code = self.codes[head % SCANCODE_QUEUE_SIZE].load(Ordering::Relaxed);
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(code)
+    }
+
+    /// ## 函数说明
+    /// 队列中是否还有扫描码
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+    }
+
+    /// ## 函数说明
+    /// 因队列已满而丢弃的扫描码数量
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for ScancodeQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static SCANCODES: ScancodeQueue = ScancodeQueue::new();
+
+//由键盘中断处理函数调用，只做入队
+pub(crate) fn push_scancode(code: u8) {
+    SCANCODES.push(code);
+}
+
+/// ## 函数说明
+/// 取出键盘中断收到的下一个原始扫描码，队列为空时返回`None`
+/// 与`poll_event`共用同一个队列，同一时刻只能有一个读取者
+pub fn pop_scancode() -> Option<u8> {
+    SCANCODES.pop()
+}
+
+/// ## 函数说明
+/// 是否有尚未处理的扫描码
+pub fn has_pending() -> bool {
+    !SCANCODES.is_empty()
+}
+
+/// ## 函数说明
+/// 因处理不及时而丢弃的按键扫描码数量
+pub fn dropped_scancodes() -> u64 {
+    SCANCODES.dropped()
+}
+
+/// ## 函数说明
+/// 取出并解码排队的扫描码，直到得到一个按键事件或队列为空，同时调用`on_key`注册的回调
+///
+/// ## 用法
+/// ```rust
+/// while let Some(event) = keyboard::poll_event() {
+///     //...
+/// }
+/// ```
+pub fn poll_event() -> Option<KeyEventExt> {
+    while let Some(code) = pop_scancode() {
+        if let Some(event) = handle_scancode(code) {
+            return Some(event);
+        }
+    }
+    None
+}
+
+/// 键盘使用的PIC管脚
+pub const KEYBOARD_IRQ: u8 = 1;
+
+/// ## 函数说明
+/// 根据`drivers::ps2::init`的结果启用键盘：第一个端口上有键盘时解除IRQ1的屏蔽，否则屏蔽IRQ1
+/// 解码器只支持第1套扫描码，控制器关闭了扫描码转换时同样视为没有键盘。键盘可用时指示灯与锁定键状态同步
+/// 返回键盘是否可用
+///
+/// ## 用法
+/// ```rust
+/// if !keyboard::init() {
+///     println!("no PS/2 keyboard");
+/// }
+/// ```
+pub fn init() -> bool {
+    let available = ps2::status().is_some_and(|status| status.has_keyboard() && status.translation);
+    if available {
+        let _ = sync_leds(); //复位后指示灯全灭，而Num Lock默认开启
+        crate::interrupts::enable_irq(KEYBOARD_IRQ);
+    } else {
+        crate::interrupts::disable_irq(KEYBOARD_IRQ);
+    }
+    available
+}
+
+#[cfg(test)]
+fn decode_all(decoder: &mut Decoder, scancodes: &[u8]) -> Option<DecodedKey> {
+    scancodes
+        .iter()
+        .fold(None, |last, &code| decoder.add_byte(code).or(last))
+}
+
+#[test_case]
+fn test_layouts_decode_differently() {
+    //Shift+2：按下左Shift、按下2、释放2、释放左Shift
+    let shift_2 = [0x2a, 0x03, 0x83, 0xaa];
+    let mut us = Decoder::new(Layout::Us104);
+    let mut uk = Decoder::new(Layout::Uk105);
+    assert_eq!(
+        decode_all(&mut us, &shift_2),
+        Some(DecodedKey::Unicode('@'))
+    );
+    assert_eq!(
+        decode_all(&mut uk, &shift_2),
+        Some(DecodedKey::Unicode('"'))
+    );
+
+    //Q键的位置在AZERTY布局上是A
+    let q = [0x10, 0x90];
+    let mut azerty = Decoder::new(Layout::Azerty);
+    assert_eq!(decode_all(&mut us, &q), Some(DecodedKey::Unicode('q')));
+    assert_eq!(decode_all(&mut azerty, &q), Some(DecodedKey::Unicode('a')));
+}
+
+#[test_case]
+fn test_switch_resets_modifiers() {
+    let previous = layout();
+    let feed = handle_scancode;
+    //按住Shift后切换布局，新的解码器不知道Shift已按下
+    set_layout(Layout::Us104);
+    assert_eq!(feed(0x2a).map(|event| event.shift), Some(true));
+    set_layout(Layout::Uk105);
+    assert_eq!(layout(), Layout::Uk105);
+    let event = feed(0x03).unwrap();
+    assert_eq!(event.key, DecodedKey::Unicode('2'));
+    assert!(!event.shift);
+    feed(0x83);
+    set_layout(previous);
+
+    assert_eq!(Layout::from_name("AZERTY"), Some(Layout::Azerty));
+    assert_eq!(Layout::from_name("de"), None);
+}
+
+#[test_case]
+fn test_ctrl_c_events() {
+    let key = |key, ctrl, pressed| KeyEventExt {
+        key,
+        ctrl,
+        alt: false,
+        shift: false,
+        pressed,
+    };
+    let ctrl = DecodedKey::RawKey(KeyCode::ControlLeft);
+    let mut decoder = Decoder::new(Layout::Us104);
+    //按下Ctrl、按下C、释放C、释放Ctrl
+    assert_eq!(decoder.add_event(0x1d), Some(key(ctrl, true, true)));
+    assert_eq!(
+        decoder.add_event(0x2e),
+        Some(key(DecodedKey::Unicode('c'), true, true))
+    );
+    assert_eq!(
+        decoder.add_event(0xae),
+        Some(key(DecodedKey::RawKey(KeyCode::C), true, false))
+    );
+    assert_eq!(decoder.add_event(0x9d), Some(key(ctrl, false, false)));
+
+    //右Alt是0xe0前缀的扩展扫描码，前缀本身不产生事件
+    assert_eq!(decoder.add_event(0xe0), None);
+    let alt = decoder.add_event(0x38).unwrap();
+    assert!(alt.alt && alt.pressed);
+}
+
+#[cfg(test)]
+static SEEN_KEYS: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+#[cfg(test)]
+fn count_key(_event: KeyEventExt) {
+    SEEN_KEYS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[test_case]
+fn test_on_key_receives_releases() {
+    on_key(count_key).unwrap();
+    let before = SEEN_KEYS.load(Ordering::Relaxed);
+    handle_scancode(0x1e);
+    handle_scancode(0x9e);
+    assert_eq!(SEEN_KEYS.load(Ordering::Relaxed), before + 2);
+}
+
+#[test_case]
+fn test_scancode_queue_wraps() {
+    let queue = ScancodeQueue::new();
+    //反复写入和读取，让位置越过数组末尾
+    for round in 0..3 {
+        for i in 0..100u8 {
+            assert!(queue.push(i.wrapping_add(round)));
+        }
+        for i in 0..100u8 {
+            assert_eq!(queue.pop(), Some(i.wrapping_add(round)));
+        }
+    }
+    assert_eq!(queue.pop(), None);
+    assert_eq!(queue.dropped(), 0);
+}
+
+#[test_case]
+fn test_scancode_queue_overflow() {
+    let queue = ScancodeQueue::new();
+    for i in 0..SCANCODE_QUEUE_SIZE + 5 {
+        queue.push(i as u8);
+    }
+    assert_eq!(queue.dropped(), 5);
+    //最早的扫描码保留，溢出的被丢弃
+    assert_eq!(queue.pop(), Some(0));
+    assert!(queue.push(0xff));
+    let remaining = core::iter::from_fn(|| queue.pop()).count();
+    assert_eq!(remaining, SCANCODE_QUEUE_SIZE);
+}
+
+#[test_case]
+fn test_lock_keys_toggle() {
+    let mut decoder = Decoder::new(Layout::Us104);
+    assert_eq!(decoder.locks(), LockKeys::new());
+    assert_eq!(decoder.locks().led_byte(), 0b010);
+    //按下并松开Caps Lock、Num Lock和Scroll Lock，只有按下会切换
+    for code in [0x3a, 0xba, 0x45, 0xc5, 0x46, 0xc6] {
+        decoder.add_event(code);
+    }
+    let locks = decoder.locks();
+    assert_eq!((locks.scroll, locks.num, locks.caps), (true, false, true));
+    assert_eq!(locks.led_byte(), 0b101);
+    //按住Caps Lock时的重复按下也会切换，与pc-keyboard保持一致
+    decoder.add_event(0x3a);
+    assert!(!decoder.locks().caps);
+    assert_eq!(
+        decode_all(&mut decoder, &[0x3a, 0xba, 0x1e]),
+        Some(DecodedKey::Unicode('A'))
+    );
+}
+
+//按脚本提供应答运行LED命令交换，返回结果、发送的字节数和转交的字节数
+#[cfg(test)]
+fn run_led_script(
+    leds: u8,
+    replies: &[(Ps2Port, u8)],
+    sent: &mut [u8; 8],
+    forwarded: &mut [(Ps2Port, u8); 4],
+) -> (Result<(), LedError>, usize, usize) {
+    let (mut sent_len, mut forwarded_len) = (0, 0);
+    let mut replies = replies.iter().copied();
+    let result = exchange_leds(
+        leds,
+        |byte| {
+            sent[sent_len] = byte;
+            sent_len += 1;
+            Ok(())
+        },
+        || replies.next().ok_or(Ps2Error::Timeout),
+        |port, code| {
+            forwarded[forwarded_len] = (port, code);
+            forwarded_len += 1;
+        },
+    );
+    (result, sent_len, forwarded_len)
+}
+
+//全部来自键盘的应答
+#[cfg(test)]
+fn from_keyboard<const N: usize>(bytes: [u8; N]) -> [(Ps2Port, u8); N] {
+    bytes.map(|byte| (Ps2Port::Port1, byte))
+}
+
+#[test_case]
+fn test_led_exchange_ack() {
+    let (mut sent, mut forwarded) = ([0; 8], [(Ps2Port::Port1, 0); 4]);
+    let (result, sent_len, forwarded_len) =
+        run_led_script(0b100, &from_keyboard([ACK, ACK]), &mut sent, &mut forwarded);
+    assert_eq!(result, Ok(()));
+    assert_eq!(sent[..sent_len], [SET_LEDS, 0b100]);
+    assert_eq!(forwarded_len, 0);
+}
+
+#[test_case]
+fn test_led_exchange_resend() {
+    let (mut sent, mut forwarded) = ([0; 8], [(Ps2Port::Port1, 0); 4]);
+    //命令字节重发一次，参数字节重发两次
+    let replies = from_keyboard([RESEND, ACK, RESEND, RESEND, ACK]);
+    let (result, sent_len, _) = run_led_script(0b001, &replies, &mut sent, &mut forwarded);
+    assert_eq!(result, Ok(()));
+    assert_eq!(sent[..sent_len], [SET_LEDS, SET_LEDS, 1, 1, 1]);
+
+    //超过重发次数上限
+    let replies = from_keyboard([ACK, RESEND, RESEND, RESEND, RESEND]);
+    let (result, sent_len, _) = run_led_script(0b001, &replies, &mut sent, &mut forwarded);
+    assert_eq!(result, Err(LedError::TooManyResends { byte: 1 }));
+    assert_eq!(sent_len, 1 + 1 + LED_RESEND_LIMIT);
+}
+
+#[test_case]
+fn test_led_exchange_forwards_scancodes() {
+    let (mut sent, mut forwarded) = ([0; 8], [(Ps2Port::Port1, 0); 4]);
+    //应答之间按下并松开了A键
+    let replies = from_keyboard([0x1e, ACK, 0x9e, ACK]);
+    let (result, sent_len, forwarded_len) = run_led_script(0, &replies, &mut sent, &mut forwarded);
+    assert_eq!(result, Ok(()));
+    assert_eq!(sent_len, 2);
+    assert_eq!(
+        forwarded[..forwarded_len],
+        [(Ps2Port::Port1, 0x1e), (Ps2Port::Port1, 0x9e)]
+    );
+}
+
+#[test_case]
+fn test_led_exchange_timeout() {
+    let (mut sent, mut forwarded) = ([0; 8], [(Ps2Port::Port1, 0); 4]);
+    //参数字节没有应答
+    let (result, sent_len, _) = run_led_script(0, &from_keyboard([ACK]), &mut sent, &mut forwarded);
+    assert_eq!(result, Err(LedError::Ps2(Ps2Error::Timeout)));
+    assert_eq!(sent_len, 2);
+}
+
+#[test_case]
+fn test_led_exchange_routes_mouse_bytes() {
+    let (mut sent, mut forwarded) = ([0; 8], [(Ps2Port::Port1, 0); 4]);
+    //等待应答时鼠标发来一个数据包，其中的0xFA不是键盘的应答
+    let replies = [
+        (Ps2Port::Port2, 0x08),
+        (Ps2Port::Port2, ACK),
+        (Ps2Port::Port1, ACK),
+        (Ps2Port::Port2, 0x00),
+        (Ps2Port::Port1, ACK),
+    ];
+    let (result, sent_len, forwarded_len) = run_led_script(0, &replies, &mut sent, &mut forwarded);
+    assert_eq!(result, Ok(()));
+    assert_eq!(sent_len, 2);
+    assert_eq!(
+        forwarded[..forwarded_len],
+        [
+            (Ps2Port::Port2, 0x08),
+            (Ps2Port::Port2, ACK),
+            (Ps2Port::Port2, 0x00)
+        ]
+    );
+}
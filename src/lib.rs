@@ -9,16 +9,39 @@
 extern crate alloc;
 
 pub mod allocator;
+#[cfg(feature = "apic")]
+pub mod apic;
+pub mod cpu;
+pub mod crash;
+pub mod drivers;
+pub mod framebuffer;
+pub mod fs;
 pub mod gdt;
+pub mod hpet;
 pub mod interrupts;
+#[cfg(feature = "apic")]
+pub mod ioapic;
+pub mod keyboard;
 pub mod memory;
+pub mod mouse;
+pub mod percpu;
+pub mod power;
+pub mod rand;
+pub mod rtc;
 pub mod serial;
+pub mod storage;
+pub mod syscall;
+pub mod time;
 pub mod vga_buffer;
+pub mod vga_graphics;
+pub mod watchdog;
 
 use core::panic::PanicInfo;
 
+use bootloader::BootInfo;
+
 #[cfg(test)]
-use bootloader::{entry_point, BootInfo};
+use bootloader::entry_point;
 
 #[cfg(test)]
 entry_point!(test_kernel_main);
@@ -59,12 +82,20 @@ pub fn test_runner(tests: &[&dyn Testable]) {
     for test in tests {
         test.run();
     }
+    serial_println!("Peak heap usage: {} bytes", allocator::peak_usage());
     exit_qemu(QemuExitCode::Success);
+    //没有isa-debug-exit设备时（例如在真实硬件上运行测试）关机
+    #[cfg(feature = "poweroff-after-tests")]
+    power::shutdown();
 }
 
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
-    serial_println!("[failed]\n");
-    serial_println!("Error: {}\n", info);
+    //在其他代码覆盖寄存器之前保存
+    let regs = crash::snapshot();
+    //panic可能发生在持有串口锁的代码中
+    serial_emergency_println!("[failed]\n");
+    serial_emergency_println!("Error: {}\n", info);
+    crash::dump_to_serial(&regs);
     exit_qemu(QemuExitCode::Failed);
     loop {}
 }
@@ -81,8 +112,8 @@ fn panic(info: &PanicInfo) -> ! {
 /// ## 用法
 /// 这个函数不需要你来调用
 #[cfg(test)]
-fn test_kernel_main(_boot_info: &'static BootInfo) -> ! {
-    init();
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    init(boot_info);
     test_main();
     hlt_loop();
 }
@@ -90,17 +121,86 @@ fn test_kernel_main(_boot_info: &'static BootInfo) -> ! {
 /// ## 函数说明
 /// 初始化一些列操作
 ///
+/// ## 参数
+/// * `boot_info` - bootloader提供的启动信息
+///
 /// ## 用法
 /// ```rust
-/// init();
+/// init(boot_info);
 /// ```
-pub fn init() {
+pub fn init(boot_info: &'static BootInfo) {
+    cpu::enable_sse(); //SSE指令和#XM依赖CR0、CR4，不使用引导程序留下的状态
+    memory::vspace::init(); //在堆和MMIO映射之前确定虚拟地址布局
+    assert!(
+        cpu::enable_nx(), //之后映射的堆、栈和W^X保护的数据页面都会设置NX位
+        "W^X protection requires the NX bit, but CPUID reports no NX support"
+    );
+    init_paging(boot_info); //GDT中的IST栈需要映射页面，因此先安装页表
+    memory::with_paging(|paging| {
+        memory::apply_wx_protection(&mut paging.mapper, &memory::kernel_regions());
+        memory::protect_boot_mappings(&mut paging.mapper);
+    });
+    memory::with_paging(|paging| {
+        vga_buffer::remap(&mut paging.mapper, &mut paging.frame_allocator)
+    })
+    .expect("paging not installed")
+    .expect("failed to remap VGA buffer"); //控制台不再依赖0xb8000的恒等映射
+    let _ = framebuffer::init(boot_info); //没有帧缓冲区时继续使用VGA文本模式
+    unmap_boot_identity(boot_info); //此后解引用较小的整数地址会引发页错误
     gdt::init(); //在初始化IDT前加载GDT处理Double Fault等情况
+    percpu::init(); //之后不能再加载GS段寄存器，否则会清除GS基址
     interrupts::init_idt();
     unsafe { interrupts::PICS.lock().initialize() };
+    #[cfg(feature = "apic")]
+    {
+        //屏蔽PIC，改由IOAPIC投递IRQ，向量不变
+        apic::init().expect("local APIC initialization failed");
+        ioapic::init().expect("IOAPIC initialization failed");
+    }
+    //控制器初始化失败时键盘和鼠标都不可用，系统照常启动
+    let _ = drivers::ps2::init();
+    keyboard::init();
+    time::init(time::DEFAULT_FREQUENCY).expect("invalid timer frequency");
+    //没有HPET时uptime_ms继续使用时钟滴答
+    let _ = hpet::init(x86_64::PhysAddr::new(hpet::DEFAULT_BASE));
+    let _ = time::tsc::init(); //有HPET时以它为参考重新校准TSC，失败时保留time::init的结果
+    drivers::cmos::begin_boot(); //启动次数加一，由power::shutdown标记正常关机
     x86_64::instructions::interrupts::enable();
 }
 
+/// ## 函数说明
+/// 移除bootloader留下的低端恒等映射，只保留BootInfo
+/// 需要在`vga_buffer::remap`之后调用，否则内核会失去自己的控制台
+///
+/// ## 参数
+/// * `boot_info` - bootloader提供的启动信息
+pub fn unmap_boot_identity(boot_info: &'static BootInfo) {
+    let boot_info_start = boot_info as *const BootInfo as u64;
+    let keep = [boot_info_start..boot_info_start + core::mem::size_of::<BootInfo>() as u64];
+    memory::with_paging(|paging| {
+        memory::unmap_identity_region(&mut paging.mapper, &keep, &mut paging.frame_allocator)
+    });
+}
+
+/// ## 函数说明
+/// 根据bootloader提供的信息创建页表和帧分配器，并安装为全局页表
+///
+/// ## 参数
+/// * `boot_info` - bootloader提供的启动信息
+pub fn init_paging(boot_info: &'static BootInfo) {
+    use memory::BootInfoFrameAllocator;
+    use x86_64::{PhysAddr, VirtAddr};
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    //低端内存（包括VGA缓冲区）不参与分配
+    frame_allocator
+        .reserve_range(PhysAddr::new(0), PhysAddr::new(memory::LOW_MEMORY_END))
+        .expect("failed to reserve low memory");
+    memory::install_paging(mapper, frame_allocator);
+}
+
 /// ## 函数说明
 /// 使用hlt指令让CPU在下一个中断出发之前休眠，来代替loop{}
 ///
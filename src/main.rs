@@ -11,7 +11,7 @@ extern crate alloc;
 use alloc::boxed::Box;
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
-use os::println;
+use os::{print, println};
 
 entry_point!(kernel_main);
 
@@ -25,18 +25,20 @@ entry_point!(kernel_main);
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     println!("Hello World{}", "!");
 
-    os::init();
+    os::init(boot_info);
+    os::cpu::cpuid::print_summary();
+    if let Err(err) = os::mouse::init() {
+        println!("PS/2 mouse unavailable: {:?}", err);
+    }
 
     // --------------------
     use os::allocator;
-    use os::memory::{self, BootInfoFrameAllocator};
-    use x86_64::VirtAddr;
-    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
-    let mut mapper = unsafe { memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    use os::memory;
+    memory::print_memory_map(&boot_info.memory_map);
 
     // new
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    allocator::init_heap().expect("heap initialization failed");
+    memory::print_frame_stats();
     use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
     let heap_value = Box::new(41);
     println!("heap_value at {:p}", heap_value);
@@ -67,15 +69,50 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 
     println!("It did not crash!");
 
-    os::hlt_loop();
+    os::watchdog::arm(5 * u64::from(os::time::frequency())); //5秒内没有时钟中断或键盘中断卡住时报告
+    echo_keys();
+}
+
+/// ## 函数说明
+/// 解码键盘中断收到的扫描码并打印按下的键，没有输入时停机等待下一个中断
+fn echo_keys() -> ! {
+    use os::keyboard;
+    use pc_keyboard::DecodedKey;
+    use x86_64::instructions::interrupts;
+
+    loop {
+        os::watchdog::check();
+        while let Some(event) = keyboard::poll_event() {
+            if event.pressed && !event.is_modifier() {
+                match event.key {
+                    DecodedKey::Unicode(character) => print!("{}", character),
+                    DecodedKey::RawKey(key) => print!("{:?}", key),
+                }
+            }
+        }
+        //关闭中断后再检查队列，避免扫描码在检查之后、hlt之前到达而要等下一个中断才被处理
+        interrupts::disable();
+        if keyboard::has_pending() {
+            interrupts::enable();
+        } else {
+            interrupts::enable_and_hlt();
+        }
+    }
 }
 
 /// This function is called on panic.
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("{}", info);
-    loop {}
+    //在其他代码覆盖寄存器之前保存
+    let regs = os::crash::snapshot();
+    //panic可能发生在持有WRITER或串口锁的代码中，只使用不会死锁的打印方式
+    os::try_println!("{}", info);
+    os::crash::dump_to_screen(&regs);
+    os::serial_emergency_println!("{}", info);
+    os::crash::dump_to_serial(&regs);
+    os::drivers::speaker::on_panic();
+    os::power::after_panic();
 }
 
 #[cfg(test)]
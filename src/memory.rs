@@ -1,41 +1,627 @@
+pub mod address_space;
+pub mod audit;
+pub mod boot_map;
+pub mod cow;
+pub mod debug;
+pub mod error;
+pub mod fault;
+pub mod guard;
+pub mod identity;
+pub mod lazy;
+pub mod low_pool;
+pub mod phys;
+pub mod protect;
+pub mod replace;
+pub mod stack_allocator;
+pub mod vmalloc;
+pub mod vspace;
+pub mod wx;
+pub mod zeroing_wrapper;
+
+pub use address_space::{
+    switch_address_space, with_address_space, AddressSpace, AddressSpaceToken, SwitchError,
+};
+pub use audit::{audit, AuditReport};
+pub use boot_map::{classify, holes, overlapping_regions, RegionTotals};
+pub use cow::mark_cow;
+pub use debug::{
+    count_mapped_pages, dump_translation, entry_flags, format_entry, print_fault_entries,
+    table_frames_allocated, EntryFormat, FlagString, MappingCounts,
+};
+pub use error::MemoryError;
+pub use fault::{classify_fault, FaultRegion, FaultSummary};
+pub use identity::unmap_identity_region;
+pub use lazy::alloc_lazy;
+pub use low_pool::{alloc_dma_frames, free_dma_frames, LowFramePool, DMA_LIMIT};
+pub use phys::{phys_offset, phys_read, phys_slice, phys_to_virt, phys_write};
+pub use protect::{protect, protect_range, ProtectError};
+pub use replace::{replace_mapping, ReplaceError};
+pub use stack_allocator::{KernelStack, StackAllocError, StackAllocator};
+pub use vmalloc::{vfree, vmalloc, VmallocError};
+pub use wx::{apply_wx_protection, kernel_regions, protect_boot_mappings};
+pub use zeroing_wrapper::ZeroingFrameAllocator;
+
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
 use x86_64::{
     structures::paging::{
-        FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame, Size4KiB,
+        mapper::{MapToError, MappedFrame, TranslateResult, UnmapError},
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTable,
+        PageTableFlags, PhysFrame, Size2MiB, Size4KiB, Translate,
     },
     PhysAddr, VirtAddr,
 };
 
+/// ## 说明
+/// 内核全局的页表与帧分配器，供堆增长等无法获得局部mapper的场景使用
+pub struct KernelPaging {
+    pub mapper: OffsetPageTable<'static>,
+    pub frame_allocator: ZeroingFrameAllocator<ReusingFrameAllocator<BootInfoFrameAllocator>>,
+}
+
+static KERNEL_PAGING: spin::Mutex<Option<KernelPaging>> = spin::Mutex::new(None);
+
+/// ## 函数说明
+/// 将页表和帧分配器交给全局，之后通过`with_paging`访问
+/// 帧分配器会被包装为可回收帧的`ReusingFrameAllocator`，并在分配时清零
+///
+/// ## 用法
+/// ```rust
+/// install_paging(mapper, frame_allocator);
+/// ```
+pub fn install_paging(mapper: OffsetPageTable<'static>, frame_allocator: BootInfoFrameAllocator) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let phys_offset = mapper.phys_offset();
+        *KERNEL_PAGING.lock() = Some(KernelPaging {
+            mapper,
+            frame_allocator: unsafe {
+                ZeroingFrameAllocator::new(
+                    ReusingFrameAllocator::new(frame_allocator, phys_offset),
+                    phys_offset,
+                )
+            },
+        });
+    });
+}
+
+/// ## 函数说明
+/// 在禁用中断的情况下访问全局页表，尚未调用`install_paging`时返回`None`
+///
+/// 闭包内不能进行堆分配，否则可能与堆增长发生死锁
+///
+/// ## 用法
+/// ```rust
+/// with_paging(|paging| paging.mapper.translate_addr(addr));
+/// ```
+pub fn with_paging<R>(f: impl FnOnce(&mut KernelPaging) -> R) -> Option<R> {
+    x86_64::instructions::interrupts::without_interrupts(|| KERNEL_PAGING.lock().as_mut().map(f))
+}
+
+/// 1MiB以下的低端内存的结束地址，这部分内存留给将来的SMP启动代码，不由帧分配器分配
+pub const LOW_MEMORY_END: u64 = 0x10_0000;
+
+/// 每个`BootInfoFrameAllocator`最多可以保留的物理范围数
+pub const MAX_RESERVED_RANGES: usize = 8;
+
+/// ## 说明
+/// `reserve_range`可能出现的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReserveError {
+    /// 已经开始分配帧，之后的保留无法保证生效
+    AllocationStarted,
+    /// 保留的范围数已达`MAX_RESERVED_RANGES`
+    TooManyRanges,
+    /// 起始地址不小于结束地址
+    EmptyRange,
+}
+
+/// ## 说明
+/// 从bootloader提供的内存映射中依次分配可用帧
+///
+/// ## 成员
+/// * `memory_map` - 内存映射
+/// * `region` - 当前所在内存区域的下标
+/// * `next_addr` - 当前区域中下一个待分配帧的地址
+/// * `allocated` - 已分配的4KiB帧数，一个2MiB帧计为512个
+/// * `total_usable` - 内存映射中可用的4KiB帧总数（不含保留的帧），在`init`时计算
+/// * `reserved` - 不会被分配的物理范围[起始地址, 结束地址)，已按帧对齐
+/// * `low_pool` - 16MiB以下的可用帧，游标不会进入这一范围
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
-    next: usize,
+    region: usize,
+    next_addr: u64,
+    allocated: usize,
+    total_usable: usize,
+    reserved: [Option<(u64, u64)>; MAX_RESERVED_RANGES],
+    low_pool: LowFramePool,
 }
 
 impl BootInfoFrameAllocator {
     pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
-        BootInfoFrameAllocator {
+        boot_map::set_memory_map(memory_map);
+        let mut low_pool = LowFramePool::new();
+        for region in memory_map.iter() {
+            if region.region_type == MemoryRegionType::Usable {
+                let range = &region.range;
+                low_pool.add_range(
+                    PhysAddr::new(range.start_addr()),
+                    PhysAddr::new(range.end_addr()),
+                );
+            }
+        }
+        let mut allocator = BootInfoFrameAllocator {
             memory_map,
-            next: 0,
+            region: 0,
+            next_addr: 0,
+            allocated: 0,
+            total_usable: 0,
+            reserved: [None; MAX_RESERVED_RANGES],
+            low_pool,
+        };
+        allocator.total_usable = allocator.count_usable();
+        allocator
+    }
+
+    //游标可分配的帧加上低端内存池中的帧
+    fn count_usable(&self) -> usize {
+        self.usable_frames().count() + self.low_pool.free_frames()
+    }
+
+    /// ## 函数说明
+    /// 保留一段物理范围，其中的帧即使在内存映射中标记为可用也不会被分配
+    /// 范围会扩展到帧边界；必须在第一次分配之前调用
+    ///
+    /// ## 参数
+    /// * `start` - 起始物理地址
+    /// * `end` - 结束物理地址（不含）
+    ///
+    /// ## 用法
+    /// ```rust
+    /// //为SMP启动代码保留1MiB以下的内存
+    /// frame_allocator.reserve_range(PhysAddr::new(0), PhysAddr::new(0x10_0000))?;
+    /// ```
+    pub fn reserve_range(&mut self, start: PhysAddr, end: PhysAddr) -> Result<(), ReserveError> {
+        //游标已经移动说明分配已经开始（包括失败的分配）
+        if self.allocated > 0 || self.region > 0 || self.next_addr > 0 {
+            return Err(ReserveError::AllocationStarted);
         }
+        if start >= end {
+            return Err(ReserveError::EmptyRange);
+        }
+        let slot = self
+            .reserved
+            .iter_mut()
+            .find(|range| range.is_none())
+            .ok_or(ReserveError::TooManyRanges)?;
+        let range = (
+            start.align_down(4096u64).as_u64(),
+            end.align_up(4096u64).as_u64(),
+        );
+        *slot = Some(range);
+        self.low_pool.remove_range(start, end);
+        self.total_usable = self.count_usable();
+        Ok(())
+    }
+
+    /// ## 函数说明
+    /// 从低端内存池分配一个物理地址低于`limit`的帧，`limit`超过`DMA_LIMIT`时按`DMA_LIMIT`处理
+    ///
+    /// ## 参数
+    /// * `limit` - 物理地址上限
+    pub fn allocate_frame_below(&mut self, limit: PhysAddr) -> Option<PhysFrame> {
+        self.allocate_contiguous_below(1, limit)
+    }
+
+    /// ## 函数说明
+    /// 从低端内存池分配`count`个物理上连续且低于`limit`的帧，返回第一个帧
+    ///
+    /// ## 参数
+    /// * `count` - 帧数
+    /// * `limit` - 物理地址上限
+    pub fn allocate_contiguous_below(
+        &mut self,
+        count: usize,
+        limit: PhysAddr,
+    ) -> Option<PhysFrame> {
+        let frame = self.low_pool.allocate_contiguous_below(count, limit)?;
+        self.allocated += count;
+        Some(frame)
+    }
+
+    /// ## 函数说明
+    /// 将低端内存池分配的帧归还给内存池，而不是交给通用的空闲栈
+    ///
+    /// ## 参数
+    /// * `frame` - 低于`DMA_LIMIT`的帧
+    pub fn deallocate_low(&mut self, frame: PhysFrame) {
+        self.low_pool.deallocate(frame);
+        self.allocated -= 1;
+    }
+
+    /// ## 函数说明
+    /// 创建分配器时使用的内存映射
+    pub fn memory_map(&self) -> &'static MemoryMap {
+        self.memory_map
+    }
+
+    /// ## 函数说明
+    /// 低端内存池中剩余的帧数
+    pub fn remaining_low_frames(&self) -> usize {
+        self.low_pool.free_frames()
+    }
+
+    //若[addr, addr + size)与某个保留范围重叠，返回该范围的结束地址
+    fn reserved_end(&self, addr: u64, size: u64) -> Option<u64> {
+        overlapping_reserved(&self.reserved, addr, size)
     }
 
     pub fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
+        let reserved = self.reserved;
         let regions = self.memory_map.iter();
         let usable_regions = regions.filter(|r| r.region_type == MemoryRegionType::Usable);
         // 将每个区域映射到其地址范围
         let addr_ranges = usable_regions.map(|r| r.range.start_addr()..r.range.end_addr());
         // 转化为一个帧起始地址的迭代器
         let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
+        // 跳过低端内存池和保留的帧
+        let frame_addresses = frame_addresses.filter(move |&addr| {
+            addr >= DMA_LIMIT && overlapping_reserved(&reserved, addr, 4096).is_none()
+        });
         // 从起始地址创建 `PhysFrame`  类型
         frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
     }
 }
 
+fn overlapping_reserved(reserved: &[Option<(u64, u64)>], addr: u64, size: u64) -> Option<u64> {
+    reserved
+        .iter()
+        .flatten()
+        .find(|&&(start, end)| addr < end && start < addr + size)
+        .map(|&(_, end)| end)
+}
+
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+    //逐区域推进游标，返回的帧序列与usable_frames()一致
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        while let Some(region) = self.memory_map.get(self.region) {
+            if region.region_type == MemoryRegionType::Usable {
+                let addr = self.next_addr.max(region.range.start_addr());
+                if addr < DMA_LIMIT {
+                    self.next_addr = DMA_LIMIT; //低端内存由low_pool管理
+                    continue;
+                }
+                if let Some(end) = self.reserved_end(addr, 4096) {
+                    self.next_addr = end; //跳过保留的范围后在同一区域中重试
+                    continue;
+                }
+                if addr < region.range.end_addr() {
+                    self.next_addr = addr + 4096;
+                    self.allocated += 1;
+                    return Some(PhysFrame::containing_address(PhysAddr::new(addr)));
+                }
+            }
+            self.region += 1;
+            self.next_addr = 0;
+        }
+
+        //高端内存耗尽后才借用低端内存池，并保留LOW_POOL_MIN个帧给DMA
+        if self.low_pool.free_frames() > low_pool::LOW_POOL_MIN {
+            return self.allocate_frame_below(PhysAddr::new(DMA_LIMIT));
+        }
+        None
+    }
+}
+
+//从当前区域中分配按2MiB对齐的连续帧，为对齐而跳过的4KiB帧不会再被分配
+unsafe impl FrameAllocator<Size2MiB> for BootInfoFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        while let Some(region) = self.memory_map.get(self.region) {
+            if region.region_type == MemoryRegionType::Usable {
+                let addr = align_up(
+                    self.next_addr.max(region.range.start_addr()),
+                    Size2MiB::SIZE,
+                );
+                if addr < DMA_LIMIT {
+                    self.next_addr = DMA_LIMIT;
+                    continue;
+                }
+                if let Some(end) = self.reserved_end(addr, Size2MiB::SIZE) {
+                    self.next_addr = end;
+                    continue;
+                }
+                if addr + Size2MiB::SIZE <= region.range.end_addr() {
+                    self.next_addr = addr + Size2MiB::SIZE;
+                    self.allocated += (Size2MiB::SIZE / 4096) as usize;
+                    return Some(PhysFrame::containing_address(PhysAddr::new(addr)));
+                }
+            }
+            self.region += 1;
+            self.next_addr = 0;
+        }
+
+        None
+    }
+}
+
+fn align_up(addr: u64, align: u64) -> u64 {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// ## 说明
+/// 帧分配器的使用统计，单位均为4KiB帧
+pub trait FrameStats {
+    /// 已分配且尚未归还的帧数，`ReusingFrameAllocator`中为分配数减去归还数，
+    /// 归还不是由它分配的帧时会少计
+    fn allocated_frames(&self) -> usize;
+
+    /// 可用的帧总数
+    fn total_usable_frames(&self) -> usize;
+
+    /// 剩余可分配的帧数
+    fn remaining_frames(&self) -> usize {
+        self.total_usable_frames()
+            .saturating_sub(self.allocated_frames())
+    }
+}
+
+//为2MiB对齐而跳过的帧不会被计入已分配帧数
+impl FrameStats for BootInfoFrameAllocator {
+    fn allocated_frames(&self) -> usize {
+        self.allocated
+    }
+
+    fn total_usable_frames(&self) -> usize {
+        self.total_usable
+    }
+}
+
+/// ## 说明
+/// 可回收物理帧的帧分配器，被释放的帧组成一个栈，优先从栈中分配
+/// 栈直接保存在空闲帧中：每个空闲帧的头8字节存放下一个空闲帧的物理地址
+///
+/// ## 成员
+/// * `inner` - 栈为空时使用的帧分配器
+/// * `phys_offset` - 物理内存映射的偏移量，用于访问空闲帧
+/// * `free_head` - 栈顶的空闲帧
+/// * `free_count` - 栈中的帧数
+pub struct ReusingFrameAllocator<A> {
+    inner: A,
+    phys_offset: VirtAddr,
+    free_head: Option<PhysFrame>,
+    free_count: usize,
+}
+
+impl<A: FrameAllocator<Size4KiB>> ReusingFrameAllocator<A> {
+    /// ## 函数说明
+    /// 包装一个帧分配器
+    ///
+    /// ## 参数
+    /// * `inner` - 被包装的帧分配器
+    /// * `phys_offset` - 完整物理内存映射的起始虚拟地址，调用者需保证其正确
+    pub unsafe fn new(inner: A, phys_offset: VirtAddr) -> Self {
+        ReusingFrameAllocator {
+            inner,
+            phys_offset,
+            free_head: None,
+            free_count: 0,
+        }
+    }
+
+    /// ## 函数说明
+    /// 空闲栈中的帧数
+    pub fn free_frames(&self) -> usize {
+        self.free_count
+    }
+
+    /// ## 函数说明
+    /// 获取被包装的帧分配器
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// ## 函数说明
+    /// 获取被包装的帧分配器的可变引用
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    fn link_ptr(&self, frame: PhysFrame) -> *mut u64 {
+        (self.phys_offset + frame.start_address().as_u64()).as_mut_ptr()
+    }
+}
+
+unsafe impl<A: FrameAllocator<Size4KiB>> FrameAllocator<Size4KiB> for ReusingFrameAllocator<A> {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
+        match self.free_head {
+            Some(frame) => {
+                let next = unsafe { self.link_ptr(frame).read() };
+                self.free_count -= 1;
+                self.free_head = if self.free_count == 0 {
+                    None
+                } else {
+                    Some(PhysFrame::containing_address(PhysAddr::new(next)))
+                };
+                Some(frame)
+            }
+            None => self.inner.allocate_frame(),
+        }
+    }
+}
+
+//2MiB帧不经过空闲栈，直接由被包装的分配器提供
+unsafe impl<A> FrameAllocator<Size2MiB> for ReusingFrameAllocator<A>
+where
+    A: FrameAllocator<Size4KiB> + FrameAllocator<Size2MiB>,
+{
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        self.inner.allocate_frame()
+    }
+}
+
+//已分配帧数按"inner分配的帧数减去空闲栈中的帧数"计算。空闲栈也接收不是inner分配的帧，
+//例如`unmap_identity_region`归还的引导程序页表帧，每归还一个这样的帧，已分配帧数就少计一个，
+//剩余帧数相应多计一个，因此使用饱和减法
+impl<A: FrameStats> FrameStats for ReusingFrameAllocator<A> {
+    fn allocated_frames(&self) -> usize {
+        self.inner
+            .allocated_frames()
+            .saturating_sub(self.free_count)
+    }
+
+    fn total_usable_frames(&self) -> usize {
+        self.inner.total_usable_frames()
+    }
+}
+
+impl<A: FrameAllocator<Size4KiB>> FrameDeallocator<Size4KiB> for ReusingFrameAllocator<A> {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let next = self.free_head.map_or(0, |head| head.start_address().as_u64());
+        self.link_ptr(frame).write(next);
+        self.free_head = Some(frame);
+        self.free_count += 1;
+    }
+}
+
+/// ## 说明
+/// 以KiB/MiB为单位显示字节数，格式化时不分配内存
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const KIB: u64 = 1024;
+        const MIB: u64 = 1024 * KIB;
+        match self.0 {
+            n if n >= MIB && n % MIB == 0 => write!(f, "{} MiB", n / MIB),
+            n if n >= MIB => write!(f, "{}.{:02} MiB", n / MIB, n % MIB * 100 / MIB),
+            n if n >= KIB => write!(f, "{} KiB", n / KIB),
+            n => write!(f, "{} B", n),
+        }
+    }
+}
+
+/// ## 函数说明
+/// 统计内存映射中可用内存的总字节数
+///
+/// ## 参数
+/// * `memory_map` - 内存映射
+pub fn total_usable_bytes(memory_map: &MemoryMap) -> u64 {
+    memory_map
+        .iter()
+        .filter(|r| r.region_type == MemoryRegionType::Usable)
+        .map(|r| r.range.end_addr() - r.range.start_addr())
+        .sum()
+}
+
+/// ## 函数说明
+/// 内存映射中最后一个区域的结束地址，即物理内存的上限
+///
+/// ## 参数
+/// * `memory_map` - 内存映射
+pub fn phys_memory_end(memory_map: &MemoryMap) -> u64 {
+    memory_map
+        .iter()
+        .map(|r| r.range.end_addr())
+        .max()
+        .unwrap_or(0)
+}
+
+/// ## 函数说明
+/// 统计内存映射中不可用（已被占用或保留）内存的总字节数
+///
+/// ## 参数
+/// * `memory_map` - 内存映射
+pub fn total_reserved_bytes(memory_map: &MemoryMap) -> u64 {
+    memory_map
+        .iter()
+        .filter(|r| r.region_type != MemoryRegionType::Usable)
+        .map(|r| r.range.end_addr() - r.range.start_addr())
+        .sum()
+}
+
+/// ## 函数说明
+/// 在屏幕上打印每个内存区域的起止地址、大小和类型，每种类型的总量，区域之间的空洞，
+/// 以及可用与保留内存的总量；重叠的区域会作为固件错误给出警告
+///
+/// ## 参数
+/// * `memory_map` - 内存映射
+///
+/// ## 用法
+/// ```rust
+/// print_memory_map(&boot_info.memory_map);
+/// ```
+pub fn print_memory_map(memory_map: &MemoryMap) {
+    use crate::println;
+
+    for region in memory_map.iter() {
+        let (start, end) = (region.range.start_addr(), region.range.end_addr());
+        println!(
+            "{:#012x}-{:#012x} {} {:?}",
+            start,
+            end,
+            ByteSize(end - start),
+            region.region_type
+        );
+    }
+    for (kind, total) in RegionTotals::new(memory_map).iter() {
+        println!("  {:?}: {}", kind, ByteSize(total));
+    }
+    for (start, end) in holes(memory_map) {
+        println!(
+            "hole: {:#012x}-{:#012x} {}",
+            start,
+            end,
+            ByteSize(end - start)
+        );
+    }
+    for (a, b) in overlapping_regions(memory_map) {
+        println!(
+            "warning: firmware reported overlapping regions {:#x}-{:#x} {:?} and {:#x}-{:#x} {:?}",
+            a.range.start_addr(),
+            a.range.end_addr(),
+            a.region_type,
+            b.range.start_addr(),
+            b.range.end_addr(),
+            b.region_type
+        );
+    }
+    println!(
+        "usable: {}, reserved: {}",
+        ByteSize(total_usable_bytes(memory_map)),
+        ByteSize(total_reserved_bytes(memory_map))
+    );
+}
+
+/// ## 函数说明
+/// 在屏幕上打印全局帧分配器的使用情况，尚未安装全局页表时不打印
+///
+/// ## 用法
+/// ```rust
+/// print_frame_stats();
+/// ```
+pub fn print_frame_stats() {
+    use crate::println;
+
+    let stats = with_paging(|paging| {
+        let frames = &paging.frame_allocator;
+        let offset = paging.mapper.phys_offset();
+        (
+            frames.allocated_frames(),
+            frames.remaining_frames(),
+            frames.total_usable_frames(),
+            table_frames_allocated(paging.mapper.level_4_table(), offset),
+        )
+    });
+    if let Some((allocated, remaining, total, tables)) = stats {
+        println!(
+            "frames: {} allocated ({} page tables), {} remaining of {} ({})",
+            allocated,
+            tables,
+            remaining,
+            total,
+            ByteSize(remaining as u64 * 4096)
+        );
     }
 }
 
@@ -62,68 +648,546 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut
     &mut *page_table_ptr
 }
 
+/// ## 函数说明
+/// 通过当前活动的4级页表将虚拟地址转换为物理地址，仅为兼容旧代码保留
+#[deprecated(note = "use memory::translate with a mapper instead")]
 pub unsafe fn translate_addr(addr: VirtAddr, physical_memory_offset: VirtAddr) -> Option<PhysAddr> {
-    translate_addr_inner(addr, physical_memory_offset)
+    let mapper = OffsetPageTable::new(
+        active_level_4_table(physical_memory_offset),
+        physical_memory_offset,
+    );
+    translate(&mapper, addr).map(|(phys, _, _)| phys)
+}
+
+/// ## 说明
+/// 映射的页面大小
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingSize {
+    Size4KiB,
+    Size2MiB,
+    Size1GiB,
 }
 
 /// ## 函数说明
-/// 由translate_addr调用。此函数只能通过`unsafe fn`从这个模块的外部到达。
+/// 将虚拟地址转换为物理地址，并返回该地址所在映射的页面大小和页表项标志，支持大页
 ///
 /// ## 参数
-/// * `addr` - 地址
-/// * `physical_memory_offset` - 偏移量
-fn translate_addr_inner(addr: VirtAddr, physical_memory_offset: VirtAddr) -> Option<PhysAddr> {
-    use x86_64::registers::control::Cr3;
-    use x86_64::structures::paging::page_table::FrameError;
-
-    // 从CR3寄存器读取活动的4级frame
-    let (level_4_table_frame, _) = Cr3::read();
-
-    // 构建页表索引数组
-    let table_indexes = [
-        addr.p4_index(),
-        addr.p3_index(),
-        addr.p2_index(),
-        addr.p1_index(),
-    ];
-
-    let mut frame = level_4_table_frame;
-    //遍历多级页表
-    for &index in &table_indexes {
-        let virt = physical_memory_offset + frame.start_address().as_u64();
-        let table_ptr: *const PageTable = virt.as_ptr();
-        let table = unsafe { &*table_ptr };
-
-        //读取页表条目并更新frame
-        let entry = &table[index];
-        frame = match entry.frame() {
-            Ok(frame) => frame,
-            Err(FrameError::FrameNotPresent) => return None, //注意return
-            Err(FrameError::HugeFrame) => panic!("huge pages not supported"),
-        };
+/// * `mapper` - 页表
+/// * `addr` - 虚拟地址
+///
+/// ## 用法
+/// ```rust
+/// let (phys, size, flags) = translate(&mapper, addr)?;
+/// ```
+pub fn translate(
+    mapper: &OffsetPageTable,
+    addr: VirtAddr,
+) -> Option<(PhysAddr, MappingSize, PageTableFlags)> {
+    match mapper.translate(addr) {
+        TranslateResult::Mapped {
+            frame,
+            offset,
+            flags,
+        } => {
+            let (start, size) = match frame {
+                MappedFrame::Size4KiB(frame) => (frame.start_address(), MappingSize::Size4KiB),
+                MappedFrame::Size2MiB(frame) => (frame.start_address(), MappingSize::Size2MiB),
+                MappedFrame::Size1GiB(frame) => (frame.start_address(), MappingSize::Size1GiB),
+            };
+            Some((start + offset, size, flags))
+        }
+        TranslateResult::NotMapped | TranslateResult::InvalidFrameAddress(_) => None,
     }
-
-    //添加页面偏移量计算物理地址
-    Some(frame.start_address() + u64::from(addr.page_offset()))
 }
 
 /// ## 函数说明
-/// 初始化一个新的OffsetPageTable
+/// 初始化一个新的OffsetPageTable，并记录物理内存偏移量供`phys_offset`使用
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    phys::set_phys_offset(physical_memory_offset);
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
 
+/// VGA文本缓冲区所在的物理帧
+pub const VGA_FRAME: u64 = 0xb8000;
+
+/// ## 函数说明
+/// 将`page`映射到VGA帧，VGA帧本身会先通过`identity_map`确认其恒等映射存在
+/// 保留旧名称以兼容已有代码，新代码请直接使用`map_page`
 pub fn create_example_mapping(
     page: Page,
     mapper: &mut OffsetPageTable,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) {
+) -> Result<(), MemoryError> {
+    let frame = PhysFrame::containing_address(PhysAddr::new(VGA_FRAME));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    identity_map(frame, flags, mapper, frame_allocator)?;
+    map_page(page, frame, flags, mapper, frame_allocator)
+}
+
+/// ## 函数说明
+/// 将`page`映射到`frame`并刷新TLB，页面已映射到同一帧时视为成功
+/// 页面已映射到其他帧时不会覆盖，返回`AlreadyMapped`及当前映射的帧
+///
+/// ## 参数
+/// * `page` - 页面
+/// * `frame` - 物理帧
+/// * `flags` - 页表项标志，必须包含PRESENT且不能包含HUGE_PAGE
+/// * `mapper` - 页表
+/// * `frame_allocator` - 用于分配页表帧的帧分配器
+///
+/// ## 用法
+/// ```rust
+/// map_page(page, frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE, &mut mapper, &mut frame_allocator)?;
+/// ```
+pub fn map_page(
+    page: Page,
+    frame: PhysFrame,
+    flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MemoryError> {
+    check_flags(page, flags)?;
+    match unsafe { mapper.map_to(page, frame, flags, frame_allocator) } {
+        Ok(flush) => {
+            flush.flush();
+            Ok(())
+        }
+        Err(MapToError::PageAlreadyMapped(current)) if current == frame => Ok(()),
+        Err(err) => Err(MemoryError::from_map_to(err, page, frame)),
+    }
+}
+
+//4KiB页面的表项必须存在且不能是大页
+fn check_flags(page: Page, flags: PageTableFlags) -> Result<(), MemoryError> {
+    wx::assert_nx_usable(flags);
+    if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::HUGE_PAGE) {
+        return Err(MemoryError::InvalidFlags { page, flags });
+    }
+    Ok(())
+}
+
+/// ## 函数说明
+/// 将物理帧映射到与其物理地址相同的虚拟地址
+/// 已经恒等映射时直接返回成功；页面已映射到其他帧时不会覆盖，返回`AlreadyMapped`及当前映射的帧
+///
+/// ## 参数
+/// * `frame` - 物理帧
+/// * `flags` - 页表项标志
+/// * `mapper` - 页表
+/// * `frame_allocator` - 用于分配页表帧的帧分配器
+///
+/// ## 用法
+/// ```rust
+/// let frame = PhysFrame::containing_address(PhysAddr::new(0x8000));
+/// identity_map(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE, &mut mapper, &mut frame_allocator)?;
+/// ```
+pub fn identity_map(
+    frame: PhysFrame,
+    flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MemoryError> {
+    let page = Page::containing_address(VirtAddr::new(frame.start_address().as_u64()));
+    map_page(page, frame, flags, mapper, frame_allocator)
+}
+
+/// ## 函数说明
+/// 恒等映射从`start`开始的`count`个物理帧，遇到第一个错误时停止
+///
+/// ## 参数
+/// * `start` - 第一个物理帧
+/// * `count` - 帧数量
+/// * `flags` - 页表项标志
+/// * `mapper` - 页表
+/// * `frame_allocator` - 用于分配页表帧的帧分配器
+pub fn identity_map_range(
+    start: PhysFrame,
+    count: u64,
+    flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MemoryError> {
+    for frame in PhysFrame::range(start, start + count) {
+        identity_map(frame, flags, mapper, frame_allocator)?;
+    }
+    Ok(())
+}
+
+/// ## 函数说明
+/// 取消页面的映射并刷新TLB，物理帧交还给`frame_deallocator`
+///
+/// ## 参数
+/// * `page` - 被取消映射的页面
+/// * `mapper` - 页表
+/// * `frame_deallocator` - 接收物理帧的帧回收器
+///
+/// ## 用法
+/// ```rust
+/// let frame = unmap_page(page, &mut mapper, &mut frame_allocator)?;
+/// ```
+pub fn unmap_page(
+    page: Page,
+    mapper: &mut OffsetPageTable,
+    frame_deallocator: &mut impl FrameDeallocator<Size4KiB>,
+) -> Result<PhysFrame, UnmapError> {
+    let frame = unmap_page_keep_frame(page, mapper)?;
+    unsafe { frame_deallocator.deallocate_frame(frame) };
+    Ok(frame)
+}
+
+/// ## 函数说明
+/// 取消页面的映射并刷新TLB，但不释放物理帧，用于0xb8000等MMIO帧
+///
+/// ## 参数
+/// * `page` - 被取消映射的页面
+/// * `mapper` - 页表
+pub fn unmap_page_keep_frame(
+    page: Page,
+    mapper: &mut OffsetPageTable,
+) -> Result<PhysFrame, UnmapError> {
+    let (frame, flush) = mapper.unmap(page)?;
+    flush.flush();
+    Ok(frame)
+}
+
+/// ## 函数说明
+/// 取消从`start`开始的`count`个页面的映射，遇到第一个错误时停止
+///
+/// ## 参数
+/// * `start` - 第一个页面
+/// * `count` - 页面数量
+/// * `mapper` - 页表
+/// * `frame_deallocator` - 接收物理帧的帧回收器
+pub fn unmap_range(
+    start: Page,
+    count: u64,
+    mapper: &mut OffsetPageTable,
+    frame_deallocator: &mut impl FrameDeallocator<Size4KiB>,
+) -> Result<(), UnmapError> {
+    for page in Page::range(start, start + count) {
+        unmap_page(page, mapper, frame_deallocator)?;
+    }
+    Ok(())
+}
+
+/// ## 函数说明
+/// 为从`start`开始的`count`个页面各分配一个新帧并映射；任何一页失败时撤销已映射的页面并归还所有帧，
+/// 包括映射失败的那一帧
+///
+/// ## 参数
+/// * `start` - 第一个页面
+/// * `count` - 页面数量
+/// * `flags` - 页表项标志
+/// * `mapper` - 页表
+/// * `frame_allocator` - 帧分配器，同时用于回收帧
+pub fn map_fresh_range(
+    start: Page,
+    count: u64,
+    flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut (impl FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>),
+) -> Result<(), MapToError<Size4KiB>> {
+    for i in 0..count {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed);
+        let mapped = frame.and_then(|frame| {
+            unsafe { mapper.map_to(start + i, frame, flags, frame_allocator) }
+                .inspect_err(|_| unsafe { frame_allocator.deallocate_frame(frame) })
+        });
+        match mapped {
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                //撤销已映射的页面并归还帧
+                for page in Page::range(start, start + i) {
+                    let _ = unmap_page(page, mapper, frame_allocator);
+                }
+                return Err(err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// MMIO映射窗口的大小，窗口位置由`vspace`中的"mmio"区域决定
+pub const MMIO_SIZE: u64 = 64 * 1024 * 1024 * 1024; // 64 GiB
+
+//MMIO窗口中下一个未使用的虚拟地址，0表示尚未使用；区域按顺序分配，取消映射后不会复用
+static MMIO_NEXT: AtomicU64 = AtomicU64::new(0);
+
+/// ## 说明
+/// 在虚拟地址窗口中建立映射时可能出现的错误
+#[derive(Debug)]
+pub enum MapError {
+    /// 窗口中没有足够的虚拟地址空间
+    WindowExhausted,
+    /// 全局页表尚未通过`install_paging`安装
+    NoPaging,
+    /// 页表映射失败
+    Map(MapToError<Size4KiB>),
+}
+
+impl From<MapToError<Size4KiB>> for MapError {
+    fn from(err: MapToError<Size4KiB>) -> Self {
+        MapError::Map(err)
+    }
+}
+
+/// ## 函数说明
+/// 以不可缓存的方式将一段物理内存（通常是设备内存）映射到MMIO窗口中
+/// 区域会被扩展到页边界，返回值保留了`phys_start`在页内的偏移
+///
+/// ## 参数
+/// * `phys_start` - 物理起始地址
+/// * `size` - 区域大小（字节）
+/// * `mapper` - 页表
+/// * `frame_allocator` - 用于分配页表帧的帧分配器
+///
+/// ## 用法
+/// ```rust
+/// let lapic = map_physical_region(PhysAddr::new(0xfee0_0000), 4096, &mut mapper, &mut frame_allocator)?;
+/// ```
+pub fn map_physical_region(
+    phys_start: PhysAddr,
+    size: usize,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<VirtAddr, MapError> {
     use x86_64::structures::paging::PageTableFlags as Flags;
 
-    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
-    let flags = Flags::PRESENT | Flags::WRITABLE;
-    let map_to_res = unsafe { mapper.map_to(page, frame, flags, frame_allocator) };
+    let offset = phys_start.as_u64() & 0xfff;
+    let first_frame = PhysFrame::<Size4KiB>::containing_address(phys_start);
+    let page_count = (offset + size.max(1) as u64).div_ceil(4096);
+    let region_size = page_count * 4096;
+
+    //从窗口中取出一段连续的虚拟地址
+    let window = vspace::find("mmio").ok_or(MapError::WindowExhausted)?;
+    let window_start = window.start.as_u64();
+    let virt_start = MMIO_NEXT
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |next| {
+            let end = next.max(window_start).checked_add(region_size)?;
+            (end <= window.end().as_u64()).then_some(end)
+        })
+        .map_err(|_| MapError::WindowExhausted)?
+        .max(window_start);
+
+    let mut flags = Flags::PRESENT | Flags::WRITABLE | Flags::NO_CACHE | Flags::WRITE_THROUGH;
+    if crate::cpu::nx_enabled() {
+        flags |= Flags::NO_EXECUTE; //设备内存不会被执行
+    }
+    let first_page = Page::containing_address(VirtAddr::new(virt_start));
+    for i in 0..page_count {
+        let result =
+            unsafe { mapper.map_to(first_page + i, first_frame + i, flags, frame_allocator) };
+        match result {
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                //撤销已经建立的映射
+                for page in Page::range(first_page, first_page + i) {
+                    let _ = unmap_page_keep_frame(page, mapper);
+                }
+                return Err(err.into());
+            }
+        }
+    }
+
+    Ok(first_page.start_address() + offset)
+}
+
+/// ## 函数说明
+/// 取消`map_physical_region`建立的映射，设备帧不会被回收
+///
+/// ## 参数
+/// * `virt_start` - `map_physical_region`的返回值
+/// * `size` - 映射时传入的区域大小
+/// * `mapper` - 页表
+pub fn unmap_physical_region(
+    virt_start: VirtAddr,
+    size: usize,
+    mapper: &mut OffsetPageTable,
+) -> Result<(), UnmapError> {
+    let offset = virt_start.as_u64() & 0xfff;
+    let page_count = (offset + size.max(1) as u64).div_ceil(4096);
+    let first_page = Page::<Size4KiB>::containing_address(virt_start);
+    for page in Page::range(first_page, first_page + page_count) {
+        unmap_page_keep_frame(page, mapper)?;
+    }
+    Ok(())
+}
+
+/// ## 函数说明
+/// 使用2MiB大页映射，页表本身仍从`frame_allocator`分配4KiB帧
+///
+/// ## 参数
+/// * `page` - 2MiB对齐的页面
+/// * `frame` - 2MiB对齐的物理帧
+/// * `flags` - 页表项标志，HUGE_PAGE会自动设置
+/// * `mapper` - 页表
+/// * `frame_allocator` - 用于分配页表帧的帧分配器
+///
+/// ## 用法
+/// ```rust
+/// map_huge(page, frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE, &mut mapper, &mut frame_allocator)?;
+/// ```
+pub fn map_huge(
+    page: Page<Size2MiB>,
+    frame: PhysFrame<Size2MiB>,
+    flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size2MiB>> {
+    wx::assert_nx_usable(flags);
+    unsafe { mapper.map_to(page, frame, flags, frame_allocator) }?.flush();
+    Ok(())
+}
+
+/// ## 函数说明
+/// 用2MiB大页映射一段连续的物理内存，三个参数都必须按2MiB对齐，遇到第一个错误时停止
+///
+/// ## 参数
+/// * `virt_start` - 虚拟起始地址
+/// * `phys_start` - 物理起始地址
+/// * `size` - 区域大小（字节）
+/// * `flags` - 页表项标志
+/// * `mapper` - 页表
+/// * `frame_allocator` - 用于分配页表帧的帧分配器
+pub fn map_range_huge(
+    virt_start: VirtAddr,
+    phys_start: PhysAddr,
+    size: u64,
+    flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size2MiB>> {
+    assert!(
+        virt_start.is_aligned(Size2MiB::SIZE)
+            && phys_start.is_aligned(Size2MiB::SIZE)
+            && size.is_multiple_of(Size2MiB::SIZE),
+        "map_range_huge requires 2MiB alignment"
+    );
+    let first_page = Page::<Size2MiB>::containing_address(virt_start);
+    let first_frame = PhysFrame::<Size2MiB>::containing_address(phys_start);
+    for i in 0..size / Size2MiB::SIZE {
+        map_huge(
+            first_page + i,
+            first_frame + i,
+            flags,
+            mapper,
+            frame_allocator,
+        )?;
+    }
+    Ok(())
+}
+
+/// ## 函数说明
+/// 映射一个用户态可访问的页面，USER_ACCESSIBLE会同时设置在P4、P3、P2各级的父表项上，
+/// 否则只有P1表项带有该标志时ring 3访问仍会触发页错误
+///
+/// ## 参数
+/// * `page` - 页面
+/// * `frame` - 物理帧
+/// * `flags` - 页表项标志，PRESENT和USER_ACCESSIBLE会自动设置
+/// * `mapper` - 页表
+/// * `frame_allocator` - 用于分配页表帧的帧分配器
+///
+/// ## 用法
+/// ```rust
+/// map_user_page(page, frame, PageTableFlags::WRITABLE, &mut mapper, &mut frame_allocator)?;
+/// ```
+pub fn map_user_page(
+    page: Page,
+    frame: PhysFrame,
+    flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MemoryError> {
+    let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    check_flags(page, flags)?;
+    //父表项只放宽权限，实际的读写限制由P1表项决定
+    let parent_flags =
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    unsafe { mapper.map_to_with_table_flags(page, frame, flags, parent_flags, frame_allocator) }
+        .map_err(|err| MemoryError::from_map_to(err, page, frame))?
+        .flush();
+    Ok(())
+}
 
-    map_to_res.expect("map_to failed").flush();
+/// ## 函数说明
+/// 为从`start`开始的`count`个页面各分配一个帧，并以用户态可访问的方式映射，遇到第一个错误时停止
+/// 映射失败的页面的帧会被归还，之前已映射的页面保持不变
+///
+/// ## 参数
+/// * `start` - 第一个页面
+/// * `count` - 页面数量
+/// * `flags` - 页表项标志，PRESENT和USER_ACCESSIBLE会自动设置
+/// * `mapper` - 页表
+/// * `frame_allocator` - 帧分配器，同时用于回收帧
+pub fn map_user_region(
+    start: Page,
+    count: u64,
+    flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut (impl FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>),
+) -> Result<(), MemoryError> {
+    for page in Page::range(start, start + count) {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MemoryError::FrameAllocationFailed { page })?;
+        map_user_page(page, frame, flags, mapper, frame_allocator)
+            .inspect_err(|_| unsafe { frame_allocator.deallocate_frame(frame) })?;
+    }
+    Ok(())
+}
+
+#[test_case]
+fn test_memory_map_totals() {
+    use bootloader::bootinfo::{FrameRange, MemoryRegion};
+
+    let mut memory_map = MemoryMap::new();
+    let regions = [
+        (0x0, 0x1000, MemoryRegionType::FrameZero),
+        (0x1000, 0x9f000, MemoryRegionType::Usable),
+        (0x9f000, 0x100000, MemoryRegionType::Reserved),
+        (0x100000, 0x400000, MemoryRegionType::Usable),
+    ];
+    for (start, end, region_type) in regions {
+        memory_map.add_region(MemoryRegion {
+            range: FrameRange::new(start, end),
+            region_type,
+        });
+    }
+
+    assert_eq!(total_usable_bytes(&memory_map), 0x9e000 + 0x300000);
+    assert_eq!(total_reserved_bytes(&memory_map), 0x1000 + 0x61000);
+}
+
+#[test_case]
+fn test_byte_size_display() {
+    use core::fmt::Write;
+
+    //固定大小的缓冲区，避免测试依赖堆
+    struct Buf([u8; 32], usize);
+    impl Write for Buf {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.1 + s.len();
+            self.0
+                .get_mut(self.1..end)
+                .ok_or(fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.1 = end;
+            Ok(())
+        }
+    }
+
+    for (bytes, expected) in [
+        (512, "512 B"),
+        (4096, "4 KiB"),
+        (3 * 1024 * 1024, "3 MiB"),
+        (1024 * 1024 + 512 * 1024, "1.50 MiB"),
+    ] {
+        let mut buf = Buf([0; 32], 0);
+        write!(buf, "{}", ByteSize(bytes)).unwrap();
+        assert_eq!(&buf.0[..buf.1], expected.as_bytes());
+    }
 }
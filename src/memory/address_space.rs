@@ -0,0 +1,248 @@
+use core::marker::PhantomData;
+use x86_64::registers::control::{Cr3, Cr3Flags};
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, OffsetPageTable, PageTable, PageTableFlags, PhysFrame,
+    Size4KiB,
+};
+use x86_64::VirtAddr;
+
+use super::vspace::{KERNEL_AREA_END, KERNEL_AREA_START};
+
+const ENTRY_COUNT: usize = 512;
+//一个P4条目覆盖的地址范围
+const P4_ENTRY_SIZE: u64 = 512 * 1024 * 1024 * 1024; // 512 GiB
+
+/// ## 说明
+/// 一个独立的地址空间，即一个P4页表
+/// 创建时复制当前P4中已使用的条目（内核、堆、物理内存映射、VGA等），这些条目指向的下级页表与内核共享；
+/// 在创建时为空的条目中建立的映射只属于这个地址空间
+///
+/// 销毁时释放此地址空间独有的页表帧（不包括映射到的数据帧），通过全局帧分配器归还，
+/// 因此不能在`with_paging`的闭包中销毁，也不能销毁当前正在使用的地址空间
+///
+/// ## 成员
+/// * `p4_frame` - P4页表所在的帧
+/// * `phys_offset` - 物理内存映射的偏移量
+/// * `shared` - 从内核复制、与内核共享的P4条目
+pub struct AddressSpace {
+    p4_frame: PhysFrame,
+    phys_offset: VirtAddr,
+    shared: [bool; ENTRY_COUNT],
+}
+
+impl AddressSpace {
+    /// ## 函数说明
+    /// 分配一个新的P4页表并复制内核P4中已使用的条目，全局页表尚未安装或分配帧失败时返回`None`
+    /// 内核可分配区域对应的P4条目会先在内核页表中补齐，以便之后新增的内核映射对所有地址空间可见
+    ///
+    /// 通过`with_paging`持有全局页表的锁修改内核P4，因此不能在`with_paging`的闭包中调用
+    ///
+    /// ## 用法
+    /// ```rust
+    /// let mut space = AddressSpace::new().unwrap();
+    /// space.mapper().map_to(page, frame, flags, &mut frame_allocator)?.ignore();
+    /// unsafe { space.activate() };
+    /// ```
+    pub fn new() -> Option<Self> {
+        super::with_paging(|paging| {
+            let phys_offset = paging.mapper.phys_offset();
+            let frame_allocator = &mut paging.frame_allocator;
+            let current = paging.mapper.level_4_table();
+
+            let first = (KERNEL_AREA_START / P4_ENTRY_SIZE) as usize;
+            let last = ((KERNEL_AREA_END - 1) / P4_ENTRY_SIZE) as usize;
+            for entry in current.iter_mut().take(last + 1).skip(first) {
+                if entry.is_unused() {
+                    let frame = new_table(frame_allocator, phys_offset)?;
+                    entry.set_frame(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+                }
+            }
+
+            let p4_frame = new_table(frame_allocator, phys_offset)?;
+            let p4 = unsafe { &mut *table_ptr(p4_frame, phys_offset) };
+            let mut shared = [false; ENTRY_COUNT];
+            for (i, entry) in current.iter().enumerate() {
+                if !entry.is_unused() {
+                    p4[i] = entry.clone();
+                    shared[i] = true;
+                }
+            }
+
+            Some(AddressSpace {
+                p4_frame,
+                phys_offset,
+                shared,
+            })
+        })
+        .flatten()
+    }
+
+    /// ## 函数说明
+    /// 获取用于修改此地址空间的页表，地址空间不需要处于激活状态
+    pub fn mapper(&mut self) -> OffsetPageTable<'_> {
+        let p4 = unsafe { &mut *table_ptr(self.p4_frame, self.phys_offset) };
+        unsafe { OffsetPageTable::new(p4, self.phys_offset) }
+    }
+
+    /// ## 函数说明
+    /// 写入CR3寄存器时使用的值
+    pub fn cr3_value(&self) -> u64 {
+        self.p4_frame.start_address().as_u64()
+    }
+
+    /// ## 函数说明
+    /// 将CR3切换到此地址空间，需要检查内核映射时使用[`switch_address_space`]
+    ///
+    /// ## Safety
+    /// 调用者需保证当前执行的代码、栈和物理内存映射在新地址空间中同样有效，
+    /// 并且在此地址空间被销毁之前切换回其他地址空间
+    pub unsafe fn activate(&self) {
+        Cr3::write(self.p4_frame, Cr3Flags::empty());
+    }
+}
+
+/// ## 说明
+/// `switch_address_space`拒绝切换的原因，`what`说明缺少的是哪一部分内核映射
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchError {
+    /// 目标P4中对应的条目不存在
+    Missing { what: &'static str, index: usize },
+    /// 目标P4中对应的条目与当前P4指向不同的页表
+    Mismatch { what: &'static str, index: usize },
+}
+
+/// ## 说明
+/// 切换地址空间的凭证，销毁时切换回之前的CR3，生命周期不能超过目标地址空间
+///
+/// ## 成员
+/// * `previous` - 切换前的P4帧和CR3标志
+pub struct AddressSpaceToken<'a> {
+    previous: (PhysFrame, Cr3Flags),
+    _space: PhantomData<&'a AddressSpace>,
+}
+
+impl Drop for AddressSpaceToken<'_> {
+    fn drop(&mut self) {
+        let (frame, flags) = self.previous;
+        x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+            Cr3::write(frame, flags)
+        });
+    }
+}
+
+/// ## 函数说明
+/// 检查目标地址空间包含当前执行所需的内核映射后将CR3切换过去，返回的凭证销毁时切换回来
+/// 需要检查的是当前栈、内核代码和物理内存映射所在的P4条目：它们在目标P4中必须存在，且与当前P4指向同一页表，
+/// 否则切换后会立即引发三重错误
+///
+/// ## 参数
+/// * `space` - 目标地址空间
+///
+/// ## 用法
+/// ```rust
+/// let token = switch_address_space(&space)?;
+/// //此处使用space中的映射
+/// drop(token);
+/// ```
+pub fn switch_address_space(space: &AddressSpace) -> Result<AddressSpaceToken<'_>, SwitchError> {
+    let stack_marker = 0u8;
+    let required = [
+        ("stack", VirtAddr::from_ptr(&stack_marker)),
+        (
+            "code",
+            VirtAddr::new(switch_address_space as *const () as u64),
+        ),
+        ("physical memory", space.phys_offset),
+    ];
+
+    let previous = Cr3::read();
+    let current = unsafe { &*table_ptr(previous.0, space.phys_offset) };
+    let target = unsafe { &*table_ptr(space.p4_frame, space.phys_offset) };
+    for (what, addr) in required {
+        let index = usize::from(addr.p4_index());
+        if target[index].is_unused() {
+            return Err(SwitchError::Missing { what, index });
+        }
+        if target[index].addr() != current[index].addr() {
+            return Err(SwitchError::Mismatch { what, index });
+        }
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe { space.activate() });
+    Ok(AddressSpaceToken {
+        previous,
+        _space: PhantomData,
+    })
+}
+
+/// ## 函数说明
+/// 在`space`中执行闭包，返回前切换回原来的地址空间；`space`缺少内核映射时不会切换
+///
+/// ## 参数
+/// * `space` - 目标地址空间
+/// * `f` - 在目标地址空间中执行的闭包
+///
+/// ## 用法
+/// ```rust
+/// let value = with_address_space(&space, || unsafe { ptr.read_volatile() })?;
+/// ```
+pub fn with_address_space<R>(
+    space: &AddressSpace,
+    f: impl FnOnce() -> R,
+) -> Result<R, SwitchError> {
+    let _token = switch_address_space(space)?;
+    Ok(f())
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        assert_ne!(
+            Cr3::read().0,
+            self.p4_frame,
+            "dropping the active address space"
+        );
+        let p4 = unsafe { &*table_ptr(self.p4_frame, self.phys_offset) };
+        //全局页表未安装时无法归还帧，只能泄漏
+        super::with_paging(|paging| {
+            for (entry, shared) in p4.iter().zip(self.shared.iter()) {
+                if let (false, Ok(frame)) = (shared, entry.frame()) {
+                    free_tables(frame, 3, self.phys_offset, &mut paging.frame_allocator);
+                }
+            }
+            unsafe { paging.frame_allocator.deallocate_frame(self.p4_frame) };
+        });
+    }
+}
+
+fn table_ptr(frame: PhysFrame, phys_offset: VirtAddr) -> *mut PageTable {
+    (phys_offset + frame.start_address().as_u64()).as_mut_ptr()
+}
+
+//分配一个帧并初始化为空页表
+fn new_table(
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    phys_offset: VirtAddr,
+) -> Option<PhysFrame> {
+    let frame = frame_allocator.allocate_frame()?;
+    unsafe { table_ptr(frame, phys_offset).write(PageTable::new()) };
+    Some(frame)
+}
+
+//释放第`level`级页表及其下级页表所在的帧，P1中的数据帧和大页不会被释放
+fn free_tables(
+    frame: PhysFrame,
+    level: u8,
+    phys_offset: VirtAddr,
+    frame_deallocator: &mut impl FrameDeallocator<Size4KiB>,
+) {
+    if level > 1 {
+        let table = unsafe { &*table_ptr(frame, phys_offset) };
+        //大页条目的frame()返回错误，因此会被跳过
+        for entry in table.iter() {
+            if let Ok(next) = entry.frame() {
+                free_tables(next, level - 1, phys_offset, frame_deallocator);
+            }
+        }
+    }
+    unsafe { frame_deallocator.deallocate_frame(frame) };
+}
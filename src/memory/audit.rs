@@ -0,0 +1,143 @@
+use core::fmt;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::VirtAddr;
+
+use super::protect::is_kernel_address;
+use super::{format_entry, EntryFormat, MappingCounts};
+
+/// ## 说明
+/// `audit`的结果，可以直接用`print!`打印
+///
+/// ## 成员
+/// * `mappings` - 各种大小的映射数量
+/// * `wx_violations` - 同时可写和可执行的映射数，按各级表项的有效权限计算
+/// * `first_wx_violation` - 第一个W^X违规的虚拟地址及其最后一级表项
+/// * `user_in_kernel` - 内核地址上用户态可访问的映射数
+/// * `out_of_range` - 帧地址超出物理内存的映射数
+/// * `first_out_of_range` - 第一个帧地址超出物理内存的虚拟地址
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AuditReport {
+    pub mappings: MappingCounts,
+    pub wx_violations: usize,
+    pub first_wx_violation: Option<(VirtAddr, EntryFormat)>,
+    pub user_in_kernel: usize,
+    pub out_of_range: usize,
+    pub first_out_of_range: Option<VirtAddr>,
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "page table audit:")?;
+        writeln!(f, "  mappings: {}", self.mappings)?;
+        write!(f, "  writable+executable: {}", self.wx_violations)?;
+        if let Some((addr, entry)) = self.first_wx_violation {
+            write!(f, " (first at {:#x}: {})", addr.as_u64(), entry)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "  user pages in kernel space: {}", self.user_in_kernel)?;
+        write!(f, "  frames beyond physical memory: {}", self.out_of_range)?;
+        if let Some(addr) = self.first_out_of_range {
+            write!(f, " (first at {:#x})", addr.as_u64())?;
+        }
+        Ok(())
+    }
+}
+
+//从上一级继承的有效权限：所有级别都可写才可写，任一级别设置NX即不可执行
+#[derive(Clone, Copy)]
+struct Inherited {
+    writable: bool,
+    executable: bool,
+    user: bool,
+}
+
+struct Auditor {
+    offset: VirtAddr,
+    phys_end: u64,
+    report: AuditReport,
+}
+
+impl Auditor {
+    fn walk(&mut self, table: &PageTable, level: u8, base: u64, inherited: Inherited) {
+        let shift = 12 + 9 * (level as u64 - 1);
+        for (index, entry) in table.iter().enumerate() {
+            let flags = entry.flags();
+            if !flags.contains(PageTableFlags::PRESENT) {
+                continue;
+            }
+            let addr = base | ((index as u64) << shift);
+            let effective = Inherited {
+                writable: inherited.writable && flags.contains(PageTableFlags::WRITABLE),
+                executable: inherited.executable && !flags.contains(PageTableFlags::NO_EXECUTE),
+                user: inherited.user && flags.contains(PageTableFlags::USER_ACCESSIBLE),
+            };
+            let huge = flags.contains(PageTableFlags::HUGE_PAGE);
+            match level {
+                1 => self.report.mappings.size_4k += 1,
+                2 if huge => self.report.mappings.size_2m += 1,
+                3 if huge => self.report.mappings.size_1g += 1,
+                _ => {
+                    let virt = self.offset + entry.addr().as_u64();
+                    let next = unsafe { &*virt.as_ptr::<PageTable>() };
+                    self.walk(next, level - 1, addr, effective);
+                    continue;
+                }
+            }
+            self.check_leaf(VirtAddr::new_truncate(addr), entry, effective);
+        }
+    }
+
+    fn check_leaf(&mut self, addr: VirtAddr, entry: &PageTableEntry, effective: Inherited) {
+        if effective.writable && effective.executable {
+            self.report.wx_violations += 1;
+            self.report
+                .first_wx_violation
+                .get_or_insert((addr, format_entry(entry)));
+        }
+        if effective.user && is_kernel_address(addr) {
+            self.report.user_in_kernel += 1;
+        }
+        if entry.addr().as_u64() >= self.phys_end {
+            self.report.out_of_range += 1;
+            self.report.first_out_of_range.get_or_insert(addr);
+        }
+    }
+}
+
+/// ## 函数说明
+/// 递归遍历当前CR3指向的4级页表，统计映射数量并检查异常：W^X违规、内核地址上的用户页面和超出物理内存的帧
+/// 跳过不存在的表项，大页作为一个映射计数。物理内存的上限来自全局帧分配器的内存映射，
+/// 全局页表尚未安装时不检查帧地址
+///
+/// ## 参数
+/// * `physical_memory_offset` - 偏移量
+///
+/// ## 用法
+/// ```rust
+/// println!("{}", audit(phys_offset()));
+/// ```
+pub fn audit(physical_memory_offset: VirtAddr) -> AuditReport {
+    let phys_end = super::with_paging(|paging| {
+        super::phys_memory_end(paging.frame_allocator.inner().inner().memory_map())
+    })
+    .unwrap_or(u64::MAX);
+
+    let (level_4_table_frame, _) = Cr3::read();
+    let virt = physical_memory_offset + level_4_table_frame.start_address().as_u64();
+    let level_4_table = unsafe { &*virt.as_ptr::<PageTable>() };
+
+    let mut auditor = Auditor {
+        offset: physical_memory_offset,
+        phys_end,
+        report: AuditReport::default(),
+    };
+    let root = Inherited {
+        writable: true,
+        executable: true,
+        user: true,
+    };
+    auditor.walk(level_4_table, 4, 0, root);
+    auditor.report
+}
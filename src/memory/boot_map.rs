@@ -0,0 +1,121 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegion, MemoryRegionType};
+use spin::Once;
+use x86_64::PhysAddr;
+
+//MemoryRegionType的变体数量
+const REGION_TYPES: usize = 16;
+
+//bootloader提供的内存映射，由`BootInfoFrameAllocator::init`设置一次
+static MEMORY_MAP: Once<&'static MemoryMap> = Once::new();
+
+pub(super) fn set_memory_map(memory_map: &'static MemoryMap) {
+    MEMORY_MAP.call_once(|| memory_map);
+}
+
+/// ## 说明
+/// 按`MemoryRegionType`分组的内存总量
+///
+/// ## 成员
+/// * `totals` - 每种类型及其总字节数，按首次出现的顺序排列
+pub struct RegionTotals {
+    totals: [Option<(MemoryRegionType, u64)>; REGION_TYPES],
+}
+
+impl RegionTotals {
+    /// ## 函数说明
+    /// 遍历内存映射，累加每种类型的区域大小
+    ///
+    /// ## 参数
+    /// * `memory_map` - 内存映射
+    pub fn new(memory_map: &MemoryMap) -> Self {
+        let mut totals = [None; REGION_TYPES];
+        for region in memory_map.iter() {
+            let size = region.range.end_addr() - region.range.start_addr();
+            for slot in totals.iter_mut() {
+                match slot {
+                    Some((kind, total)) if *kind == region.region_type => *total += size,
+                    Some(_) => continue,
+                    None => *slot = Some((region.region_type, size)),
+                }
+                break;
+            }
+        }
+        RegionTotals { totals }
+    }
+
+    /// ## 函数说明
+    /// 遍历每种类型及其总字节数
+    pub fn iter(&self) -> impl Iterator<Item = (MemoryRegionType, u64)> + '_ {
+        self.totals.iter().flatten().copied()
+    }
+
+    /// ## 函数说明
+    /// 某种类型的总字节数，内存映射中没有该类型时为0
+    ///
+    /// ## 参数
+    /// * `kind` - 区域类型
+    pub fn get(&self, kind: MemoryRegionType) -> u64 {
+        self.iter()
+            .find(|&(k, _)| k == kind)
+            .map_or(0, |(_, total)| total)
+    }
+}
+
+/// ## 函数说明
+/// 按地址顺序遍历相邻区域之间没有被任何区域覆盖的空洞[起始地址, 结束地址)
+///
+/// ## 参数
+/// * `memory_map` - 内存映射，bootloader提供的映射已按起始地址排序
+pub fn holes(memory_map: &MemoryMap) -> impl Iterator<Item = (u64, u64)> + '_ {
+    let mut end = 0;
+    memory_map.iter().filter_map(move |region| {
+        let start = region.range.start_addr();
+        let hole = (start > end).then_some((end, start));
+        end = end.max(region.range.end_addr());
+        hole
+    })
+}
+
+/// ## 函数说明
+/// 遍历互相重叠的相邻区域，正确的固件不会报告这样的区域
+///
+/// ## 参数
+/// * `memory_map` - 内存映射
+pub fn overlapping_regions(
+    memory_map: &MemoryMap,
+) -> impl Iterator<Item = (&MemoryRegion, &MemoryRegion)> + '_ {
+    memory_map
+        .iter()
+        .zip(memory_map.iter().skip(1))
+        .filter(|(a, b)| b.range.start_addr() < a.range.end_addr())
+}
+
+/// ## 函数说明
+/// 在给定的内存映射中查找物理地址所属区域的类型，重叠时返回第一个包含它的区域，位于空洞中时返回`None`
+///
+/// ## 参数
+/// * `memory_map` - 内存映射
+/// * `addr` - 物理地址
+pub fn classify_in(memory_map: &MemoryMap, addr: PhysAddr) -> Option<MemoryRegionType> {
+    let addr = addr.as_u64();
+    memory_map
+        .iter()
+        .find(|r| (r.range.start_addr()..r.range.end_addr()).contains(&addr))
+        .map(|r| r.region_type)
+}
+
+/// ## 函数说明
+/// 查询物理地址在bootloader内存映射中的类型，帧分配器初始化之前总是返回`None`
+///
+/// ## 参数
+/// * `addr` - 物理地址
+///
+/// ## 用法
+/// ```rust
+/// if classify(table_addr) == Some(MemoryRegionType::AcpiReclaimable) {
+///     //...
+/// }
+/// ```
+pub fn classify(addr: PhysAddr) -> Option<MemoryRegionType> {
+    classify_in(MEMORY_MAP.r#try()?, addr)
+}
@@ -0,0 +1,195 @@
+use spin::Mutex;
+use x86_64::structures::paging::mapper::{MappedFrame, TranslateResult};
+use x86_64::structures::paging::{
+    FrameDeallocator, Mapper, OffsetPageTable, Page, PageTableFlags, PhysFrame, Translate,
+};
+use x86_64::VirtAddr;
+
+/// 标记写时复制页面的页表项可用位
+pub const COW_FLAG: PageTableFlags = PageTableFlags::BIT_9;
+
+const MAX_COW_FRAMES: usize = 256;
+
+/// ## 说明
+/// 写时复制帧的引用计数表，使用固定大小的数组，因此可以在页错误处理函数中使用而无需堆分配
+///
+/// ## 成员
+/// * `entries` - (帧起始地址, 引用数)，引用数为0的项是空闲的
+struct CowTable {
+    entries: [(u64, usize); MAX_COW_FRAMES],
+}
+
+impl CowTable {
+    const fn new() -> Self {
+        CowTable {
+            entries: [(0, 0); MAX_COW_FRAMES],
+        }
+    }
+
+    fn count(&self, frame: PhysFrame) -> usize {
+        let addr = frame.start_address().as_u64();
+        self.entries
+            .iter()
+            .find(|&&(a, n)| n > 0 && a == addr)
+            .map_or(0, |&(_, n)| n)
+    }
+
+    fn increment(&mut self, frame: PhysFrame) -> Result<(), CowError> {
+        let addr = frame.start_address().as_u64();
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.1 > 0 && e.0 == addr) {
+            entry.1 += 1;
+            return Ok(());
+        }
+        let slot = self
+            .entries
+            .iter_mut()
+            .find(|e| e.1 == 0)
+            .ok_or(CowError::TableFull)?;
+        *slot = (addr, 1);
+        Ok(())
+    }
+
+    fn decrement(&mut self, frame: PhysFrame) {
+        let addr = frame.start_address().as_u64();
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.1 > 0 && e.0 == addr) {
+            entry.1 -= 1;
+        }
+    }
+}
+
+static COW_FRAMES: Mutex<CowTable> = Mutex::new(CowTable::new());
+
+/// ## 说明
+/// 标记写时复制页面时可能出现的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CowError {
+    /// 页面没有映射
+    NotMapped,
+    /// 页面属于大页，只支持4KiB页面
+    HugePage,
+    /// 引用计数表已满
+    TableFull,
+}
+
+/// ## 函数说明
+/// 将页面标记为写时复制：清除WRITABLE，设置`COW_FLAG`，并增加物理帧的引用计数
+/// 共享同一帧的每个页面都需要分别标记，否则未标记的页面写入时会修改共享内容
+///
+/// ## 参数
+/// * `page` - 已映射的4KiB页面
+/// * `mapper` - 页表
+///
+/// ## 用法
+/// ```rust
+/// mark_cow(page, &mut mapper)?;
+/// ```
+pub fn mark_cow(page: Page, mapper: &mut OffsetPageTable) -> Result<PhysFrame, CowError> {
+    let (frame, flags) = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } => (frame, flags),
+        TranslateResult::Mapped { .. } => return Err(CowError::HugePage),
+        _ => return Err(CowError::NotMapped),
+    };
+
+    if !flags.contains(COW_FLAG) {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            COW_FRAMES.lock().increment(frame)
+        })?;
+    }
+    let flags = (flags - PageTableFlags::WRITABLE) | COW_FLAG;
+    unsafe { mapper.update_flags(page, flags) }
+        .map_err(|_| CowError::NotMapped)?
+        .flush();
+    Ok(frame)
+}
+
+/// ## 函数说明
+/// 帧被写时复制页面引用的次数
+///
+/// ## 参数
+/// * `frame` - 物理帧
+pub fn cow_refcount(frame: PhysFrame) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| COW_FRAMES.lock().count(frame))
+}
+
+/// ## 函数说明
+/// 处理写时复制页面上的写错误，由页错误处理函数调用
+/// 帧仍被其他页面共享时复制到新帧，否则直接恢复可写；返回`false`表示不是写时复制错误
+///
+/// 不能在持有全局页表时触发写时复制错误，否则会死锁
+///
+/// ## 参数
+/// * `addr` - 引发错误的地址（CR2）
+pub fn handle_write_fault(addr: VirtAddr) -> bool {
+    let page: Page = Page::containing_address(addr);
+    super::with_paging(|paging| {
+        //与mark_cow的加锁顺序一致：先页表后引用计数表
+        let mut cow_frames = COW_FRAMES.lock();
+        let (frame, flags) = match paging.mapper.translate(page.start_address()) {
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size4KiB(frame),
+                flags,
+                ..
+            } if flags.contains(COW_FLAG) => (frame, flags),
+            _ => return false,
+        };
+        let flags = (flags - COW_FLAG) | PageTableFlags::WRITABLE;
+
+        //最后一个引用直接恢复可写
+        if cow_frames.count(frame) <= 1 {
+            cow_frames.decrement(frame);
+            return match unsafe { paging.mapper.update_flags(page, flags) } {
+                Ok(flush) => {
+                    flush.flush();
+                    true
+                }
+                Err(_) => false,
+            };
+        }
+
+        //新帧会被完整覆盖，无需清零
+        let new_frame = match paging.frame_allocator.allocate_frame_uninit() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        let offset = paging.mapper.phys_offset();
+        unsafe {
+            let src: *const u8 = (offset + frame.start_address().as_u64()).as_ptr();
+            let dst: *mut u8 = (offset + new_frame.start_address().as_u64()).as_mut_ptr();
+            core::ptr::copy_nonoverlapping(src, dst, 4096);
+        }
+
+        //一次写入替换表项，页面在替换过程中始终保持映射
+        if super::replace_mapping(page, new_frame, flags, &mut paging.mapper).is_err() {
+            unsafe { paging.frame_allocator.deallocate_frame(new_frame) };
+            return false;
+        }
+        cow_frames.decrement(frame);
+        true
+    })
+    .unwrap_or(false)
+}
+
+#[test_case]
+fn test_cow_table_counts() {
+    use x86_64::PhysAddr;
+
+    let mut table = CowTable::new();
+    let frame = PhysFrame::containing_address(PhysAddr::new(0x5000));
+    assert_eq!(table.count(frame), 0);
+    table.increment(frame).unwrap();
+    table.increment(frame).unwrap();
+    assert_eq!(table.count(frame), 2);
+    table.decrement(frame);
+    table.decrement(frame);
+    assert_eq!(table.count(frame), 0);
+
+    for i in 0..MAX_COW_FRAMES as u64 {
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x10_0000 + i * 4096));
+        table.increment(frame).unwrap();
+    }
+    assert_eq!(table.increment(frame), Err(CowError::TableFull));
+}
@@ -0,0 +1,379 @@
+use core::fmt;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::page_table::{FrameError, PageTableEntry};
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::{println, serial_println};
+
+use super::cow::COW_FLAG;
+use super::MappingSize;
+
+/// ## 说明
+/// 页表项标志的简洁表示：P W U H A D G NX COW，未设置的标志显示为`-`
+/// `COW`是被用作写时复制标记的可用位（BIT_9）
+pub struct FlagString(pub PageTableFlags);
+
+impl fmt::Display for FlagString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            (PageTableFlags::PRESENT, "P"),
+            (PageTableFlags::WRITABLE, "W"),
+            (PageTableFlags::USER_ACCESSIBLE, "U"),
+            (PageTableFlags::HUGE_PAGE, "H"),
+            (PageTableFlags::ACCESSED, "A"),
+            (PageTableFlags::DIRTY, "D"),
+            (PageTableFlags::GLOBAL, "G"),
+            (PageTableFlags::NO_EXECUTE, "NX"),
+            (COW_FLAG, "COW"),
+        ];
+        for (i, (flag, name)) in flags.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            if self.0.contains(*flag) {
+                f.write_str(name)?;
+            } else {
+                f.write_str("-")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ## 说明
+/// 页表项的可读表示：原始值、帧地址和标志，由`format_entry`创建
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryFormat {
+    raw: u64,
+    addr: PhysAddr,
+    flags: PageTableFlags,
+}
+
+impl fmt::Display for EntryFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#018x} frame {:#x} [{}]",
+            self.raw,
+            self.addr.as_u64(),
+            FlagString(self.flags)
+        )
+    }
+}
+
+/// ## 函数说明
+/// 将页表项格式化为原始值、帧地址和标志字符串，不需要堆分配
+///
+/// ## 参数
+/// * `entry` - 页表项
+///
+/// ## 用法
+/// ```rust
+/// serial_println!("{}", format_entry(&table[index]));
+/// //0x80000000000b8063 frame 0xb8000 [P W - - A D - NX -]
+/// ```
+pub fn format_entry(entry: &PageTableEntry) -> EntryFormat {
+    EntryFormat {
+        raw: entry.flags().bits() | entry.addr().as_u64(),
+        addr: entry.addr(),
+        flags: entry.flags(),
+    }
+}
+
+/// ## 函数说明
+/// 从CR3开始手动逐级遍历页表，并向串口打印每一级的页表地址、索引、原始表项和标志
+/// 遇到不存在的表项时停止，返回值与`translate`相同
+///
+/// ## 参数
+/// * `addr` - 虚拟地址
+/// * `physical_memory_offset` - 偏移量
+///
+/// ## 用法
+/// ```rust
+/// dump_translation(VirtAddr::new(0xb8000), phys_mem_offset);
+/// ```
+pub fn dump_translation(
+    addr: VirtAddr,
+    physical_memory_offset: VirtAddr,
+) -> Option<(PhysAddr, MappingSize)> {
+    let (level_4_table_frame, _) = Cr3::read();
+    let table_indexes = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+
+    serial_println!("translation of {:#x}:", addr.as_u64());
+    let mut frame = level_4_table_frame;
+    for (level, &index) in table_indexes.iter().enumerate() {
+        let virt = physical_memory_offset + frame.start_address().as_u64();
+        let table = unsafe { &*virt.as_ptr::<PageTable>() };
+        let entry = &table[index];
+        serial_println!(
+            "  P{} table {:#x} index {:>3} entry {}",
+            4 - level,
+            frame.start_address().as_u64(),
+            u16::from(index),
+            format_entry(entry)
+        );
+
+        frame = match entry.frame() {
+            Ok(frame) => frame,
+            Err(FrameError::FrameNotPresent) => {
+                serial_println!("  not present");
+                return None;
+            }
+            Err(FrameError::HugeFrame) => {
+                let (size, offset_mask) = match level {
+                    1 => (MappingSize::Size1GiB, (1u64 << 30) - 1),
+                    2 => (MappingSize::Size2MiB, (1u64 << 21) - 1),
+                    _ => {
+                        serial_println!("  invalid huge page at P4");
+                        return None;
+                    }
+                };
+                let phys = entry.addr() + (addr.as_u64() & offset_mask);
+                serial_println!("  -> {:#x} ({:?})", phys.as_u64(), size);
+                return Some((phys, size));
+            }
+        };
+    }
+
+    let phys = frame.start_address() + u64::from(addr.page_offset());
+    serial_println!("  -> {:#x} (Size4KiB)", phys.as_u64());
+    Some((phys, MappingSize::Size4KiB))
+}
+
+/// ## 函数说明
+/// 返回`addr`的翻译路径上P4到P1各级表项的标志，遇到不存在的表项或大页后其余级别为`None`
+///
+/// ## 参数
+/// * `addr` - 虚拟地址
+/// * `physical_memory_offset` - 偏移量
+///
+/// ## 用法
+/// ```rust
+/// let [p4, p3, p2, p1] = entry_flags(addr, phys_mem_offset);
+/// ```
+pub fn entry_flags(
+    addr: VirtAddr,
+    physical_memory_offset: VirtAddr,
+) -> [Option<PageTableFlags>; 4] {
+    let (mut frame, _) = Cr3::read();
+    let table_indexes = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+
+    let mut flags = [None; 4];
+    for (level, &index) in table_indexes.iter().enumerate() {
+        let virt = physical_memory_offset + frame.start_address().as_u64();
+        let entry = &unsafe { &*virt.as_ptr::<PageTable>() }[index];
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            break;
+        }
+        flags[level] = Some(entry.flags());
+        frame = match entry.frame() {
+            Ok(frame) => frame,
+            Err(_) => break,
+        };
+    }
+    flags
+}
+
+/// ## 函数说明
+/// 向屏幕打印`addr`的翻译路径上的各级页表项，供页错误处理函数诊断使用
+/// 物理内存映射尚未初始化时什么都不做
+///
+/// ## 参数
+/// * `addr` - 虚拟地址
+pub fn print_fault_entries(addr: VirtAddr) {
+    let offset = match super::phys::try_phys_offset() {
+        Some(offset) => offset,
+        None => return,
+    };
+    let (mut frame, _) = Cr3::read();
+    let table_indexes = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+
+    for (level, &index) in table_indexes.iter().enumerate() {
+        let virt = offset + frame.start_address().as_u64();
+        let entry = &unsafe { &*virt.as_ptr::<PageTable>() }[index];
+        println!("P{} entry {}", 4 - level, format_entry(entry));
+        frame = match entry.frame() {
+            Ok(frame) => frame,
+            Err(_) => break,
+        };
+    }
+}
+
+/// ## 说明
+/// 各种大小的映射数量
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MappingCounts {
+    pub size_4k: usize,
+    pub size_2m: usize,
+    pub size_1g: usize,
+}
+
+impl fmt::Display for MappingCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "4KiB: {}, 2MiB: {}, 1GiB: {}",
+            self.size_4k, self.size_2m, self.size_1g
+        )
+    }
+}
+
+/// ## 函数说明
+/// 递归遍历所有页表，统计4KiB、2MiB和1GiB映射的数量
+///
+/// ## 参数
+/// * `level_4_table` - 4级页表，例如`mapper.level_4_table()`
+/// * `physical_memory_offset` - 偏移量
+///
+/// ## 用法
+/// ```rust
+/// serial_println!("{}", count_mapped_pages(mapper.level_4_table(), phys_mem_offset));
+/// ```
+pub fn count_mapped_pages(
+    level_4_table: &PageTable,
+    physical_memory_offset: VirtAddr,
+) -> MappingCounts {
+    let mut counts = MappingCounts::default();
+    count_table(level_4_table, 4, physical_memory_offset, &mut counts);
+    counts
+}
+
+fn count_table(table: &PageTable, level: u8, offset: VirtAddr, counts: &mut MappingCounts) {
+    for entry in table.iter() {
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let huge = entry.flags().contains(PageTableFlags::HUGE_PAGE);
+        match level {
+            1 => counts.size_4k += 1,
+            2 if huge => counts.size_2m += 1,
+            3 if huge => counts.size_1g += 1,
+            _ => {
+                let virt = offset + entry.addr().as_u64();
+                let next = unsafe { &*virt.as_ptr::<PageTable>() };
+                count_table(next, level - 1, offset, counts);
+            }
+        }
+    }
+}
+
+/// ## 函数说明
+/// 统计页表结构本身占用的帧数（包括4级页表），用于区分映射消耗的数据帧和页表帧
+///
+/// ## 参数
+/// * `level_4_table` - 4级页表，例如`mapper.level_4_table()`
+/// * `physical_memory_offset` - 偏移量
+pub fn table_frames_allocated(
+    level_4_table: &PageTable,
+    physical_memory_offset: VirtAddr,
+) -> usize {
+    count_tables(level_4_table, 4, physical_memory_offset)
+}
+
+fn count_tables(table: &PageTable, level: u8, offset: VirtAddr) -> usize {
+    if level == 1 {
+        return 1;
+    }
+    let children = table
+        .iter()
+        .filter(|entry| {
+            let flags = entry.flags();
+            flags.contains(PageTableFlags::PRESENT) && !flags.contains(PageTableFlags::HUGE_PAGE)
+        })
+        .map(|entry| {
+            let virt = offset + entry.addr().as_u64();
+            count_tables(unsafe { &*virt.as_ptr::<PageTable>() }, level - 1, offset)
+        })
+        .sum::<usize>();
+    children + 1
+}
+
+//写入固定缓冲区，用于在没有堆的情况下比较格式化结果
+#[cfg(test)]
+pub(crate) struct StrBuf {
+    buf: [u8; 128],
+    len: usize,
+}
+
+#[cfg(test)]
+impl StrBuf {
+    pub(crate) fn format(args: fmt::Arguments) -> Self {
+        let mut buf = StrBuf {
+            buf: [0; 128],
+            len: 0,
+        };
+        fmt::write(&mut buf, args).expect("buffer too small");
+        buf
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl fmt::Write for StrBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_format_entry() {
+    let mut entry = PageTableEntry::new();
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::ACCESSED
+        | PageTableFlags::DIRTY
+        | PageTableFlags::NO_EXECUTE;
+    entry.set_addr(PhysAddr::new(0xb8000), flags);
+    assert_eq!(
+        StrBuf::format(format_args!("{}", format_entry(&entry))).as_str(),
+        "0x80000000000b8063 frame 0xb8000 [P W - - A D - NX -]"
+    );
+
+    //写时复制页面：只读、用户可访问，并带有COW标记
+    let flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | COW_FLAG;
+    entry.set_addr(PhysAddr::new(0x20_0000), flags);
+    assert_eq!(
+        StrBuf::format(format_args!("{}", format_entry(&entry))).as_str(),
+        "0x0000000000200205 frame 0x200000 [P - U - - - - - COW]"
+    );
+
+    entry.set_unused();
+    assert_eq!(
+        StrBuf::format(format_args!("{}", format_entry(&entry))).as_str(),
+        "0x0000000000000000 frame 0x0 [- - - - - - - - -]"
+    );
+}
+
+#[test_case]
+fn test_flag_string_huge_global() {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE | PageTableFlags::GLOBAL;
+    assert_eq!(
+        StrBuf::format(format_args!("{}", FlagString(flags))).as_str(),
+        "P - - H - - G - -"
+    );
+}
@@ -0,0 +1,76 @@
+use core::fmt;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame, Size4KiB};
+
+/// ## 说明
+/// 内存模块中映射函数的错误，记录了出错的页面和相关的帧
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryError {
+    /// 分配数据帧或页表帧失败
+    FrameAllocationFailed { page: Page },
+    /// 页面已经映射到另一个帧，不会被覆盖
+    AlreadyMapped {
+        page: Page,
+        current: PhysFrame,
+        requested: PhysFrame,
+    },
+    /// 页面位于一个大页之中
+    ParentEntryHugePage { page: Page },
+    /// 页表项标志无效：缺少PRESENT，或对4KiB页面设置了HUGE_PAGE
+    InvalidFlags { page: Page, flags: PageTableFlags },
+}
+
+impl MemoryError {
+    /// ## 函数说明
+    /// 为`map_to`返回的错误补充页面和帧的信息
+    ///
+    /// ## 参数
+    /// * `err` - `map_to`返回的错误
+    /// * `page` - 正在映射的页面
+    /// * `frame` - 请求映射到的帧
+    pub fn from_map_to(err: MapToError<Size4KiB>, page: Page, frame: PhysFrame) -> Self {
+        match err {
+            MapToError::FrameAllocationFailed => MemoryError::FrameAllocationFailed { page },
+            MapToError::ParentEntryHugePage => MemoryError::ParentEntryHugePage { page },
+            MapToError::PageAlreadyMapped(current) => MemoryError::AlreadyMapped {
+                page,
+                current,
+                requested: frame,
+            },
+        }
+    }
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryError::FrameAllocationFailed { page } => write!(
+                f,
+                "frame allocation failed while mapping page {:#x}",
+                page.start_address().as_u64()
+            ),
+            MemoryError::AlreadyMapped {
+                page,
+                current,
+                requested,
+            } => write!(
+                f,
+                "page {:#x} is already mapped to frame {:#x}, requested frame {:#x}",
+                page.start_address().as_u64(),
+                current.start_address().as_u64(),
+                requested.start_address().as_u64()
+            ),
+            MemoryError::ParentEntryHugePage { page } => write!(
+                f,
+                "page {:#x} lies inside a huge page",
+                page.start_address().as_u64()
+            ),
+            MemoryError::InvalidFlags { page, flags } => write!(
+                f,
+                "invalid flags {:?} for page {:#x}",
+                flags,
+                page.start_address().as_u64()
+            ),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use core::fmt;
+use x86_64::structures::idt::PageFaultErrorCode;
+use x86_64::VirtAddr;
+
+use super::{guard, lazy, vspace, wx};
+
+/// ## 说明
+/// 页错误的一句话描述，如"write to unmapped address 0xdeadbeef from kernel mode (instruction fetch: no)"
+/// NX和SMEP都未启用时CPU不报告取指，显示为"instruction fetch: unknown"
+///
+/// ## 成员
+/// * `addr` - 引发错误的地址（CR2）
+/// * `error_code` - CPU压入的错误码
+#[derive(Debug, Clone, Copy)]
+pub struct FaultSummary {
+    pub addr: VirtAddr,
+    pub error_code: PageFaultErrorCode,
+}
+
+impl fmt::Display for FaultSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.error_code;
+        let fetch = code.contains(PageFaultErrorCode::INSTRUCTION_FETCH);
+        let access = if fetch {
+            "instruction fetch from"
+        } else if code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+            "write to"
+        } else {
+            "read from"
+        };
+        //保留位错误时页面一定存在，优先报告
+        let target = if code.contains(PageFaultErrorCode::MALFORMED_TABLE) {
+            "address with reserved page table bits set"
+        } else if code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+            "protected address"
+        } else {
+            "unmapped address"
+        };
+        let mode = if code.contains(PageFaultErrorCode::USER_MODE) {
+            "user"
+        } else {
+            "kernel"
+        };
+        write!(
+            f,
+            "{} {} {:#x} from {} mode (instruction fetch: {})",
+            access,
+            target,
+            self.addr.as_u64(),
+            mode,
+            match (fetch, fetch_reported()) {
+                (true, _) => "yes",
+                (false, true) => "no",
+                (false, false) => "unknown",
+            }
+        )
+    }
+}
+
+//只有启用了NX或SMEP时，CPU才会在错误码中设置取指位
+fn fetch_reported() -> bool {
+    use x86_64::registers::control::{Cr4, Cr4Flags};
+
+    crate::cpu::nx_enabled() || Cr4::read().contains(Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION)
+}
+
+/// ## 说明
+/// 引发页错误的地址所在的已知区域
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultRegion {
+    /// 第一个页面，通常是空指针解引用
+    NullPage,
+    /// 已映射的堆
+    Heap,
+    /// 堆区域中已映射部分之后的空间，通常是堆溢出
+    HeapOverflow,
+    /// 内核栈下方的保护页，即栈溢出
+    StackGuard(&'static str),
+    /// 按需分配区域
+    Lazy,
+    /// 内核代码段
+    KernelCode,
+    /// 虚拟地址布局中的其他区域，如"mmio"、"stacks"、"vmalloc"
+    Region(&'static str),
+    /// 不属于任何已知区域
+    Unknown,
+}
+
+impl fmt::Display for FaultRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FaultRegion::NullPage => write!(f, "null page (null pointer dereference?)"),
+            FaultRegion::Heap => write!(f, "heap"),
+            FaultRegion::HeapOverflow => write!(f, "past the end of the heap (heap overflow?)"),
+            FaultRegion::StackGuard(name) => {
+                write!(f, "guard page of stack {} (stack overflow)", name)
+            }
+            FaultRegion::Lazy => write!(f, "lazily allocated range"),
+            FaultRegion::KernelCode => write!(f, "kernel code"),
+            FaultRegion::Region(name) => write!(f, "{} region", name),
+            FaultRegion::Unknown => write!(f, "no known region"),
+        }
+    }
+}
+
+/// ## 函数说明
+/// 判断地址属于哪个已知区域，供页错误处理函数使用
+/// 区域表的锁被出错的代码持有时跳过对应的检查，不会死锁
+///
+/// ## 参数
+/// * `addr` - 引发错误的地址（CR2）
+pub fn classify_fault(addr: VirtAddr) -> FaultRegion {
+    if addr.as_u64() < 4096 {
+        return FaultRegion::NullPage;
+    }
+    if let Some(name) = guard::find_guard(addr) {
+        return FaultRegion::StackGuard(name);
+    }
+    if wx::is_kernel_code(addr) {
+        return FaultRegion::KernelCode;
+    }
+    if lazy::try_contains(addr) == Some(true) {
+        return FaultRegion::Lazy;
+    }
+    match vspace::try_region_of(addr) {
+        Some(region) if region.name == "heap" => match crate::allocator::heap_range() {
+            Some(heap) if heap.contains(&addr) => FaultRegion::Heap,
+            _ => FaultRegion::HeapOverflow,
+        },
+        Some(region) => FaultRegion::Region(region.name),
+        None => FaultRegion::Unknown,
+    }
+}
+
+#[cfg(test)]
+use super::debug::StrBuf;
+
+#[test_case]
+fn test_fault_summary() {
+    let summary = |addr, bits| {
+        let summary = FaultSummary {
+            addr: VirtAddr::new(addr),
+            error_code: PageFaultErrorCode::from_bits_truncate(bits),
+        };
+        StrBuf::format(format_args!("{}", summary))
+    };
+    assert_eq!(
+        summary(0xdeadbeef, 0b10).as_str(),
+        "write to unmapped address 0xdeadbeef from kernel mode (instruction fetch: no)"
+    );
+    //存在、用户态、读
+    assert_eq!(
+        summary(0x1000, 0b101).as_str(),
+        "read from protected address 0x1000 from user mode (instruction fetch: no)"
+    );
+    assert_eq!(
+        summary(0x2000, 0b1_0001).as_str(),
+        "instruction fetch from protected address 0x2000 from kernel mode (instruction fetch: yes)"
+    );
+    assert_eq!(
+        summary(0x3000, 0b1001).as_str(),
+        "read from address with reserved page table bits set 0x3000 from kernel mode (instruction fetch: no)"
+    );
+}
+
+#[test_case]
+fn test_classify_fault() {
+    assert_eq!(classify_fault(VirtAddr::new(0)), FaultRegion::NullPage);
+    assert_eq!(classify_fault(VirtAddr::new(0x18)), FaultRegion::NullPage);
+    let mmio = vspace::find("mmio").unwrap();
+    assert_eq!(classify_fault(mmio.start), FaultRegion::Region("mmio"));
+    let heap = vspace::find("heap").unwrap();
+    //堆没有增长到整个区域（或者尚未初始化），区域的最后一个字节总在已映射的堆之后
+    let last = heap.end() - 1u64;
+    assert_eq!(classify_fault(last), FaultRegion::HeapOverflow);
+    let code = classify_fault(VirtAddr::new(classify_fault as *const () as u64));
+    assert_eq!(code, FaultRegion::KernelCode);
+}
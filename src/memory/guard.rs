@@ -0,0 +1,107 @@
+use spin::Mutex;
+use x86_64::structures::paging::{Page, Size4KiB, Translate};
+use x86_64::VirtAddr;
+
+use super::stack_allocator::{with_kernel_stacks, StackAllocError};
+use super::MapError;
+
+const MAX_GUARDS: usize = 16;
+//向下查找启动栈保护页时最多检查的页数
+const BOOT_STACK_SCAN_PAGES: u64 = 4096;
+
+/// ## 说明
+/// 栈下方不映射的保护页，访问它说明栈已经溢出
+///
+/// ## 成员
+/// * `name` - 栈的名称
+/// * `page` - 保护页
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guard {
+    pub name: &'static str,
+    pub page: Page,
+}
+
+static GUARDS: Mutex<[Option<Guard>; MAX_GUARDS]> = Mutex::new([None; MAX_GUARDS]);
+
+/// ## 函数说明
+/// 登记一个保护页，登记表已满时返回`false`
+///
+/// ## 参数
+/// * `name` - 栈的名称
+/// * `page` - 保护页
+pub fn register_guard(name: &'static str, page: Page) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut guards = GUARDS.lock();
+        match guards.iter_mut().find(|guard| guard.is_none()) {
+            Some(slot) => {
+                *slot = Some(Guard { name, page });
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// ## 函数说明
+/// 查找包含`addr`的保护页，返回对应栈的名称，供页错误和double fault处理函数使用
+///
+/// ## 参数
+/// * `addr` - 引发错误的地址（CR2）
+pub fn find_guard(addr: VirtAddr) -> Option<&'static str> {
+    let page = Page::containing_address(addr);
+    GUARDS
+        .lock()
+        .iter()
+        .flatten()
+        .find(|guard| guard.page == page)
+        .map(|guard| guard.name)
+}
+
+/// ## 函数说明
+/// 通过全局栈分配器在"stacks"区域中映射一个`pages`页的栈，并登记其下方的保护页
+/// 返回栈顶地址
+///
+/// ## 参数
+/// * `name` - 栈的名称，出现栈溢出时会被报告
+/// * `pages` - 栈的页数，不含保护页
+///
+/// ## 用法
+/// ```rust
+/// let stack_top = alloc_guarded_stack("double fault", 5)?;
+/// ```
+pub fn alloc_guarded_stack(name: &'static str, pages: u64) -> Result<VirtAddr, MapError> {
+    let stack = with_kernel_stacks(|stacks| {
+        super::with_paging(|paging| {
+            stacks.allocate(pages, &mut paging.mapper, &mut paging.frame_allocator)
+        })
+    })
+    .ok_or(MapError::NoPaging)?
+    .map_err(|err| match err {
+        StackAllocError::Map(err) => MapError::Map(err),
+        _ => MapError::WindowExhausted,
+    })?;
+
+    register_guard(name, stack.guard_page());
+    Ok(stack.top())
+}
+
+/// ## 函数说明
+/// 从当前栈指针向下查找第一个未映射的页面，将其登记为启动栈"boot"的保护页
+/// bootloader在启动栈下方保留了一个保护页，但没有告知其位置
+pub fn register_boot_stack_guard() -> bool {
+    let rsp: u64;
+    unsafe { core::arch::asm!("mov {}, rsp", out(reg) rsp) };
+    let top: Page<Size4KiB> = Page::containing_address(VirtAddr::new(rsp));
+
+    let guard = super::with_paging(|paging| {
+        (1..BOOT_STACK_SCAN_PAGES)
+            .map(|i| top - i)
+            .find(|page| paging.mapper.translate_addr(page.start_address()).is_none())
+    })
+    .flatten();
+
+    match guard {
+        Some(page) => register_guard("boot", page),
+        None => false,
+    }
+}
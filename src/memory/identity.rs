@@ -0,0 +1,121 @@
+use core::ops::Range;
+use x86_64::instructions::tlb;
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{
+    FrameDeallocator, OffsetPageTable, PageTable, PageTableFlags, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+use super::{kernel_regions, vspace};
+
+/// 低于该地址的恒等映射会被`unmap_identity_region`移除
+pub const IDENTITY_LIMIT: u64 = 1 << 30; // 1 GiB
+
+//通过物理内存映射访问表项指向的下一级页表
+unsafe fn next_table(offset: VirtAddr, entry: &PageTableEntry) -> &'static mut PageTable {
+    &mut *(offset + entry.addr().as_u64()).as_mut_ptr::<PageTable>()
+}
+
+//表项是否指向下一级页表（存在且不是大页）
+fn is_table(entry: &PageTableEntry) -> bool {
+    let flags = entry.flags();
+    flags.contains(PageTableFlags::PRESENT) && !flags.contains(PageTableFlags::HUGE_PAGE)
+}
+
+//[addr, addr + size)是否需要保留：调用者指定的范围、内核映像或虚拟地址布局中的区域
+fn is_kept(addr: u64, size: u64, keep: &[Range<u64>]) -> bool {
+    let overlaps = |start: u64, end: u64| start < addr + size && addr < end;
+    keep.iter().any(|r| overlaps(r.start, r.end))
+        || kernel_regions()
+            .iter()
+            .any(|r| overlaps(r.start.as_u64(), r.end.as_u64()))
+        || vspace::overlaps(VirtAddr::new(addr), size)
+}
+
+//若页表已经没有任何表项，释放它并清除父表项
+unsafe fn free_if_empty(
+    parent: &mut PageTableEntry,
+    table: &PageTable,
+    deallocator: &mut impl FrameDeallocator<Size4KiB>,
+) {
+    if table.iter().all(PageTableEntry::is_unused) {
+        let frame = parent.frame().expect("parent entry is not a table");
+        parent.set_unused();
+        deallocator.deallocate_frame(frame);
+    }
+}
+
+/// ## 函数说明
+/// 移除bootloader在`IDENTITY_LIMIT`以下留下的恒等映射（虚拟地址等于物理地址的页面），
+/// 之后解引用较小的整数地址会引发页错误。`keep`中的范围、内核映像和`vspace`中的区域会被保留，
+/// 变为空的页表帧交给`deallocator`，数据帧不会被释放。返回移除的映射数（大页计为一个）
+///
+/// 物理内存偏移量本身位于`IDENTITY_LIMIT`以下时什么都不做
+///
+/// ## 参数
+/// * `mapper` - 页表
+/// * `keep` - 需要保留的物理（同时也是虚拟）地址范围，例如VGA缓冲区
+/// * `deallocator` - 回收页表帧的帧分配器
+///
+/// ## 用法
+/// ```rust
+/// let vga = VGA_FRAME..VGA_FRAME + 4096;
+/// unmap_identity_region(&mut mapper, &[vga], &mut frame_allocator);
+/// ```
+pub fn unmap_identity_region(
+    mapper: &mut OffsetPageTable,
+    keep: &[Range<u64>],
+    deallocator: &mut impl FrameDeallocator<Size4KiB>,
+) -> usize {
+    let offset = mapper.phys_offset();
+    if offset.as_u64() < IDENTITY_LIMIT {
+        return 0;
+    }
+
+    //IDENTITY_LIMIT以下的地址都位于第一个P4条目中
+    let p4_entry = &mut mapper.level_4_table()[0];
+    if !is_table(p4_entry) {
+        return 0;
+    }
+    let p3 = unsafe { next_table(offset, p4_entry) };
+
+    let mut unmapped = 0;
+    for p3_index in 0..(IDENTITY_LIMIT >> 30) as usize {
+        let p3_entry = &mut p3[p3_index];
+        if !is_table(p3_entry) {
+            continue; //不拆分1GiB大页
+        }
+        let p2 = unsafe { next_table(offset, p3_entry) };
+        for p2_index in 0..512 {
+            let base = ((p3_index as u64) << 30) | ((p2_index as u64) << 21);
+            let p2_entry = &mut p2[p2_index];
+            if !p2_entry.flags().contains(PageTableFlags::PRESENT) {
+                continue;
+            }
+            if p2_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+                if p2_entry.addr().as_u64() == base && !is_kept(base, 1 << 21, keep) {
+                    p2_entry.set_unused();
+                    unmapped += 1;
+                }
+                continue;
+            }
+            let p1 = unsafe { next_table(offset, p2_entry) };
+            for (p1_index, entry) in p1.iter_mut().enumerate() {
+                let addr = base | ((p1_index as u64) << 12);
+                if entry.flags().contains(PageTableFlags::PRESENT)
+                    && entry.addr().as_u64() == addr
+                    && !is_kept(addr, 4096, keep)
+                {
+                    entry.set_unused();
+                    unmapped += 1;
+                }
+            }
+            unsafe { free_if_empty(p2_entry, p1, deallocator) };
+        }
+        unsafe { free_if_empty(p3_entry, p2, deallocator) };
+    }
+    unsafe { free_if_empty(p4_entry, p3, deallocator) };
+
+    tlb::flush_all();
+    unmapped
+}
@@ -0,0 +1,95 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, Mapper, Page, Size4KiB};
+use x86_64::VirtAddr;
+
+use super::vspace;
+
+const MAX_LAZY_RANGES: usize = 16;
+//按2MiB对齐，不超过2MiB的区域只会用到一个P1页表
+const LAZY_ALIGN: u64 = 2 * 1024 * 1024;
+
+//已登记的按需分配区域(起始地址, 结束地址)，使用固定数组以便在页错误处理函数中访问
+static LAZY_RANGES: Mutex<[Option<(u64, u64)>; MAX_LAZY_RANGES]> =
+    Mutex::new([None; MAX_LAZY_RANGES]);
+//按需分配已映射的帧数
+static RESIDENT_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// ## 函数说明
+/// 从虚拟地址布局中保留`pages`个页面并登记为按需分配区域，不映射任何页面
+/// 首次访问某个页面时由页错误处理函数分配一个清零的帧；区域已满或空间不足时返回`None`
+///
+/// ## 参数
+/// * `pages` - 页面数量
+///
+/// ## 用法
+/// ```rust
+/// let buffer = alloc_lazy(64).expect("no lazy range");
+/// ```
+pub fn alloc_lazy(pages: u64) -> Option<VirtAddr> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut ranges = LAZY_RANGES.lock();
+        let slot = ranges.iter_mut().find(|range| range.is_none())?;
+        let start = vspace::reserve("lazy", pages * 4096, LAZY_ALIGN)?;
+        *slot = Some((start.as_u64(), start.as_u64() + pages * 4096));
+        Some(start)
+    })
+}
+
+/// ## 函数说明
+/// 按需分配已映射的页面数量
+pub fn resident_pages() -> usize {
+    RESIDENT_PAGES.load(Ordering::SeqCst)
+}
+
+//地址是否位于某个按需分配区域，锁被占用时返回`None`
+pub(crate) fn try_contains(addr: VirtAddr) -> Option<bool> {
+    let addr = addr.as_u64();
+    let ranges = LAZY_RANGES.try_lock()?;
+    Some(
+        ranges
+            .iter()
+            .flatten()
+            .any(|&(start, end)| (start..end).contains(&addr)),
+    )
+}
+
+/// ## 函数说明
+/// 处理按需分配区域中的缺页错误，由页错误处理函数调用
+/// 返回`false`表示地址不在任何按需分配区域内，或分配帧失败
+/// 被打断的代码可能正持有区域表的锁，此时不等待锁而是返回`false`，错误按普通页错误报告
+///
+/// ## 参数
+/// * `addr` - 引发错误的地址（CR2）
+pub fn handle_fault(addr: VirtAddr) -> bool {
+    if try_contains(addr) != Some(true) {
+        return false;
+    }
+
+    let page: Page<Size4KiB> = Page::containing_address(addr);
+    super::with_paging(|paging| {
+        //全局帧分配器返回的帧已清零
+        let frame = match paging.frame_allocator.allocate_frame() {
+            Some(frame) => frame,
+            None => return false,
+        };
+
+        let flags = super::wx::data_flags();
+        match unsafe {
+            paging
+                .mapper
+                .map_to(page, frame, flags, &mut paging.frame_allocator)
+        } {
+            Ok(flush) => {
+                flush.flush();
+                RESIDENT_PAGES.fetch_add(1, Ordering::SeqCst);
+                true
+            }
+            Err(_) => {
+                unsafe { paging.frame_allocator.deallocate_frame(frame) };
+                false
+            }
+        }
+    })
+    .unwrap_or(false)
+}
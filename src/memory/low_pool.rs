@@ -0,0 +1,187 @@
+use x86_64::structures::paging::PhysFrame;
+use x86_64::PhysAddr;
+
+/// ISA DMA能够访问的物理内存上限，低于它的帧由低端内存池单独管理
+pub const DMA_LIMIT: u64 = 16 * 1024 * 1024; // 16 MiB
+/// 普通分配在可用帧耗尽后可以借用低端内存池，但至少为DMA保留这么多帧
+pub const LOW_POOL_MIN: usize = 256;
+
+const POOL_FRAMES: usize = (DMA_LIMIT / 4096) as usize;
+
+/// ## 说明
+/// 管理16MiB以下物理帧的位图，每一位表示一个空闲帧，供需要低端内存的DMA设备使用
+///
+/// ## 成员
+/// * `free` - 空闲帧位图
+/// * `free_count` - 空闲帧数
+pub struct LowFramePool {
+    free: [u64; POOL_FRAMES / 64],
+    free_count: usize,
+}
+
+impl LowFramePool {
+    /// ## 函数说明
+    /// 创建一个没有空闲帧的内存池
+    pub const fn new() -> Self {
+        LowFramePool {
+            free: [0; POOL_FRAMES / 64],
+            free_count: 0,
+        }
+    }
+
+    fn is_free(&self, index: usize) -> bool {
+        self.free[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn set_free(&mut self, index: usize, free: bool) {
+        if self.is_free(index) != free {
+            self.free[index / 64] ^= 1 << (index % 64);
+            if free {
+                self.free_count += 1;
+            } else {
+                self.free_count -= 1;
+            }
+        }
+    }
+
+    //[start, end)中位于内存池范围内的帧下标
+    fn indexes(start: u64, end: u64) -> core::ops::Range<usize> {
+        let first = start.min(DMA_LIMIT).div_ceil(4096);
+        let last = end.min(DMA_LIMIT) / 4096;
+        first as usize..(last as usize).max(first as usize)
+    }
+
+    /// ## 函数说明
+    /// 将[start, end)中完整的帧标记为空闲，超出16MiB的部分被忽略
+    pub fn add_range(&mut self, start: PhysAddr, end: PhysAddr) {
+        for index in Self::indexes(start.as_u64(), end.as_u64()) {
+            self.set_free(index, true);
+        }
+    }
+
+    /// ## 函数说明
+    /// 将[start, end)中的帧移出内存池，部分覆盖的帧也会被移出
+    pub fn remove_range(&mut self, start: PhysAddr, end: PhysAddr) {
+        let start = start.align_down(4096u64).as_u64();
+        let end = end.align_up(4096u64).as_u64();
+        for index in Self::indexes(start, end) {
+            self.set_free(index, false);
+        }
+    }
+
+    /// ## 函数说明
+    /// 分配一个物理地址低于`limit`的帧，`limit`超过16MiB时按16MiB处理
+    ///
+    /// ## 参数
+    /// * `limit` - 帧的结束地址不能超过的上限
+    pub fn allocate_below(&mut self, limit: PhysAddr) -> Option<PhysFrame> {
+        self.allocate_contiguous_below(1, limit)
+    }
+
+    /// ## 函数说明
+    /// 分配`count`个物理上连续且都低于`limit`的帧，返回第一个帧
+    ///
+    /// ## 参数
+    /// * `count` - 帧数
+    /// * `limit` - 最后一个帧的结束地址不能超过的上限
+    pub fn allocate_contiguous_below(
+        &mut self,
+        count: usize,
+        limit: PhysAddr,
+    ) -> Option<PhysFrame> {
+        let frames = (limit.as_u64().min(DMA_LIMIT) / 4096) as usize;
+        if count == 0 || count > frames {
+            return None;
+        }
+        let mut run = 0;
+        for index in 0..frames {
+            run = if self.is_free(index) { run + 1 } else { 0 };
+            if run == count {
+                let first = index + 1 - count;
+                for i in first..=index {
+                    self.set_free(i, false);
+                }
+                let addr = PhysAddr::new(first as u64 * 4096);
+                return Some(PhysFrame::containing_address(addr));
+            }
+        }
+        None
+    }
+
+    /// ## 函数说明
+    /// 将帧归还给内存池，调用者需保证帧来自本内存池且不再被使用
+    ///
+    /// ## 参数
+    /// * `frame` - 低于16MiB的帧
+    pub fn deallocate(&mut self, frame: PhysFrame) {
+        let addr = frame.start_address().as_u64();
+        assert!(addr < DMA_LIMIT, "frame {:#x} is not a low frame", addr);
+        self.set_free((addr / 4096) as usize, true);
+    }
+
+    /// ## 函数说明
+    /// 内存池中的空闲帧数
+    pub fn free_frames(&self) -> usize {
+        self.free_count
+    }
+}
+
+impl Default for LowFramePool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ## 函数说明
+/// 通过全局帧分配器从低端内存池分配`count`个连续的帧，返回第一个帧
+/// 这些帧不会被清零；全局页表尚未安装或内存池中没有满足条件的范围时返回`None`
+///
+/// ## 参数
+/// * `count` - 帧数
+/// * `limit` - 物理地址上限，例如ISA DMA的`DMA_LIMIT`
+///
+/// ## 用法
+/// ```rust
+/// let buffer = alloc_dma_frames(16, PhysAddr::new(DMA_LIMIT)).expect("no low memory");
+/// ```
+pub fn alloc_dma_frames(count: usize, limit: PhysAddr) -> Option<PhysFrame> {
+    super::with_paging(|paging| {
+        paging
+            .frame_allocator
+            .inner_mut()
+            .inner_mut()
+            .allocate_contiguous_below(count, limit)
+    })
+    .flatten()
+}
+
+/// ## 函数说明
+/// 将`alloc_dma_frames`分配的帧归还给低端内存池
+///
+/// ## 参数
+/// * `first` - `alloc_dma_frames`的返回值
+/// * `count` - 分配时的帧数
+pub fn free_dma_frames(first: PhysFrame, count: usize) {
+    super::with_paging(|paging| {
+        let frames = paging.frame_allocator.inner_mut().inner_mut();
+        for frame in PhysFrame::range(first, first + count as u64) {
+            frames.deallocate_low(frame);
+        }
+    });
+}
+
+#[test_case]
+fn test_low_pool_contiguous() {
+    let frame_at = |addr| Some(PhysFrame::containing_address(PhysAddr::new(addr)));
+    let mut pool = LowFramePool::new();
+    pool.add_range(PhysAddr::new(0x1000), PhysAddr::new(0x9000));
+    pool.remove_range(PhysAddr::new(0x2000), PhysAddr::new(0x2001));
+    assert_eq!(pool.free_frames(), 7);
+
+    //0x2000被移出，连续3帧只能从0x3000开始
+    let limit = PhysAddr::new(DMA_LIMIT);
+    assert_eq!(pool.allocate_contiguous_below(3, limit), frame_at(0x3000));
+    assert_eq!(pool.allocate_below(PhysAddr::new(0x2000)), frame_at(0x1000));
+    assert_eq!(pool.allocate_below(PhysAddr::new(0x2000)), None);
+    assert_eq!(pool.free_frames(), 3);
+}
@@ -0,0 +1,85 @@
+use spin::Once;
+use x86_64::{PhysAddr, VirtAddr};
+
+//完整物理内存映射的偏移量，由`memory::init`设置一次
+static PHYS_OFFSET: Once<VirtAddr> = Once::new();
+
+//由`memory::init`调用，重复调用时保留第一次的值
+pub(super) fn set_phys_offset(offset: VirtAddr) {
+    PHYS_OFFSET.call_once(|| offset);
+}
+
+/// ## 函数说明
+/// 完整物理内存映射的起始虚拟地址，在`memory::init`之前调用会panic
+///
+/// ## 用法
+/// ```rust
+/// let virt = phys_offset() + frame.start_address().as_u64();
+/// ```
+pub fn phys_offset() -> VirtAddr {
+    *PHYS_OFFSET
+        .r#try()
+        .expect("memory::phys_offset called before memory::init")
+}
+
+//供可能早于`memory::init`运行的代码（如异常处理函数）使用
+pub(crate) fn try_phys_offset() -> Option<VirtAddr> {
+    PHYS_OFFSET.r#try().copied()
+}
+
+/// ## 函数说明
+/// 物理地址在物理内存映射中对应的虚拟地址
+///
+/// ## 参数
+/// * `addr` - 物理地址
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    phys_offset() + addr.as_u64()
+}
+
+/// ## 函数说明
+/// 通过物理内存映射读取一个值
+///
+/// ## 参数
+/// * `addr` - 物理地址
+///
+/// ## Safety
+/// 调用者需保证`addr`处的物理内存存在、按`T`对齐，且内容是合法的`T`
+///
+/// ## 用法
+/// ```rust
+/// let link: u64 = unsafe { phys_read(frame.start_address()) };
+/// ```
+pub unsafe fn phys_read<T: Copy>(addr: PhysAddr) -> T {
+    phys_to_virt(addr).as_ptr::<T>().read_volatile()
+}
+
+/// ## 函数说明
+/// 通过物理内存映射写入一个值
+///
+/// ## 参数
+/// * `addr` - 物理地址
+/// * `value` - 写入的值
+///
+/// ## Safety
+/// 调用者需保证`addr`处的物理内存存在、按`T`对齐，且没有其他映射或代码依赖该处的内容
+pub unsafe fn phys_write<T: Copy>(addr: PhysAddr, value: T) {
+    phys_to_virt(addr).as_mut_ptr::<T>().write_volatile(value)
+}
+
+/// ## 函数说明
+/// 以字节切片的形式访问一段物理内存
+///
+/// ## 参数
+/// * `addr` - 物理起始地址
+/// * `len` - 字节数
+///
+/// ## Safety
+/// 调用者需保证`[addr, addr + len)`整个范围都存在，且在返回的切片使用期间不被修改
+///
+/// ## 用法
+/// ```rust
+/// let bytes = unsafe { phys_slice(PhysAddr::new(0xb8000), 160) };
+/// ```
+pub unsafe fn phys_slice(addr: PhysAddr, len: usize) -> &'static [u8] {
+    core::slice::from_raw_parts(phys_to_virt(addr).as_ptr(), len)
+}
@@ -0,0 +1,98 @@
+use x86_64::structures::paging::mapper::FlagUpdateError;
+use x86_64::structures::paging::{Mapper, OffsetPageTable, Page, PageTableFlags};
+use x86_64::VirtAddr;
+
+use super::vspace::{KERNEL_AREA_END, KERNEL_AREA_START};
+
+//高半部分的起始地址，目前只有内核会使用
+const HIGHER_HALF_START: u64 = 0xffff_8000_0000_0000;
+
+/// ## 说明
+/// 修改已有映射的标志时可能出现的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtectError {
+    /// 页面没有被映射
+    NotMapped,
+    /// 页面位于大页中
+    HugePage,
+    /// 试图在内核地址上设置USER_ACCESSIBLE，但没有传入`allow_user`
+    UserOnKernel,
+}
+
+impl From<FlagUpdateError> for ProtectError {
+    fn from(err: FlagUpdateError) -> Self {
+        match err {
+            FlagUpdateError::PageNotMapped => ProtectError::NotMapped,
+            FlagUpdateError::ParentEntryHugePage => ProtectError::HugePage,
+        }
+    }
+}
+
+/// ## 函数说明
+/// 地址是否属于内核：内核映像、内核可分配区域（堆、栈、MMIO等）或高半部分
+///
+/// ## 参数
+/// * `addr` - 虚拟地址
+pub fn is_kernel_address(addr: VirtAddr) -> bool {
+    let in_image = super::kernel_regions()
+        .iter()
+        .any(|r| (r.start..r.end).contains(&addr));
+    in_image
+        || (KERNEL_AREA_START..KERNEL_AREA_END).contains(&addr.as_u64())
+        || addr.as_u64() >= HIGHER_HALF_START
+}
+
+/// ## 函数说明
+/// 将已映射页面的P1表项标志替换为`new_flags`并刷新TLB，不会取消映射，PRESENT会自动设置
+/// 只修改P1表项，用户态页面的父表项需要事先由`map_user_page`设置
+///
+/// ## 参数
+/// * `page` - 页面
+/// * `new_flags` - 新的页表项标志
+/// * `mapper` - 页表
+/// * `allow_user` - 是否允许在内核地址上设置USER_ACCESSIBLE
+///
+/// ## 用法
+/// ```rust
+/// //代码写入完成后改为只读可执行
+/// protect(page, PageTableFlags::PRESENT, &mut mapper, false)?;
+/// ```
+pub fn protect(
+    page: Page,
+    new_flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    allow_user: bool,
+) -> Result<(), ProtectError> {
+    if new_flags.contains(PageTableFlags::USER_ACCESSIBLE)
+        && !allow_user
+        && is_kernel_address(page.start_address())
+    {
+        return Err(ProtectError::UserOnKernel);
+    }
+    let flags = new_flags | PageTableFlags::PRESENT;
+    unsafe { mapper.update_flags(page, flags) }?.flush();
+    Ok(())
+}
+
+/// ## 函数说明
+/// 对从`start`开始的`count`个页面依次调用`protect`，遇到第一个错误时停止并返回出错的页面
+/// 出错页面之前的页面已经被修改
+///
+/// ## 参数
+/// * `start` - 第一个页面
+/// * `count` - 页面数量
+/// * `new_flags` - 新的页表项标志
+/// * `mapper` - 页表
+/// * `allow_user` - 是否允许在内核地址上设置USER_ACCESSIBLE
+pub fn protect_range(
+    start: Page,
+    count: u64,
+    new_flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    allow_user: bool,
+) -> Result<(), (Page, ProtectError)> {
+    for page in Page::range(start, start + count) {
+        protect(page, new_flags, mapper, allow_user).map_err(|err| (page, err))?;
+    }
+    Ok(())
+}
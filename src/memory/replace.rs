@@ -0,0 +1,63 @@
+use x86_64::instructions::tlb;
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame};
+
+/// ## 说明
+/// 替换映射时可能出现的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaceError {
+    /// 页面或某一级父表项不存在
+    NotMapped,
+    /// 页面位于大页中
+    HugePage,
+}
+
+/// ## 函数说明
+/// 用一次写入将已映射页面的P1表项替换为指向`new_frame`的新表项，刷新该页面的TLB后返回原来映射的帧
+/// 替换过程中页面始终处于映射状态，同时访问该页面的中断处理函数只会看到旧帧或新帧，不会引发页错误
+/// 原来的帧不会被释放，由调用者决定释放或保留；PRESENT会自动设置
+///
+/// ## 参数
+/// * `page` - 已映射的4KiB页面
+/// * `new_frame` - 新的物理帧
+/// * `new_flags` - 新的页表项标志
+/// * `mapper` - 页表
+///
+/// ## 用法
+/// ```rust
+/// let old = replace_mapping(page, new_frame, DATA_FLAGS, &mut mapper)?;
+/// unsafe { frame_allocator.deallocate_frame(old) };
+/// ```
+pub fn replace_mapping(
+    page: Page,
+    new_frame: PhysFrame,
+    new_flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+) -> Result<PhysFrame, ReplaceError> {
+    let offset = mapper.phys_offset();
+    let addr = page.start_address();
+    let mut table: &mut PageTable = mapper.level_4_table();
+    for index in [addr.p4_index(), addr.p3_index(), addr.p2_index()] {
+        let entry = &table[index];
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return Err(ReplaceError::NotMapped);
+        }
+        if flags.contains(PageTableFlags::HUGE_PAGE) {
+            return Err(ReplaceError::HugePage);
+        }
+        table = unsafe { &mut *(offset + entry.addr().as_u64()).as_mut_ptr::<PageTable>() };
+    }
+
+    let entry = &mut table[addr.p1_index()];
+    let old_frame = entry.frame().map_err(|_| ReplaceError::NotMapped)?;
+    let mut new_entry = PageTableEntry::new();
+    new_entry.set_addr(
+        new_frame.start_address(),
+        new_flags | PageTableFlags::PRESENT,
+    );
+    //表项是一个对齐的u64，单次写入对同时进行的页表遍历是原子的
+    unsafe { core::ptr::write_volatile(entry as *mut PageTableEntry, new_entry) };
+    tlb::flush(addr);
+    Ok(old_frame)
+}
@@ -0,0 +1,244 @@
+use spin::Mutex;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, OffsetPageTable, Page, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+use super::vspace;
+
+const MAX_STACKS: usize = 64;
+
+/// ## 说明
+/// 由`StackAllocator`分配的内核栈，栈下方紧邻一个不映射的保护页
+///
+/// ## 成员
+/// * `guard` - 保护页
+/// * `pages` - 栈的页数，不含保护页
+#[derive(Debug, PartialEq, Eq)]
+pub struct KernelStack {
+    guard: Page,
+    pages: u64,
+}
+
+impl KernelStack {
+    /// ## 函数说明
+    /// 栈顶地址（不含），按页对齐，可以直接作为栈指针使用
+    pub fn top(&self) -> VirtAddr {
+        (self.guard + 1 + self.pages).start_address()
+    }
+
+    /// ## 函数说明
+    /// 栈的最低地址，即保护页的上边界
+    pub fn bottom(&self) -> VirtAddr {
+        (self.guard + 1).start_address()
+    }
+
+    /// ## 函数说明
+    /// 栈下方的保护页
+    pub fn guard_page(&self) -> Page {
+        self.guard
+    }
+
+    /// ## 函数说明
+    /// 栈的页数，不含保护页
+    pub fn pages(&self) -> u64 {
+        self.pages
+    }
+}
+
+/// ## 说明
+/// 分配和释放内核栈时可能出现的错误
+#[derive(Debug)]
+pub enum StackAllocError {
+    /// 请求的页数为0
+    ZeroSize,
+    /// 虚拟地址范围中没有足够大的空闲范围
+    OutOfVirtualSpace,
+    /// 登记表已满
+    TooManyStacks,
+    /// 页表映射失败
+    Map(MapToError<Size4KiB>),
+    /// 栈不是由该分配器分配的
+    NotAllocated,
+}
+
+impl From<MapToError<Size4KiB>> for StackAllocError {
+    fn from(err: MapToError<Size4KiB>) -> Self {
+        StackAllocError::Map(err)
+    }
+}
+
+/// ## 说明
+/// 在一段虚拟地址范围中分配内核栈，每个栈的下方保留一个保护页，因此相邻的栈之间至少间隔一个保护页
+/// 释放后的范围可以被再次分配
+///
+/// ## 成员
+/// * `start` - 范围的起始地址
+/// * `end` - 范围的结束地址（不含）
+/// * `stacks` - 已分配的栈(保护页地址, 页数)
+pub struct StackAllocator {
+    start: u64,
+    end: u64,
+    stacks: [Option<(u64, u64)>; MAX_STACKS],
+}
+
+impl StackAllocator {
+    /// ## 函数说明
+    /// 创建一个管理`[start, start + size)`的栈分配器，范围需要按页对齐
+    ///
+    /// ## 参数
+    /// * `start` - 起始地址
+    /// * `size` - 大小（字节）
+    ///
+    /// ## 用法
+    /// ```rust
+    /// let start = vspace::reserve("task stacks", size, 4096).unwrap();
+    /// let mut stacks = StackAllocator::new(start, size);
+    /// ```
+    pub fn new(start: VirtAddr, size: u64) -> Self {
+        assert!(start.is_aligned(4096u64) && size.is_multiple_of(4096));
+        StackAllocator {
+            start: start.as_u64(),
+            end: start.as_u64() + size,
+            stacks: [None; MAX_STACKS],
+        }
+    }
+
+    //找到能容纳`span`字节的最低地址，候选位置为范围起点和每个已分配栈的栈顶
+    fn find_free(&self, span: u64) -> Option<u64> {
+        let overlaps = |start: u64| {
+            self.stacks
+                .iter()
+                .flatten()
+                .any(|&(s, n)| start < s + (n + 1) * 4096 && s < start + span)
+        };
+        let candidates = core::iter::once(self.start).chain(
+            self.stacks
+                .iter()
+                .flatten()
+                .map(|&(s, n)| s + (n + 1) * 4096),
+        );
+        candidates
+            .filter(|&start| start + span <= self.end && !overlaps(start))
+            .min()
+    }
+
+    /// ## 函数说明
+    /// 分配并映射一个`pages`页的栈，保护页不映射
+    ///
+    /// ## 参数
+    /// * `pages` - 栈的页数，不含保护页
+    /// * `mapper` - 页表
+    /// * `frame_allocator` - 帧分配器
+    ///
+    /// ## 用法
+    /// ```rust
+    /// let stack = stacks.allocate(4, &mut mapper, &mut frame_allocator)?;
+    /// let rsp = stack.top();
+    /// ```
+    pub fn allocate(
+        &mut self,
+        pages: u64,
+        mapper: &mut OffsetPageTable,
+        frame_allocator: &mut (impl FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>),
+    ) -> Result<KernelStack, StackAllocError> {
+        if pages == 0 {
+            return Err(StackAllocError::ZeroSize);
+        }
+        let slot = self
+            .stacks
+            .iter()
+            .position(|stack| stack.is_none())
+            .ok_or(StackAllocError::TooManyStacks)?;
+        let start = self
+            .find_free((pages + 1) * 4096)
+            .ok_or(StackAllocError::OutOfVirtualSpace)?;
+
+        let stack = KernelStack {
+            guard: Page::containing_address(VirtAddr::new(start)),
+            pages,
+        };
+        let first = stack.guard + 1;
+        super::map_fresh_range(
+            first,
+            pages,
+            super::wx::data_flags(),
+            mapper,
+            frame_allocator,
+        )
+        .map_err(StackAllocError::Map)?;
+
+        self.stacks[slot] = Some((start, pages));
+        Ok(stack)
+    }
+
+    /// ## 函数说明
+    /// 取消栈的映射并归还帧，之后其地址范围可以再次分配
+    ///
+    /// ## 参数
+    /// * `stack` - `allocate`的返回值
+    /// * `mapper` - 页表
+    /// * `frame_allocator` - 用于回收帧的帧分配器
+    pub fn deallocate(
+        &mut self,
+        stack: KernelStack,
+        mapper: &mut OffsetPageTable,
+        frame_allocator: &mut impl FrameDeallocator<Size4KiB>,
+    ) -> Result<(), StackAllocError> {
+        let entry = Some((stack.guard.start_address().as_u64(), stack.pages));
+        let slot = self
+            .stacks
+            .iter_mut()
+            .find(|slot| **slot == entry)
+            .ok_or(StackAllocError::NotAllocated)?;
+        *slot = None;
+
+        let first = stack.guard + 1;
+        for page in Page::range(first, first + stack.pages) {
+            let _ = super::unmap_page(page, mapper, frame_allocator);
+        }
+        Ok(())
+    }
+}
+
+//管理"stacks"区域的全局栈分配器，首次使用时创建；锁顺序：KERNEL_STACKS → KERNEL_PAGING
+static KERNEL_STACKS: Mutex<Option<StackAllocator>> = Mutex::new(None);
+
+/// ## 函数说明
+/// 在禁用中断的情况下访问管理"stacks"区域的全局栈分配器
+///
+/// ## 用法
+/// ```rust
+/// let stack = with_kernel_stacks(|stacks| {
+///     memory::with_paging(|paging| {
+///         stacks.allocate(4, &mut paging.mapper, &mut paging.frame_allocator)
+///     })
+/// });
+/// ```
+pub fn with_kernel_stacks<R>(f: impl FnOnce(&mut StackAllocator) -> R) -> R {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut stacks = KERNEL_STACKS.lock();
+        let stacks = stacks.get_or_insert_with(|| {
+            let region = vspace::find("stacks").expect("no stacks region");
+            StackAllocator::new(region.start, region.size)
+        });
+        f(stacks)
+    })
+}
+
+#[test_case]
+fn test_find_free_reuses_holes() {
+    let base = 0x10_0000;
+    let mut stacks = StackAllocator::new(VirtAddr::new(base), 16 * 4096);
+    assert_eq!(stacks.find_free(5 * 4096), Some(base));
+
+    stacks.stacks[0] = Some((base, 4));
+    stacks.stacks[1] = Some((base + 5 * 4096, 4));
+    //下一个栈的保护页紧接在上一个栈的栈顶之上
+    assert_eq!(stacks.find_free(3 * 4096), Some(base + 10 * 4096));
+
+    stacks.stacks[0] = None;
+    assert_eq!(stacks.find_free(5 * 4096), Some(base));
+    assert_eq!(stacks.find_free(7 * 4096), None);
+}
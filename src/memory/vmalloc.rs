@@ -0,0 +1,140 @@
+use spin::Mutex;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{Page, Size4KiB};
+use x86_64::VirtAddr;
+
+use super::vspace;
+
+const MAX_VMALLOC_REGIONS: usize = 32;
+/// "vmalloc"区域的大小
+pub const VMALLOC_SIZE: u64 = 1024 * 1024 * 1024; // 1 GiB
+
+//已分配的区域(起始地址, 页数)，相邻区域之间至少间隔一个未映射的页面
+static REGIONS: Mutex<[Option<(u64, u64)>; MAX_VMALLOC_REGIONS]> =
+    Mutex::new([None; MAX_VMALLOC_REGIONS]);
+
+/// ## 说明
+/// vmalloc和vfree可能出现的错误
+#[derive(Debug)]
+pub enum VmallocError {
+    /// 请求的大小为0
+    ZeroSize,
+    /// "vmalloc"区域中没有足够大的空闲范围
+    OutOfVirtualSpace,
+    /// 登记表已满
+    TooManyRegions,
+    /// 页表映射失败
+    Map(MapToError<Size4KiB>),
+    /// 全局页表尚未安装
+    NoPaging,
+    /// 地址不是vmalloc返回的起始地址
+    NotAllocated,
+}
+
+//在区域中找到能容纳`pages`页的最低地址，与已有区域之间保留一个页面的间隔
+fn find_gap(
+    regions: &[Option<(u64, u64)>],
+    area_start: u64,
+    area_end: u64,
+    pages: u64,
+) -> Option<u64> {
+    let size = pages * 4096;
+    let overlaps = |start: u64| {
+        regions.iter().flatten().any(|&(s, n)| {
+            let end = s + (n + 1) * 4096;
+            start < end && s < start + size + 4096
+        })
+    };
+    let candidates = core::iter::once(area_start)
+        .chain(regions.iter().flatten().map(|&(s, n)| s + (n + 1) * 4096));
+    candidates
+        .filter(|&start| start + size <= area_end && !overlaps(start))
+        .min()
+}
+
+/// ## 函数说明
+/// 分配一段虚拟地址连续、物理帧不必连续的内存，每个页面单独映射到帧分配器给出的帧
+///
+/// ## 参数
+/// * `size` - 字节数，会向上取整到页
+///
+/// ## 用法
+/// ```rust
+/// let buffer = vmalloc(1024 * 1024)?;
+/// vfree(buffer)?;
+/// ```
+pub fn vmalloc(size: usize) -> Result<VirtAddr, VmallocError> {
+    if size == 0 {
+        return Err(VmallocError::ZeroSize);
+    }
+    let pages = (size as u64).div_ceil(4096);
+    let area = vspace::find("vmalloc").ok_or(VmallocError::OutOfVirtualSpace)?;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        let slot = regions
+            .iter()
+            .position(|region| region.is_none())
+            .ok_or(VmallocError::TooManyRegions)?;
+        let start = find_gap(&*regions, area.start.as_u64(), area.end().as_u64(), pages)
+            .ok_or(VmallocError::OutOfVirtualSpace)?;
+
+        let first: Page = Page::containing_address(VirtAddr::new(start));
+        super::with_paging(|paging| {
+            super::map_fresh_range(
+                first,
+                pages,
+                super::wx::data_flags(),
+                &mut paging.mapper,
+                &mut paging.frame_allocator,
+            )
+            .map_err(VmallocError::Map)
+        })
+        .ok_or(VmallocError::NoPaging)??;
+
+        regions[slot] = Some((start, pages));
+        Ok(VirtAddr::new(start))
+    })
+}
+
+/// ## 函数说明
+/// 释放`vmalloc`分配的内存，取消映射并把帧交还给全局帧分配器
+///
+/// ## 参数
+/// * `addr` - `vmalloc`的返回值
+pub fn vfree(addr: VirtAddr) -> Result<(), VmallocError> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        let slot = regions
+            .iter_mut()
+            .find(|region| matches!(region, Some((start, _)) if *start == addr.as_u64()))
+            .ok_or(VmallocError::NotAllocated)?;
+        let (start, pages) = slot.take().unwrap();
+
+        let first: Page = Page::containing_address(VirtAddr::new(start));
+        super::with_paging(|paging| {
+            for page in Page::range(first, first + pages) {
+                let _ = super::unmap_page(page, &mut paging.mapper, &mut paging.frame_allocator);
+            }
+        })
+        .ok_or(VmallocError::NoPaging)
+    })
+}
+
+#[test_case]
+fn test_find_gap_reuses_holes() {
+    let mut regions = [None; 4];
+    let base = 0x10_0000;
+    let end = base + 64 * 4096;
+    assert_eq!(find_gap(&regions, base, end, 4), Some(base));
+
+    regions[0] = Some((base, 4));
+    regions[1] = Some((base + 5 * 4096, 4));
+    //两个区域之间保留一个页面的间隔
+    assert_eq!(find_gap(&regions, base, end, 2), Some(base + 10 * 4096));
+
+    regions[0] = None;
+    assert_eq!(find_gap(&regions, base, end, 4), Some(base));
+    assert_eq!(find_gap(&regions, base, end, 5), Some(base + 10 * 4096));
+    assert_eq!(find_gap(&regions, base, end, 64), None);
+}
@@ -0,0 +1,278 @@
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+use crate::allocator::HEAP_MAX_SIZE;
+use crate::memory::vmalloc::VMALLOC_SIZE;
+use crate::memory::MMIO_SIZE;
+
+/// 内核可分配虚拟区域的起始地址
+pub const KERNEL_AREA_START: u64 = 0x_4444_4444_0000;
+/// 内核可分配虚拟区域的结束地址（不含）
+pub const KERNEL_AREA_END: u64 = 0x_6000_0000_0000;
+/// 为内核栈保留的虚拟空间大小
+pub const STACKS_SIZE: u64 = 1024 * 1024 * 1024; // 1 GiB
+
+const MAX_REGIONS: usize = 32;
+
+/// 内核的虚拟地址布局，由`init`注册预定义的区域
+pub static REGIONS: Mutex<RegionManager> =
+    Mutex::new(RegionManager::new(KERNEL_AREA_START, KERNEL_AREA_END));
+
+/// ## 说明
+/// 一段已保留的虚拟地址区域
+///
+/// ## 成员
+/// * `name` - 区域名称
+/// * `start` - 起始地址
+/// * `size` - 大小（字节）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub name: &'static str,
+    pub start: VirtAddr,
+    pub size: u64,
+}
+
+impl Region {
+    /// ## 说明
+    /// 区域的结束地址（不含）
+    pub fn end(&self) -> VirtAddr {
+        self.start + self.size
+    }
+}
+
+/// ## 说明
+/// 从一段虚拟地址空间中按顺序划分互不重叠的区域，区域一经保留不会释放
+/// 使用固定大小的数组保存区域，因此可以在堆初始化之前使用
+///
+/// ## 成员
+/// * `start` - 可分配空间的起始地址
+/// * `end` - 可分配空间的结束地址（不含）
+/// * `next` - 下一个未使用的地址
+/// * `regions` - 已保留的区域
+/// * `len` - 已保留区域的数量
+pub struct RegionManager {
+    start: u64,
+    end: u64,
+    next: u64,
+    regions: [Option<Region>; MAX_REGIONS],
+    len: usize,
+}
+
+impl RegionManager {
+    pub const fn new(start: u64, end: u64) -> Self {
+        RegionManager {
+            start,
+            end,
+            next: start,
+            regions: [None; MAX_REGIONS],
+            len: 0,
+        }
+    }
+
+    /// ## 函数说明
+    /// 保留一段大小为`size`、按`alignment`对齐的区域，空间不足或区域数量已满时返回`None`
+    ///
+    /// ## 参数
+    /// * `name` - 区域名称
+    /// * `size` - 大小（字节）
+    /// * `alignment` - 对齐方式，必须是2的幂
+    ///
+    /// ## 用法
+    /// ```rust
+    /// let stack = REGIONS.lock().reserve("stack0", 64 * 1024, 4096)?;
+    /// ```
+    pub fn reserve(&mut self, name: &'static str, size: u64, alignment: u64) -> Option<VirtAddr> {
+        assert!(
+            alignment.is_power_of_two(),
+            "alignment must be a power of two"
+        );
+        if self.len == MAX_REGIONS {
+            return None;
+        }
+
+        let start = self.next.checked_add(alignment - 1)? & !(alignment - 1);
+        let end = start.checked_add(size)?;
+        if end > self.end {
+            return None;
+        }
+
+        let region = Region {
+            name,
+            start: VirtAddr::new(start),
+            size,
+        };
+        self.regions[self.len] = Some(region);
+        self.len += 1;
+        self.next = end;
+        Some(region.start)
+    }
+
+    /// ## 函数说明
+    /// 按名称查找区域，同名时返回最早保留的区域
+    ///
+    /// ## 参数
+    /// * `name` - 区域名称
+    pub fn find(&self, name: &str) -> Option<Region> {
+        self.regions().find(|region| region.name == name)
+    }
+
+    /// ## 函数说明
+    /// 按地址从低到高遍历已保留的区域
+    pub fn regions(&self) -> impl Iterator<Item = Region> + '_ {
+        self.regions[..self.len].iter().flatten().copied()
+    }
+
+    /// ## 函数说明
+    /// 尚未保留的空间大小
+    pub fn remaining(&self) -> u64 {
+        self.end - self.next
+    }
+
+    /// ## 函数说明
+    /// 向串口打印虚拟地址布局
+    pub fn dump(&self) {
+        use crate::serial_println;
+
+        serial_println!("kernel virtual area {:#x}-{:#x}", self.start, self.end);
+        for region in self.regions() {
+            serial_println!(
+                "  {:#016x}-{:#016x} {:<8} {}",
+                region.start.as_u64(),
+                region.end().as_u64(),
+                region.name,
+                super::ByteSize(region.size)
+            );
+        }
+        serial_println!("  {} unreserved", super::ByteSize(self.remaining()));
+    }
+}
+
+/// ## 函数说明
+/// 注册预定义的区域：堆（heap）、MMIO窗口（mmio）、内核栈（stacks）和vmalloc，重复调用不会重复注册
+///
+/// ## 用法
+/// ```rust
+/// vspace::init();
+/// ```
+pub fn init() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        if regions.find("heap").is_some() {
+            return;
+        }
+        const PAGE: u64 = 4096;
+        const HUGE_PAGE: u64 = 2 * 1024 * 1024;
+        //堆按2MiB对齐，以便init_heap使用大页
+        regions
+            .reserve("heap", HEAP_MAX_SIZE as u64, HUGE_PAGE)
+            .expect("no space for heap region");
+        regions
+            .reserve("mmio", MMIO_SIZE, PAGE)
+            .expect("no space for mmio region");
+        regions
+            .reserve("stacks", STACKS_SIZE, PAGE)
+            .expect("no space for stacks region");
+        regions
+            .reserve("vmalloc", VMALLOC_SIZE, PAGE)
+            .expect("no space for vmalloc region");
+    });
+}
+
+/// ## 函数说明
+/// 在全局布局中查找区域，必要时先注册预定义的区域
+///
+/// ## 参数
+/// * `name` - 区域名称
+pub fn find(name: &str) -> Option<Region> {
+    init();
+    x86_64::instructions::interrupts::without_interrupts(|| REGIONS.lock().find(name))
+}
+
+/// ## 函数说明
+/// 在全局布局中保留一段新区域
+///
+/// ## 参数
+/// * `name` - 区域名称
+/// * `size` - 大小（字节）
+/// * `alignment` - 对齐方式，必须是2的幂
+pub fn reserve(name: &'static str, size: u64, alignment: u64) -> Option<VirtAddr> {
+    init();
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        REGIONS.lock().reserve(name, size, alignment)
+    })
+}
+
+/// ## 函数说明
+/// [start, start + size)是否与全局布局中已保留的某个区域重叠
+///
+/// ## 参数
+/// * `start` - 起始地址
+/// * `size` - 大小（字节）
+pub fn overlaps(start: VirtAddr, size: u64) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        REGIONS
+            .lock()
+            .regions()
+            .any(|region| region.start < start + size && start < region.end())
+    })
+}
+
+//供页错误处理函数使用：出错的代码可能正持有锁，此时放弃查找而不是死锁
+pub(crate) fn try_region_of(addr: VirtAddr) -> Option<Region> {
+    REGIONS
+        .try_lock()?
+        .regions()
+        .find(|region| (region.start..region.end()).contains(&addr))
+}
+
+/// ## 函数说明
+/// 向串口打印全局虚拟地址布局
+pub fn dump() {
+    x86_64::instructions::interrupts::without_interrupts(|| REGIONS.lock().dump());
+}
+
+#[test_case]
+fn test_reserve_disjoint_and_aligned() {
+    let mut manager = RegionManager::new(0x1000, 0x10_0000);
+    let requests = [
+        (1, 1),
+        (4095, 4096),
+        (4097, 4096),
+        (3, 16),
+        (0x2000, 0x8000),
+        (17, 8),
+        (1, 0x1_0000),
+    ];
+    for &(size, alignment) in requests.iter() {
+        let start = manager.reserve("test", size, alignment).unwrap();
+        assert!(start.is_aligned(alignment));
+    }
+
+    let regions = manager.regions();
+    for (i, a) in regions.enumerate() {
+        assert!(a.start.as_u64() >= 0x1000 && a.end().as_u64() <= 0x10_0000);
+        for b in manager.regions().skip(i + 1) {
+            assert!(a.end() <= b.start || b.end() <= a.start);
+        }
+    }
+}
+
+#[test_case]
+fn test_reserve_exhaustion() {
+    let mut manager = RegionManager::new(0x1000, 0x3000);
+    assert_eq!(
+        manager.reserve("a", 0x1000, 0x1000),
+        Some(VirtAddr::new(0x1000))
+    );
+    assert_eq!(manager.reserve("b", 0x2000, 0x1000), None);
+    assert_eq!(
+        manager.reserve("c", 0x1000, 0x1000),
+        Some(VirtAddr::new(0x2000))
+    );
+    assert_eq!(
+        manager.find("c").map(|r| r.start),
+        Some(VirtAddr::new(0x2000))
+    );
+    assert_eq!(manager.find("b"), None);
+    assert_eq!(manager.remaining(), 0);
+}
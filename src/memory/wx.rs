@@ -0,0 +1,211 @@
+use x86_64::structures::paging::mapper::{MappedFrame, TranslateResult};
+use x86_64::structures::paging::{Mapper, OffsetPageTable, Page, PageTableFlags, Translate};
+use x86_64::VirtAddr;
+
+use super::vspace::{KERNEL_AREA_END, KERNEL_AREA_START};
+
+//由链接器定义的符号：ELF头（第一个段的起点）、代码段的起点（见kernel.ld）、代码段的结束和整个映像的结束
+extern "C" {
+    static __ehdr_start: u8;
+    static __text_start: u8;
+    static etext: u8;
+    static end: u8;
+}
+
+/// 数据页面（堆、栈等）使用的页表项标志，写入但不执行
+/// 建立映射时使用[`data_flags`]，它会检查NX已经启用
+pub const DATA_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_EXECUTE);
+
+/// ## 说明
+/// 内核映像中区域的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelRegionKind {
+    /// 代码：清除WRITABLE
+    Code,
+    /// 只读数据：清除WRITABLE，设置NO_EXECUTE
+    ReadOnly,
+    /// 可写数据和bss：设置NO_EXECUTE
+    Data,
+}
+
+/// ## 说明
+/// 内核映像中的一段区域
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelRegion {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub kind: KernelRegionKind,
+}
+
+/// ## 函数说明
+/// 根据链接器符号划分内核映像：[ELF头, __text_start)为只读数据，[__text_start, etext)为代码，其后到end为可写数据
+/// lld按页放置各个段，因此代码段从只读数据之后的新页面开始，etext之后的下一页开始就是数据段
+pub fn kernel_regions() -> [KernelRegion; 3] {
+    let (ehdr, text_start, text_end, image_end) = unsafe {
+        (
+            VirtAddr::from_ptr(&__ehdr_start),
+            VirtAddr::from_ptr(&__text_start),
+            VirtAddr::from_ptr(&etext),
+            VirtAddr::from_ptr(&end),
+        )
+    };
+    //代码段的第一页可能以只读数据的文件内容开头，该页按代码处理
+    let rodata_end = text_start.align_down(4096u64);
+    let data_start = text_end.align_up(4096u64);
+    [
+        KernelRegion {
+            start: ehdr,
+            end: rodata_end,
+            kind: KernelRegionKind::ReadOnly,
+        },
+        KernelRegion {
+            start: text_start,
+            end: text_end,
+            kind: KernelRegionKind::Code,
+        },
+        KernelRegion {
+            start: data_start,
+            end: image_end,
+            kind: KernelRegionKind::Data,
+        },
+    ]
+}
+
+/// ## 函数说明
+/// 地址是否位于内核代码区域，用于区分W^X违规
+///
+/// ## 参数
+/// * `addr` - 虚拟地址
+pub fn is_kernel_code(addr: VirtAddr) -> bool {
+    kernel_regions()
+        .iter()
+        .any(|r| r.kind == KernelRegionKind::Code && (r.start..r.end).contains(&addr))
+}
+
+/// ## 函数说明
+/// 地址是否位于内核映像的只读部分（代码或只读数据），用于区分写入只读页面的错误
+///
+/// ## 参数
+/// * `addr` - 虚拟地址
+pub fn is_kernel_read_only(addr: VirtAddr) -> bool {
+    kernel_regions()
+        .iter()
+        .any(|r| r.kind != KernelRegionKind::Data && (r.start..r.end).contains(&addr))
+}
+
+/// ## 函数说明
+/// 数据页面使用的页表项标志，即[`DATA_FLAGS`]
+pub fn data_flags() -> PageTableFlags {
+    assert_nx_usable(DATA_FLAGS);
+    DATA_FLAGS
+}
+
+/// ## 函数说明
+/// 检查页表项标志中的NO_EXECUTE只在`cpu::enable_nx`之后使用，否则这个位是保留位
+///
+/// ## 参数
+/// * `flags` - 将要写入页表项的标志
+#[inline]
+pub fn assert_nx_usable(flags: PageTableFlags) {
+    debug_assert!(
+        !flags.contains(PageTableFlags::NO_EXECUTE) || crate::cpu::nx_enabled(),
+        "NO_EXECUTE used before cpu::enable_nx"
+    );
+}
+
+/// ## 函数说明
+/// 按区域类型修改内核映像的映射：代码页不可写，只读数据页不可写也不可执行，数据页不可执行
+/// 跳过未映射的页面和大页，返回修改的页数
+///
+/// 只处理内核映像，引导程序建立的物理内存映射窗口和引导栈由[`protect_boot_mappings`]处理
+///
+/// ## 参数
+/// * `mapper` - 页表
+/// * `kernel_regions` - 内核区域，通常来自`kernel_regions()`
+///
+/// ## 用法
+/// ```rust
+/// apply_wx_protection(&mut mapper, &kernel_regions());
+/// ```
+pub fn apply_wx_protection(mapper: &mut OffsetPageTable, kernel_regions: &[KernelRegion]) -> usize {
+    assert_nx_usable(PageTableFlags::NO_EXECUTE);
+
+    let mut updated = 0;
+    for region in kernel_regions {
+        if region.start >= region.end {
+            continue;
+        }
+        let first: Page = Page::containing_address(region.start);
+        let last: Page = Page::containing_address(region.end - 1u64);
+        for page in Page::range_inclusive(first, last) {
+            let flags = match mapper.translate(page.start_address()) {
+                TranslateResult::Mapped {
+                    frame: MappedFrame::Size4KiB(_),
+                    flags,
+                    ..
+                } => flags,
+                _ => continue,
+            };
+            let new_flags = match region.kind {
+                KernelRegionKind::Code => flags - PageTableFlags::WRITABLE,
+                KernelRegionKind::ReadOnly => {
+                    (flags - PageTableFlags::WRITABLE) | PageTableFlags::NO_EXECUTE
+                }
+                KernelRegionKind::Data => flags | PageTableFlags::NO_EXECUTE,
+            };
+            if new_flags != flags {
+                if let Ok(flush) = unsafe { mapper.update_flags(page, new_flags) } {
+                    flush.flush();
+                    updated += 1;
+                }
+            }
+        }
+    }
+    updated
+}
+
+/// ## 函数说明
+/// 为引导程序建立的映射设置NO_EXECUTE：物理内存映射窗口、BootInfo、引导栈和递归映射各自占据独立的P4条目，
+/// 这些条目中的页面都可写，直接在P4条目上设置NO_EXECUTE即可覆盖其下所有级别的映射
+/// 跳过包含内核映像的条目和内核可分配区域的条目，返回修改的条目数
+///
+/// ## 参数
+/// * `mapper` - 页表
+///
+/// ## 用法
+/// ```rust
+/// protect_boot_mappings(&mut mapper);
+/// ```
+pub fn protect_boot_mappings(mapper: &mut OffsetPageTable) -> usize {
+    assert_nx_usable(PageTableFlags::NO_EXECUTE);
+
+    let p4_index = |addr: u64| usize::from(VirtAddr::new(addr).p4_index());
+    let image = kernel_regions();
+    let in_image = |i: usize| {
+        image
+            .iter()
+            .filter(|r| r.start < r.end)
+            .any(|r| (p4_index(r.start.as_u64())..=p4_index(r.end.as_u64() - 1)).contains(&i))
+    };
+    let kernel_area = p4_index(KERNEL_AREA_START)..=p4_index(KERNEL_AREA_END - 1);
+
+    let mut updated = 0;
+    for (i, entry) in mapper.level_4_table().iter_mut().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT)
+            || flags.contains(PageTableFlags::NO_EXECUTE)
+            || in_image(i)
+            || kernel_area.contains(&i)
+        {
+            continue;
+        }
+        entry.set_flags(flags | PageTableFlags::NO_EXECUTE);
+        updated += 1;
+    }
+    if updated > 0 {
+        x86_64::instructions::tlb::flush_all();
+    }
+    updated
+}
@@ -0,0 +1,87 @@
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size4KiB};
+use x86_64::VirtAddr;
+
+use super::FrameStats;
+
+/// ## 说明
+/// 在返回帧之前将其清零的帧分配器包装，避免新映射的页面泄露旧数据
+/// 对于会被完整覆盖的帧（例如写时复制的目标帧）可以使用`allocate_frame_uninit`跳过清零
+///
+/// ## 成员
+/// * `inner` - 被包装的帧分配器
+/// * `phys_offset` - 物理内存映射的偏移量，用于访问新分配的帧
+pub struct ZeroingFrameAllocator<A> {
+    inner: A,
+    phys_offset: VirtAddr,
+}
+
+impl<A> ZeroingFrameAllocator<A> {
+    /// ## 函数说明
+    /// 包装一个帧分配器
+    ///
+    /// ## 参数
+    /// * `inner` - 被包装的帧分配器
+    /// * `phys_offset` - 完整物理内存映射的起始虚拟地址
+    ///
+    /// ## Safety
+    /// `phys_offset`必须是完整物理内存映射的起始地址，`inner`返回的每个帧都能通过它写入；
+    /// 否则清零会写到任意的虚拟地址
+    ///
+    /// ## 用法
+    /// ```rust
+    /// let frames = unsafe { ZeroingFrameAllocator::new(frame_allocator, phys_offset) };
+    /// ```
+    pub unsafe fn new(inner: A, phys_offset: VirtAddr) -> Self {
+        ZeroingFrameAllocator { inner, phys_offset }
+    }
+
+    /// ## 函数说明
+    /// 获取被包装的帧分配器
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// ## 函数说明
+    /// 获取被包装的帧分配器的可变引用，通过它分配的帧不会被清零
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    /// ## 函数说明
+    /// 分配一个不清零的帧，调用者需保证在映射给其他代码之前完整覆盖其内容
+    pub fn allocate_frame_uninit(&mut self) -> Option<PhysFrame>
+    where
+        A: FrameAllocator<Size4KiB>,
+    {
+        self.inner.allocate_frame()
+    }
+}
+
+unsafe impl<A, S> FrameAllocator<S> for ZeroingFrameAllocator<A>
+where
+    A: FrameAllocator<S>,
+    S: PageSize,
+{
+    fn allocate_frame(&mut self) -> Option<PhysFrame<S>> {
+        let frame = self.inner.allocate_frame()?;
+        let ptr: *mut u8 = (self.phys_offset + frame.start_address().as_u64()).as_mut_ptr();
+        unsafe { ptr.write_bytes(0, S::SIZE as usize) };
+        Some(frame)
+    }
+}
+
+impl<A: FrameStats> FrameStats for ZeroingFrameAllocator<A> {
+    fn allocated_frames(&self) -> usize {
+        self.inner.allocated_frames()
+    }
+
+    fn total_usable_frames(&self) -> usize {
+        self.inner.total_usable_frames()
+    }
+}
+
+impl<A: FrameDeallocator<Size4KiB>> FrameDeallocator<Size4KiB> for ZeroingFrameAllocator<A> {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.inner.deallocate_frame(frame)
+    }
+}
@@ -0,0 +1,343 @@
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+use crate::drivers::ps2::{self, Ps2Error, Ps2Port};
+use crate::interrupts::{enable_irq, register_irq_handler};
+use crate::vga_buffer::{self, BUFFER_HEIGHT, BUFFER_WIDTH};
+
+/// 鼠标使用的PIC管脚，对应向量`PIC_2_OFFSET + 4`
+pub const MOUSE_IRQ: u8 = 12;
+/// 事件队列的容量，队列满时最旧的事件被丢弃
+pub const EVENT_QUEUE_SIZE: usize = 32;
+
+//PS/2控制器的数据端口
+const DATA_PORT: u16 = 0x60;
+//鼠标命令
+const SET_DEFAULTS: u8 = 0xf6;
+const ENABLE_REPORTING: u8 = 0xf4;
+
+//数据包第一个字节的各个位
+const SYNC_BIT: u8 = 0x08;
+const X_SIGN: u8 = 0x10;
+const Y_SIGN: u8 = 0x20;
+const X_OVERFLOW: u8 = 0x40;
+const Y_OVERFLOW: u8 = 0x80;
+
+//光标每移动一格需要的鼠标计数
+const CURSOR_SCALE: i32 = 8;
+
+/// ## 说明
+/// 鼠标初始化错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseError {
+    /// 与PS/2控制器或鼠标通信失败，控制器尚未初始化时为`Ps2Error::NotInitialized`
+    Ps2(Ps2Error),
+    /// `drivers::ps2::init`没有在第二个端口上发现鼠标
+    NoDevice,
+    /// IRQ12已经注册了处理函数
+    IrqInUse,
+}
+
+/// ## 说明
+/// 事件发生时按下的鼠标按键
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+/// ## 说明
+/// 一个鼠标数据包
+///
+/// ## 成员
+/// * `dx` - 水平移动量，向右为正
+/// * `dy` - 垂直移动量，与PS/2约定相同，向上为正
+/// * `buttons` - 按键状态
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub dx: i16,
+    pub dy: i16,
+    pub buttons: MouseButtons,
+}
+
+/// ## 说明
+/// 将PS/2鼠标的字节流组装成3字节的数据包
+///
+/// ## 成员
+/// * `bytes` - 当前数据包中已经收到的字节
+/// * `len` - 已经收到的字节数
+#[derive(Default)]
+pub struct PacketDecoder {
+    bytes: [u8; 3],
+    len: usize,
+}
+
+impl PacketDecoder {
+    /// ## 函数说明
+    /// 创建一个等待数据包第一个字节的解码器
+    pub const fn new() -> Self {
+        PacketDecoder {
+            bytes: [0; 3],
+            len: 0,
+        }
+    }
+
+    /// ## 函数说明
+    /// 输入一个字节，收满一个数据包时返回事件
+    /// 第一个字节的第3位恒为1，不满足时说明与鼠标失去同步，丢弃该字节直到重新对齐
+    ///
+    /// ## 参数
+    /// * `byte` - 从数据端口读到的字节
+    pub fn feed(&mut self, byte: u8) -> Option<MouseEvent> {
+        if self.len == 0 && byte & SYNC_BIT == 0 {
+            return None;
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        if self.len < 3 {
+            return None;
+        }
+        self.len = 0;
+        Some(decode_packet(self.bytes))
+    }
+}
+
+//9位有符号的移动量，溢出时限制为能表示的最大值
+fn axis(value: u8, sign: bool, overflow: bool) -> i16 {
+    match (overflow, sign) {
+        (true, true) => -256,
+        (true, false) => 255,
+        (false, true) => i16::from(value) - 256,
+        (false, false) => i16::from(value),
+    }
+}
+
+fn decode_packet([flags, x, y]: [u8; 3]) -> MouseEvent {
+    MouseEvent {
+        dx: axis(x, flags & X_SIGN != 0, flags & X_OVERFLOW != 0),
+        dy: axis(y, flags & Y_SIGN != 0, flags & Y_OVERFLOW != 0),
+        buttons: MouseButtons {
+            left: flags & 0x01 != 0,
+            right: flags & 0x02 != 0,
+            middle: flags & 0x04 != 0,
+        },
+    }
+}
+
+//固定大小的环形事件队列
+struct EventQueue {
+    events: [MouseEvent; EVENT_QUEUE_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl EventQueue {
+    const fn new() -> Self {
+        EventQueue {
+            events: [MouseEvent {
+                dx: 0,
+                dy: 0,
+                buttons: MouseButtons {
+                    left: false,
+                    right: false,
+                    middle: false,
+                },
+            }; EVENT_QUEUE_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, event: MouseEvent) {
+        let tail = (self.head + self.len) % EVENT_QUEUE_SIZE;
+        self.events[tail] = event;
+        if self.len == EVENT_QUEUE_SIZE {
+            self.head = (self.head + 1) % EVENT_QUEUE_SIZE;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<MouseEvent> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.events[self.head];
+        self.head = (self.head + 1) % EVENT_QUEUE_SIZE;
+        self.len -= 1;
+        Some(event)
+    }
+}
+
+//屏幕光标，位置以鼠标计数为单位，显示到字符格
+struct Cursor {
+    enabled: bool,
+    x: i32,
+    y: i32,
+}
+
+impl Cursor {
+    fn cell(&self) -> (usize, usize) {
+        (
+            (self.y / CURSOR_SCALE) as usize,
+            (self.x / CURSOR_SCALE) as usize,
+        )
+    }
+
+    fn toggle(&self) {
+        let (row, col) = self.cell();
+        vga_buffer::invert_cell(row, col);
+    }
+
+    fn apply(&mut self, event: MouseEvent) {
+        if !self.enabled {
+            return;
+        }
+        //先恢复旧位置再反色新位置，屏幕坐标向下为正
+        self.toggle();
+        let max_x = BUFFER_WIDTH as i32 * CURSOR_SCALE - 1;
+        let max_y = BUFFER_HEIGHT as i32 * CURSOR_SCALE - 1;
+        self.x = (self.x + i32::from(event.dx)).clamp(0, max_x);
+        self.y = (self.y - i32::from(event.dy)).clamp(0, max_y);
+        self.toggle();
+    }
+}
+
+struct Mouse {
+    decoder: PacketDecoder,
+    queue: EventQueue,
+    cursor: Cursor,
+}
+
+lazy_static! {
+    static ref MOUSE: Mutex<Mouse> = Mutex::new(Mouse {
+        decoder: PacketDecoder::new(),
+        queue: EventQueue::new(),
+        cursor: Cursor {
+            enabled: false,
+            x: 0,
+            y: 0,
+        },
+    });
+}
+
+fn mouse_irq(_irq: u8) {
+    let byte = unsafe { Port::<u8>::new(DATA_PORT).read() };
+    feed_byte(byte);
+}
+
+/// ## 函数说明
+/// 把从第二个端口读到的一个字节交给数据包解码器，凑齐数据包时放入事件队列并移动光标
+/// 由IRQ12处理函数和其他需要轮询控制器的代码（如键盘设置指示灯时）调用，调用方需要关闭中断
+///
+/// ## 参数
+/// * `byte` - 鼠标发来的字节
+pub(crate) fn feed_byte(byte: u8) {
+    let mut mouse = MOUSE.lock();
+    if let Some(event) = mouse.decoder.feed(byte) {
+        mouse.queue.push(event);
+        mouse.cursor.apply(event);
+    }
+}
+
+/// ## 函数说明
+/// 让`drivers::ps2::init`在第二个端口上找到的鼠标开始发送数据包，并在PIC上解除IRQ12和级联管脚的屏蔽
+/// 端口和IRQ12已经由控制器初始化启用，需要在`os::init`之后调用
+///
+/// ## 用法
+/// ```rust
+/// if let Err(err) = mouse::init() {
+///     println!("no PS/2 mouse: {:?}", err);
+/// }
+/// ```
+pub fn init() -> Result<(), MouseError> {
+    let status = ps2::status().ok_or(MouseError::Ps2(Ps2Error::NotInitialized))?;
+    if !status.has_mouse() {
+        return Err(MouseError::NoDevice);
+    }
+    register_irq_handler(MOUSE_IRQ, mouse_irq).map_err(|_| MouseError::IrqInUse)?;
+    interrupts::without_interrupts(|| {
+        ps2::send(Ps2Port::Port2, SET_DEFAULTS).map_err(MouseError::Ps2)?;
+        ps2::send(Ps2Port::Port2, ENABLE_REPORTING).map_err(MouseError::Ps2)?;
+
+        enable_irq(MOUSE_IRQ);
+        Ok(())
+    })
+}
+
+/// ## 函数说明
+/// 取出最早的一个鼠标事件，没有事件时返回`None`
+///
+/// ## 用法
+/// ```rust
+/// while let Some(event) = mouse::poll_event() {
+///     //...
+/// }
+/// ```
+pub fn poll_event() -> Option<MouseEvent> {
+    interrupts::without_interrupts(|| MOUSE.lock().queue.pop())
+}
+
+/// ## 函数说明
+/// 显示或隐藏屏幕上的鼠标光标，光标是一个前景色和背景色互换的字符格
+///
+/// ## 参数
+/// * `enabled` - 是否显示
+pub fn show_cursor(enabled: bool) {
+    interrupts::without_interrupts(|| {
+        let mut mouse = MOUSE.lock();
+        if mouse.cursor.enabled != enabled {
+            mouse.cursor.enabled = enabled;
+            mouse.cursor.toggle();
+        }
+    });
+}
+
+#[test_case]
+fn test_packet_decoding() {
+    let mut decoder = PacketDecoder::new();
+    //左键按下，向右5、向下3
+    assert_eq!(decoder.feed(0x29), None);
+    assert_eq!(decoder.feed(5), None);
+    let event = decoder.feed(0xfd).unwrap();
+    assert_eq!((event.dx, event.dy), (5, -3));
+    assert!(event.buttons.left && !event.buttons.right && !event.buttons.middle);
+
+    //X方向正向溢出、Y方向负向溢出时取最大值
+    let bytes = [0x08 | X_OVERFLOW | Y_OVERFLOW | Y_SIGN, 0x12, 0x34];
+    let event = bytes
+        .iter()
+        .filter_map(|&b| decoder.feed(b))
+        .next()
+        .unwrap();
+    assert_eq!((event.dx, event.dy), (255, -256));
+}
+
+#[test_case]
+fn test_packet_resync() {
+    let mut decoder = PacketDecoder::new();
+    //丢失了第一个字节：剩下的两个字节没有同步位，被丢弃后从下一个数据包重新开始
+    let stream = [0x01, 0x02, 0x0a, 0x01, 0x02];
+    let events: [Option<MouseEvent>; 5] = stream.map(|b| decoder.feed(b));
+    assert_eq!(events[..4], [None; 4]);
+    let event = events[4].unwrap();
+    assert_eq!((event.dx, event.dy), (1, 2));
+    assert!(event.buttons.right);
+}
+
+#[test_case]
+fn test_event_queue_drops_oldest() {
+    let mut queue = EventQueue::new();
+    for dx in 0..EVENT_QUEUE_SIZE as i16 + 2 {
+        queue.push(MouseEvent {
+            dx,
+            ..MouseEvent::default()
+        });
+    }
+    assert_eq!(queue.pop().map(|e| e.dx), Some(2));
+    let rest = core::iter::from_fn(|| queue.pop()).count();
+    assert_eq!(rest, EVENT_QUEUE_SIZE - 1);
+}
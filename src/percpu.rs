@@ -0,0 +1,164 @@
+use core::arch::asm;
+use core::mem::offset_of;
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use x86_64::registers::model_specific::{GsBase, KernelGsBase};
+use x86_64::VirtAddr;
+
+/// ## 说明
+/// 每个CPU自己的数据，地址保存在IA32_GS_BASE中，通过`gs:`前缀的偏移访问
+/// 字段的偏移是与汇编代码（如系统调用入口）之间的约定，只能在末尾追加字段
+///
+/// ## 成员
+/// * `self_ptr` - 结构自身的地址，`gs:[0]`即可得到`&PerCpu`
+/// * `cpu_id` - CPU编号，即初始APIC ID
+/// * `current_task` - 当前任务，尚无调度器时为0
+/// * `nesting` - 中断嵌套深度，由`interrupts`在处理函数的入口和返回时更新
+/// * `user_rsp` - 系统调用入口保存用户栈指针的位置
+/// * `kernel_rsp` - 系统调用入口切换到的内核栈顶
+#[repr(C)]
+pub struct PerCpu {
+    self_ptr: AtomicU64,
+    pub cpu_id: AtomicU32,
+    pub current_task: AtomicU64,
+    pub nesting: AtomicUsize,
+    pub user_rsp: AtomicU64,
+    pub kernel_rsp: AtomicU64,
+}
+
+impl PerCpu {
+    const fn new() -> Self {
+        PerCpu {
+            self_ptr: AtomicU64::new(0),
+            cpu_id: AtomicU32::new(0),
+            current_task: AtomicU64::new(0),
+            nesting: AtomicUsize::new(0),
+            user_rsp: AtomicU64::new(0),
+            kernel_rsp: AtomicU64::new(0),
+        }
+    }
+}
+
+/// `cpu_id`在PerCpu中的偏移
+pub const CPU_ID_OFFSET: usize = offset_of!(PerCpu, cpu_id);
+/// `current_task`在PerCpu中的偏移
+pub const CURRENT_TASK_OFFSET: usize = offset_of!(PerCpu, current_task);
+/// `nesting`在PerCpu中的偏移
+pub const NESTING_OFFSET: usize = offset_of!(PerCpu, nesting);
+/// `user_rsp`在PerCpu中的偏移，供系统调用入口使用
+pub const USER_RSP_OFFSET: usize = offset_of!(PerCpu, user_rsp);
+/// `kernel_rsp`在PerCpu中的偏移，供系统调用入口使用
+pub const KERNEL_RSP_OFFSET: usize = offset_of!(PerCpu, kernel_rsp);
+
+//目前只有引导处理器；启动其他CPU时再为每个CPU分配一份
+static BSP: PerCpu = PerCpu::new();
+
+/// ## 函数说明
+/// 初始化引导处理器的PerCpu，并把它的地址同时写入IA32_GS_BASE和IA32_KERNEL_GS_BASE
+/// 内核态中两者相同，之后系统调用入口执行swapgs时GS基址不会变成无效值
+///
+/// ## 用法
+/// ```rust
+/// percpu::init();
+/// ```
+pub fn init() {
+    let addr = VirtAddr::from_ptr(&BSP);
+    BSP.self_ptr.store(addr.as_u64(), Ordering::SeqCst);
+    let apic_id = core::arch::x86_64::__cpuid(1).ebx >> 24;
+    BSP.cpu_id.store(apic_id, Ordering::SeqCst);
+    GsBase::write(addr);
+    KernelGsBase::write(addr);
+}
+
+/// ## 函数说明
+/// GS基址是否指向已初始化的PerCpu
+pub fn is_initialized() -> bool {
+    let base = GsBase::read();
+    base.as_u64() != 0 && BSP.self_ptr.load(Ordering::SeqCst) == base.as_u64()
+}
+
+//读取gs:[offset]处的四字
+#[inline]
+fn read_gs_u64(offset: usize) -> u64 {
+    let value;
+    unsafe {
+        asm!("mov {}, gs:[{}]", out(reg) value, in(reg) offset, options(nostack, readonly, preserves_flags));
+    }
+    value
+}
+
+//写入gs:[offset]处的四字
+#[inline]
+fn write_gs_u64(offset: usize, value: u64) {
+    unsafe {
+        asm!("mov gs:[{}], {}", in(reg) offset, in(reg) value, options(nostack, preserves_flags));
+    }
+}
+
+/// ## 函数说明
+/// 当前CPU的PerCpu，需要先调用`init`
+pub fn current() -> &'static PerCpu {
+    debug_assert!(is_initialized(), "percpu::init has not been called");
+    let ptr = read_gs_u64(offset_of!(PerCpu, self_ptr)) as *const PerCpu;
+    unsafe { &*ptr }
+}
+
+/// ## 函数说明
+/// 当前CPU的编号
+pub fn cpu_id() -> u32 {
+    let value: u32;
+    unsafe {
+        asm!("mov {:e}, gs:[{}]", out(reg) value, in(reg) CPU_ID_OFFSET, options(nostack, readonly, preserves_flags));
+    }
+    value
+}
+
+/// ## 函数说明
+/// 当前CPU上运行的任务，0表示没有
+pub fn current_task() -> u64 {
+    read_gs_u64(CURRENT_TASK_OFFSET)
+}
+
+/// ## 函数说明
+/// 设置当前CPU上运行的任务，由调度器在切换任务时调用
+///
+/// ## 参数
+/// * `task` - 任务结构的地址
+pub fn set_current_task(task: u64) {
+    write_gs_u64(CURRENT_TASK_OFFSET, task);
+}
+
+/// ## 函数说明
+/// 系统调用入口切换到的内核栈顶
+pub fn kernel_rsp() -> u64 {
+    read_gs_u64(KERNEL_RSP_OFFSET)
+}
+
+/// ## 函数说明
+/// 设置系统调用入口切换到的内核栈顶，与`gdt::set_kernel_stack`一起在切换任务时调用
+///
+/// ## 参数
+/// * `rsp` - 内核栈顶
+pub fn set_kernel_rsp(rsp: u64) {
+    write_gs_u64(KERNEL_RSP_OFFSET, rsp);
+}
+
+#[test_case]
+fn test_percpu_accessors() {
+    assert!(is_initialized());
+    assert_eq!(GsBase::read(), KernelGsBase::read());
+    assert_eq!(current() as *const PerCpu, &BSP as *const PerCpu);
+    assert_eq!(cpu_id(), core::arch::x86_64::__cpuid(1).ebx >> 24);
+
+    //通过gs写入，再用MSR中的基址加偏移读取
+    let saved = current_task();
+    set_current_task(0xdead_beef);
+    let raw = GsBase::read().as_u64() + CURRENT_TASK_OFFSET as u64;
+    assert_eq!(unsafe { (raw as *const u64).read_volatile() }, 0xdead_beef);
+    assert_eq!(current().current_task.load(Ordering::SeqCst), 0xdead_beef);
+    set_current_task(saved);
+
+    set_kernel_rsp(0x1234_5000);
+    let raw = GsBase::read().as_u64() + KERNEL_RSP_OFFSET as u64;
+    assert_eq!(unsafe { (raw as *const u64).read_volatile() }, 0x1234_5000);
+    set_kernel_rsp(0);
+}
@@ -0,0 +1,294 @@
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+use x86_64::instructions::tables::lidt;
+use x86_64::structures::DescriptorTablePointer;
+use x86_64::VirtAddr;
+
+//8042控制器的状态/命令端口，状态位1表示输入缓冲区还没有被控制器取走
+const STATUS_PORT: u16 = 0x64;
+const INPUT_FULL: u8 = 0x02;
+//拉低CPU复位线的控制器命令
+const PULSE_RESET: u8 = 0xfe;
+//等待输入缓冲区清空时读取状态寄存器的次数上限
+const WAIT_SPINS: usize = 100_000;
+//发出复位脉冲后等待复位生效的时间
+const RESET_WAIT_US: u64 = 100_000;
+//两个PIC的数据端口，写入全1屏蔽所有管脚
+const PIC_1_DATA: u16 = 0x21;
+const PIC_2_DATA: u16 = 0xa1;
+
+//模拟器的传统关机端口及写入的值：QEMU的PIIX4 PM、Bochs及旧版QEMU、VirtualBox
+const LEGACY_POWER_OFF: [(u16, u16); 3] = [(0x604, 0x2000), (0xb004, 0x2000), (0x4004, 0x3400)];
+
+//ACPI代码注册的S5关机函数，空指针表示尚未注册
+static ACPI_POWER_OFF: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+//panic之后是否重启，默认停机
+static REBOOT_ON_PANIC: AtomicBool = AtomicBool::new(false);
+
+/// ## 说明
+/// 非测试内核panic之后的行为
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// 调用[`halt`]，保留屏幕上的panic信息
+    Halt,
+    /// 调用[`reboot`]
+    Reboot,
+}
+
+/// ## 函数说明
+/// 设置panic之后的行为
+///
+/// ## 参数
+/// * `policy` - panic之后停机还是重启
+pub fn set_panic_policy(policy: PanicPolicy) {
+    REBOOT_ON_PANIC.store(policy == PanicPolicy::Reboot, Ordering::SeqCst);
+}
+
+/// ## 函数说明
+/// 当前的panic策略
+pub fn panic_policy() -> PanicPolicy {
+    if REBOOT_ON_PANIC.load(Ordering::SeqCst) {
+        PanicPolicy::Reboot
+    } else {
+        PanicPolicy::Halt
+    }
+}
+
+/// ## 函数说明
+/// 按[`panic_policy`]停机或重启，由panic处理函数在打印信息之后调用
+pub fn after_panic() -> ! {
+    match panic_policy() {
+        PanicPolicy::Halt => halt(),
+        PanicPolicy::Reboot => reboot(),
+    }
+}
+
+//反复读取状态，直到输入缓冲区为空，超过`spins`次时返回false
+fn wait_input_clear(mut read_status: impl FnMut() -> u8, spins: usize) -> bool {
+    for _ in 0..spins {
+        if read_status() & INPUT_FULL == 0 {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+/// ## 函数说明
+/// 重启计算机：先通过8042控制器发出复位脉冲，无效时加载空IDT并触发断点异常引发三重错误，
+/// 两者都失败时停机。可以在panic处理函数中调用，只使用不会死锁的打印方式
+///
+/// ## 用法
+/// ```rust
+/// power::reboot();
+/// ```
+pub fn reboot() -> ! {
+    interrupts::disable();
+    crate::serial_emergency_println!("rebooting");
+    crate::try_println!("rebooting");
+
+    let mut status: Port<u8> = Port::new(STATUS_PORT);
+    if wait_input_clear(|| unsafe { status.read() }, WAIT_SPINS) {
+        unsafe { status.write(PULSE_RESET) };
+        crate::time::sleep_busy_us(RESET_WAIT_US);
+    }
+
+    crate::serial_emergency_println!("8042 reset failed, forcing a triple fault");
+    //界限为0的IDT中找不到任何处理函数，int3会依次升级为#GP、double fault和三重错误
+    let empty = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::new(0),
+    };
+    unsafe {
+        lidt(&empty);
+        core::arch::asm!("int3", options(nomem, nostack));
+    }
+
+    crate::serial_emergency_println!("triple fault did not reset the machine, halting");
+    halt();
+}
+
+/// ## 函数说明
+/// 屏蔽所有PIC管脚并关闭中断后停机，之后只有NMI能唤醒CPU
+/// 与`hlt_loop`不同，时钟中断不会再周期性地唤醒CPU
+///
+/// ## 用法
+/// ```rust
+/// power::halt();
+/// ```
+pub fn halt() -> ! {
+    interrupts::disable();
+    //panic时PICS可能已被锁定，直接写端口
+    unsafe {
+        Port::<u8>::new(PIC_1_DATA).write(0xff);
+        Port::<u8>::new(PIC_2_DATA).write(0xff);
+    }
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// ## 函数说明
+/// 注册通过ACPI进入S5的关机函数，由解析了FADT和DSDT的ACPI代码调用
+/// 函数应写入PM1a_CNT（以及PM1b_CNT）的SLP_TYP和SLP_EN，成功时不会返回
+///
+/// ## 参数
+/// * `power_off` - 关机函数
+pub fn register_acpi_power_off(power_off: fn()) {
+    ACPI_POWER_OFF.store(power_off as *mut (), Ordering::Release);
+}
+
+//已注册的ACPI关机函数
+fn acpi_power_off() -> Option<fn()> {
+    let power_off = ACPI_POWER_OFF.load(Ordering::Acquire);
+    if power_off.is_null() {
+        None
+    } else {
+        //非空指针只可能由关机函数转换而来
+        Some(unsafe { core::mem::transmute::<*mut (), fn()>(power_off) })
+    }
+}
+
+//关机时依次尝试的方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PowerOffStep {
+    //向模拟器的传统关机端口写入对应的值
+    Legacy(u16, u16),
+    //写入isa-debug-exit设备
+    DebugExit,
+    //调用ACPI代码注册的S5关机函数
+    Acpi,
+}
+
+//按顺序执行各个关机方法，在对应的环境中第一个有效的方法之后不会返回
+//`acpi`表示是否注册了ACPI关机函数
+fn power_off_sequence(acpi: bool, mut run: impl FnMut(PowerOffStep)) {
+    for &(port, value) in LEGACY_POWER_OFF.iter() {
+        run(PowerOffStep::Legacy(port, value));
+    }
+    run(PowerOffStep::DebugExit);
+    if acpi {
+        run(PowerOffStep::Acpi);
+    }
+}
+
+/// ## 函数说明
+/// 关闭计算机：依次尝试QEMU/Bochs/VirtualBox的传统关机端口、isa-debug-exit设备和ACPI的S5状态，
+/// 全部无效时停机。ACPI关机需要先通过[`register_acpi_power_off`]注册
+/// 关机之前在CMOS的启动记录中标记本次启动正常结束
+///
+/// ## 用法
+/// ```rust
+/// power::shutdown();
+/// ```
+pub fn shutdown() -> ! {
+    interrupts::disable();
+    crate::drivers::cmos::mark_clean_shutdown();
+    crate::serial_emergency_println!("powering off");
+    crate::try_println!("powering off");
+
+    let acpi = acpi_power_off();
+    power_off_sequence(acpi.is_some(), |step| match step {
+        PowerOffStep::Legacy(port, value) => unsafe { Port::<u16>::new(port).write(value) },
+        //只有以`-device isa-debug-exit`启动的QEMU才有这个设备，否则写入没有效果
+        PowerOffStep::DebugExit => crate::exit_qemu(crate::QemuExitCode::Success),
+        PowerOffStep::Acpi => {
+            if let Some(power_off) = acpi {
+                power_off();
+            }
+        }
+    });
+
+    crate::serial_emergency_println!("power off failed, halting");
+    halt();
+}
+
+#[test_case]
+fn test_wait_input_clear() {
+    //第3次读取时输入缓冲区变为空
+    let mut reads = 0;
+    let cleared = wait_input_clear(
+        || {
+            reads += 1;
+            if reads < 3 {
+                INPUT_FULL
+            } else {
+                0
+            }
+        },
+        10,
+    );
+    assert!(cleared);
+    assert_eq!(reads, 3);
+
+    //一直不清空时恰好读取`spins`次后放弃，其他状态位不影响判断
+    let mut reads = 0;
+    let cleared = wait_input_clear(
+        || {
+            reads += 1;
+            INPUT_FULL | 0x01
+        },
+        10,
+    );
+    assert!(!cleared);
+    assert_eq!(reads, 10);
+    assert!(wait_input_clear(|| 0x01, 1));
+}
+
+#[test_case]
+fn test_panic_policy() {
+    assert_eq!(panic_policy(), PanicPolicy::Halt);
+    set_panic_policy(PanicPolicy::Reboot);
+    assert_eq!(panic_policy(), PanicPolicy::Reboot);
+    set_panic_policy(PanicPolicy::Halt);
+    assert_eq!(panic_policy(), PanicPolicy::Halt);
+}
+
+#[test_case]
+fn test_power_off_order() {
+    //记录每一步而不真正写端口
+    let mut steps = [PowerOffStep::DebugExit; 6];
+    let mut count = 0;
+    power_off_sequence(true, |step| {
+        steps[count] = step;
+        count += 1;
+    });
+    assert_eq!(
+        &steps[..count],
+        &[
+            PowerOffStep::Legacy(0x604, 0x2000),
+            PowerOffStep::Legacy(0xb004, 0x2000),
+            PowerOffStep::Legacy(0x4004, 0x3400),
+            PowerOffStep::DebugExit,
+            PowerOffStep::Acpi,
+        ]
+    );
+
+    //没有注册ACPI关机函数时在isa-debug-exit之后结束
+    let mut count = 0;
+    let mut last = None;
+    power_off_sequence(false, |step| {
+        count += 1;
+        last = Some(step);
+    });
+    assert_eq!(count, 4);
+    assert_eq!(last, Some(PowerOffStep::DebugExit));
+}
+
+#[test_case]
+fn test_acpi_power_off_registration() {
+    static CALLED: AtomicBool = AtomicBool::new(false);
+    fn fake_acpi() {
+        CALLED.store(true, Ordering::SeqCst);
+    }
+
+    assert!(acpi_power_off().is_none());
+    register_acpi_power_off(fake_acpi);
+    //取回的函数就是注册的函数
+    acpi_power_off().expect("power off not registered")();
+    assert!(CALLED.load(Ordering::SeqCst));
+    ACPI_POWER_OFF.store(ptr::null_mut(), Ordering::Release);
+}
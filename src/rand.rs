@@ -0,0 +1,225 @@
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::cpu;
+use crate::time::rdtsc;
+
+//Intel建议RDRAND连续失败10次后再认为硬件出错
+const RDRAND_RETRIES: usize = 10;
+//RDSEED的熵池耗尽得更快，两次尝试之间暂停一下
+const RDSEED_RETRIES: usize = 100;
+//从TSC抖动中收集种子时的采样次数
+const JITTER_SAMPLES: usize = 64;
+
+//后备生成器的状态，0表示尚未播种
+static FALLBACK_STATE: AtomicU64 = AtomicU64::new(0);
+
+//测试中强制使用后备生成器，验证没有RDRAND/RDSEED的CPU上的路径
+#[cfg(test)]
+static FORCE_FALLBACK: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+fn hardware_available() -> bool {
+    #[cfg(test)]
+    if FORCE_FALLBACK.load(Ordering::SeqCst) {
+        return false;
+    }
+    let features = cpu::features();
+    features.rdrand || features.rdseed
+}
+
+/// ## 说明
+/// 一个随机数及其来源
+///
+/// ## 成员
+/// * `value` - 随机数
+/// * `hardware` - 是否来自RDRAND/RDSEED，为false时来自以TSC抖动播种的伪随机数生成器，不能用于密钥等场合
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Random {
+    pub value: u64,
+    pub hardware: bool,
+}
+
+fn rdrand() -> Option<u64> {
+    for _ in 0..RDRAND_RETRIES {
+        let value: u64;
+        let ok: u8;
+        //CF为1表示得到了有效的随机数
+        unsafe {
+            asm!("rdrand {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+        }
+        if ok != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn rdseed() -> Option<u64> {
+    for _ in 0..RDSEED_RETRIES {
+        let value: u64;
+        let ok: u8;
+        unsafe {
+            asm!("rdseed {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+        }
+        if ok != 0 {
+            return Some(value);
+        }
+        core::hint::spin_loop();
+    }
+    None
+}
+
+/// ## 函数说明
+/// 读取一个硬件随机数，优先使用RDRAND，其次RDSEED
+/// CPU不支持这两条指令，或重试之后仍然失败时返回None
+///
+/// ## 用法
+/// ```rust
+/// let canary = rand::u64().expect("no hardware entropy");
+/// ```
+pub fn u64() -> Option<u64> {
+    if !hardware_available() {
+        return None;
+    }
+    let features = cpu::features();
+    let value = if features.rdrand { rdrand() } else { None };
+    value.or_else(|| if features.rdseed { rdseed() } else { None })
+}
+
+/// ## 函数说明
+/// RDSEED直接来自熵源，适合作为其他生成器的种子；CPU不支持或熵暂时耗尽时返回None
+pub fn seed() -> Option<u64> {
+    if !hardware_available() || !cpu::features().rdseed {
+        return None;
+    }
+    rdseed()
+}
+
+//取多次读TSC之间的间隔的低位，这些低位受缓存、中断和SMI影响而抖动
+fn jitter_seed() -> u64 {
+    let mut seed = rdtsc();
+    for _ in 0..JITTER_SAMPLES {
+        let start = rdtsc();
+        core::hint::spin_loop();
+        let delta = rdtsc().wrapping_sub(start);
+        seed = (seed.rotate_left(5) ^ delta).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+    seed
+}
+
+fn xorshift(mut x: u64) -> u64 {
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    x
+}
+
+//xorshift64*，状态不能为0；中断处理函数中也可以调用，因此不加锁
+fn fallback_u64() -> u64 {
+    if FALLBACK_STATE.load(Ordering::SeqCst) == 0 {
+        //并发播种时只有一个种子生效
+        let _ = FALLBACK_STATE.compare_exchange(
+            0,
+            jitter_seed() | 1,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+    }
+    let previous = FALLBACK_STATE
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |state| {
+            Some(xorshift(state))
+        })
+        .unwrap();
+    xorshift(previous).wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
+
+/// ## 函数说明
+/// 总能得到一个随机数：有硬件随机数时使用硬件，否则使用后备的伪随机数生成器
+///
+/// ## 用法
+/// ```rust
+/// let random = rand::next_u64();
+/// if !random.hardware {
+///     println!("warning: no hardware entropy");
+/// }
+/// ```
+pub fn next_u64() -> Random {
+    match u64() {
+        Some(value) => Random {
+            value,
+            hardware: true,
+        },
+        None => Random {
+            value: fallback_u64(),
+            hardware: false,
+        },
+    }
+}
+
+/// ## 函数说明
+/// 用随机字节填满缓冲区，返回是否全部来自硬件随机数
+///
+/// ## 参数
+/// * `buf` - 缓冲区，长度不需要是8的倍数
+///
+/// ## 用法
+/// ```rust
+/// let mut cookie = [0u8; 16];
+/// rand::fill(&mut cookie);
+/// ```
+pub fn fill(buf: &mut [u8]) -> bool {
+    let mut hardware = true;
+    for chunk in buf.chunks_mut(8) {
+        let random = next_u64();
+        hardware &= random.hardware;
+        chunk.copy_from_slice(&random.value.to_le_bytes()[..chunk.len()]);
+    }
+    hardware
+}
+
+#[test_case]
+fn test_consecutive_values_differ() {
+    let first = next_u64();
+    let second = next_u64();
+    assert_ne!(first.value, second.value);
+    let hardware = cpu::features().rdrand || cpu::features().rdseed;
+    assert_eq!(first.hardware, hardware);
+    assert_eq!(u64().is_some(), hardware);
+}
+
+#[test_case]
+fn test_fill_odd_lengths() {
+    const SENTINEL: u8 = 0xaa;
+    for len in [3, 5, 7, 9, 13, 31] {
+        let mut first = [SENTINEL; 32];
+        let mut second = [SENTINEL; 32];
+        fill(&mut first[..len]);
+        fill(&mut second[..len]);
+        //缓冲区之后的字节没有被写入
+        assert!(first[len..].iter().all(|&b| b == SENTINEL));
+        assert!(second[len..].iter().all(|&b| b == SENTINEL));
+        assert_ne!(first[..len], second[..len]);
+        //最后一个不满8字节的块也被填充：几次之中总有一次不等于哨兵值
+        assert!((0..8).any(|_| {
+            let mut buf = [SENTINEL; 32];
+            fill(&mut buf[..len]);
+            buf[len - 1] != SENTINEL
+        }));
+    }
+}
+
+#[test_case]
+fn test_forced_fallback() {
+    FORCE_FALLBACK.store(true, Ordering::SeqCst);
+    assert_eq!(u64(), None);
+    assert_eq!(seed(), None);
+    let first = next_u64();
+    let second = next_u64();
+    let mut buf = [0u8; 5];
+    let hardware = fill(&mut buf);
+    FORCE_FALLBACK.store(false, Ordering::SeqCst);
+
+    assert!(!first.hardware && !second.hardware && !hardware);
+    assert_ne!(first.value, second.value);
+    assert_ne!(FALLBACK_STATE.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,250 @@
+use core::fmt;
+use x86_64::instructions::interrupts;
+
+//MC146818的时间寄存器
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+//大多数固件把世纪放在0x32，准确的位置应由ACPI FADT给出
+const REG_CENTURY: u8 = 0x32;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+//状态寄存器A：正在更新时间
+const UPDATE_IN_PROGRESS: u8 = 0x80;
+//状态寄存器B：24小时制、二进制格式
+const HOUR_24: u8 = 0x02;
+const BINARY_MODE: u8 = 0x04;
+//12小时制时小时寄存器的最高位表示下午
+const HOUR_PM: u8 = 0x80;
+
+/// ## 说明
+/// 从CMOS RTC读取的日期和时间，RTC通常保存UTC时间
+///
+/// ## 成员
+/// * `year` - 完整的年份，如2024
+/// * `month` - 月，1~12
+/// * `day` - 日，1~31
+/// * `hour` - 时，0~23
+/// * `minute` - 分，0~59
+/// * `second` - 秒，0~59
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl RtcTime {
+    /// ## 函数说明
+    /// 换算为Unix时间戳（自1970-01-01 00:00:00 UTC以来的秒数）
+    pub fn unix_timestamp(&self) -> u64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let secs_of_day =
+            u64::from(self.hour) * 3600 + u64::from(self.minute) * 60 + u64::from(self.second);
+        days as u64 * 86400 + secs_of_day
+    }
+}
+
+impl fmt::Display for RtcTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+//CMOS寄存器中未经转换的值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+/// ## 函数说明
+/// 将BCD编码的字节转换为二进制值，如0x59转换为59
+///
+/// ## 参数
+/// * `bcd` - BCD编码的字节
+pub fn bcd_to_binary(bcd: u8) -> u8 {
+    (bcd >> 4) * 10 + (bcd & 0x0f)
+}
+
+/// ## 函数说明
+/// 公历日期距1970-01-01的天数，早于该日期时为负数
+///
+/// ## 参数
+/// * `year` - 年
+/// * `month` - 月，1~12
+/// * `day` - 日，1~31
+pub fn days_from_civil(year: u16, month: u8, day: u8) -> i64 {
+    //把3月当作一年的开始，闰日就落在年末
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+//按状态寄存器B的格式转换寄存器的值
+fn decode(raw: RawTime, status_b: u8) -> RtcTime {
+    let convert = |value: u8| {
+        if status_b & BINARY_MODE != 0 {
+            value
+        } else {
+            bcd_to_binary(value)
+        }
+    };
+
+    let mut hour = convert(raw.hour & !HOUR_PM);
+    if status_b & HOUR_24 == 0 {
+        //12小时制：12 AM为0点，下午的小时加12，12 PM仍为12点
+        let pm = raw.hour & HOUR_PM != 0;
+        hour = match (hour, pm) {
+            (12, false) => 0,
+            (12, true) => 12,
+            (hour, true) => hour + 12,
+            (hour, false) => hour,
+        };
+    }
+
+    //世纪寄存器不存在时读到的值没有意义，假定为21世纪
+    let century = match convert(raw.century) {
+        century @ 19..=21 => u16::from(century),
+        _ => 20,
+    };
+
+    RtcTime {
+        year: century * 100 + u16::from(convert(raw.year)),
+        month: convert(raw.month),
+        day: convert(raw.day),
+        hour,
+        minute: convert(raw.minute),
+        second: convert(raw.second),
+    }
+}
+
+fn read_register(register: u8) -> u8 {
+    crate::drivers::cmos::read(register)
+}
+
+fn update_in_progress() -> bool {
+    read_register(REG_STATUS_A) & UPDATE_IN_PROGRESS != 0
+}
+
+fn read_raw() -> RawTime {
+    while update_in_progress() {
+        core::hint::spin_loop();
+    }
+    RawTime {
+        second: read_register(REG_SECONDS),
+        minute: read_register(REG_MINUTES),
+        hour: read_register(REG_HOURS),
+        day: read_register(REG_DAY),
+        month: read_register(REG_MONTH),
+        year: read_register(REG_YEAR),
+        century: read_register(REG_CENTURY),
+    }
+}
+
+/// ## 函数说明
+/// 读取RTC的当前日期和时间。连续读取两次直到结果一致，避免读到更新到一半的值
+///
+/// ## 用法
+/// ```rust
+/// let now = rtc::now();
+/// println!("{} ({})", now, now.unix_timestamp());
+/// ```
+pub fn now() -> RtcTime {
+    //索引端口和数据端口必须成对访问，期间不能被中断处理函数打断
+    interrupts::without_interrupts(|| {
+        let mut last = read_raw();
+        loop {
+            let current = read_raw();
+            if current == last {
+                break decode(current, read_register(REG_STATUS_B));
+            }
+            last = current;
+        }
+    })
+}
+
+#[test_case]
+fn test_bcd_to_binary() {
+    assert_eq!(bcd_to_binary(0x00), 0);
+    assert_eq!(bcd_to_binary(0x09), 9);
+    assert_eq!(bcd_to_binary(0x10), 10);
+    assert_eq!(bcd_to_binary(0x59), 59);
+    assert_eq!(bcd_to_binary(0x99), 99);
+}
+
+#[test_case]
+fn test_days_from_civil() {
+    assert_eq!(days_from_civil(1970, 1, 1), 0);
+    assert_eq!(days_from_civil(1969, 12, 31), -1);
+    assert_eq!(days_from_civil(1999, 12, 31), 10956);
+    assert_eq!(days_from_civil(2000, 3, 1), 11017);
+    assert_eq!(days_from_civil(2024, 2, 29), 19782);
+
+    let time = RtcTime {
+        year: 2024,
+        month: 2,
+        day: 29,
+        hour: 13,
+        minute: 45,
+        second: 30,
+    };
+    assert_eq!(time.unix_timestamp(), 1_709_214_330);
+}
+
+#[test_case]
+fn test_decode_bcd_12_hour() {
+    //2024-02-29 01:45:30 PM，BCD格式、12小时制
+    let raw = RawTime {
+        second: 0x30,
+        minute: 0x45,
+        hour: HOUR_PM | 0x01,
+        day: 0x29,
+        month: 0x02,
+        year: 0x24,
+        century: 0x20,
+    };
+    let time = decode(raw, 0);
+    assert_eq!(time.hour, 13);
+    assert_eq!(time.unix_timestamp(), 1_709_214_330);
+
+    //12 AM是0点，二进制格式、没有世纪寄存器
+    let raw = RawTime {
+        hour: 12,
+        year: 24,
+        century: 0xff,
+        ..raw
+    };
+    assert_eq!(decode(raw, BINARY_MODE).hour, 0);
+    assert_eq!(decode(raw, BINARY_MODE).year, 2024);
+}
+
+#[test_case]
+fn test_live_read() {
+    let now = now();
+    assert!(now.year >= 2000);
+    assert!((1..=12).contains(&now.month));
+    assert!((1..=31).contains(&now.day));
+    assert!(now.hour < 24);
+    assert!(now.minute < 60);
+    assert!(now.second < 60);
+}
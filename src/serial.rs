@@ -24,6 +24,23 @@ pub fn _print(args: ::core::fmt::Arguments) {
     });
 }
 
+//用于double fault等致命错误：锁被持有时强制解锁，持锁的代码不会再继续运行
+#[doc(hidden)]
+pub fn _emergency_print(args: ::core::fmt::Arguments) {
+    use core::fmt::Write;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut serial = match SERIAL1.try_lock() {
+            Some(serial) => serial,
+            None => {
+                unsafe { SERIAL1.force_unlock() };
+                SERIAL1.lock()
+            }
+        };
+        let _ = serial.write_fmt(args);
+    });
+}
+
 /// ## 说明
 /// 向串口连接的设备打印
 ///
@@ -55,3 +72,35 @@ macro_rules! serial_println {
     ($fmt:expr, $($arg:tt)*) => ($crate::serial_print!(
         concat!($fmt, "\n"), $($arg)*));
 }
+
+/// ## 说明
+/// 向串口打印表格的一行，每列左对齐并填充到`width`宽度
+///
+/// ## 参数
+/// * `columns` - 各列的内容
+/// * `width` - 每列宽度
+///
+/// ## 用法
+/// ```rust
+/// print_table_row(&[&"name", &42], 16);
+/// ```
+pub fn print_table_row(columns: &[&dyn core::fmt::Display], width: usize) {
+    for column in columns {
+        serial_print!("{:<width$}", column, width = width);
+    }
+    serial_println!();
+}
+
+/// ## 说明
+/// 在致命错误处理函数中向串口打印并追加新的一行，串口被锁定时强制解锁，不会死锁
+///
+/// ## 用法
+/// ```rust
+/// serial_emergency_println!("EXCEPTION: DOUBLE FAULT");
+/// ```
+#[macro_export]
+macro_rules! serial_emergency_println {
+    ($($arg:tt)*) => {
+        $crate::serial::_emergency_print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
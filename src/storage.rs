@@ -0,0 +1,223 @@
+pub mod conformance;
+pub mod mbr;
+pub mod ramdisk;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+pub use ramdisk::RamDisk;
+
+/// ## 说明
+/// 块设备操作的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// 请求的块超出设备的范围
+    OutOfRange { lba: u64, blocks: u64 },
+    /// 缓冲区的长度不是块大小的整数倍
+    BadBufferLength { len: usize, block_size: usize },
+    /// 分区超出底层设备的范围
+    BadPartition { start: u64, blocks: u64 },
+    /// 已经注册了同名的设备
+    AlreadyRegistered,
+    /// 后端设备不存在或尚未初始化
+    NoDevice,
+    /// 等待设备超时
+    Timeout,
+    /// 设备报告读写失败，如坏块或无法纠正的数据错误
+    Io,
+}
+
+/// ## 说明
+/// 按块读写的存储设备，文件系统只通过这个接口访问ATA硬盘、内存盘等后端
+/// 一次读写的块数由缓冲区的长度决定，长度必须是`block_size()`的整数倍
+pub trait BlockDevice {
+    /// 每块的字节数
+    fn block_size(&self) -> usize;
+
+    /// 块的总数
+    fn block_count(&self) -> u64;
+
+    /// 从`lba`开始读取`buf.len() / block_size()`个块
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    /// 把`buf`写入从`lba`开始的`buf.len() / block_size()`个块
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError>;
+}
+
+/// ## 函数说明
+/// 检查一次读写请求：缓冲区长度是块大小的整数倍，且所有块都在设备的范围内，返回块数
+/// 供`BlockDevice`的实现在访问后端之前调用
+///
+/// ## 参数
+/// * `device` - 设备
+/// * `lba` - 第一个块
+/// * `len` - 缓冲区长度
+pub fn check_request(device: &dyn BlockDevice, lba: u64, len: usize) -> Result<u64, BlockError> {
+    let block_size = device.block_size();
+    if !len.is_multiple_of(block_size) {
+        return Err(BlockError::BadBufferLength { len, block_size });
+    }
+    let blocks = (len / block_size) as u64;
+    match lba.checked_add(blocks) {
+        Some(end) if end <= device.block_count() => Ok(blocks),
+        _ => Err(BlockError::OutOfRange { lba, blocks }),
+    }
+}
+
+/// ## 说明
+/// 底层设备的一段连续块，用于把MBR分区作为独立的设备
+/// 块号从0开始，访问超出分区的块时返回`OutOfRange`，不会触及分区之外的数据
+pub struct PartitionDevice<D: BlockDevice> {
+    inner: D,
+    start: u64,
+    blocks: u64,
+}
+
+impl<D: BlockDevice> PartitionDevice<D> {
+    /// ## 函数说明
+    /// 创建分区设备，分区需要完全位于底层设备之内
+    ///
+    /// ## 参数
+    /// * `inner` - 底层设备
+    /// * `start` - 分区在底层设备中的第一个块
+    /// * `blocks` - 分区的块数
+    pub fn new(inner: D, start: u64, blocks: u64) -> Result<Self, BlockError> {
+        match start.checked_add(blocks) {
+            Some(end) if end <= inner.block_count() => Ok(PartitionDevice {
+                inner,
+                start,
+                blocks,
+            }),
+            _ => Err(BlockError::BadPartition { start, blocks }),
+        }
+    }
+
+    /// ## 函数说明
+    /// 取回底层设备
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for PartitionDevice<D> {
+    fn block_size(&self) -> usize {
+        self.inner.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.blocks
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        self.inner.read_blocks(self.start + lba, buf)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        self.inner.write_blocks(self.start + lba, buf)
+    }
+}
+
+//让多个分区共享同一个设备
+impl<D: BlockDevice> BlockDevice for Arc<Mutex<D>> {
+    fn block_size(&self) -> usize {
+        self.lock().block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.lock().block_count()
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.lock().read_blocks(lba, buf)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.lock().write_blocks(lba, buf)
+    }
+}
+
+//已注册的设备，按注册顺序排列
+static DEVICES: Mutex<Vec<(String, Box<dyn BlockDevice + Send>)>> = Mutex::new(Vec::new());
+
+/// ## 函数说明
+/// 以`name`注册一个块设备，名字已被使用时返回`AlreadyRegistered`
+///
+/// ## 参数
+/// * `name` - 设备名，如"ata0"
+/// * `device` - 设备
+///
+/// ## 用法
+/// ```rust
+/// storage::register("ram0", Box::new(RamDisk::new(512, 128)))?;
+/// ```
+pub fn register(name: &str, device: Box<dyn BlockDevice + Send>) -> Result<(), BlockError> {
+    let mut devices = DEVICES.lock();
+    if devices.iter().any(|(existing, _)| existing == name) {
+        return Err(BlockError::AlreadyRegistered);
+    }
+    devices.push((String::from(name), device));
+    Ok(())
+}
+
+/// ## 函数说明
+/// 一次注册多个设备，任何一个名字已被使用或在`devices`中重复时一个也不注册，返回`AlreadyRegistered`
+///
+/// ## 参数
+/// * `devices` - 设备名和设备的列表
+pub fn register_all(devices: Vec<(String, Box<dyn BlockDevice + Send>)>) -> Result<(), BlockError> {
+    let mut registered = DEVICES.lock();
+    for (i, (name, _)) in devices.iter().enumerate() {
+        if registered
+            .iter()
+            .chain(&devices[..i])
+            .any(|(existing, _)| existing == name)
+        {
+            return Err(BlockError::AlreadyRegistered);
+        }
+    }
+    registered.extend(devices);
+    Ok(())
+}
+
+/// ## 函数说明
+/// 注销设备并返回它，设备不存在时返回None
+///
+/// ## 参数
+/// * `name` - 设备名
+pub fn unregister(name: &str) -> Option<Box<dyn BlockDevice + Send>> {
+    let mut devices = DEVICES.lock();
+    let index = devices.iter().position(|(existing, _)| existing == name)?;
+    Some(devices.remove(index).1)
+}
+
+/// ## 函数说明
+/// 查找名为`name`的设备并对它调用`f`，设备不存在时返回None
+/// `f`执行期间持有设备表的锁，不能在其中注册或查找其他设备
+///
+/// ## 参数
+/// * `name` - 设备名
+/// * `f` - 对设备的操作
+///
+/// ## 用法
+/// ```rust
+/// let mut sector = [0u8; 512];
+/// storage::with_device("ata0", |dev| dev.read_blocks(0, &mut sector));
+/// ```
+pub fn with_device<R>(name: &str, f: impl FnOnce(&mut dyn BlockDevice) -> R) -> Option<R> {
+    let mut devices = DEVICES.lock();
+    let (_, device) = devices.iter_mut().find(|(existing, _)| existing == name)?;
+    Some(f(device.as_mut()))
+}
+
+/// ## 函数说明
+/// 对每个已注册的设备调用`f`，参数为设备名和设备
+pub fn for_each_device(mut f: impl FnMut(&str, &dyn BlockDevice)) {
+    for (name, device) in DEVICES.lock().iter() {
+        f(name, device.as_ref());
+    }
+}
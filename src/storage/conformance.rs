@@ -0,0 +1,154 @@
+//所有`BlockDevice`实现都应通过的检查，供内存盘和ATA硬盘的集成测试共用
+
+use alloc::vec;
+
+use super::{BlockDevice, BlockError};
+
+/// ## 函数说明
+/// 对设备执行全部检查，失败时panic
+/// 会改写最后两个块，结束时恢复原来的内容；设备至少需要两个块
+///
+/// ## 参数
+/// * `device` - 被测设备
+///
+/// ## 用法
+/// ```rust
+/// storage::conformance::run(&mut RamDisk::new(512, 64));
+/// ```
+pub fn run(device: &mut dyn BlockDevice) {
+    assert!(device.block_count() >= 2, "conformance needs two blocks");
+    boundary_reads(device);
+    cross_block_buffers(device);
+    out_of_range(device);
+    bad_buffer_lengths(device);
+    write_read_back(device);
+}
+
+/// ## 函数说明
+/// 检查写入的数据在重新打开设备之后仍然存在，失败时panic
+/// 会改写最后一个块，结束时恢复原来的内容
+///
+/// ## 参数
+/// * `device` - 被测设备
+/// * `reopen` - 刷新并重新初始化设备的驱动，内存盘可以传入空闭包
+///
+/// ## 用法
+/// ```rust
+/// storage::conformance::persistence(&mut AtaDisk, &mut || {
+///     ata::flush().unwrap();
+///     ata::init().unwrap();
+/// });
+/// ```
+pub fn persistence(device: &mut dyn BlockDevice, reopen: &mut dyn FnMut()) {
+    let size = device.block_size();
+    let lba = device.block_count() - 1;
+    let mut original = vec![0u8; size];
+    device.read_blocks(lba, &mut original).unwrap();
+
+    let pattern: alloc::vec::Vec<u8> = (0..size)
+        .map(|i| (i as u8).wrapping_mul(13) ^ 0x3c)
+        .collect();
+    device
+        .write_blocks(lba, &pattern)
+        .expect("write last block");
+    reopen();
+    let mut read_back = vec![0u8; size];
+    device.read_blocks(lba, &mut read_back).unwrap();
+    assert_eq!(read_back, pattern, "write did not survive reopening");
+
+    device.write_blocks(lba, &original).unwrap();
+    reopen();
+    device.read_blocks(lba, &mut read_back).unwrap();
+    assert_eq!(read_back, original);
+}
+
+//第一个块和最后一个块可以读取，空缓冲区不访问设备
+fn boundary_reads(device: &mut dyn BlockDevice) {
+    let size = device.block_size();
+    let last = device.block_count() - 1;
+    let mut block = vec![0u8; size];
+    device.read_blocks(0, &mut block).expect("read first block");
+    device
+        .read_blocks(last, &mut block)
+        .expect("read last block");
+    device
+        .read_blocks(last + 1, &mut [])
+        .expect("empty read at end");
+}
+
+//一次读取两个块的结果与分别读取相同
+fn cross_block_buffers(device: &mut dyn BlockDevice) {
+    let size = device.block_size();
+    let last = device.block_count() - 1;
+    let mut both = vec![0u8; 2 * size];
+    let mut single = vec![0u8; 2 * size];
+    for lba in [0, last - 1] {
+        device.read_blocks(lba, &mut both).expect("read two blocks");
+        device.read_blocks(lba, &mut single[..size]).unwrap();
+        device.read_blocks(lba + 1, &mut single[size..]).unwrap();
+        assert_eq!(both, single, "two-block read at {} differs", lba);
+    }
+}
+
+fn out_of_range(device: &mut dyn BlockDevice) {
+    let size = device.block_size();
+    let count = device.block_count();
+    let mut two = vec![0u8; 2 * size];
+    assert_eq!(
+        device.read_blocks(count, &mut two[..size]),
+        Err(BlockError::OutOfRange {
+            lba: count,
+            blocks: 1
+        })
+    );
+    //跨过末尾的请求整体被拒绝
+    assert_eq!(
+        device.read_blocks(count - 1, &mut two),
+        Err(BlockError::OutOfRange {
+            lba: count - 1,
+            blocks: 2
+        })
+    );
+    assert_eq!(
+        device.write_blocks(count - 1, &two),
+        Err(BlockError::OutOfRange {
+            lba: count - 1,
+            blocks: 2
+        })
+    );
+    assert!(device.read_blocks(u64::MAX, &mut two[..size]).is_err());
+}
+
+fn bad_buffer_lengths(device: &mut dyn BlockDevice) {
+    let size = device.block_size();
+    let mut buf = vec![0u8; size + 1];
+    let expected = Err(BlockError::BadBufferLength {
+        len: size + 1,
+        block_size: size,
+    });
+    assert_eq!(device.read_blocks(0, &mut buf), expected);
+    assert_eq!(device.write_blocks(0, &buf), expected);
+}
+
+//写入最后两个块并读回，然后恢复
+fn write_read_back(device: &mut dyn BlockDevice) {
+    let size = device.block_size();
+    let lba = device.block_count() - 2;
+    let mut original = vec![0u8; 2 * size];
+    device.read_blocks(lba, &mut original).unwrap();
+
+    let pattern: alloc::vec::Vec<u8> = (0..2 * size).map(|i| (i as u8) ^ 0xa5).collect();
+    device
+        .write_blocks(lba, &pattern)
+        .expect("write two blocks");
+    let mut read_back = vec![0u8; 2 * size];
+    device.read_blocks(lba, &mut read_back).unwrap();
+    assert_eq!(read_back, pattern);
+    //第二个块单独读取也得到对应的一半
+    device.read_blocks(lba + 1, &mut read_back[..size]).unwrap();
+    assert_eq!(read_back[..size], pattern[size..]);
+
+    device.write_blocks(lba, &original).unwrap();
+    device.read_blocks(lba, &mut read_back).unwrap();
+    assert_eq!(read_back, original);
+}
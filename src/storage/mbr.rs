@@ -0,0 +1,249 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::vec::Vec;
+
+use super::{BlockDevice, BlockError, PartitionDevice};
+
+/// MBR要求的块大小
+pub const SECTOR_SIZE: usize = 512;
+/// 分区类型：扩展分区（CHS和LBA两种）
+pub const TYPE_EXTENDED_CHS: u8 = 0x05;
+pub const TYPE_EXTENDED_LBA: u8 = 0x0f;
+
+//分区表在扇区中的偏移、每项的大小和引导签名的位置
+const TABLE_OFFSET: usize = 0x1be;
+const ENTRY_SIZE: usize = 16;
+const SIGNATURE_OFFSET: usize = 510;
+const SIGNATURE: [u8; 2] = [0x55, 0xaa];
+//跟随扩展分区链的上限，防止损坏的链构成环
+const MAX_LOGICAL: usize = 64;
+//逻辑分区从5开始编号
+const FIRST_LOGICAL: usize = 5;
+
+/// ## 说明
+/// 解析MBR时的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MbrError {
+    /// 读取设备失败
+    Read(BlockError),
+    /// 块大小不是512字节
+    BadBlockSize(usize),
+    /// 第0个扇区没有0x55AA签名
+    NoSignature,
+}
+
+impl From<BlockError> for MbrError {
+    fn from(err: BlockError) -> Self {
+        MbrError::Read(err)
+    }
+}
+
+/// ## 说明
+/// 分区表中可疑但不妨碍解析的问题
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionProblem {
+    /// 分区超出设备的末尾，不会被注册
+    OutOfRange,
+    /// 与编号为`0`的分区重叠
+    Overlaps(usize),
+}
+
+/// ## 说明
+/// 一个主分区或逻辑分区，扩展分区本身不列出
+///
+/// ## 成员
+/// * `index` - 分区编号，主分区为1~4，逻辑分区从5开始
+/// * `kind` - 分区类型字节
+/// * `bootable` - 活动分区标志
+/// * `start_lba` - 在设备中的第一个扇区（绝对LBA）
+/// * `sectors` - 扇区数
+/// * `problem` - 解析时发现的问题
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MbrPartition {
+    pub index: usize,
+    pub kind: u8,
+    pub bootable: bool,
+    pub start_lba: u64,
+    pub sectors: u64,
+    pub problem: Option<PartitionProblem>,
+}
+
+impl MbrPartition {
+    fn end(&self) -> u64 {
+        self.start_lba.saturating_add(self.sectors)
+    }
+}
+
+//分区表中的一项，CHS字段被忽略
+struct Entry {
+    bootable: bool,
+    kind: u8,
+    start: u32,
+    sectors: u32,
+}
+
+impl Entry {
+    fn is_empty(&self) -> bool {
+        self.kind == 0 || self.sectors == 0
+    }
+
+    fn is_extended(&self) -> bool {
+        self.kind == TYPE_EXTENDED_CHS || self.kind == TYPE_EXTENDED_LBA
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+//读取一个MBR或EBR扇区，检查签名并解码四个表项
+fn read_table(dev: &mut dyn BlockDevice, lba: u64) -> Result<[Entry; 4], MbrError> {
+    let mut sector = [0u8; SECTOR_SIZE];
+    dev.read_blocks(lba, &mut sector)?;
+    if sector[SIGNATURE_OFFSET..] != SIGNATURE {
+        return Err(MbrError::NoSignature);
+    }
+    Ok(core::array::from_fn(|i| {
+        let entry = &sector[TABLE_OFFSET + i * ENTRY_SIZE..][..ENTRY_SIZE];
+        Entry {
+            bootable: entry[0] & 0x80 != 0,
+            kind: entry[4],
+            start: read_u32(&entry[8..12]),
+            sectors: read_u32(&entry[12..16]),
+        }
+    }))
+}
+
+/// ## 函数说明
+/// 解析设备第0个扇区的MBR，列出主分区和扩展分区链中的逻辑分区
+/// 超出设备或互相重叠的分区记录在`problem`中并打印警告，不作为错误
+///
+/// ## 参数
+/// * `dev` - 块大小为512字节的设备
+///
+/// ## 用法
+/// ```rust
+/// let partitions = storage::mbr::parse(&mut AtaDisk)?;
+/// ```
+pub fn parse(dev: &mut dyn BlockDevice) -> Result<Vec<MbrPartition>, MbrError> {
+    if dev.block_size() != SECTOR_SIZE {
+        return Err(MbrError::BadBlockSize(dev.block_size()));
+    }
+    let mut partitions = Vec::new();
+    let mut extended = None;
+    for (i, entry) in read_table(dev, 0)?.iter().enumerate() {
+        if entry.is_empty() {
+            continue;
+        }
+        if entry.is_extended() {
+            extended.get_or_insert(u64::from(entry.start));
+            continue;
+        }
+        partitions.push(partition(i + 1, entry, 0));
+    }
+    if let Some(extended_start) = extended {
+        parse_logical(dev, extended_start, &mut partitions);
+    }
+
+    check_partitions(&mut partitions, dev.block_count());
+    for partition in partitions.iter().filter(|p| p.problem.is_some()) {
+        crate::println!(
+            "warning: MBR partition {}: {:?}",
+            partition.index,
+            partition.problem.unwrap()
+        );
+    }
+    Ok(partitions)
+}
+
+fn partition(index: usize, entry: &Entry, base: u64) -> MbrPartition {
+    MbrPartition {
+        index,
+        kind: entry.kind,
+        bootable: entry.bootable,
+        start_lba: base + u64::from(entry.start),
+        sectors: u64::from(entry.sectors),
+        problem: None,
+    }
+}
+
+//EBR的第一项是相对于这个EBR的逻辑分区，第二项是相对于扩展分区起点的下一个EBR
+fn parse_logical(dev: &mut dyn BlockDevice, extended_start: u64, out: &mut Vec<MbrPartition>) {
+    let mut ebr = extended_start;
+    for index in FIRST_LOGICAL..FIRST_LOGICAL + MAX_LOGICAL {
+        let table = match read_table(dev, ebr) {
+            Ok(table) => table,
+            Err(err) => {
+                crate::println!(
+                    "warning: extended partition chain broken at LBA {}: {:?}",
+                    ebr,
+                    err
+                );
+                return;
+            }
+        };
+        if !table[0].is_empty() {
+            out.push(partition(index, &table[0], ebr));
+        }
+        if table[1].is_empty() {
+            return;
+        }
+        ebr = extended_start + u64::from(table[1].start);
+    }
+    crate::println!(
+        "warning: more than {} logical partitions, ignoring the rest",
+        MAX_LOGICAL
+    );
+}
+
+//标记超出设备和与前面的分区重叠的分区
+fn check_partitions(partitions: &mut [MbrPartition], block_count: u64) {
+    for i in 0..partitions.len() {
+        let current = partitions[i];
+        partitions[i].problem = if current.end() > block_count {
+            Some(PartitionProblem::OutOfRange)
+        } else {
+            partitions[..i]
+                .iter()
+                .find(|other| other.start_lba < current.end() && current.start_lba < other.end())
+                .map(|other| PartitionProblem::Overlaps(other.index))
+        };
+    }
+}
+
+/// ## 函数说明
+/// 把解析得到的分区注册为`{name}p{index}`，超出设备的分区被跳过，返回注册的个数
+/// 任何一个分区无法注册时一个也不注册，不会留下注册了一半的设备
+/// 设备需要可以被多个分区共享，如`AtaDisk`或`Arc<Mutex<RamDisk>>`
+///
+/// ## 参数
+/// * `name` - 整个设备的名字，如"ata0"
+/// * `dev` - 设备
+/// * `partitions` - `parse`的结果
+///
+/// ## 用法
+/// ```rust
+/// let partitions = mbr::parse(&mut AtaDisk)?;
+/// mbr::register_partitions("ata0", AtaDisk, &partitions)?;
+/// ```
+pub fn register_partitions<D>(
+    name: &str,
+    dev: D,
+    partitions: &[MbrPartition],
+) -> Result<usize, BlockError>
+where
+    D: BlockDevice + Clone + Send + 'static,
+{
+    //先创建所有分区设备，再一次性注册
+    let mut devices: Vec<(_, Box<dyn BlockDevice + Send>)> = Vec::new();
+    for partition in partitions {
+        if partition.problem == Some(PartitionProblem::OutOfRange) {
+            continue;
+        }
+        let device = PartitionDevice::new(dev.clone(), partition.start_lba, partition.sectors)?;
+        devices.push((format!("{}p{}", name, partition.index), Box::new(device)));
+    }
+    let registered = devices.len();
+    super::register_all(devices)?;
+    Ok(registered)
+}
@@ -0,0 +1,62 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{check_request, BlockDevice, BlockError};
+
+/// ## 说明
+/// 以堆上的缓冲区为后端的块设备，创建时内容全为0
+pub struct RamDisk {
+    block_size: usize,
+    data: Vec<u8>,
+}
+
+impl RamDisk {
+    /// ## 函数说明
+    /// 创建一个`block_count`块、每块`block_size`字节的内存盘
+    ///
+    /// ## 参数
+    /// * `block_size` - 每块的字节数，不能为0
+    /// * `block_count` - 块数
+    ///
+    /// ## 用法
+    /// ```rust
+    /// let disk = RamDisk::new(512, 2048); //1MiB
+    /// ```
+    pub fn new(block_size: usize, block_count: usize) -> Self {
+        assert!(block_size > 0, "block size must not be zero");
+        RamDisk {
+            block_size,
+            data: vec![0; block_size * block_count],
+        }
+    }
+
+    /// ## 函数说明
+    /// 内存盘的全部内容
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.data.len() / self.block_size) as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        let start = lba as usize * self.block_size;
+        buf.copy_from_slice(&self.data[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        let start = lba as usize * self.block_size;
+        self.data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+use core::arch::global_asm;
+use x86_64::VirtAddr;
+
+use crate::{print, serial_print};
+
+/// 系统调用使用的中断向量
+pub const SYSCALL_VECTOR: u8 = 0x80;
+
+/// `write(fd, ptr, len)`：向fd 1（VGA控制台）或fd 2（串口）写入UTF-8字符串，返回写入的字节数
+pub const SYS_WRITE: u64 = 0;
+/// `exit(code)`：结束运行，不返回
+pub const SYS_EXIT: u64 = 1;
+/// `uptime()`：返回启动以来经过的毫秒数
+pub const SYS_UPTIME: u64 = 2;
+
+//一次write最多写入的字节数
+const MAX_WRITE: u64 = 4096;
+
+/// ## 说明
+/// 系统调用的错误，以负数的形式放在rax中返回，数值与Linux的errno相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum SyscallError {
+    /// 无效的文件描述符
+    BadFd = 9,
+    /// 缓冲区地址无效
+    Fault = 14,
+    /// 参数无效，如非UTF-8的字符串或长度超过限制
+    Invalid = 22,
+    /// 没有这个系统调用
+    NoSys = 38,
+}
+
+impl SyscallError {
+    /// ## 函数说明
+    /// 写回rax的值，即errno的相反数
+    pub fn as_return(self) -> u64 {
+        (self as u64).wrapping_neg()
+    }
+}
+
+/// ## 说明
+/// 入口代码保存在栈上的调用参数，约定与Linux相同：rax为调用号，rdi、rsi、rdx依次为参数
+/// 分派函数返回后，入口代码把`rax`恢复到寄存器中作为返回值
+///
+/// ## 成员
+/// * `rax` - 调用号，返回时为返回值
+/// * `rdi` - 第一个参数
+/// * `rsi` - 第二个参数
+/// * `rdx` - 第三个参数
+#[derive(Debug)]
+#[repr(C)]
+pub struct SyscallFrame {
+    pub rax: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+}
+
+/*
+    int 0x80的入口
+    保存所有调用者保存的寄存器，最后压入的四个构成SyscallFrame，再以它的地址调用syscall_dispatch
+    CPU压入的中断栈帧（40字节）加上9个寄存器正好是16的倍数，调用时栈保持对齐
+*/
+global_asm!(
+    ".global syscall_entry",
+    "syscall_entry:",
+    "push r11",
+    "push r10",
+    "push r9",
+    "push r8",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rax",
+    "cld",
+    "mov rdi, rsp",
+    "call {dispatch}",
+    "pop rax",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop r8",
+    "pop r9",
+    "pop r10",
+    "pop r11",
+    "iretq",
+    dispatch = sym syscall_dispatch,
+);
+
+extern "C" {
+    fn syscall_entry();
+}
+
+/// ## 函数说明
+/// 系统调用入口代码的地址，用于在IDT中注册
+pub fn entry_addr() -> VirtAddr {
+    VirtAddr::new(syscall_entry as *const () as u64)
+}
+
+extern "C" fn syscall_dispatch(frame: &mut SyscallFrame) {
+    let result = match frame.rax {
+        SYS_WRITE => sys_write(frame.rdi, frame.rsi, frame.rdx),
+        SYS_EXIT => sys_exit(frame.rdi),
+        SYS_UPTIME => Ok(crate::time::uptime_ms()),
+        _ => Err(SyscallError::NoSys),
+    };
+    frame.rax = result.unwrap_or_else(SyscallError::as_return);
+}
+
+fn sys_write(fd: u64, ptr: u64, len: u64) -> Result<u64, SyscallError> {
+    if fd != 1 && fd != 2 {
+        return Err(SyscallError::BadFd);
+    }
+    if len > MAX_WRITE {
+        return Err(SyscallError::Invalid);
+    }
+    if ptr == 0 || VirtAddr::try_new(ptr).is_err() || ptr.checked_add(len).is_none() {
+        return Err(SyscallError::Fault);
+    }
+    //目前只有内核自己调用，地址由调用者保证有效；有了用户态之后需要检查页面的映射和权限
+    let bytes = unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) };
+    let text = core::str::from_utf8(bytes).map_err(|_| SyscallError::Invalid)?;
+    match fd {
+        1 => print!("{}", text),
+        _ => {
+            serial_print!("{}", text);
+        }
+    }
+    Ok(len)
+}
+
+fn sys_exit(code: u64) -> Result<u64, SyscallError> {
+    #[cfg(test)]
+    crate::exit_qemu(if code == 0 {
+        crate::QemuExitCode::Success
+    } else {
+        crate::QemuExitCode::Failed
+    });
+    crate::println!("exit({})", code);
+    crate::hlt_loop();
+}
+
+#[cfg(test)]
+fn syscall(number: u64, a: u64, b: u64, c: u64) -> u64 {
+    let ret;
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            inlateout("rax") number => ret,
+            in("rdi") a,
+            in("rsi") b,
+            in("rdx") c,
+        );
+    }
+    ret
+}
+
+#[test_case]
+fn test_syscall_write() {
+    use crate::vga_buffer::{read_row, BUFFER_HEIGHT};
+
+    let s = "\nwritten by int 0x80\n";
+    let ret = syscall(SYS_WRITE, 1, s.as_ptr() as u64, s.len() as u64);
+    assert_eq!(ret, s.len() as u64);
+    let row = read_row(BUFFER_HEIGHT - 2);
+    assert_eq!(&row[..s.len() - 2], s.trim().as_bytes());
+}
+
+#[test_case]
+fn test_syscall_errors() {
+    let s = "x";
+    let bad_fd = syscall(SYS_WRITE, 3, s.as_ptr() as u64, 1);
+    assert_eq!(bad_fd, SyscallError::BadFd.as_return());
+    assert_eq!(syscall(SYS_WRITE, 2, 0, 1), SyscallError::Fault.as_return());
+    let invalid = [0xffu8];
+    let ret = syscall(SYS_WRITE, 2, invalid.as_ptr() as u64, 1);
+    assert_eq!(ret, SyscallError::Invalid.as_return());
+    assert_eq!(syscall(99, 0, 0, 0), SyscallError::NoSys.as_return());
+}
+
+#[test_case]
+fn test_syscall_uptime() {
+    let before = crate::time::uptime_ms();
+    let uptime = syscall(SYS_UPTIME, 0, 0, 0);
+    assert!(uptime >= before);
+    assert!(uptime <= crate::time::uptime_ms());
+}
@@ -0,0 +1,344 @@
+use core::fmt;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+pub mod tsc;
+
+/// PIT的输入时钟频率（Hz）
+pub const PIT_FREQUENCY: u32 = 1_193_182;
+/// 支持的最低时钟中断频率，接近16位分频系数的上限
+pub const MIN_FREQUENCY: u32 = 19;
+/// 支持的最高时钟中断频率
+pub const MAX_FREQUENCY: u32 = 1000;
+/// `lib::init`使用的时钟中断频率
+pub const DEFAULT_FREQUENCY: u32 = 100;
+
+//PIT通道0的数据端口和模式/命令端口
+const CHANNEL_0: u16 = 0x40;
+const COMMAND: u16 = 0x43;
+//通道0，先低字节后高字节，模式3（方波）
+const SQUARE_WAVE: u8 = 0x36;
+
+//时钟中断处理函数只做原子加法，不需要加锁
+static TICKS: AtomicU64 = AtomicU64::new(0);
+//未编程时PIT使用最大分频系数，约18.2Hz
+static FREQUENCY: AtomicU32 = AtomicU32::new(18);
+//最近一次修改频率时的滴答数和已经过的毫秒数，之后的滴答按当前频率换算
+static BASE_TICKS: AtomicU64 = AtomicU64::new(0);
+static BASE_MS: AtomicU64 = AtomicU64::new(0);
+
+/// 可以同时注册的时钟回调数量
+pub const MAX_CALLBACKS: usize = 8;
+
+//注册后每`interval`个滴答调用一次，`next`为下一次调用时的滴答数
+#[derive(Clone, Copy)]
+struct Callback {
+    interval: u64,
+    next: u64,
+    callback: fn(),
+}
+
+//时钟回调表，只在关闭中断时修改；`generation`区分同一个槽位先后注册的回调
+struct Callbacks {
+    slots: [Option<Callback>; MAX_CALLBACKS],
+    generation: [u32; MAX_CALLBACKS],
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    slots: [None; MAX_CALLBACKS],
+    generation: [0; MAX_CALLBACKS],
+});
+
+/// ## 说明
+/// `every_ticks`返回的回调句柄，用于`cancel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallbackId {
+    slot: usize,
+    generation: u32,
+}
+
+/// ## 说明
+/// 时钟中断频率设置错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// 请求的频率超出[MIN_FREQUENCY, MAX_FREQUENCY]，PIT已按`applied`编程
+    OutOfRange { requested: u32, applied: u32 },
+    /// 已经注册了`MAX_CALLBACKS`个时钟回调
+    NoFreeSlot,
+}
+
+/// ## 函数说明
+/// 按`frequency_hz`编程PIT通道0，超出支持范围的请求会被限制到最近的边界，同时返回错误
+/// 第一次调用时还会使用PIT通道2校准TSC，供`sleep_busy_us`使用
+///
+/// ## 参数
+/// * `frequency_hz` - 时钟中断频率，范围为[MIN_FREQUENCY, MAX_FREQUENCY]
+///
+/// ## 用法
+/// ```rust
+/// time::init(time::DEFAULT_FREQUENCY).expect("invalid timer frequency");
+/// ```
+pub fn init(frequency_hz: u32) -> Result<(), TimerError> {
+    let applied = frequency_hz.clamp(MIN_FREQUENCY, MAX_FREQUENCY);
+    let divisor = (PIT_FREQUENCY / applied) as u16;
+
+    let mut command: Port<u8> = Port::new(COMMAND);
+    let mut data: Port<u8> = Port::new(CHANNEL_0);
+    interrupts::without_interrupts(|| {
+        unsafe {
+            command.write(SQUARE_WAVE);
+            data.write(divisor as u8);
+            data.write((divisor >> 8) as u8);
+        }
+        rebase(applied);
+    });
+
+    tsc_per_ms();
+
+    if applied == frequency_hz {
+        Ok(())
+    } else {
+        Err(TimerError::OutOfRange {
+            requested: frequency_hz,
+            applied,
+        })
+    }
+}
+
+//按旧频率结算已经过的时间，之后的滴答按新频率换算，需要在关中断时调用
+fn rebase(frequency_hz: u32) {
+    let now = ticks();
+    let elapsed = ticks_to_ms(now - BASE_TICKS.load(Ordering::SeqCst), frequency());
+    BASE_MS.fetch_add(elapsed, Ordering::SeqCst);
+    BASE_TICKS.store(now, Ordering::SeqCst);
+    FREQUENCY.store(frequency_hz, Ordering::SeqCst);
+}
+
+/// ## 函数说明
+/// 时钟中断改由其他时钟源（如本地APIC计时器）以`frequency_hz`产生时调用，PIT不再被编程
+///
+/// ## 参数
+/// * `frequency_hz` - 新时钟源的中断频率
+#[cfg(feature = "apic")]
+pub(crate) fn set_tick_source(frequency_hz: u32) {
+    interrupts::without_interrupts(|| rebase(frequency_hz));
+}
+
+/// ## 函数说明
+/// 当前的时钟中断频率（Hz）
+pub fn frequency() -> u32 {
+    FREQUENCY.load(Ordering::SeqCst)
+}
+
+/// ## 函数说明
+/// 时钟中断发生的次数
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::SeqCst)
+}
+
+//分别换算整秒和余下的滴答，避免滴答数很大时乘法溢出
+fn ticks_to_ms(ticks: u64, frequency: u32) -> u64 {
+    let frequency = u64::from(frequency);
+    ticks / frequency * 1000 + ticks % frequency * 1000 / frequency
+}
+
+/// ## 函数说明
+/// 启动以来经过的毫秒数，启用了HPET时精确到毫秒，否则精度为一个时钟周期
+///
+/// ## 用法
+/// ```rust
+/// let start = time::uptime_ms();
+/// ```
+pub fn uptime_ms() -> u64 {
+    //HPET的精度远高于时钟滴答，存在时优先使用
+    if let Some(ns) = crate::hpet::now_ns() {
+        return ns / 1_000_000;
+    }
+    interrupts::without_interrupts(|| {
+        let ticks = ticks() - BASE_TICKS.load(Ordering::SeqCst);
+        BASE_MS.load(Ordering::SeqCst) + ticks_to_ms(ticks, frequency())
+    })
+}
+
+//结果向上取整，保证至少等待`ms`毫秒
+fn ms_to_ticks(ms: u64, frequency: u32) -> u64 {
+    let frequency = u64::from(frequency);
+    ms / 1000 * frequency + (ms % 1000 * frequency).div_ceil(1000)
+}
+
+/// ## 函数说明
+/// 通过`hlt`等待至少`ms`毫秒，精度为一个时钟周期
+/// 需要开启中断，并且不能在中断处理函数中调用：中断门会清除IF，时钟中断无法到达，调用会永远等待；
+/// 调试构建中会检查IF
+///
+/// ## 参数
+/// * `ms` - 毫秒数
+///
+/// ## 用法
+/// ```rust
+/// time::sleep_ms(10); //等待PS/2控制器稳定
+/// ```
+pub fn sleep_ms(ms: u64) {
+    debug_assert!(
+        interrupts::are_enabled(),
+        "sleep_ms called with interrupts disabled or from interrupt context"
+    );
+    debug_assert!(
+        !crate::interrupts::in_interrupt_context(),
+        "sleep_ms called from interrupt context"
+    );
+    //当前周期已经过去了一部分，因此多等一个滴答
+    let target = ticks() + ms_to_ticks(ms, frequency()) + 1;
+    while ticks() < target {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// ## 函数说明
+/// 每毫秒的TSC周期数，即`tsc::cycles_per_ms`，第一次调用时进行校准
+pub fn tsc_per_ms() -> u64 {
+    tsc::cycles_per_ms()
+}
+
+/// ## 函数说明
+/// 通过轮询TSC等待至少`us`微秒，用于`sleep_ms`精度不够的短暂等待，可以在中断处理函数中使用
+///
+/// ## 参数
+/// * `us` - 微秒数
+///
+/// ## 用法
+/// ```rust
+/// time::sleep_busy_us(500); //等待设备响应命令
+/// ```
+pub fn sleep_busy_us(us: u64) {
+    let per_ms = tsc_per_ms();
+    let cycles = us / 1000 * per_ms + us % 1000 * per_ms / 1000;
+    let start = rdtsc();
+    while rdtsc() - start < cycles {
+        core::hint::spin_loop();
+    }
+}
+
+/// ## 说明
+/// 启动以来经过的时间，打印为`秒.毫秒s`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Uptime {
+    pub secs: u64,
+    pub millis: u32,
+}
+
+impl fmt::Display for Uptime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:03}s", self.secs, self.millis)
+    }
+}
+
+/// ## 函数说明
+/// 启动以来经过的时间
+///
+/// ## 用法
+/// ```rust
+/// serial_println!("[{}] booted", time::uptime());
+/// ```
+pub fn uptime() -> Uptime {
+    let ms = uptime_ms();
+    Uptime {
+        secs: ms / 1000,
+        millis: (ms % 1000) as u32,
+    }
+}
+
+/// ## 函数说明
+/// 注册一个每`n`个滴答调用一次的回调，第一次调用在`n`个滴答之后
+/// 回调在时钟中断处理函数中运行，不能分配内存、获取可能被打断的代码持有的锁或等待；
+/// 耗时的工作应该只设置一个标志，交给其他代码处理
+///
+/// ## 参数
+/// * `n` - 调用间隔的滴答数，为0时按1处理
+/// * `f` - 回调函数
+///
+/// ## 用法
+/// ```rust
+/// let id = time::every_ticks(u64::from(time::frequency()), update_clock)?;
+/// ```
+pub fn every_ticks(n: u64, f: fn()) -> Result<CallbackId, TimerError> {
+    let interval = n.max(1);
+    interrupts::without_interrupts(|| {
+        let mut callbacks = CALLBACKS.lock();
+        let slot = callbacks
+            .slots
+            .iter()
+            .position(Option::is_none)
+            .ok_or(TimerError::NoFreeSlot)?;
+        callbacks.slots[slot] = Some(Callback {
+            interval,
+            next: ticks() + interval,
+            callback: f,
+        });
+        callbacks.generation[slot] = callbacks.generation[slot].wrapping_add(1);
+        Ok(CallbackId {
+            slot,
+            generation: callbacks.generation[slot],
+        })
+    })
+}
+
+/// ## 函数说明
+/// 取消一个时钟回调，返回之后它不会再被调用；已经取消的句柄会被忽略
+///
+/// ## 参数
+/// * `id` - `every_ticks`返回的句柄
+pub fn cancel(id: CallbackId) {
+    interrupts::without_interrupts(|| {
+        let mut callbacks = CALLBACKS.lock();
+        if callbacks.generation[id.slot] == id.generation {
+            callbacks.slots[id.slot] = None;
+        }
+    });
+}
+
+//由时钟中断处理函数调用
+pub(crate) fn tick() {
+    let now = TICKS.fetch_add(1, Ordering::SeqCst) + 1;
+
+    //先取出到期的回调并释放锁，回调中可以调用`every_ticks`和`cancel`
+    let mut due: [Option<fn()>; MAX_CALLBACKS] = [None; MAX_CALLBACKS];
+    {
+        let mut callbacks = CALLBACKS.lock();
+        for (slot, due) in callbacks.slots.iter_mut().zip(due.iter_mut()) {
+            if let Some(callback) = slot.as_mut().filter(|c| c.next <= now) {
+                callback.next = now + callback.interval;
+                *due = Some(callback.callback);
+            }
+        }
+    }
+    for callback in due.iter().flatten() {
+        callback();
+    }
+}
+
+/// ## 函数说明
+/// 读取CPU时间戳计数器(TSC)，返回自上电以来的周期数
+///
+/// ## 用法
+/// ```rust
+/// let start = rdtsc();
+/// ```
+pub fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[test_case]
+fn test_ticks_to_ms_large() {
+    assert_eq!(ticks_to_ms(250, 100), 2500);
+    assert_eq!(ticks_to_ms(1, 19), 52);
+    //直接计算ticks * 1000会溢出
+    let ticks = u64::MAX / 10;
+    assert_eq!(ticks_to_ms(ticks, 1000), ticks);
+    assert_eq!(ticks_to_ms(ticks, 100), ticks * 10);
+    //换算成滴答时向上取整
+    assert_eq!(ms_to_ticks(1, 100), 1);
+    assert_eq!(ms_to_ticks(1500, 100), 150);
+}
@@ -0,0 +1,272 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+use super::{rdtsc, COMMAND, PIT_FREQUENCY};
+
+//PIT通道2的数据端口
+const CHANNEL_2: u16 = 0x42;
+//通道2，先低字节后高字节，模式0（计数结束时输出变为高电平）
+const ONE_SHOT_2: u8 = 0xb0;
+//键盘控制器端口B：位0为通道2的门控，位1为扬声器，位5为通道2的输出
+const PORT_B: u16 = 0x61;
+//每次测量等待的毫秒数
+const CALIBRATE_MS: u32 = 10;
+//测量的次数，取中位数排除被SMI等打断的测量
+const CALIBRATE_ROUNDS: usize = 5;
+//等待通道2输出变高时读取端口B的次数上限，每次端口读取至少需要数百纳秒，远超过CALIBRATE_MS
+const PIT_SPINS: usize = 1_000_000;
+//改用时钟中断测量时，等待每个滴答最多执行hlt的次数，其他中断也会唤醒CPU
+const TICK_WAITS: usize = 1000;
+//两种测量都失败时使用的估计值，按5GHz计算，使忙等待只会偏长而不会偏短
+const FALLBACK_CYCLES_PER_MS: u64 = 5_000_000;
+
+//每毫秒的TSC周期数，0表示尚未校准
+static CYCLES_PER_MS: AtomicU64 = AtomicU64::new(0);
+
+/// ## 说明
+/// TSC校准失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TscError {
+    /// PIT通道2的输出在`PIT_SPINS`次读取内没有变高
+    PitTimeout,
+    /// 中断被关闭或时钟中断没有到来，无法按滴答测量
+    NoTimerTicks,
+}
+
+/// ## 说明
+/// 校准TSC时使用的参考时钟
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+    /// PIT通道2单次计数
+    Pit,
+    /// HPET主计数器，已启用时优先使用
+    Hpet,
+}
+
+fn reference() -> Reference {
+    if crate::hpet::is_enabled() {
+        Reference::Hpet
+    } else {
+        Reference::Pit
+    }
+}
+
+//使用PIT通道2单次计数CALIBRATE_MS毫秒，测量期间经过的TSC周期数
+//没有通道2门控的平台上输出不会变高，读取PIT_SPINS次后返回错误
+fn sample_pit() -> Result<u64, TscError> {
+    let count = (PIT_FREQUENCY / 1000 * CALIBRATE_MS) as u16;
+    let mut port_b: Port<u8> = Port::new(PORT_B);
+    let mut command: Port<u8> = Port::new(COMMAND);
+    let mut channel_2: Port<u8> = Port::new(CHANNEL_2);
+
+    interrupts::without_interrupts(|| unsafe {
+        let saved = port_b.read();
+        port_b.write((saved & !0x02) | 0x01); //打开门控，关闭扬声器
+        command.write(ONE_SHOT_2);
+        channel_2.write(count as u8);
+        channel_2.write((count >> 8) as u8);
+
+        let start = rdtsc();
+        let mut spins = 0;
+        while port_b.read() & 0x20 == 0 && spins < PIT_SPINS {
+            spins += 1;
+            core::hint::spin_loop();
+        }
+        let cycles = rdtsc() - start;
+        port_b.write(saved);
+        if spins == PIT_SPINS {
+            Err(TscError::PitTimeout)
+        } else {
+            Ok(cycles)
+        }
+    })
+}
+
+//等待下一个时钟中断，执行TICK_WAITS次hlt后滴答数仍未变化时返回错误
+fn wait_tick() -> Result<(), TscError> {
+    let current = super::ticks();
+    for _ in 0..TICK_WAITS {
+        x86_64::instructions::hlt();
+        if super::ticks() != current {
+            return Ok(());
+        }
+    }
+    Err(TscError::NoTimerTicks)
+}
+
+//通道2不可用时改为测量PIT通道0的时钟中断，把经过的TSC周期数换算为CALIBRATE_MS毫秒的周期数
+//需要中断已经开启，精度受滴答间隔限制
+fn sample_ticks() -> Result<u64, TscError> {
+    if !interrupts::are_enabled() {
+        return Err(TscError::NoTimerTicks);
+    }
+    let frequency = u64::from(super::frequency());
+    let ticks = (frequency * u64::from(CALIBRATE_MS)).div_ceil(1000);
+    //从滴答的边沿开始计时
+    wait_tick()?;
+    let start = rdtsc();
+    for _ in 0..ticks {
+        wait_tick()?;
+    }
+    let cycles = (rdtsc() - start) as u128;
+    let elapsed_us = u128::from(ticks) * 1_000_000 / u128::from(frequency);
+    Ok((cycles * u128::from(CALIBRATE_MS) * 1000 / elapsed_us) as u64)
+}
+
+//按参考时钟测量一次，PIT通道2失败时退回到时钟中断
+fn sample(reference: Reference) -> Result<u64, TscError> {
+    match reference {
+        Reference::Pit => sample_pit().or_else(|_| sample_ticks()),
+        Reference::Hpet => Ok(sample_hpet()),
+    }
+}
+
+//轮询HPET主计数器CALIBRATE_MS毫秒，测量期间经过的TSC周期数
+fn sample_hpet() -> u64 {
+    let ns = u64::from(CALIBRATE_MS) * 1_000_000;
+    interrupts::without_interrupts(|| {
+        let start_ns = crate::hpet::now_ns().unwrap_or(0);
+        let start = rdtsc();
+        loop {
+            let elapsed = crate::hpet::now_ns().unwrap_or(u64::MAX) - start_ns;
+            if elapsed >= ns {
+                //轮询的最后一次读取可能超出目标，按实际经过的时间换算
+                return ((rdtsc() - start) as u128 * ns as u128 / elapsed as u128) as u64;
+            }
+            core::hint::spin_loop();
+        }
+    })
+}
+
+/// ## 函数说明
+/// 对参考时钟测量几次每毫秒的TSC周期数并取中位数，不修改已保存的结果
+/// 每次调用需要约50ms，比较两次校准的结果可以判断TSC是否稳定
+/// PIT通道2没有响应时改用时钟中断测量，两者都失败时返回错误
+pub fn calibrate() -> Result<u64, TscError> {
+    let reference = reference();
+    let mut samples = [0u64; CALIBRATE_ROUNDS];
+    for slot in samples.iter_mut() {
+        *slot = sample(reference)?;
+    }
+    samples.sort_unstable();
+    Ok((samples[CALIBRATE_ROUNDS / 2] / u64::from(CALIBRATE_MS)).max(1))
+}
+
+/// ## 函数说明
+/// 校准TSC并保存结果，HPET已启用时以它为参考，否则使用PIT
+/// CPUID没有报告不变TSC时打印警告，此时TSC的频率可能随电源状态变化
+/// 校准失败时保留原来的结果
+/// 需要在`hpet::init`之后调用，由`os::init`调用
+///
+/// ## 用法
+/// ```rust
+/// let per_ms = time::tsc::init()?;
+/// ```
+pub fn init() -> Result<u64, TscError> {
+    if !crate::cpu::features().invariant_tsc {
+        crate::println!("warning: TSC is not invariant, TSC-based timing may drift");
+    }
+    let cycles = calibrate()?;
+    CYCLES_PER_MS.store(cycles, Ordering::SeqCst);
+    Ok(cycles)
+}
+
+/// ## 函数说明
+/// 每毫秒的TSC周期数，尚未校准时先使用PIT测量一次
+/// 测量失败时使用偏大的估计值`FALLBACK_CYCLES_PER_MS`
+pub fn cycles_per_ms() -> u64 {
+    match CYCLES_PER_MS.load(Ordering::SeqCst) {
+        0 => {
+            let cycles = sample(Reference::Pit).map_or(FALLBACK_CYCLES_PER_MS, |cycles| {
+                (cycles / u64::from(CALIBRATE_MS)).max(1)
+            });
+            CYCLES_PER_MS.store(cycles, Ordering::SeqCst);
+            cycles
+        }
+        cycles => cycles,
+    }
+}
+
+/// ## 函数说明
+/// 当前的TSC周期数
+pub fn now_cycles() -> u64 {
+    rdtsc()
+}
+
+/// ## 函数说明
+/// 把TSC周期数换算为纳秒，中间结果使用u128，不会溢出
+///
+/// ## 参数
+/// * `cycles` - 周期数
+pub fn cycles_to_ns(cycles: u64) -> u64 {
+    (cycles as u128 * 1_000_000 / cycles_per_ms() as u128) as u64
+}
+
+/// ## 函数说明
+/// 把纳秒换算为TSC周期数
+///
+/// ## 参数
+/// * `ns` - 纳秒数
+pub fn ns_to_cycles(ns: u64) -> u64 {
+    (ns as u128 * cycles_per_ms() as u128 / 1_000_000) as u64
+}
+
+/// ## 函数说明
+/// 上电以来经过的纳秒数，按当前的校准结果换算
+pub fn now_ns() -> u64 {
+    cycles_to_ns(now_cycles())
+}
+
+/// ## 函数说明
+/// 从`since`以来经过的纳秒数
+///
+/// ## 参数
+/// * `since` - 之前`now_cycles`的返回值
+///
+/// ## 用法
+/// ```rust
+/// let start = tsc::now_cycles();
+/// run_benchmark();
+/// serial_println!("{} ns", tsc::elapsed_ns(start));
+/// ```
+pub fn elapsed_ns(since: u64) -> u64 {
+    cycles_to_ns(now_cycles().saturating_sub(since))
+}
+
+#[test_case]
+fn test_ns_round_trip() {
+    let per_ms = cycles_per_ms();
+    assert_eq!(cycles_to_ns(per_ms), 1_000_000);
+    assert_eq!(ns_to_cycles(1_000_000), per_ms);
+    //每纳秒的周期数不是整数，往返换算的误差不超过一纳秒对应的周期数
+    for cycles in [1, 999, 123_456_789, u64::MAX / 4096] {
+        let back = ns_to_cycles(cycles_to_ns(cycles));
+        assert!(back <= cycles);
+        assert!(cycles - back <= per_ms / 1_000_000 + 1);
+    }
+}
+
+#[test_case]
+fn test_calibrations_agree() {
+    let first = calibrate().unwrap();
+    let second = calibrate().unwrap();
+    //两次测量在真实硬件上相差不超过5%；QEMU的TCG模式下TSC和参考时钟都由宿主机模拟，放宽到25%
+    let divisor = if crate::cpu::features().hypervisor {
+        4
+    } else {
+        20
+    };
+    assert!(first.abs_diff(second) * divisor <= first.max(second));
+    assert!(cycles_per_ms().abs_diff(first) * divisor <= first.max(cycles_per_ms()));
+}
+
+#[test_case]
+fn test_elapsed_ns() {
+    let start = now_cycles();
+    super::sleep_busy_us(1000);
+    let elapsed = elapsed_ns(start);
+    assert!(elapsed >= 1_000_000);
+    assert!(elapsed < 1_000_000_000);
+    assert!(now_ns() >= elapsed);
+}
@@ -2,9 +2,14 @@ use core::fmt;
 use lazy_static::lazy_static; //延迟初始化
 use spin::Mutex; //自旋锁
 use volatile::Volatile; //引入Volatile类型，该类型会告诉编译器优化写入Buffer会产生负效应
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::{FrameAllocator, OffsetPageTable, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
 
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+use crate::memory::MapError;
+
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
 
 /// ## 说明
 /// VGA颜色枚举类型
@@ -30,11 +35,42 @@ pub enum Color {
     White,
 }
 
+impl Color {
+    /// ## 函数说明
+    /// 颜色在标准VGA调色板中的(红, 绿, 蓝)值，用于在帧缓冲区上显示相同的颜色
+    pub fn rgb(self) -> (u8, u8, u8) {
+        RGB[self as usize]
+    }
+}
+
+//按Color的顺序排列的标准VGA调色板
+const RGB: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0xaa),
+    (0x00, 0xaa, 0x00),
+    (0x00, 0xaa, 0xaa),
+    (0xaa, 0x00, 0x00),
+    (0xaa, 0x00, 0xaa),
+    (0xaa, 0x55, 0x00),
+    (0xaa, 0xaa, 0xaa),
+    (0x55, 0x55, 0x55),
+    (0x55, 0x55, 0xff),
+    (0x55, 0xff, 0x55),
+    (0x55, 0xff, 0xff),
+    (0xff, 0x55, 0x55),
+    (0xff, 0x55, 0xff),
+    (0xff, 0xff, 0x55),
+    (0xff, 0xff, 0xff),
+];
+
 /// ## 说明
 /// ColorCode 颜色代码字节包装类型
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)] //确保类型和它的单个成员有相同的内存布局
-struct ColorCode(u8);
+pub struct ColorCode(u8);
+
+/// 控制台默认的颜色：黑底黄字
+pub const DEFAULT_COLOR: ColorCode = ColorCode::new(Color::Yellow, Color::Black);
 
 impl ColorCode {
     /// ## 函数说明
@@ -42,9 +78,18 @@ impl ColorCode {
     /// ## 参数
     ///  * `foreground:Color` - 前景色
     ///  * `background:Color` - 背景色
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    pub const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
+
+    /// ## 函数说明
+    /// 前景色和背景色的(红, 绿, 蓝)值
+    pub fn rgb(self) -> ((u8, u8, u8), (u8, u8, u8)) {
+        (
+            RGB[usize::from(self.0 & 0x0f)],
+            RGB[usize::from(self.0 >> 4)],
+        )
+    }
 }
 
 /// ## 说明
@@ -70,58 +115,61 @@ struct Buffer {
 }
 
 /// ## 说明
-/// Writer类型写屏幕最后一行，并在一行写满或者接受换行符'\n'所有字符向上移动一行
-///
-/// ## 成员
-/// * `column_position` - 跟踪最后一行位置
-/// * `color_code` - 前景色和背景色
-/// * `buffer` - VGA字符缓冲区的可变借用
-pub struct Writer {
-    column_position: usize,
-    color_code: ColorCode,
-    buffer: &'static mut Buffer,
-}
+/// 文字控制台的公共接口，由VGA文本缓冲区的Writer和帧缓冲区的`framebuffer::FbWriter`实现，
+/// `print!`使用启动时检测到的那一个
+pub trait Console: fmt::Write {
+    /// ## 函数说明
+    /// 在最后一行打印一个字节，行满时换行；'\n'换行
+    ///
+    /// ## 参数
+    /// * `byte` - 被打印的字符
+    fn write_byte(&mut self, byte: u8);
 
-impl Writer {
     /// ## 函数说明
-    /// 打印字符，检测行是否已满，满则换行
-    /// 如果是换行符，调用new_line方法换行
-    /// 如果不是换行则打印字符
+    /// 设置之后打印的字符的颜色
     ///
     /// ## 参数
+    /// * `color_code` - 前景色和背景色
+    fn set_color(&mut self, color_code: ColorCode);
+
+    /// ## 函数说明
+    /// 通过调用循环调用write_byte方法打印字符串，不能打印的字节显示为0xfe
     ///
-    /// * `byte` - 被打印的字符
+    /// ## 参数
+    /// * `str` - 被打印的字符串
     ///
     /// ## 用法
-    ///
     /// ```rust
-    /// Writer.write_byte('x');
+    /// Writer.write_string("Genshin,Starting");
     /// ```
-
-    pub fn write_byte(&mut self, byte: u8) {
-        match byte {
-            b'\n' => self.new_line(),
-            byte => {
-                //检查是否行已满，是则换行
-                if self.column_position >= BUFFER_WIDTH {
-                    self.new_line();
-                }
-
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.column_position;
-
-                let color_code = self.color_code;
-
-                self.buffer.chars[row][col].write(ScreenChar {
-                    ascii_character: byte,
-                    color_code,
-                });
-
-                self.column_position += 1;
+    fn write_string(&mut self, str: &str) {
+        for byte in str.bytes() {
+            match byte {
+                // 可以是能打印的 ASCII 码字节，也可以是换行符
+                0x20..=0x7e | b'\n' => self.write_byte(byte),
+                // 不包含在上述范围之内的字节
+                _ => self.write_byte(0xfe),
             }
         }
     }
+}
+
+/// ## 说明
+/// Writer类型写屏幕最后一行，并在一行写满或者接受换行符'\n'所有字符向上移动一行
+///
+/// ## 成员
+/// * `column_position` - 跟踪最后一行位置
+/// * `color_code` - 前景色和背景色
+/// * `buffer` - VGA字符缓冲区的可变借用
+/// * `hardware` - 图形模式期间保存的VGA字符缓冲区，此时`buffer`指向内存中的影子缓冲区
+pub struct Writer {
+    column_position: usize,
+    color_code: ColorCode,
+    buffer: &'static mut Buffer,
+    hardware: Option<&'static mut Buffer>,
+}
 
+impl Writer {
     /// ## 函数说明
     /// 换行方法，本质上向上移动一行
     ///
@@ -163,27 +211,51 @@ impl Writer {
             self.buffer.chars[row][col].write(blank);
         }
     }
+}
 
+impl Console for Writer {
     /// ## 函数说明
-    /// 通过调用循环调用write_byte方法打印字符串
+    /// 打印字符，检测行是否已满，满则换行
+    /// 如果是换行符，调用new_line方法换行
+    /// 如果不是换行则打印字符
     ///
     /// ## 参数
-    /// * `str` - 被打印的字符串
+    ///
+    /// * `byte` - 被打印的字符
     ///
     /// ## 用法
+    ///
     /// ```rust
-    /// Writer.write_string("Genshin,Starting");
+    /// Writer.write_byte('x');
     /// ```
-    pub fn write_string(&mut self, str: &str) {
-        for byte in str.bytes() {
-            match byte {
-                // 可以是能打印的 ASCII 码字节，也可以是换行符
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // 不包含在上述范围之内的字节
-                _ => self.write_byte(0xfe),
+
+    fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            byte => {
+                //检查是否行已满，是则换行
+                if self.column_position >= BUFFER_WIDTH {
+                    self.new_line();
+                }
+
+                let row = BUFFER_HEIGHT - 1;
+                let col = self.column_position;
+
+                let color_code = self.color_code;
+
+                self.buffer.chars[row][col].write(ScreenChar {
+                    ascii_character: byte,
+                    color_code,
+                });
+
+                self.column_position += 1;
             }
         }
     }
+
+    fn set_color(&mut self, color_code: ColorCode) {
+        self.color_code = color_code;
+    }
 }
 
 //支持Rust提供的格式化宏
@@ -198,8 +270,136 @@ impl fmt::Write for Writer {
 lazy_static! {
     pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
         column_position: 0,
-        color_code: ColorCode::new(Color::Yellow, Color::Black),
+        color_code: DEFAULT_COLOR,
         buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+        hardware: None,
+    });
+}
+
+//图形模式期间WRITER写入的影子缓冲区，ScreenChar只需要1字节对齐
+static mut SHADOW: [u8; core::mem::size_of::<Buffer>()] = [0; core::mem::size_of::<Buffer>()];
+
+//把`from`的全部字符复制到`to`
+fn copy_buffer(from: &Buffer, to: &mut Buffer) {
+    for (src, dst) in from.chars.iter().zip(to.chars.iter_mut()) {
+        for (src, dst) in src.iter().zip(dst.iter_mut()) {
+            dst.write(src.read());
+        }
+    }
+}
+
+/// ## 函数说明
+/// 切换到图形模式之前调用：把屏幕内容复制到影子缓冲区，之后的输出都写入影子缓冲区，
+/// 不再访问VGA内存。已经暂停时什么都不做
+pub fn suspend() {
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        if writer.hardware.is_some() {
+            return;
+        }
+        let shadow = unsafe { &mut *core::ptr::addr_of_mut!(SHADOW).cast::<Buffer>() };
+        copy_buffer(writer.buffer, shadow);
+        let hardware = core::mem::replace(&mut writer.buffer, shadow);
+        writer.hardware = Some(hardware);
+    });
+}
+
+/// ## 函数说明
+/// 回到文本模式之后调用：把影子缓冲区（包括暂停期间的输出）写回屏幕，恢复直接输出
+/// 没有暂停时什么都不做
+pub fn resume() {
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        if let Some(hardware) = writer.hardware.take() {
+            copy_buffer(writer.buffer, hardware);
+            writer.buffer = hardware;
+        }
+    });
+}
+
+/// ## 函数说明
+/// 输出是否因为图形模式而暂停
+pub fn is_suspended() -> bool {
+    interrupts::without_interrupts(|| WRITER.lock().hardware.is_some())
+}
+
+/// ## 函数说明
+/// 将VGA帧映射到MMIO窗口中的高地址，并在同一个临界区内把WRITER的缓冲区切换到新地址，返回新地址
+/// 新旧地址指向同一物理帧，切换前后的输出不会丢失或重复；此后0xb8000的恒等映射可以被移除
+///
+/// ## 参数
+/// * `mapper` - 页表
+/// * `frame_allocator` - 帧分配器
+///
+/// ## 用法
+/// ```rust
+/// let vga = remap(&mut mapper, &mut frame_allocator)?;
+/// ```
+pub fn remap(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<VirtAddr, MapError> {
+    let size = core::mem::size_of::<Buffer>();
+    let phys = PhysAddr::new(crate::memory::VGA_FRAME);
+    let virt = crate::memory::map_physical_region(phys, size, mapper, frame_allocator)?;
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        let buffer = unsafe { &mut *virt.as_mut_ptr::<Buffer>() };
+        //暂停期间只更换保存的硬件缓冲区，恢复时再写回
+        match writer.hardware.as_mut() {
+            Some(hardware) => *hardware = buffer,
+            None => writer.buffer = buffer,
+        }
+    });
+    Ok(virt)
+}
+
+/// ## 函数说明
+/// VGA字符缓冲区的地址，暂停输出期间也返回VGA内存而不是影子缓冲区
+pub fn buffer_addr() -> VirtAddr {
+    interrupts::without_interrupts(|| {
+        let writer = WRITER.lock();
+        match writer.hardware.as_deref() {
+            Some(hardware) => VirtAddr::from_ptr(hardware),
+            None => VirtAddr::from_ptr(&*writer.buffer),
+        }
+    })
+}
+
+/// ## 函数说明
+/// 读回屏幕上一行的ASCII字符
+///
+/// ## 参数
+/// * `row` - 行号，0为第一行，`BUFFER_HEIGHT - 1`为正在写入的最后一行
+///
+/// ## 用法
+/// ```rust
+/// let last = read_row(BUFFER_HEIGHT - 1);
+/// ```
+pub fn read_row(row: usize) -> [u8; BUFFER_WIDTH] {
+    interrupts::without_interrupts(|| {
+        let writer = WRITER.lock();
+        let mut line = [0; BUFFER_WIDTH];
+        for (col, byte) in line.iter_mut().enumerate() {
+            *byte = writer.buffer.chars[row][col].read().ascii_character;
+        }
+        line
+    })
+}
+
+/// ## 函数说明
+/// 交换屏幕上一个字符的前景色和背景色，再调用一次即可恢复，可用作光标
+///
+/// ## 参数
+/// * `row` - 行号，小于`BUFFER_HEIGHT`
+/// * `col` - 列号，小于`BUFFER_WIDTH`
+pub fn invert_cell(row: usize, col: usize) {
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        let cell = &mut writer.buffer.chars[row][col];
+        let mut char = cell.read();
+        char.color_code = ColorCode(char.color_code.0.rotate_left(4));
+        cell.write(char);
     });
 }
 
@@ -216,17 +416,47 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+/// 与`print!`相同，但WRITER已被锁定时放弃输出而不是等待，返回是否已输出
+/// 供NMI等可能打断持锁代码的处理函数使用
+#[macro_export]
+macro_rules! try_print {
+    ($($arg:tt)*) => ($crate::vga_buffer::_try_print(format_args!($($arg)*)));
+}
+
+/// 与`println!`相同，但WRITER已被锁定时放弃输出
+#[macro_export]
+macro_rules! try_println {
+    () => ($crate::try_print!("\n"));
+    ($($arg:tt)*) => ($crate::try_print!("{}\n", format_args!($($arg)*)));
+}
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
 
     //在Mutex被锁定时禁用中断，防止死锁
-    use x86_64::instructions::interrupts;
     interrupts::without_interrupts(|| {
-        WRITER.lock().write_fmt(args).unwrap();
+        if crate::framebuffer::print(args, true).is_none() {
+            WRITER.lock().write_fmt(args).unwrap();
+        }
     });
 }
 
+#[doc(hidden)]
+pub fn _try_print(args: fmt::Arguments) -> bool {
+    use core::fmt::Write;
+
+    interrupts::without_interrupts(|| {
+        if let Some(printed) = crate::framebuffer::print(args, false) {
+            return printed;
+        }
+        match WRITER.try_lock() {
+            Some(mut writer) => writer.write_fmt(args).is_ok(),
+            None => false,
+        }
+    })
+}
+
 /* ---------------测试------------------ */
 
 #[test_case]
@@ -244,7 +474,6 @@ fn test_println_many() {
 #[test_case]
 fn test_println_output() {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
 
     let s = "Some test string that fits on a single line";
     //避免死锁，禁用中断
@@ -268,3 +497,52 @@ fn test_println_output() {
         }
     });
 }
+
+#[test_case]
+fn test_suspend_replays_output() {
+    let before = "line printed before suspend";
+    let during = "line printed while suspended";
+    println!("{}", before);
+    let hardware = buffer_addr();
+    suspend();
+    assert!(is_suspended());
+    assert_eq!(buffer_addr(), hardware);
+    println!("{}", during);
+    //VGA内存没有被修改
+    let vga = unsafe { &*hardware.as_ptr::<Buffer>() };
+    let last = vga.chars[BUFFER_HEIGHT - 2][0].read().ascii_character;
+    assert_eq!(last, before.as_bytes()[0]);
+    //影子缓冲区中可以读到暂停期间的输出
+    assert_eq!(
+        &read_row(BUFFER_HEIGHT - 2)[..during.len()],
+        during.as_bytes()
+    );
+
+    resume();
+    assert!(!is_suspended());
+    let first = read_row(BUFFER_HEIGHT - 3);
+    let second = read_row(BUFFER_HEIGHT - 2);
+    assert_eq!(&first[..before.len()], before.as_bytes());
+    assert_eq!(&second[..during.len()], during.as_bytes());
+}
+
+#[test_case]
+fn test_remap_keeps_output() {
+    let before = "line printed before remap";
+    let after = "line printed after remap";
+    println!("{}", before);
+    let old = buffer_addr();
+    let new =
+        crate::memory::with_paging(|paging| remap(&mut paging.mapper, &mut paging.frame_allocator))
+            .expect("paging not installed")
+            .expect("remap failed");
+    assert_ne!(old, new);
+    assert_eq!(buffer_addr(), new);
+    println!("{}", after);
+
+    //先打印的一行被上移，两行都在新的映射中可见
+    let first = read_row(BUFFER_HEIGHT - 3);
+    let second = read_row(BUFFER_HEIGHT - 2);
+    assert_eq!(&first[..before.len()], before.as_bytes());
+    assert_eq!(&second[..after.len()], after.as_bytes());
+}
@@ -0,0 +1,493 @@
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::memory::MapError;
+use crate::vga_buffer;
+
+/// 模式13h的宽度和高度（像素），每个像素1字节，为调色板中的下标
+pub const WIDTH: usize = 320;
+pub const HEIGHT: usize = 200;
+
+//图形模式的显存窗口，同时用于在文本模式下访问字体所在的平面2
+const GRAPHICS_FRAME: u64 = 0xa0000;
+const WINDOW_SIZE: usize = 0x10000;
+//字体：256个字符，每个字符在平面2中占32字节
+const FONT_SIZE: usize = 256 * 32;
+const PALETTE_SIZE: usize = 256 * 3;
+
+//VGA寄存器端口
+const MISC_WRITE: u16 = 0x3c2;
+const SEQ_INDEX: u16 = 0x3c4;
+const SEQ_DATA: u16 = 0x3c5;
+const DAC_READ_INDEX: u16 = 0x3c7;
+const DAC_WRITE_INDEX: u16 = 0x3c8;
+const DAC_DATA: u16 = 0x3c9;
+const GC_INDEX: u16 = 0x3ce;
+const GC_DATA: u16 = 0x3cf;
+const CRTC_INDEX: u16 = 0x3d4;
+const CRTC_DATA: u16 = 0x3d5;
+const AC_INDEX: u16 = 0x3c0;
+//读取这个端口会把属性控制器的触发器复位到索引状态
+const INPUT_STATUS_1: u16 = 0x3da;
+//写入属性控制器索引时置位，重新打开显示
+const AC_PALETTE_ENABLE: u8 = 0x20;
+//CRTC寄存器0x11的位7为0~7号寄存器的写保护，寄存器0x03的位7必须为1
+const CRTC_PROTECT: u8 = 0x80;
+
+/*
+    两种模式的寄存器值：杂项输出、5个定序器、25个CRTC、9个图形控制器、21个属性控制器寄存器
+    取自标准VGA BIOS设置的模式13h和模式03h
+*/
+struct ModeRegisters {
+    misc: u8,
+    seq: [u8; 5],
+    crtc: [u8; 25],
+    gc: [u8; 9],
+    ac: [u8; 21],
+}
+
+const MODE_13H: ModeRegisters = ModeRegisters {
+    misc: 0x63,
+    seq: [0x03, 0x01, 0x0f, 0x00, 0x0e],
+    crtc: [
+        0x5f, 0x4f, 0x50, 0x82, 0x54, 0x80, 0xbf, 0x1f, 0x00, 0x41, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x9c, 0x0e, 0x8f, 0x28, 0x40, 0x96, 0xb9, 0xa3, 0xff,
+    ],
+    gc: [0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x05, 0x0f, 0xff],
+    ac: [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x41, 0x00, 0x0f, 0x00, 0x00,
+    ],
+};
+
+const MODE_TEXT_80X25: ModeRegisters = ModeRegisters {
+    misc: 0x67,
+    seq: [0x03, 0x00, 0x03, 0x00, 0x02],
+    crtc: [
+        0x5f, 0x4f, 0x50, 0x82, 0x55, 0x81, 0xbf, 0x1f, 0x00, 0x4f, 0x0d, 0x0e, 0x00, 0x00, 0x00,
+        0x50, 0x9c, 0x0e, 0x8f, 0x28, 0x1f, 0x96, 0xb9, 0xa3, 0xff,
+    ],
+    gc: [0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x0e, 0x00, 0xff],
+    ac: [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x14, 0x07, 0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e,
+        0x3f, 0x0c, 0x00, 0x0f, 0x08, 0x00,
+    ],
+};
+
+/// ## 说明
+/// 切换显示模式时可能出现的错误
+#[derive(Debug)]
+pub enum GraphicsError {
+    /// 已经处于图形模式
+    AlreadyActive,
+    /// 不在图形模式中
+    NotActive,
+    /// 映射显存窗口失败
+    Map(MapError),
+}
+
+//切换模式时保存的文本模式状态
+struct State {
+    //显存窗口映射后的虚拟地址，只映射一次
+    window: Option<VirtAddr>,
+    active: bool,
+    font: [u8; FONT_SIZE],
+    palette: [u8; PALETTE_SIZE],
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    window: None,
+    active: false,
+    font: [0; FONT_SIZE],
+    palette: [0; PALETTE_SIZE],
+});
+
+unsafe fn outb(port: u16, value: u8) {
+    Port::<u8>::new(port).write(value);
+}
+
+unsafe fn inb(port: u16) -> u8 {
+    Port::<u8>::new(port).read()
+}
+
+unsafe fn write_indexed(index_port: u16, data_port: u16, index: u8, value: u8) {
+    outb(index_port, index);
+    outb(data_port, value);
+}
+
+//按顺序写入一种模式的全部寄存器
+unsafe fn write_registers(mode: &ModeRegisters) {
+    outb(MISC_WRITE, mode.misc);
+    for (i, &value) in mode.seq.iter().enumerate() {
+        write_indexed(SEQ_INDEX, SEQ_DATA, i as u8, value);
+    }
+
+    //先解除CRTC 0~7号寄存器的写保护，表中的值也要保持解除状态，否则写到0x11时会重新加锁
+    outb(CRTC_INDEX, 0x03);
+    let value = inb(CRTC_DATA);
+    outb(CRTC_DATA, value | CRTC_PROTECT);
+    outb(CRTC_INDEX, 0x11);
+    let value = inb(CRTC_DATA);
+    outb(CRTC_DATA, value & !CRTC_PROTECT);
+    for (i, &value) in mode.crtc.iter().enumerate() {
+        let value = match i {
+            0x03 => value | CRTC_PROTECT,
+            0x11 => value & !CRTC_PROTECT,
+            _ => value,
+        };
+        write_indexed(CRTC_INDEX, CRTC_DATA, i as u8, value);
+    }
+
+    for (i, &value) in mode.gc.iter().enumerate() {
+        write_indexed(GC_INDEX, GC_DATA, i as u8, value);
+    }
+
+    //属性控制器的索引和数据使用同一个端口，由触发器区分
+    for (i, &value) in mode.ac.iter().enumerate() {
+        inb(INPUT_STATUS_1);
+        outb(AC_INDEX, i as u8);
+        outb(AC_INDEX, value);
+    }
+    inb(INPUT_STATUS_1);
+    outb(AC_INDEX, AC_PALETTE_ENABLE);
+}
+
+//在文本模式下让显存窗口只访问平面2，用于读写字体
+unsafe fn select_font_plane() {
+    write_indexed(SEQ_INDEX, SEQ_DATA, 0x02, 0x04); //只写平面2
+    write_indexed(SEQ_INDEX, SEQ_DATA, 0x04, 0x07); //顺序寻址，关闭奇偶模式
+    write_indexed(GC_INDEX, GC_DATA, 0x04, 0x02); //读平面2
+    write_indexed(GC_INDEX, GC_DATA, 0x05, 0x00); //关闭奇偶模式
+    write_indexed(GC_INDEX, GC_DATA, 0x06, 0x04); //窗口为0xa0000开始的64KiB
+}
+
+//恢复文本模式下的平面访问方式，与MODE_TEXT_80X25中的值相同
+unsafe fn select_text_planes() {
+    write_indexed(SEQ_INDEX, SEQ_DATA, 0x02, MODE_TEXT_80X25.seq[2]);
+    write_indexed(SEQ_INDEX, SEQ_DATA, 0x04, MODE_TEXT_80X25.seq[4]);
+    write_indexed(GC_INDEX, GC_DATA, 0x04, MODE_TEXT_80X25.gc[4]);
+    write_indexed(GC_INDEX, GC_DATA, 0x05, MODE_TEXT_80X25.gc[5]);
+    write_indexed(GC_INDEX, GC_DATA, 0x06, MODE_TEXT_80X25.gc[6]);
+}
+
+unsafe fn read_palette(palette: &mut [u8; PALETTE_SIZE]) {
+    outb(DAC_READ_INDEX, 0);
+    for component in palette.iter_mut() {
+        *component = inb(DAC_DATA);
+    }
+}
+
+unsafe fn write_palette(palette: &[u8; PALETTE_SIZE]) {
+    outb(DAC_WRITE_INDEX, 0);
+    for &component in palette.iter() {
+        outb(DAC_DATA, component);
+    }
+}
+
+fn map_window() -> Result<VirtAddr, MapError> {
+    crate::memory::with_paging(|paging| {
+        crate::memory::map_physical_region(
+            PhysAddr::new(GRAPHICS_FRAME),
+            WINDOW_SIZE,
+            &mut paging.mapper,
+            &mut paging.frame_allocator,
+        )
+    })
+    .unwrap_or(Err(MapError::NoPaging))
+}
+
+/// ## 函数说明
+/// 切换到320x200、256色的模式13h并清屏。先暂停文本输出，保存字体和DAC调色板，
+/// 之后的`print!`写入内存中的缓冲区，在[`exit_to_text_mode`]时显示
+/// 需要在`os::init`之后调用
+///
+/// ## 用法
+/// ```rust
+/// vga_graphics::enter_mode_13h()?;
+/// vga_graphics::fill_rect(10, 10, 100, 50, 4);
+/// ```
+pub fn enter_mode_13h() -> Result<(), GraphicsError> {
+    let mut state = STATE.lock();
+    if state.active {
+        return Err(GraphicsError::AlreadyActive);
+    }
+    let window = match state.window {
+        Some(window) => window,
+        None => {
+            let window = map_window().map_err(GraphicsError::Map)?;
+            state.window = Some(window);
+            window
+        }
+    };
+
+    vga_buffer::suspend();
+    interrupts::without_interrupts(|| unsafe {
+        let font = window.as_ptr::<u8>();
+        select_font_plane();
+        for (i, byte) in state.font.iter_mut().enumerate() {
+            *byte = font.add(i).read_volatile();
+        }
+        select_text_planes();
+        read_palette(&mut state.palette);
+
+        write_registers(&MODE_13H);
+        let pixels = window.as_mut_ptr::<u8>();
+        for i in 0..WIDTH * HEIGHT {
+            pixels.add(i).write_volatile(0);
+        }
+    });
+    state.active = true;
+    Ok(())
+}
+
+/// ## 函数说明
+/// 回到80x25文本模式：恢复寄存器、字体和调色板，再把文本缓冲区（包括图形模式期间的输出）写回屏幕
+///
+/// ## 用法
+/// ```rust
+/// vga_graphics::exit_to_text_mode()?;
+/// println!("back in text mode");
+/// ```
+pub fn exit_to_text_mode() -> Result<(), GraphicsError> {
+    let mut state = STATE.lock();
+    let window = match (state.active, state.window) {
+        (true, Some(window)) => window,
+        _ => return Err(GraphicsError::NotActive),
+    };
+    interrupts::without_interrupts(|| unsafe {
+        write_registers(&MODE_TEXT_80X25);
+        //图形模式覆盖了平面2，重新写入字体
+        let font = window.as_mut_ptr::<u8>();
+        select_font_plane();
+        for (i, &byte) in state.font.iter().enumerate() {
+            font.add(i).write_volatile(byte);
+        }
+        select_text_planes();
+        write_palette(&state.palette);
+    });
+    state.active = false;
+    drop(state);
+    vga_buffer::resume();
+    Ok(())
+}
+
+/// ## 函数说明
+/// 是否处于图形模式
+pub fn is_active() -> bool {
+    STATE.lock().active
+}
+
+/// ## 说明
+/// 矩形与屏幕相交的部分
+///
+/// ## 成员
+/// * `x`, `y` - 屏幕上的左上角
+/// * `src_x`, `src_y` - 对应的矩形内的偏移，左边或上边超出屏幕时不为0
+/// * `width`, `height` - 可见部分的大小
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clip {
+    pub x: usize,
+    pub y: usize,
+    pub src_x: usize,
+    pub src_y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// ## 函数说明
+/// 把左上角在(`x`, `y`)、大小为`width`x`height`的矩形裁剪到屏幕内，完全不可见时返回None
+///
+/// ## 参数
+/// * `x`, `y` - 左上角，可以为负数
+/// * `width`, `height` - 矩形大小
+pub fn clip(x: i32, y: i32, width: usize, height: usize) -> Option<Clip> {
+    fn axis(pos: i32, len: usize, limit: usize) -> Option<(usize, usize, usize)> {
+        let start = i64::from(pos).max(0);
+        let len = i64::try_from(len).unwrap_or(i64::MAX);
+        let end = i64::from(pos).saturating_add(len).min(limit as i64);
+        (start < end).then(|| {
+            let skip = (start - i64::from(pos)) as usize;
+            (start as usize, skip, (end - start) as usize)
+        })
+    }
+    let (x, src_x, width) = axis(x, width, WIDTH)?;
+    let (y, src_y, height) = axis(y, height, HEIGHT)?;
+    Some(Clip {
+        x,
+        y,
+        src_x,
+        src_y,
+        width,
+        height,
+    })
+}
+
+//在图形模式下以显存地址调用`f`，不在图形模式时什么都不做
+fn with_pixels<R>(f: impl FnOnce(*mut u8) -> R) -> Option<R> {
+    let state = STATE.lock();
+    match (state.active, state.window) {
+        (true, Some(window)) => Some(f(window.as_mut_ptr())),
+        _ => None,
+    }
+}
+
+/// ## 函数说明
+/// 设置一个像素，超出屏幕或不在图形模式时忽略
+///
+/// ## 参数
+/// * `x`, `y` - 坐标
+/// * `color` - 调色板下标
+pub fn put_pixel(x: usize, y: usize, color: u8) {
+    if x < WIDTH && y < HEIGHT {
+        with_pixels(|pixels| unsafe { pixels.add(y * WIDTH + x).write_volatile(color) });
+    }
+}
+
+/// ## 函数说明
+/// 读取一个像素，超出屏幕或不在图形模式时返回None
+///
+/// ## 参数
+/// * `x`, `y` - 坐标
+pub fn get_pixel(x: usize, y: usize) -> Option<u8> {
+    if x >= WIDTH || y >= HEIGHT {
+        return None;
+    }
+    with_pixels(|pixels| unsafe { pixels.add(y * WIDTH + x).read_volatile() })
+}
+
+/// ## 函数说明
+/// 填充矩形，超出屏幕的部分被裁剪
+///
+/// ## 参数
+/// * `x`, `y` - 左上角，可以为负数
+/// * `width`, `height` - 矩形大小
+/// * `color` - 调色板下标
+pub fn fill_rect(x: i32, y: i32, width: usize, height: usize, color: u8) {
+    let Some(clip) = clip(x, y, width, height) else {
+        return;
+    };
+    with_pixels(|pixels| {
+        for row in clip.y..clip.y + clip.height {
+            for col in clip.x..clip.x + clip.width {
+                unsafe { pixels.add(row * WIDTH + col).write_volatile(color) };
+            }
+        }
+    });
+}
+
+/// ## 函数说明
+/// 把按行存放的`width`x`height`图像复制到屏幕上，超出屏幕的部分被裁剪
+///
+/// ## 参数
+/// * `image` - 像素数据，长度至少为`width * height`
+/// * `width`, `height` - 图像大小
+/// * `x`, `y` - 屏幕上的左上角，可以为负数
+///
+/// ## 用法
+/// ```rust
+/// vga_graphics::blit(&LOGO, 64, 64, 128, 68);
+/// ```
+pub fn blit(image: &[u8], width: usize, height: usize, x: i32, y: i32) {
+    assert!(
+        image.len() >= width * height,
+        "image has {} bytes, {}x{} needs {}",
+        image.len(),
+        width,
+        height,
+        width * height
+    );
+    let Some(clip) = clip(x, y, width, height) else {
+        return;
+    };
+    with_pixels(|pixels| {
+        for row in 0..clip.height {
+            let src = &image[(clip.src_y + row) * width + clip.src_x..][..clip.width];
+            let dst = (clip.y + row) * WIDTH + clip.x;
+            for (i, &color) in src.iter().enumerate() {
+                unsafe { pixels.add(dst + i).write_volatile(color) };
+            }
+        }
+    });
+}
+
+/// ## 函数说明
+/// 通过DAC设置全部256种颜色，每个分量为6位（0~63），更高的位被忽略
+/// 离开图形模式时会恢复进入前的调色板
+///
+/// ## 参数
+/// * `palette` - 每种颜色的(红, 绿, 蓝)
+pub fn set_palette(palette: &[(u8, u8, u8); 256]) {
+    interrupts::without_interrupts(|| unsafe {
+        outb(DAC_WRITE_INDEX, 0);
+        for &(r, g, b) in palette.iter() {
+            outb(DAC_DATA, r & 0x3f);
+            outb(DAC_DATA, g & 0x3f);
+            outb(DAC_DATA, b & 0x3f);
+        }
+    });
+}
+
+/// ## 函数说明
+/// 通过DAC读回全部256种颜色，每个分量为6位（0~63），文本模式下同样可用
+pub fn get_palette() -> [(u8, u8, u8); 256] {
+    let mut palette = [(0, 0, 0); 256];
+    interrupts::without_interrupts(|| unsafe {
+        outb(DAC_READ_INDEX, 0);
+        for (r, g, b) in palette.iter_mut() {
+            *r = inb(DAC_DATA);
+            *g = inb(DAC_DATA);
+            *b = inb(DAC_DATA);
+        }
+    });
+    palette
+}
+
+#[test_case]
+fn test_clip_inside() {
+    let clip = clip(10, 20, 30, 40).unwrap();
+    assert_eq!(
+        clip,
+        Clip {
+            x: 10,
+            y: 20,
+            src_x: 0,
+            src_y: 0,
+            width: 30,
+            height: 40
+        }
+    );
+}
+
+#[test_case]
+fn test_clip_edges() {
+    //左上角超出屏幕
+    let c = clip(-5, -8, 20, 10).unwrap();
+    assert_eq!(
+        (c.x, c.y, c.src_x, c.src_y, c.width, c.height),
+        (0, 0, 5, 8, 15, 2)
+    );
+    //右下角超出屏幕
+    let c = clip(310, 195, 20, 10).unwrap();
+    assert_eq!(
+        (c.x, c.y, c.src_x, c.src_y, c.width, c.height),
+        (310, 195, 0, 0, 10, 5)
+    );
+    //比屏幕大的矩形覆盖整个屏幕
+    let c = clip(-1, -1, 1000, 1000).unwrap();
+    assert_eq!((c.x, c.y, c.width, c.height), (0, 0, WIDTH, HEIGHT));
+}
+
+#[test_case]
+fn test_clip_invisible() {
+    assert_eq!(clip(320, 0, 10, 10), None);
+    assert_eq!(clip(0, 200, 10, 10), None);
+    assert_eq!(clip(-10, 0, 10, 10), None);
+    assert_eq!(clip(0, -10, 10, 10), None);
+    assert_eq!(clip(5, 5, 0, 10), None);
+    assert_eq!(clip(i32::MIN, 0, 10, 10), None);
+    //长度很大时不会溢出
+    let c = clip(i32::MIN, 0, usize::MAX, 1).unwrap();
+    assert_eq!((c.x, c.width, c.height), (0, WIDTH, 1));
+}
@@ -0,0 +1,135 @@
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+use crate::interrupts::{interrupt_count, InterruptIndex};
+use crate::time;
+
+//PS/2控制器的状态端口：位0为输出缓冲区已满，位5表示数据来自辅助设备（鼠标）
+const PS2_STATUS: u16 = 0x64;
+const OUTPUT_FULL: u8 = 1 << 0;
+const AUX_DATA: u8 = 1 << 5;
+
+//看门狗启用时的观察记录
+struct Armed {
+    timeout_ticks: u64,
+    //最近一次观察到的滴答数，以及滴答数增长时的TSC
+    tick: u64,
+    tick_tsc: u64,
+    //最近一次观察到的键盘中断次数，以及键盘中断次数增长或控制器中没有扫描码时的滴答数
+    keyboard: u64,
+    keyboard_tick: u64,
+}
+
+static WATCHDOG: Mutex<Option<Armed>> = Mutex::new(None);
+
+/// ## 函数说明
+/// 启用看门狗，以当前的滴答数为起点。此后[`check`]发现超过`timeout_ticks`个滴答的时间内
+/// 时钟中断没有到达，或者键盘控制器中有扫描码而键盘中断没有到达时panic
+/// 重复调用会重新开始计时
+///
+/// ## 参数
+/// * `timeout_ticks` - 超时的滴答数，按当前时钟频率换算为时间
+///
+/// ## 用法
+/// ```rust
+/// watchdog::arm(5 * u64::from(time::frequency())); //5秒
+/// ```
+pub fn arm(timeout_ticks: u64) {
+    time::tsc_per_ms(); //提前校准，避免在check中等待
+    interrupts::without_interrupts(|| {
+        let tick = time::ticks();
+        *WATCHDOG.lock() = Some(Armed {
+            timeout_ticks: timeout_ticks.max(1),
+            tick,
+            tick_tsc: time::rdtsc(),
+            keyboard: interrupt_count(InterruptIndex::Keyboard.as_u8()),
+            keyboard_tick: tick,
+        });
+    });
+}
+
+/// ## 函数说明
+/// 停用看门狗，之后的[`check`]不再做任何检查
+pub fn disarm() {
+    interrupts::without_interrupts(|| *WATCHDOG.lock() = None);
+}
+
+/// ## 函数说明
+/// 看门狗是否已启用
+pub fn is_armed() -> bool {
+    interrupts::without_interrupts(|| WATCHDOG.lock().is_some())
+}
+
+//键盘控制器的输出缓冲区中有等待读取的扫描码
+fn scancode_waiting() -> bool {
+    let status = unsafe { Port::<u8>::new(PS2_STATUS).read() };
+    status & OUTPUT_FULL != 0 && status & AUX_DATA == 0
+}
+
+//检查的结论，在释放锁之后再panic
+enum Verdict {
+    Ok,
+    NoTicks { ms: u64 },
+    KeyboardStarved { ticks: u64 },
+}
+
+/// ## 函数说明
+/// 在轮询循环中调用，看门狗未启用时什么都不做
+/// * 滴答数在超时时间内没有增长：时钟中断没有到达，可能是IF被清除或PIC屏蔽字错误
+/// * 滴答数增长但键盘中断次数没有增长，而控制器中一直有扫描码：IRQ1被屏蔽或缺少EOI
+///
+/// ## 用法
+/// ```rust
+/// loop {
+///     watchdog::check();
+///     //...
+/// }
+/// ```
+pub fn check() {
+    let if_set = interrupts::are_enabled();
+    let verdict = interrupts::without_interrupts(|| {
+        let mut watchdog = WATCHDOG.lock();
+        let state = match watchdog.as_mut() {
+            Some(state) => state,
+            None => return Verdict::Ok,
+        };
+        let tick = time::ticks();
+        if tick == state.tick {
+            //滴答数停止时无法用它计时，改用TSC
+            let ms = (time::rdtsc() - state.tick_tsc) / time::tsc_per_ms();
+            let timeout_ms = state.timeout_ticks * 1000 / u64::from(time::frequency());
+            return if ms > timeout_ms {
+                Verdict::NoTicks { ms }
+            } else {
+                Verdict::Ok
+            };
+        }
+        state.tick = tick;
+        state.tick_tsc = time::rdtsc();
+
+        let keyboard = interrupt_count(InterruptIndex::Keyboard.as_u8());
+        if keyboard != state.keyboard || !scancode_waiting() {
+            state.keyboard = keyboard;
+            state.keyboard_tick = tick;
+            return Verdict::Ok;
+        }
+        let stalled = tick - state.keyboard_tick;
+        if stalled > state.timeout_ticks {
+            Verdict::KeyboardStarved { ticks: stalled }
+        } else {
+            Verdict::Ok
+        }
+    });
+    match verdict {
+        Verdict::Ok => {}
+        Verdict::NoTicks { ms } => panic!(
+            "no timer ticks observed for {} ms \u{2014} interrupts stuck? (IF={})",
+            ms, if_set as u8
+        ),
+        Verdict::KeyboardStarved { ticks } => panic!(
+            "keyboard IRQ starved: scancode pending for {} ticks while the timer runs \u{2014} IRQ1 masked or EOI missing?",
+            ticks
+        ),
+    }
+}
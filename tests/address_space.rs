@@ -0,0 +1,101 @@
+//测试只在新地址空间中建立的映射在切换回原地址空间后不可访问
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use os::memory::{self, AddressSpace};
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::registers::control::Cr3;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, Translate};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("address_space::private_mapping...\t");
+
+    os::init_paging(boot_info);
+    os::gdt::init();
+    init_test_idt();
+
+    let mut space = AddressSpace::new().expect("no frame for address space");
+
+    //选择一个在当前P4中未使用的低半部分条目，其中的映射只属于新地址空间
+    let addr = memory::with_paging(|paging| {
+        let p4 = paging.mapper.level_4_table();
+        (1..256)
+            .find(|&i| p4[i].is_unused())
+            .map(|i| (i as u64) << 39)
+    })
+    .unwrap()
+    .expect("no unused P4 entry");
+    let page: Page = Page::containing_address(VirtAddr::new(addr));
+
+    memory::with_paging(|paging| {
+        let frame = paging.frame_allocator.allocate_frame().unwrap();
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        unsafe {
+            space
+                .mapper()
+                .map_to(page, frame, flags, &mut paging.frame_allocator)
+                .expect("map_to failed")
+                .ignore();
+        }
+    })
+    .unwrap();
+
+    let (original, original_flags) = Cr3::read();
+    let ptr: *mut u64 = page.start_address().as_mut_ptr();
+    unsafe {
+        space.activate();
+        ptr.write_volatile(0x_dead_beef);
+        assert_eq!(ptr.read_volatile(), 0x_dead_beef);
+        Cr3::write(original, original_flags);
+    }
+    assert_eq!(
+        memory::with_paging(|paging| paging.mapper.translate_addr(page.start_address())),
+        Some(None)
+    );
+
+    //销毁时归还P4以及为该映射新建的P3、P2、P1页表
+    let free_frames = || memory::with_paging(|p| p.frame_allocator.inner().free_frames()).unwrap();
+    let before = free_frames();
+    drop(space);
+    assert_eq!(free_frames(), before + 4);
+
+    unsafe { ptr.read_volatile() }; //应当触发页错误
+
+    serial_println!("[no page fault]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    _error_code: PageFaultErrorCode,
+) {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::memory::{self, AddressSpace, SwitchError};
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+fn new_space() -> AddressSpace {
+    AddressSpace::new().expect("no frame for address space")
+}
+
+#[test_case]
+fn closure_runs_in_target_space() {
+    let mut space = new_space();
+    //在当前P4未使用的条目中建立只属于新地址空间的映射
+    let addr = memory::with_paging(|paging| {
+        let p4 = paging.mapper.level_4_table();
+        (1..256)
+            .find(|&i| p4[i].is_unused())
+            .map(|i| (i as u64) << 39)
+    })
+    .unwrap()
+    .expect("no unused P4 entry");
+    let page: Page = Page::containing_address(VirtAddr::new(addr));
+    memory::with_paging(|paging| {
+        let frame = paging.frame_allocator.allocate_frame().unwrap();
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        unsafe {
+            space
+                .mapper()
+                .map_to(page, frame, flags, &mut paging.frame_allocator)
+                .expect("map_to failed")
+                .ignore();
+        }
+    })
+    .unwrap();
+
+    let before = Cr3::read();
+    let ptr: *mut u64 = page.start_address().as_mut_ptr();
+    let value = memory::with_address_space(&space, || unsafe {
+        assert_eq!(Cr3::read().0.start_address().as_u64(), space.cr3_value());
+        ptr.write_volatile(0x_1234_5678);
+        ptr.read_volatile()
+    });
+    assert_eq!(value, Ok(0x_1234_5678));
+    assert_eq!(Cr3::read(), before);
+}
+
+#[test_case]
+fn space_without_stack_is_rejected() {
+    let mut space = new_space();
+    //清除当前栈所在的P4条目，切换过去会立即引发三重错误
+    let marker = 0u8;
+    let index = usize::from(VirtAddr::from_ptr(&marker).p4_index());
+    space.mapper().level_4_table()[index].set_unused();
+
+    let before = Cr3::read();
+    let result = memory::with_address_space(&space, || panic!("switched to a broken space"));
+    assert_eq!(
+        result,
+        Err(SwitchError::Missing {
+            what: "stack",
+            index
+        })
+    );
+    assert_eq!(Cr3::read(), before);
+}
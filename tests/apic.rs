@@ -0,0 +1,94 @@
+//测试开启apic特性后的中断路径：本地APIC寄存器可以读取，发送EOI不会出错，
+//IOAPIC的重定向表项可以读写，计时器中断经IOAPIC仍然到达
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::interrupts::{InterruptIndex, PIC_1_OFFSET};
+use os::{apic, ioapic};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+#[test_case]
+fn apic_enabled() {
+    assert!(apic::is_supported());
+    assert!(apic::is_enabled());
+    assert!(ioapic::is_enabled());
+}
+
+#[test_case]
+fn id_matches_cpuid() {
+    let initial_id = (core::arch::x86_64::__cpuid(1).ebx >> 24) as u8;
+    assert_eq!(apic::id(), initial_id);
+}
+
+#[test_case]
+fn version_is_integrated_apic() {
+    let version = apic::version();
+    //集成APIC的版本号为0x10~0x15，至少有LINT0、LINT1、计时器等LVT表项
+    assert!((0x10..=0x15).contains(&(version & 0xff)), "{:#x}", version);
+    assert!((version >> 16) & 0xff >= 3, "{:#x}", version);
+}
+
+#[test_case]
+fn eoi_without_interrupt() {
+    //没有正在处理的中断时EOI不会产生任何效果
+    apic::eoi();
+    apic::eoi();
+}
+
+#[test_case]
+fn pic_lines_are_routed() {
+    //ISA覆盖：PIT在GSI 2上
+    let timer = ioapic::read_entry(ioapic::isa_irq_to_gsi(0));
+    assert_eq!(timer & 0xff, u64::from(InterruptIndex::Timer.as_u8()));
+    assert!(!ioapic::is_masked(2));
+    let keyboard = ioapic::read_entry(1);
+    assert_eq!(keyboard & 0xff, u64::from(InterruptIndex::Keyboard.as_u8()));
+    assert!(!ioapic::is_masked(1));
+    //两片PIC完全屏蔽
+    assert_eq!(os::interrupts::irq_mask(), 0xffff);
+}
+
+#[test_case]
+fn redirection_entry_round_trip() {
+    //IRQ3在QEMU中没有设备，可以自由改写
+    let gsi = 3;
+    ioapic::route(gsi, 0x50, true);
+    let entry = ioapic::read_entry(gsi);
+    assert_eq!(entry, ioapic::redirection_entry(0x50, true, apic::id()));
+    assert!(ioapic::is_masked(gsi));
+
+    ioapic::unmask(gsi);
+    assert!(!ioapic::is_masked(gsi));
+    assert_eq!(ioapic::read_entry(gsi) & 0xff, 0x50);
+    os::interrupts::disable_irq(gsi);
+    assert!(ioapic::is_masked(gsi));
+
+    ioapic::route(gsi, PIC_1_OFFSET + gsi, true);
+}
+
+#[test_case]
+fn timer_ticks_through_ioapic() {
+    let start = os::time::ticks();
+    for _ in 0..3 {
+        x86_64::instructions::hlt();
+    }
+    assert!(os::time::ticks() > start);
+}
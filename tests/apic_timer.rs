@@ -0,0 +1,76 @@
+//测试本地APIC计时器：校准结果与TSC测量一致，切换后时钟滴答继续增长
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::{apic, time};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+//用TSC计时等待`ms`毫秒，期间允许中断，返回经过的滴答数
+fn ticks_during_ms(ms: u64) -> u64 {
+    let start = time::ticks();
+    let deadline = time::rdtsc() + ms * time::tsc_per_ms();
+    while time::rdtsc() < deadline {
+        core::hint::spin_loop();
+    }
+    time::ticks() - start
+}
+
+#[test_case]
+fn calibration_is_stable() {
+    let first = apic::calibrate_timer();
+    let second = apic::recalibrate_timer();
+    assert!(first > 0);
+    assert!(
+        second.abs_diff(first) <= first / 5,
+        "{} vs {}",
+        first,
+        second
+    );
+}
+
+#[test_case]
+fn frequency_matches_tsc() {
+    apic::timer_init(100).expect("100 Hz is in range");
+    assert_eq!(time::frequency(), 100);
+    //200毫秒内应有20个滴答，允许20%的误差
+    let ticks = ticks_during_ms(200);
+    assert!((16..=24).contains(&ticks), "{} ticks", ticks);
+}
+
+#[test_case]
+fn ticks_advance_after_init() {
+    apic::timer_init(1000).expect("1000 Hz is in range");
+    let start = time::ticks();
+    for _ in 0..10 {
+        x86_64::instructions::hlt();
+    }
+    assert!(time::ticks() >= start + 10);
+}
+
+#[test_case]
+fn stop_returns_to_pit() {
+    apic::timer_init(1000).expect("1000 Hz is in range");
+    apic::timer_stop();
+    assert_eq!(time::frequency(), time::DEFAULT_FREQUENCY);
+    //PIT重新投递时钟中断
+    assert!(ticks_during_ms(100) >= 5);
+    apic::timer_stop();
+}
@@ -0,0 +1,141 @@
+//bootimage把内核映像作为主通道的主盘启动，读取开头的扇区并与映像的内容比较
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::drivers::ata::{self, AtaDisk, AtaError, SECTOR_SIZE};
+use os::storage::{self, BlockDevice, PartitionDevice};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+    os::allocator::init_heap().expect("heap initialization failed");
+    ata::init().expect("no ATA disk on the primary channel");
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+#[test_case]
+fn identify_boot_disk() {
+    let info = ata::info().unwrap();
+    assert!(info.sectors > 0);
+    //QEMU的硬盘型号为"QEMU HARDDISK"
+    assert!(info.model().starts_with("QEMU"));
+}
+
+#[test_case]
+fn read_boot_sector() {
+    let mut sector = [0u8; SECTOR_SIZE];
+    ata::read_sectors(0, 1, &mut sector).unwrap();
+    //引导扇区以0x55 0xaa结尾
+    assert_eq!(&sector[510..], &[0x55, 0xaa]);
+}
+
+#[test_case]
+fn read_multiple_sectors() {
+    let mut single = [0u8; SECTOR_SIZE * 2];
+    ata::read_sectors(0, 1, &mut single[..SECTOR_SIZE]).unwrap();
+    ata::read_sectors(1, 1, &mut single[SECTOR_SIZE..]).unwrap();
+    let mut both = [0u8; SECTOR_SIZE * 2];
+    ata::read_sectors(0, 2, &mut both).unwrap();
+    assert_eq!(single, both);
+    //第二个扇区是引导程序的后续部分，不全为0
+    assert!(both[SECTOR_SIZE..].iter().any(|&b| b != 0));
+}
+
+#[test_case]
+fn reject_bad_requests() {
+    let mut sector = [0u8; SECTOR_SIZE];
+    assert_eq!(
+        ata::read_sectors(0, 2, &mut sector),
+        Err(AtaError::BufferTooSmall {
+            needed: 2 * SECTOR_SIZE,
+            len: SECTOR_SIZE
+        })
+    );
+    let last = ata::info().unwrap().sectors;
+    assert_eq!(
+        ata::read_sectors(last, 1, &mut sector),
+        Err(AtaError::OutOfRange {
+            lba: last,
+            count: 1
+        })
+    );
+}
+
+//使用最后一个扇区：引导程序已经把内核加载到内存，本次运行不会再读取它；测试结束时恢复原来的内容
+#[test_case]
+fn write_read_back() {
+    let last = ata::info().unwrap().sectors - 1;
+    let mut original = [0u8; SECTOR_SIZE];
+    ata::read_sectors(last, 1, &mut original).unwrap();
+
+    let mut pattern = [0u8; SECTOR_SIZE];
+    for (i, byte) in pattern.iter_mut().enumerate() {
+        *byte = (i as u8).wrapping_mul(7) ^ 0x5a;
+    }
+    ata::write_sectors(last, 1, &pattern).unwrap();
+    let mut read_back = [0u8; SECTOR_SIZE];
+    ata::read_sectors(last, 1, &mut read_back).unwrap();
+    assert_eq!(read_back, pattern);
+
+    ata::write_sectors(last, 1, &original).unwrap();
+    ata::flush().unwrap();
+    ata::read_sectors(last, 1, &mut read_back).unwrap();
+    assert_eq!(read_back, original);
+}
+
+#[test_case]
+fn reject_bad_writes() {
+    let sector = [0u8; SECTOR_SIZE];
+    //写入的长度必须与扇区数完全一致
+    assert_eq!(
+        ata::write_sectors(0, 2, &sector),
+        Err(AtaError::BufferSizeMismatch {
+            expected: 2 * SECTOR_SIZE,
+            len: SECTOR_SIZE
+        })
+    );
+    let last = ata::info().unwrap().sectors;
+    assert_eq!(
+        ata::write_sectors(last - 1, 2, &[0u8; 2 * SECTOR_SIZE]),
+        Err(AtaError::OutOfRange {
+            lba: last - 1,
+            count: 2
+        })
+    );
+    assert_eq!(ata::write_sectors(0, 0, &[]), Ok(()));
+}
+
+#[test_case]
+fn block_device_conformance() {
+    let mut disk = AtaDisk;
+    assert_eq!(disk.block_count(), u64::from(ata::info().unwrap().sectors));
+    storage::conformance::run(&mut disk);
+    //从第1个扇区开始的分区
+    let blocks = disk.block_count() - 1;
+    let mut partition = PartitionDevice::new(AtaDisk, 1, blocks).unwrap();
+    storage::conformance::run(&mut partition);
+}
+
+//写入的扇区在刷新写缓存并重新IDENTIFY之后仍能读回
+#[test_case]
+fn write_survives_reinit() {
+    storage::conformance::persistence(&mut AtaDisk, &mut || {
+        ata::flush().unwrap();
+        ata::init().unwrap();
+    });
+}
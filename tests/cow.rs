@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::memory::{self, vspace};
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, Translate};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+fn translate(addr: VirtAddr) -> Option<u64> {
+    memory::with_paging(|paging| paging.mapper.translate_addr(addr))
+        .expect("paging not installed")
+        .map(|phys| phys.as_u64())
+}
+
+#[test_case]
+fn write_copies_shared_frame() {
+    let start = vspace::reserve("cow-test", 2 * 4096, 4096).expect("no virtual space");
+    let page_a: Page = Page::containing_address(start);
+    let page_b = page_a + 1;
+    let a: *mut u64 = page_a.start_address().as_mut_ptr();
+    let b: *mut u64 = page_b.start_address().as_mut_ptr();
+
+    //两个页面映射到同一帧
+    let frame = memory::with_paging(|paging| {
+        let frame = paging.frame_allocator.allocate_frame().unwrap();
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        for page in [page_a, page_b] {
+            unsafe {
+                paging
+                    .mapper
+                    .map_to(page, frame, flags, &mut paging.frame_allocator)
+            }
+            .expect("map_to failed")
+            .flush();
+        }
+        frame
+    })
+    .expect("paging not installed");
+
+    unsafe { a.write_volatile(0x1234) };
+    assert_eq!(unsafe { b.read_volatile() }, 0x1234);
+
+    memory::with_paging(|paging| {
+        memory::mark_cow(page_a, &mut paging.mapper).unwrap();
+        memory::mark_cow(page_b, &mut paging.mapper).unwrap();
+    })
+    .expect("paging not installed");
+    assert_eq!(memory::cow::cow_refcount(frame), 2);
+
+    //写入a触发复制，b仍然看到原来的内容
+    unsafe { a.write_volatile(0x5678) };
+    assert_eq!(unsafe { a.read_volatile() }, 0x5678);
+    assert_eq!(unsafe { b.read_volatile() }, 0x1234);
+    assert_ne!(translate(start), Some(frame.start_address().as_u64()));
+    assert_eq!(memory::cow::cow_refcount(frame), 1);
+
+    //b是最后一个引用，写入时不再复制
+    unsafe { b.write_volatile(0x9abc) };
+    assert_eq!(
+        translate(page_b.start_address()),
+        Some(frame.start_address().as_u64())
+    );
+    assert_eq!(memory::cow::cow_refcount(frame), 0);
+    assert_eq!(unsafe { a.read_volatile() }, 0x5678);
+
+    memory::with_paging(|paging| {
+        for page in [page_a, page_b] {
+            memory::unmap_page(page, &mut paging.mapper, &mut paging.frame_allocator)
+                .expect("unmap failed");
+        }
+    })
+    .expect("paging not installed");
+}
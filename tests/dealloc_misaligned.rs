@@ -0,0 +1,45 @@
+//测试释放非法指针会被检测到
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::alloc::Layout;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::allocator::{set_invalid_free_policy, InvalidFreePolicy};
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os::allocator;
+
+    serial_print!("dealloc_misaligned::misaligned_heap_pointer...\t");
+
+    os::init(boot_info);
+    allocator::init_heap().expect("heap initialization failed");
+    set_invalid_free_policy(InvalidFreePolicy::Panic);
+
+    invalid_dealloc();
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+fn invalid_dealloc() {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc::alloc::alloc(layout);
+        assert!(!ptr.is_null());
+        alloc::alloc::dealloc(ptr.add(1), layout);
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
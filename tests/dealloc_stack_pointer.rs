@@ -0,0 +1,42 @@
+//测试释放非法指针会被检测到
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::alloc::Layout;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::allocator::{set_invalid_free_policy, InvalidFreePolicy};
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os::allocator;
+
+    serial_print!("dealloc_stack_pointer::stack_pointer...\t");
+
+    os::init(boot_info);
+    allocator::init_heap().expect("heap initialization failed");
+    set_invalid_free_policy(InvalidFreePolicy::Panic);
+
+    invalid_dealloc();
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+fn invalid_dealloc() {
+    let mut on_stack = [0u64; 4];
+    let layout = Layout::new::<[u64; 4]>();
+    unsafe { alloc::alloc::dealloc(on_stack.as_mut_ptr() as *mut u8, layout) };
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
@@ -0,0 +1,57 @@
+//测试整数除以零会触发除法错误，而不是升级为double fault
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    serial_print!("divide_error::divide_by_zero...\t");
+
+    os::gdt::init();
+    init_test_idt();
+
+    //Rust的除法会先检查除数并panic，因此直接使用div指令
+    let zero = volatile::Volatile::new(0u64).read();
+    unsafe {
+        core::arch::asm!(
+            "div {0}",
+            in(reg) zero,
+            inout("rax") 1u64 => _,
+            inout("rdx") 0u64 => _,
+        )
+    }; //应当触发#DE
+
+    serial_println!("[no divide error]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.divide_error.set_handler_fn(test_divide_error_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_divide_error_handler(_stack_frame: InterruptStackFrame) {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
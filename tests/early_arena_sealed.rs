@@ -0,0 +1,38 @@
+//测试init_heap之后使用早期内存区会panic
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::alloc::Layout;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::allocator::early::EARLY_ARENA;
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os::allocator;
+
+    serial_print!("early_arena_sealed::alloc_after_seal...\t");
+
+    os::init(boot_info);
+    assert!(EARLY_ARENA.alloc(Layout::new::<u64>()).is_some());
+
+    allocator::init_heap().expect("heap initialization failed");
+    assert!(EARLY_ARENA.sealed());
+
+    EARLY_ARENA.alloc(Layout::new::<u64>()); //应当panic
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
@@ -0,0 +1,307 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::fs::fat::{Attributes, FatFs};
+use os::fs::FsError;
+use os::storage::{BlockDevice, RamDisk};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+    os::allocator::init_heap().expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+/*
+    测试镜像的布局（每簇1个扇区），簇数刚好超过FAT12的上限：
+      0       引导扇区
+      1~17    FAT #1
+      18~34   FAT #2
+      35~66   根目录，512项
+      67~     数据区，簇2从这里开始
+*/
+const TOTAL_SECTORS: u16 = 4300;
+const FAT_SECTORS: u16 = 17;
+const ROOT_ENTRIES: u16 = 512;
+const FAT_START: u64 = 1;
+const ROOT_START: u64 = 35;
+const DATA_START: u64 = 67;
+const README: &[u8] = b"hello from the root directory\n";
+const HELLO: &[u8] = b"nested hello\n";
+const BIG_SIZE: usize = 1500;
+//BIG.BIN占用三个不连续的簇
+const BIG_CHAIN: [u16; 3] = [10, 7, 12];
+
+struct Image {
+    disk: RamDisk,
+}
+
+impl Image {
+    fn new() -> Self {
+        let mut boot = [0u8; 512];
+        boot[0..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+        boot[3..11].copy_from_slice(b"RUSTOS  ");
+        boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+        boot[13] = 1; //每簇扇区数
+        boot[14..16].copy_from_slice(&1u16.to_le_bytes()); //保留扇区数
+        boot[16] = 2; //FAT数
+        boot[17..19].copy_from_slice(&ROOT_ENTRIES.to_le_bytes());
+        boot[19..21].copy_from_slice(&TOTAL_SECTORS.to_le_bytes());
+        boot[21] = 0xf8;
+        boot[22..24].copy_from_slice(&FAT_SECTORS.to_le_bytes());
+        boot[54..62].copy_from_slice(b"FAT16   ");
+        boot[510] = 0x55;
+        boot[511] = 0xaa;
+        let mut image = Image {
+            disk: RamDisk::new(512, TOTAL_SECTORS as usize),
+        };
+        image.disk.write_blocks(0, &boot).unwrap();
+        image.set_fat(0, 0xfff8);
+        image.set_fat(1, 0xffff);
+        image
+    }
+
+    fn modify(&mut self, lba: u64, offset: usize, data: &[u8]) {
+        let mut sector = [0u8; 512];
+        self.disk.read_blocks(lba, &mut sector).unwrap();
+        sector[offset..offset + data.len()].copy_from_slice(data);
+        self.disk.write_blocks(lba, &sector).unwrap();
+    }
+
+    //同时写入两份FAT
+    fn set_fat(&mut self, cluster: u16, value: u16) {
+        let offset = cluster as usize * 2;
+        for fat in 0..2 {
+            let lba = FAT_START + fat * u64::from(FAT_SECTORS) + (offset / 512) as u64;
+            self.modify(lba, offset % 512, &value.to_le_bytes());
+        }
+    }
+
+    fn chain(&mut self, clusters: &[u16]) {
+        for pair in clusters.windows(2) {
+            self.set_fat(pair[0], pair[1]);
+        }
+        self.set_fat(*clusters.last().unwrap(), 0xffff);
+    }
+
+    fn write_cluster(&mut self, cluster: u16, data: &[u8]) {
+        self.modify(DATA_START + u64::from(cluster) - 2, 0, data);
+    }
+
+    fn root_entry(&mut self, index: usize, entry: &[u8; 32]) {
+        self.modify(ROOT_START + (index / 16) as u64, index % 16 * 32, entry);
+    }
+
+    fn dir_entry(&mut self, cluster: u16, index: usize, entry: &[u8; 32]) {
+        self.modify(DATA_START + u64::from(cluster) - 2, index * 32, entry);
+    }
+}
+
+fn entry(name: &[u8; 11], attributes: u8, cluster: u16, size: u32) -> [u8; 32] {
+    let mut raw = [0u8; 32];
+    raw[..11].copy_from_slice(name);
+    raw[11] = attributes;
+    raw[26..28].copy_from_slice(&cluster.to_le_bytes());
+    raw[28..32].copy_from_slice(&size.to_le_bytes());
+    raw
+}
+
+fn big_contents() -> Vec<u8> {
+    (0..BIG_SIZE).map(|i| (i % 251) as u8).collect()
+}
+
+/*
+    /README.TXT             簇2，前面有一个长文件名项
+    /DOCS/                  簇3
+    /DOCS/NOTES/            簇4
+    /DOCS/NOTES/HELLO.TXT   簇5
+    /BIG.BIN                簇10 -> 7 -> 12
+    另有一个卷标和一个已删除的项
+*/
+fn mount() -> FatFs<RamDisk> {
+    let mut image = Image::new();
+    image.root_entry(0, &entry(b"RUSTOS     ", Attributes::VOLUME_ID, 0, 0));
+    let lfn = entry(b"\x41r\0e\0a\0d\0m\0", Attributes::LONG_NAME, 0, 0);
+    image.root_entry(1, &lfn);
+    image.root_entry(
+        2,
+        &entry(b"README  TXT", Attributes::ARCHIVE, 2, README.len() as u32),
+    );
+    let mut deleted = entry(b"OLD     TXT", Attributes::ARCHIVE, 6, 10);
+    deleted[0] = 0xe5;
+    image.root_entry(3, &deleted);
+    image.root_entry(4, &entry(b"DOCS       ", Attributes::DIRECTORY, 3, 0));
+    image.root_entry(
+        5,
+        &entry(b"BIG     BIN", Attributes::ARCHIVE, 10, BIG_SIZE as u32),
+    );
+
+    image.chain(&[2]);
+    image.write_cluster(2, README);
+
+    image.chain(&[3]);
+    image.dir_entry(3, 0, &entry(b".          ", Attributes::DIRECTORY, 3, 0));
+    image.dir_entry(3, 1, &entry(b"..         ", Attributes::DIRECTORY, 0, 0));
+    image.dir_entry(3, 2, &entry(b"NOTES      ", Attributes::DIRECTORY, 4, 0));
+
+    image.chain(&[4]);
+    image.dir_entry(4, 0, &entry(b".          ", Attributes::DIRECTORY, 4, 0));
+    image.dir_entry(4, 1, &entry(b"..         ", Attributes::DIRECTORY, 3, 0));
+    image.dir_entry(
+        4,
+        2,
+        &entry(b"HELLO   TXT", Attributes::ARCHIVE, 5, HELLO.len() as u32),
+    );
+
+    image.chain(&[5]);
+    image.write_cluster(5, HELLO);
+
+    image.chain(&BIG_CHAIN);
+    let big = big_contents();
+    for (chunk, &cluster) in big.chunks(512).zip(BIG_CHAIN.iter()) {
+        image.write_cluster(cluster, chunk);
+    }
+
+    FatFs::mount(image.disk).expect("failed to mount test image")
+}
+
+fn read_all(fs: &FatFs<RamDisk>, path: &str, chunk: usize) -> Vec<u8> {
+    let mut file = fs.open(path).unwrap();
+    let mut contents = Vec::new();
+    let mut buf = vec![0u8; chunk];
+    loop {
+        let n = file.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        contents.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(contents.len(), file.size() as usize);
+    contents
+}
+
+#[test_case]
+fn read_root_file() {
+    let fs = mount();
+    assert_eq!(read_all(&fs, "/README.TXT", 512), README);
+    //不区分大小写，开头的'/'可以省略
+    assert_eq!(read_all(&fs, "readme.txt", 7), README);
+}
+
+#[test_case]
+fn read_multi_cluster_file() {
+    let fs = mount();
+    let expected = big_contents();
+    //不同的读取大小会在簇和扇区边界的不同位置切分
+    for chunk in [1, 100, 512, 700, 4096] {
+        assert_eq!(read_all(&fs, "/BIG.BIN", chunk), expected);
+    }
+}
+
+#[test_case]
+fn read_nested_file() {
+    let fs = mount();
+    assert_eq!(read_all(&fs, "/DOCS/NOTES/HELLO.TXT", 64), HELLO);
+    assert_eq!(read_all(&fs, "/docs//notes/hello.txt", 64), HELLO);
+}
+
+#[test_case]
+fn list_directories() {
+    let fs = mount();
+    let root = fs.read_dir("/").unwrap();
+    let names: Vec<&str> = root.iter().map(|e| e.name()).collect();
+    //卷标、长文件名项和已删除的项不会出现
+    assert_eq!(names, ["README.TXT", "DOCS", "BIG.BIN"]);
+    assert_eq!(root[0].size, README.len() as u32);
+    assert!(!root[0].is_dir());
+    assert!(root[1].is_dir());
+    assert_eq!(root[2].size, BIG_SIZE as u32);
+
+    let notes = fs.read_dir("/DOCS/NOTES").unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].name(), "HELLO.TXT");
+    assert_eq!(notes[0].attributes, Attributes(Attributes::ARCHIVE));
+}
+
+#[test_case]
+fn missing_paths() {
+    let fs = mount();
+    assert_eq!(fs.open("/NOPE.TXT").err(), Some(FsError::NotFound));
+    assert_eq!(
+        fs.open("/DOCS/NOPE/HELLO.TXT").err(),
+        Some(FsError::NotFound)
+    );
+    assert_eq!(fs.open("/OLD.TXT").err(), Some(FsError::NotFound));
+    assert_eq!(fs.open("/README.TXT/X").err(), Some(FsError::NotADirectory));
+    assert_eq!(fs.open("/DOCS").err(), Some(FsError::IsADirectory));
+    assert_eq!(fs.open("/").err(), Some(FsError::IsADirectory));
+    assert_eq!(
+        fs.open("/LONGFILENAME.TXT").err(),
+        Some(FsError::InvalidName)
+    );
+    assert_eq!(fs.read_dir("/BIG.BIN").err(), Some(FsError::NotADirectory));
+    assert_eq!(fs.read_dir("/MISSING").err(), Some(FsError::NotFound));
+}
+
+#[test_case]
+fn reject_cyclic_chains() {
+    let mut image = Image::new();
+    //目录簇被占满且没有结束项，FAT项指向自身，不限制步数时会一直扫描下去
+    image.root_entry(0, &entry(b"LOOP       ", Attributes::DIRECTORY, 3, 0));
+    image.set_fat(3, 3);
+    for i in 0..16 {
+        let mut name = *b"F          ";
+        name[1] = b'A' + i as u8;
+        image.dir_entry(3, i, &entry(&name, Attributes::ARCHIVE, 0, 0));
+    }
+    //文件声明的大小远超磁盘容量，簇链同样指向自身
+    image.root_entry(1, &entry(b"SPIN    BIN", Attributes::ARCHIVE, 4, u32::MAX));
+    image.set_fat(4, 4);
+    let fs = FatFs::mount(image.disk).unwrap();
+
+    assert_eq!(
+        fs.read_dir("/LOOP").err(),
+        Some(FsError::CorruptChain { cluster: 3 })
+    );
+    let mut file = fs.open("/SPIN.BIN").unwrap();
+    let mut buf = [0u8; 4096];
+    let err = loop {
+        match file.read(&mut buf) {
+            Ok(n) => assert_ne!(n, 0),
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(err, FsError::CorruptChain { cluster: 4 });
+}
+
+#[test_case]
+fn reject_non_fat16() {
+    let mut image = Image::new();
+    //簇数少于4085时应当按FAT12处理
+    image.modify(0, 19, &2000u16.to_le_bytes());
+    assert_eq!(
+        FatFs::mount(image.disk).err(),
+        Some(FsError::Unsupported("FAT12"))
+    );
+    assert_eq!(
+        FatFs::mount(RamDisk::new(512, 16)).err(),
+        Some(FsError::BadBootSector)
+    );
+}
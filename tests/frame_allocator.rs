@@ -0,0 +1,306 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::memory::low_pool::LOW_POOL_MIN;
+use os::memory::{self, vspace, BootInfoFrameAllocator, FrameStats, ReserveError, DMA_LIMIT};
+use os::serial_println;
+use os::time::rdtsc;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Page, PageTableFlags, Size4KiB,
+};
+use x86_64::PhysAddr;
+
+entry_point!(main);
+
+//测试中用于构造独立的BootInfoFrameAllocator，只在test_main之前写入一次
+static mut MEMORY_MAP: Option<&'static MemoryMap> = None;
+
+fn memory_map() -> &'static MemoryMap {
+    unsafe { MEMORY_MAP }.expect("memory map not recorded")
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+    unsafe { MEMORY_MAP = Some(&boot_info.memory_map) };
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+#[test_case]
+fn reuse_returned_frame() {
+    memory::with_paging(|paging| {
+        let frames = &mut paging.frame_allocator;
+        let frame = frames.allocate_frame().unwrap();
+        let free_before = frames.inner().free_frames();
+        unsafe { frames.deallocate_frame(frame) };
+        assert_eq!(frames.inner().free_frames(), free_before + 1);
+        assert_eq!(frames.allocate_frame(), Some(frame));
+        assert_eq!(frames.inner().free_frames(), free_before);
+    })
+    .expect("paging not installed");
+}
+
+#[test_case]
+fn free_stack_is_lifo() {
+    memory::with_paging(|paging| {
+        let frames = &mut paging.frame_allocator;
+        let a = frames.allocate_frame().unwrap();
+        let b = frames.allocate_frame().unwrap();
+        unsafe {
+            frames.deallocate_frame(a);
+            frames.deallocate_frame(b);
+        }
+        assert_eq!(frames.inner().free_frames(), 2);
+        assert_eq!(frames.allocate_frame(), Some(b));
+        assert_eq!(frames.allocate_frame(), Some(a));
+        assert_eq!(frames.inner().free_frames(), 0);
+    })
+    .expect("paging not installed");
+}
+
+#[test_case]
+fn cursor_matches_usable_frames() {
+    //只做分配不做映射，因此不会影响全局帧分配器
+    let mut frames = unsafe { BootInfoFrameAllocator::init(memory_map()) };
+    let reference = unsafe { BootInfoFrameAllocator::init(memory_map()) };
+    let mut previous = None;
+    for expected in reference.usable_frames().take(10_000) {
+        let frame = frames.allocate_frame().expect("ran out of usable frames");
+        assert_eq!(frame, expected);
+        //内存映射按地址排序，严格递增即说明互不相同
+        assert!(previous.is_none_or(|p| p < frame));
+        previous = Some(frame);
+    }
+}
+
+#[test_case]
+fn cursor_is_faster_than_nth() {
+    const COUNT: usize = 1000;
+
+    let reference = unsafe { BootInfoFrameAllocator::init(memory_map()) };
+    let start = rdtsc();
+    for i in 0..COUNT {
+        core::hint::black_box(reference.usable_frames().nth(i));
+    }
+    let nth_cycles = rdtsc() - start;
+
+    let mut frames = unsafe { BootInfoFrameAllocator::init(memory_map()) };
+    let start = rdtsc();
+    for _ in 0..COUNT {
+        core::hint::black_box(FrameAllocator::<Size4KiB>::allocate_frame(&mut frames));
+    }
+    let cursor_cycles = rdtsc() - start;
+
+    serial_println!(
+        "nth(): {} cycles, cursor: {} cycles for {} frames",
+        nth_cycles,
+        cursor_cycles,
+        COUNT
+    );
+}
+
+#[test_case]
+fn boot_map_totals() {
+    let usable = memory::total_usable_bytes(memory_map());
+    assert!(usable > 0);
+    assert_eq!(usable % 4096, 0);
+    assert_eq!(memory::total_reserved_bytes(memory_map()) % 4096, 0);
+}
+
+#[test_case]
+fn mapping_consumes_counted_frames() {
+    const PAGES: u64 = 16;
+    let start = vspace::reserve("frame-stats-test", PAGES * 4096, 4096).expect("no virtual space");
+    let first: Page = Page::containing_address(start);
+
+    memory::with_paging(|paging| {
+        let offset = paging.mapper.phys_offset();
+        let allocated_before = paging.frame_allocator.allocated_frames();
+        let remaining_before = paging.frame_allocator.remaining_frames();
+        let tables_before = memory::table_frames_allocated(paging.mapper.level_4_table(), offset);
+
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        for page in Page::range(first, first + PAGES) {
+            let frame = paging.frame_allocator.allocate_frame().unwrap();
+            memory::map_page(
+                page,
+                frame,
+                flags,
+                &mut paging.mapper,
+                &mut paging.frame_allocator,
+            )
+            .expect("map_page failed");
+        }
+
+        //除数据帧外，只有新建的页表会消耗帧
+        let tables =
+            memory::table_frames_allocated(paging.mapper.level_4_table(), offset) - tables_before;
+        let allocated = paging.frame_allocator.allocated_frames() - allocated_before;
+        assert_eq!(allocated, PAGES as usize + tables);
+        assert_eq!(
+            remaining_before - paging.frame_allocator.remaining_frames(),
+            allocated
+        );
+
+        //归还数据帧后已分配帧数随之减少
+        memory::unmap_range(
+            first,
+            PAGES,
+            &mut paging.mapper,
+            &mut paging.frame_allocator,
+        )
+        .expect("unmap_range failed");
+        assert_eq!(
+            paging.frame_allocator.allocated_frames() - allocated_before,
+            tables
+        );
+    })
+    .expect("paging not installed");
+}
+
+//在第一个足够大的可用区域中间选取一段范围
+fn range_inside_usable_region() -> (PhysAddr, PhysAddr) {
+    let region = memory_map()
+        .iter()
+        .find(|r| {
+            r.region_type == MemoryRegionType::Usable
+                && r.range.end_addr() - r.range.start_addr() >= 64 * 4096
+        })
+        .expect("no large usable region");
+    let start = region.range.start_addr() + 16 * 4096;
+    (PhysAddr::new(start), PhysAddr::new(start + 32 * 4096))
+}
+
+#[test_case]
+fn reserved_range_is_never_allocated() {
+    let (start, end) = range_inside_usable_region();
+    let mut frames = unsafe { BootInfoFrameAllocator::init(memory_map()) };
+    let total = frames.total_usable_frames();
+    frames.reserve_range(start, end).expect("reserve failed");
+    assert_eq!(frames.total_usable_frames(), total - 32);
+    assert!(frames
+        .usable_frames()
+        .all(|frame| !(start..end).contains(&frame.start_address())));
+
+    for _ in 0..5000 {
+        let frame = match FrameAllocator::<Size4KiB>::allocate_frame(&mut frames) {
+            Some(frame) => frame,
+            None => break,
+        };
+        assert!(!(start..end).contains(&frame.start_address()));
+    }
+}
+
+#[test_case]
+fn reserve_after_allocation_fails() {
+    let (start, end) = range_inside_usable_region();
+    let mut frames = unsafe { BootInfoFrameAllocator::init(memory_map()) };
+    assert_eq!(
+        frames.reserve_range(end, start),
+        Err(ReserveError::EmptyRange)
+    );
+    FrameAllocator::<Size4KiB>::allocate_frame(&mut frames).unwrap();
+    assert_eq!(
+        frames.reserve_range(start, end),
+        Err(ReserveError::AllocationStarted)
+    );
+}
+
+#[test_case]
+fn low_allocations_respect_limit() {
+    let mut frames = unsafe { BootInfoFrameAllocator::init(memory_map()) };
+    let low_before = frames.remaining_low_frames();
+    let limit = PhysAddr::new(8 * 1024 * 1024);
+
+    let frame = frames.allocate_frame_below(limit).expect("no low frame");
+    assert!(frame.start_address() + 4096u64 <= limit);
+
+    //16个帧物理上连续，全部位于上限之下
+    let first = frames
+        .allocate_contiguous_below(16, limit)
+        .expect("no contiguous low frames");
+    assert!(first.start_address() + 16u64 * 4096 <= limit);
+    assert_eq!(frames.allocated_frames(), 17);
+    assert_eq!(frames.remaining_low_frames(), low_before - 17);
+
+    frames.deallocate_low(frame);
+    for i in 0..16 {
+        frames.deallocate_low(first + i);
+    }
+    assert_eq!(frames.remaining_low_frames(), low_before);
+    assert_eq!(frames.allocated_frames(), 0);
+}
+
+#[test_case]
+fn ordinary_allocation_keeps_low_reserve() {
+    let mut frames = unsafe { BootInfoFrameAllocator::init(memory_map()) };
+    let low_before = frames.remaining_low_frames();
+    assert!(low_before > LOW_POOL_MIN);
+
+    //耗尽全部可用帧，游标只返回16MiB以上的帧，之后才借用低端内存池
+    let mut high = 0;
+    while let Some(frame) = FrameAllocator::<Size4KiB>::allocate_frame(&mut frames) {
+        if frame.start_address().as_u64() >= DMA_LIMIT {
+            high += 1;
+            assert_eq!(frames.remaining_low_frames(), low_before);
+        }
+    }
+    assert_eq!(high, frames.usable_frames().count());
+    assert_eq!(frames.remaining_low_frames(), LOW_POOL_MIN);
+    assert!(frames
+        .allocate_contiguous_below(LOW_POOL_MIN, PhysAddr::new(DMA_LIMIT))
+        .is_some());
+}
+
+#[test_case]
+fn classify_agrees_with_regions() {
+    let map = memory_map();
+    let first_match = |addr: u64| {
+        map.iter()
+            .find(|r| r.range.start_addr() <= addr && addr < r.range.end_addr())
+            .map(|r| r.region_type)
+    };
+    for region in map.iter() {
+        let (start, end) = (region.range.start_addr(), region.range.end_addr());
+        for addr in [start, start + (end - start) / 2, end - 1] {
+            assert_eq!(memory::classify(PhysAddr::new(addr)), first_match(addr));
+        }
+    }
+    for (start, end) in memory::holes(map) {
+        assert!(start < end);
+        assert_eq!(memory::classify(PhysAddr::new(start)), None);
+        assert_eq!(memory::classify(PhysAddr::new(end - 1)), None);
+    }
+    assert_eq!(
+        memory::classify(PhysAddr::new(memory::phys_memory_end(map))),
+        None
+    );
+}
+
+#[test_case]
+fn region_totals_cover_map() {
+    let map = memory_map();
+    let totals = memory::RegionTotals::new(map);
+    let sum: u64 = totals.iter().map(|(_, total)| total).sum();
+    assert_eq!(
+        sum,
+        memory::total_usable_bytes(map) + memory::total_reserved_bytes(map)
+    );
+    assert_eq!(
+        totals.get(MemoryRegionType::Usable),
+        memory::total_usable_bytes(map)
+    );
+    assert_eq!(memory::overlapping_regions(map).count(), 0);
+}
@@ -0,0 +1,66 @@
+//测试加载超出GDT范围的段选择子会触发通用保护错误，并能解码错误码
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use os::interrupts::{DescriptorTable, SelectorErrorCode};
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::instructions::segmentation::{Segment, DS};
+use x86_64::structures::gdt::SegmentSelector;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::PrivilegeLevel;
+
+entry_point!(main);
+
+//GDT中不存在的最后一个索引
+const BOGUS_INDEX: u16 = 0x1fff;
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    serial_print!("general_protection::bogus_segment...\t");
+
+    os::gdt::init();
+    init_test_idt();
+
+    unsafe { DS::set_reg(SegmentSelector::new(BOGUS_INDEX, PrivilegeLevel::Ring0)) }; //应当触发GPF
+
+    serial_println!("[no general protection fault]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.general_protection_fault
+            .set_handler_fn(test_general_protection_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_general_protection_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    let code = SelectorErrorCode(error_code);
+    if code.table() == DescriptorTable::Gdt && code.index() == BOGUS_INDEX && !code.external() {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("unexpected error code: {}", code);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
@@ -16,14 +16,9 @@ entry_point!(main);
 
 fn main(boot_info: &'static BootInfo) -> ! {
     use os::allocator;
-    use os::memory::{self, BootInfoFrameAllocator};
-    use x86_64::VirtAddr;
 
-    os::init();
-    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
-    let mut mapper = unsafe { memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    os::init(boot_info);
+    allocator::init_heap().expect("heap initialization failed");
 
     test_main();
     loop {}
@@ -69,3 +64,235 @@ fn many_boxes_long_lived() {
     }
     assert_eq!(*long_lived, 1);
 }
+
+fn aligned_allocation(align: usize) {
+    use alloc::alloc::{alloc, dealloc, Layout};
+
+    let free_before = os::allocator::free_bytes();
+    let layout = Layout::from_size_align(256, align).unwrap();
+    let ptr = unsafe { alloc(layout) };
+    assert!(!ptr.is_null());
+    assert_eq!(ptr as usize % align, 0);
+    unsafe { dealloc(ptr, layout) };
+    assert_eq!(os::allocator::free_bytes(), free_before);
+}
+
+#[test_case]
+fn align_4k_allocation() {
+    aligned_allocation(4096);
+}
+
+#[test_case]
+fn align_16k_allocation() {
+    aligned_allocation(16 * 1024);
+}
+
+#[test_case]
+fn heap_range_after_init() {
+    use os::allocator::{heap_range, is_initialized};
+    use os::memory::vspace;
+
+    assert!(is_initialized());
+    let region = vspace::find("heap").expect("heap region missing");
+    let range = heap_range().expect("heap range missing after init");
+    assert_eq!(range.start, region.start);
+    assert_eq!(range.end, region.start + HEAP_SIZE);
+}
+
+#[test_case]
+fn shrink_after_large_workload() {
+    use os::allocator::{heap_info, shrink};
+    use os::memory;
+
+    let mut blocks = Vec::new();
+    for _ in 0..48 {
+        blocks.push(Vec::<u8>::with_capacity(64 * 1024));
+    }
+    let grown = heap_info().mapped_bytes;
+    assert!(grown > 3 * 1024 * 1024);
+    drop(blocks);
+
+    let free_frames =
+        || memory::with_paging(|paging| paging.frame_allocator.inner().free_frames()).unwrap();
+    let frames_before = free_frames();
+    let released = shrink();
+    assert!(released > 0);
+    assert_eq!(released, (free_frames() - frames_before) * 4096);
+    assert!(heap_info().mapped_bytes < grown);
+
+    //收缩后仍可重新增长
+    let block = Vec::<u8>::with_capacity(256 * 1024);
+    assert_eq!(block.capacity(), 256 * 1024);
+}
+
+#[test_case]
+fn usage_tracking() {
+    use os::allocator::{current_usage, peak_usage, reset_peak};
+
+    let baseline = current_usage();
+    reset_peak();
+    let a = Vec::<u8>::with_capacity(1000);
+    let b = Vec::<u8>::with_capacity(3000);
+    assert_eq!(current_usage(), baseline + 4000);
+    drop(a);
+    drop(b);
+    assert_eq!(peak_usage(), baseline + 4000);
+    assert_eq!(current_usage(), baseline);
+}
+
+#[test_case]
+fn usage_tracking_realloc() {
+    use os::allocator::current_usage;
+
+    let baseline = current_usage();
+    let mut v = Vec::<u8>::with_capacity(100);
+    v.reserve_exact(300);
+    assert_eq!(current_usage(), baseline + v.capacity());
+    v.shrink_to(10);
+    assert_eq!(current_usage(), baseline + v.capacity());
+    drop(v);
+    assert_eq!(current_usage(), baseline);
+}
+
+#[test_case]
+fn rejected_free_keeps_usage() {
+    use alloc::alloc::{alloc, dealloc, Layout};
+    use os::allocator::tag::freed_bytes;
+    use os::allocator::{current_usage, set_invalid_free_policy, with_tag, InvalidFreePolicy, Tag};
+
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let ptr = with_tag(Tag::Test, || unsafe { alloc(layout) });
+    assert!(!ptr.is_null());
+    let usage = current_usage();
+    let freed = freed_bytes(Tag::Test);
+
+    //未对齐的指针被拒绝，用量和标签统计都不变
+    set_invalid_free_policy(InvalidFreePolicy::LogAndIgnore);
+    unsafe { dealloc(ptr.add(1), layout) };
+    set_invalid_free_policy(InvalidFreePolicy::Panic);
+    assert_eq!(current_usage(), usage);
+    assert_eq!(freed_bytes(Tag::Test), freed);
+
+    unsafe { dealloc(ptr, layout) };
+    assert_eq!(current_usage(), usage - 64);
+    assert_eq!(freed_bytes(Tag::Test) - freed, 64);
+}
+
+#[test_case]
+fn usage_by_tag() {
+    use os::allocator::tag::allocated_bytes;
+    use os::allocator::{with_tag, Tag};
+
+    let keyboard_before = allocated_bytes(Tag::Keyboard);
+    let test_before = allocated_bytes(Tag::Test);
+    let a = with_tag(Tag::Keyboard, || Vec::<u8>::with_capacity(100));
+    let b = with_tag(Tag::Test, || Vec::<u8>::with_capacity(200));
+    assert_eq!(allocated_bytes(Tag::Keyboard) - keyboard_before, 100);
+    assert_eq!(allocated_bytes(Tag::Test) - test_before, 200);
+    drop(a);
+    drop(b);
+}
+
+#[test_case]
+fn free_credits_allocating_tag() {
+    use os::allocator::tag::{allocated_bytes, freed_bytes};
+    use os::allocator::{with_tag, Tag};
+
+    let keyboard_freed = freed_bytes(Tag::Keyboard);
+    let test_freed = freed_bytes(Tag::Test);
+    let v = with_tag(Tag::Keyboard, || Vec::<u8>::with_capacity(300));
+    //在另一个标签下释放，仍然记回分配时的标签
+    with_tag(Tag::Test, || drop(v));
+    assert_eq!(freed_bytes(Tag::Keyboard) - keyboard_freed, 300);
+    assert_eq!(freed_bytes(Tag::Test), test_freed);
+    assert!(allocated_bytes(Tag::Keyboard) >= freed_bytes(Tag::Keyboard));
+
+    //realloc保持分配时的标签
+    let mut v = with_tag(Tag::Keyboard, || Vec::<u8>::with_capacity(16));
+    let keyboard_allocated = allocated_bytes(Tag::Keyboard);
+    with_tag(Tag::Test, || v.reserve_exact(1024));
+    assert_eq!(
+        allocated_bytes(Tag::Keyboard) - keyboard_allocated,
+        v.capacity() - 16
+    );
+    drop(v);
+}
+
+#[test_case]
+fn free_region_layout() {
+    use alloc::alloc::{alloc, dealloc, Layout};
+    use os::allocator::{for_each_free_region, free_bytes, free_region_count, heap_range};
+
+    //返回包含`addr`的空闲区域
+    fn region_containing(addr: *mut u8) -> Option<(usize, usize)> {
+        let addr = addr as usize;
+        let mut found = None;
+        for_each_free_region(|start, size| {
+            if (start..start + size).contains(&addr) {
+                found = Some((start, size));
+            }
+        });
+        found
+    }
+
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let a = alloc(layout);
+        let b = alloc(layout);
+        let c = alloc(layout);
+
+        dealloc(b, layout);
+        assert!(region_containing(b).is_some());
+        dealloc(a, layout);
+        //相邻的空闲块被合并为一个区域
+        if a as usize + 64 == b as usize {
+            assert_eq!(region_containing(a), region_containing(b));
+        }
+        dealloc(c, layout);
+        if b as usize + 64 == c as usize {
+            assert_eq!(region_containing(b), region_containing(c));
+        }
+
+        //访问到的每个区域都在堆内，按地址排序且互不相邻，数量和总大小与分配器的统计一致
+        let mut regions = [(0, 0); 256];
+        let mut count = 0;
+        for_each_free_region(|start, size| {
+            if count < regions.len() {
+                regions[count] = (start, size);
+            }
+            count += 1;
+        });
+        assert!(count <= regions.len(), "{} free regions", count);
+        assert_eq!(count, free_region_count());
+        let regions = &mut regions[..count];
+        assert_eq!(
+            regions.iter().map(|&(_, size)| size).sum::<usize>(),
+            free_bytes()
+        );
+
+        let heap = heap_range().unwrap();
+        let (bottom, top) = (heap.start.as_u64() as usize, heap.end.as_u64() as usize);
+        for &(start, size) in regions.iter() {
+            assert!(size > 0);
+            assert!(
+                start >= bottom && start + size <= top,
+                "{:#x}+{}",
+                start,
+                size
+            );
+        }
+        for pair in regions.windows(2) {
+            assert!(
+                pair[0].0 + pair[0].1 < pair[1].0,
+                "{:x?} not coalesced",
+                pair
+            );
+        }
+        for block in [a, b, c] {
+            let addr = block as usize;
+            assert!(regions
+                .iter()
+                .any(|&(start, size)| start <= addr && addr + 64 <= start + size));
+        }
+    }
+}
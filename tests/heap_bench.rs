@@ -0,0 +1,113 @@
+//堆分配器微基准测试，使用`--features bump-allocator`可切换被测分配器
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::serial::print_table_row;
+use os::time::tsc;
+use os::{exit_qemu, serial_println, QemuExitCode};
+
+entry_point!(main);
+
+const COLUMN_WIDTH: usize = 20;
+//每次操作的周期数上限，仅用于排除明显异常的结果
+const MAX_CYCLES_PER_OP: u64 = 10_000_000;
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os::allocator;
+
+    os::init(boot_info);
+    allocator::init_heap().expect("heap initialization failed");
+
+    serial_println!();
+    print_table_row(&[&"benchmark", &"ops", &"cycles", &"cycles/op", &"ns/op"], COLUMN_WIDTH);
+    run("small_allocs", 10_000, small_allocs);
+    run("alloc_free_pairs", 10_000, alloc_free_pairs);
+    run("vec_push", 100_000, vec_push);
+    run("interleaved", 4_000, interleaved);
+
+    #[cfg(not(feature = "bump-allocator"))]
+    {
+        use os::allocator::{average_scan_length, linked_list::FitStrategy, set_fit_strategy};
+
+        serial_println!("first-fit average scan length: {}", average_scan_length());
+        set_fit_strategy(FitStrategy::NextFit);
+        run("interleaved_next_fit", 4_000, interleaved);
+        serial_println!("next-fit average scan length: {}", average_scan_length());
+    }
+
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+/// ## 函数说明
+/// 执行一个基准并打印结果行，只对结果做宽松的合理性检查
+fn run(name: &str, ops: u64, bench: fn()) {
+    let start = tsc::now_cycles();
+    bench();
+    let cycles = tsc::now_cycles() - start;
+
+    let per_op = cycles / ops;
+    let ns_per_op = tsc::cycles_to_ns(cycles) / ops;
+    print_table_row(&[&name, &ops, &cycles, &per_op, &ns_per_op], COLUMN_WIDTH);
+    assert!(cycles > 0, "{}: rdtsc did not advance", name);
+    assert!(per_op < MAX_CYCLES_PER_OP, "{}: implausible cycle count", name);
+}
+
+//分10轮，每轮保留1000个小块后整体释放
+fn small_allocs() {
+    const BATCH: usize = 1000;
+    for round in 0..10 {
+        let mut boxes: [Option<Box<u64>>; BATCH] = [const { None }; BATCH];
+        for (i, slot) in boxes.iter_mut().enumerate() {
+            *slot = Some(Box::new((round * BATCH + i) as u64));
+        }
+    }
+}
+
+fn alloc_free_pairs() {
+    for i in 0..10_000u64 {
+        let x = Box::new(i);
+        core::hint::black_box(&x);
+    }
+}
+
+//预留容量以免在100KiB的堆上倍增扩容失败
+fn vec_push() {
+    let mut vec = Vec::with_capacity(100_000);
+    for i in 0..100_000u32 {
+        vec.push(i as u8);
+    }
+    core::hint::black_box(&vec);
+}
+
+//交错分配不同大小的块并释放其中一半，之后再申请更大的块以制造碎片
+fn interleaved() {
+    const COUNT: usize = 1000;
+    let mut small: [Option<Vec<u8>>; COUNT] = [const { None }; COUNT];
+    for round in 0..2 {
+        for (i, slot) in small.iter_mut().enumerate() {
+            *slot = Some(Vec::with_capacity(16 + (i % 4) * 16));
+        }
+        for slot in small.iter_mut().skip(round).step_by(2) {
+            *slot = None;
+        }
+        for _ in 0..COUNT / 2 {
+            let large: Vec<u8> = Vec::with_capacity(96);
+            core::hint::black_box(&large);
+        }
+        for slot in small.iter_mut() {
+            *slot = None;
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
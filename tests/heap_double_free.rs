@@ -0,0 +1,44 @@
+//测试重复释放检测
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::alloc::Layout;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os::allocator;
+
+    serial_print!("heap_double_free::double_free...\t");
+
+    os::init(boot_info);
+    allocator::init_heap().expect("heap initialization failed");
+
+    double_free();
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+fn double_free() {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc::alloc::alloc(layout);
+        assert!(!ptr.is_null());
+        alloc::alloc::dealloc(ptr, layout);
+        alloc::alloc::dealloc(ptr, layout); //第二次释放应当panic
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
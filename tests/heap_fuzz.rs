@@ -0,0 +1,151 @@
+//使用固定种子的伪随机序列对堆分配器进行模糊测试，打印种子，失败时打印操作序号，用两者复现
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::alloc::{alloc, dealloc, realloc, Layout};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+
+entry_point!(main);
+
+//固定的默认种子使每次运行的结果相同；换用其他种子时断言同样成立
+const SEED: u64 = 0x2545_F491_4F6C_DD1D;
+//调试构建中每次操作都要逐字节填充和校验，操作数需要让测试在bootimage的test-timeout内完成
+const OPERATIONS: usize = 10_000;
+const SLOTS: usize = 64;
+const MAX_SIZE: u64 = 4096;
+const ALIGNS: [usize; 7] = [1, 2, 4, 8, 16, 64, 4096];
+//每个槽最多MAX_SIZE字节，存活字节数不会超过这个值，与种子无关
+const LIVE_MAX: usize = SLOTS * MAX_SIZE as usize;
+//堆映射大小的预算，远小于HEAP_MAX_SIZE，因此任何一次分配失败都是错误；
+//碎片化使堆超出预算时同样视为失败，用打印的种子复现
+const HEAP_BUDGET: usize = 8 * LIVE_MAX;
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use os::allocator;
+
+    serial_print!("heap_fuzz::fuzz...\t");
+
+    os::init(boot_info);
+    allocator::init_heap().expect("heap initialization failed");
+
+    serial_print!("(seed {:#x}) ", SEED);
+    fuzz(SEED);
+
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+/// ## 说明
+/// xorshift64伪随机数生成器
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+/// ## 说明
+/// 存活的分配，保存在定长数组中以免依赖被测的堆
+#[derive(Clone, Copy)]
+struct Slot {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+fn pattern(size: usize, i: usize) -> u8 {
+    (size as u8) ^ (i as u8).wrapping_mul(31)
+}
+
+unsafe fn fill(ptr: *mut u8, size: usize) {
+    for i in 0..size {
+        ptr.add(i).write(pattern(size, i));
+    }
+}
+
+unsafe fn verify(op: usize, ptr: *mut u8, size: usize, len: usize) {
+    for i in 0..len {
+        let byte = ptr.add(i).read();
+        if byte != pattern(size, i) {
+            panic!("op {}: pattern mismatch at {:p}+{}", op, ptr, i);
+        }
+    }
+}
+
+fn fuzz(seed: u64) {
+    let mut rng = XorShift(seed);
+    let mut slots: [Option<Slot>; SLOTS] = [None; SLOTS];
+
+    for op in 0..OPERATIONS {
+        let index = rng.below(SLOTS as u64) as usize;
+        let action = rng.below(10);
+        match slots[index] {
+            None => {
+                let size = 1 + rng.below(MAX_SIZE) as usize;
+                let align = ALIGNS[rng.below(ALIGNS.len() as u64) as usize];
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let ptr = unsafe { alloc(layout) };
+                if ptr.is_null() {
+                    panic!("op {}: unexpected null for {:?}", op, layout);
+                }
+                if !(ptr as usize).is_multiple_of(align) {
+                    panic!("op {}: {:p} not aligned to {}", op, ptr, align);
+                }
+                unsafe { fill(ptr, size) };
+                slots[index] = Some(Slot { ptr, layout });
+            }
+            Some(slot) if action < 8 => {
+                let size = slot.layout.size();
+                unsafe {
+                    verify(op, slot.ptr, size, size);
+                    dealloc(slot.ptr, slot.layout);
+                }
+                slots[index] = None;
+            }
+            Some(slot) => {
+                let old_size = slot.layout.size();
+                let new_size = 1 + rng.below(MAX_SIZE) as usize;
+                let ptr = unsafe { realloc(slot.ptr, slot.layout, new_size) };
+                if ptr.is_null() {
+                    panic!("op {}: unexpected null on realloc to {}", op, new_size);
+                }
+                let layout = Layout::from_size_align(new_size, slot.layout.align()).unwrap();
+                unsafe {
+                    verify(op, ptr, old_size, old_size.min(new_size));
+                    fill(ptr, new_size);
+                }
+                slots[index] = Some(Slot { ptr, layout });
+            }
+        }
+    }
+
+    for slot in slots.iter().flatten() {
+        unsafe { dealloc(slot.ptr, slot.layout) };
+    }
+    let mapped = os::allocator::heap_info().mapped_bytes;
+    assert!(
+        mapped <= HEAP_BUDGET,
+        "heap grew to {} bytes for at most {} live bytes",
+        mapped,
+        LIVE_MAX
+    );
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
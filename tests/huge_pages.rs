@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::memory::{self, vspace, MappingSize};
+use x86_64::structures::paging::{FrameAllocator, Page, PageTableFlags, PhysFrame, Size2MiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+const HUGE: u64 = 2 * 1024 * 1024;
+
+fn translate(addr: VirtAddr) -> Option<(PhysAddr, MappingSize)> {
+    memory::with_paging(|paging| memory::translate(&paging.mapper, addr))
+        .expect("paging not installed")
+        .map(|(phys, size, _)| (phys, size))
+}
+
+//触碰区域的开头、中间和末尾，并确认它们都位于同一个大帧中
+fn touch_and_check(start: VirtAddr, frame: PhysAddr) {
+    for offset in [0, HUGE / 2, HUGE - 8] {
+        let ptr: *mut u64 = (start + offset).as_mut_ptr();
+        unsafe {
+            ptr.write_volatile(offset);
+            assert_eq!(ptr.read_volatile(), offset);
+        }
+        assert_eq!(
+            translate(start + offset),
+            Some((frame + offset, MappingSize::Size2MiB))
+        );
+    }
+}
+
+#[test_case]
+fn map_single_huge_page() {
+    let start = vspace::reserve("huge-test", HUGE, HUGE).expect("no virtual space");
+    let frame: PhysFrame<Size2MiB> = memory::with_paging(|paging| {
+        let frame = paging
+            .frame_allocator
+            .allocate_frame()
+            .expect("no 2MiB frame");
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        memory::map_huge(
+            Page::containing_address(start),
+            frame,
+            flags,
+            &mut paging.mapper,
+            &mut paging.frame_allocator,
+        )
+        .expect("map_huge failed");
+        frame
+    })
+    .expect("paging not installed");
+
+    assert!(frame.start_address().is_aligned(HUGE));
+    touch_and_check(start, frame.start_address());
+}
+
+#[test_case]
+fn map_huge_range() {
+    let start = vspace::reserve("huge-range-test", HUGE, HUGE).expect("no virtual space");
+    let first: PhysFrame<Size2MiB> = memory::with_paging(|paging| {
+        //多个大帧不保证物理连续，因此只映射一个大帧
+        let first: PhysFrame<Size2MiB> = paging.frame_allocator.allocate_frame().unwrap();
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        memory::map_range_huge(
+            start,
+            first.start_address(),
+            HUGE,
+            flags,
+            &mut paging.mapper,
+            &mut paging.frame_allocator,
+        )
+        .expect("map_range_huge failed");
+        first
+    })
+    .expect("paging not installed");
+
+    touch_and_check(start, first.start_address());
+}
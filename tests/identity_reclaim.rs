@@ -0,0 +1,85 @@
+//测试移除低端恒等映射后，解引用较小的整数地址会引发页错误
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use os::memory::{self, MappingSize};
+use os::{exit_qemu, serial_print, serial_println, vga_buffer, QemuExitCode};
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::{PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+//预期引发页错误的地址
+const TARGET: u64 = 0x1000;
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("identity_reclaim::low_address_faults...\t");
+
+    os::init(boot_info);
+    x86_64::instructions::interrupts::disable(); //测试IDT中没有时钟中断的处理函数
+    init_test_idt();
+
+    //控制台已经改用高地址的映射，VGA帧的恒等映射也被移除
+    let (identity, console) = memory::with_paging(|paging| {
+        (
+            memory::translate(&paging.mapper, VirtAddr::new(memory::VGA_FRAME)),
+            memory::translate(&paging.mapper, vga_buffer::buffer_addr()),
+        )
+    })
+    .expect("paging not installed");
+    assert!(identity.is_none());
+    assert!(matches!(
+        console,
+        Some((phys, MappingSize::Size4KiB, _)) if phys == PhysAddr::new(memory::VGA_FRAME)
+    ));
+
+    let ptr = TARGET as *const u64;
+    core::hint::black_box(unsafe { ptr.read_volatile() }); //应当触发页错误
+
+    serial_println!("[no page fault]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+        && Cr2::read().as_u64() == TARGET
+    {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!(
+            "unexpected page fault: {:?} at {:?}",
+            error_code,
+            Cr2::read()
+        );
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
@@ -0,0 +1,148 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::fs::initrd::{self, Archive, InitrdError};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+/*
+    tests/initrd/test.cpio为newc格式，填充到1024字节：
+      .                 目录
+      hello.txt         22字节
+      empty             0字节
+      docs              目录
+      docs/readme.md    25字节
+*/
+static ARCHIVE: &[u8] = include_bytes!("initrd/test.cpio");
+//第二项（hello.txt）的头部偏移：110字节的头部加上"."和NUL
+const HELLO_OFFSET: usize = 112;
+
+#[test_case]
+fn list_entries() {
+    let archive = Archive::parse(ARCHIVE).unwrap();
+    let mut entries = archive.entries();
+    let expected = [
+        ("hello.txt", 22, false),
+        ("empty", 0, false),
+        ("docs", 0, true),
+        ("docs/readme.md", 25, false),
+    ];
+    for (name, size, is_dir) in expected {
+        let entry = entries.next().unwrap();
+        assert_eq!(entry.name, name);
+        assert_eq!(entry.data.len(), size);
+        assert_eq!(entry.is_dir(), is_dir);
+    }
+    assert!(entries.next().is_none());
+}
+
+#[test_case]
+fn open_files() {
+    let archive = Archive::parse(ARCHIVE).unwrap();
+    assert_eq!(
+        archive.open("hello.txt"),
+        Ok(&b"hello from the initrd\n"[..])
+    );
+    assert_eq!(archive.open("/empty"), Ok(&b""[..]));
+    let readme = archive.open("./docs/readme.md").unwrap();
+    assert_eq!(readme, b"# docs\nzero-copy archive\n");
+    //返回的切片直接指向归档
+    let range = ARCHIVE.as_ptr_range();
+    assert!(range.contains(&readme.as_ptr()));
+    assert_eq!(archive.open("docs"), Err(InitrdError::IsADirectory));
+    assert_eq!(archive.open("missing"), Err(InitrdError::NotFound));
+    assert_eq!(archive.open("TRAILER!!!"), Err(InitrdError::NotFound));
+}
+
+#[test_case]
+fn truncated_archive() {
+    //第一个头部不完整
+    assert_eq!(
+        Archive::parse(&ARCHIVE[..50]).err(),
+        Some(InitrdError::Truncated { offset: 0 })
+    );
+    //第二个头部不完整，以及它的内容不完整
+    for len in [HELLO_OFFSET + 60, HELLO_OFFSET + 130] {
+        assert_eq!(
+            Archive::parse(&ARCHIVE[..len]).err(),
+            Some(InitrdError::Truncated {
+                offset: HELLO_OFFSET
+            })
+        );
+    }
+    assert_eq!(
+        Archive::parse(&[]).err(),
+        Some(InitrdError::Truncated { offset: 0 })
+    );
+}
+
+#[test_case]
+fn corrupt_archive() {
+    let mut data = [0u8; 1024];
+    data.copy_from_slice(ARCHIVE);
+    data[HELLO_OFFSET + 5] = b'9';
+    assert_eq!(
+        Archive::parse(&data).err(),
+        Some(InitrdError::BadMagic {
+            offset: HELLO_OFFSET
+        })
+    );
+
+    data.copy_from_slice(ARCHIVE);
+    data[HELLO_OFFSET + 6 + 6 * 8] = b'z'; //文件大小字段
+    assert_eq!(
+        Archive::parse(&data).err(),
+        Some(InitrdError::BadField {
+            offset: HELLO_OFFSET
+        })
+    );
+
+    //文件大小远超归档
+    data.copy_from_slice(ARCHIVE);
+    data[HELLO_OFFSET + 6 + 6 * 8..][..8].copy_from_slice(b"7FFFFFFF");
+    assert_eq!(
+        Archive::parse(&data).err(),
+        Some(InitrdError::Truncated {
+            offset: HELLO_OFFSET
+        })
+    );
+
+    //文件名不以NUL结尾
+    data.copy_from_slice(ARCHIVE);
+    data[HELLO_OFFSET + 110 + 9] = b'!';
+    assert_eq!(
+        Archive::parse(&data).err(),
+        Some(InitrdError::BadName {
+            offset: HELLO_OFFSET
+        })
+    );
+}
+
+#[test_case]
+fn global_archive() {
+    assert_eq!(initrd::open("hello.txt"), Err(InitrdError::NotInitialized));
+    assert_eq!(initrd::init_from_slice(ARCHIVE), Ok(4));
+    assert_eq!(
+        initrd::init_from_slice(ARCHIVE),
+        Err(InitrdError::AlreadyInitialized)
+    );
+    assert_eq!(initrd::open("empty"), Ok(&b""[..]));
+    assert_eq!(initrd::read_dir().unwrap().count(), 4);
+}
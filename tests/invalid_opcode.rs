@@ -0,0 +1,58 @@
+//测试执行ud2会触发无效操作码异常，RIP指向ud2指令
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    serial_print!("invalid_opcode::ud2...\t");
+
+    os::gdt::init();
+    init_test_idt();
+
+    unsafe { core::arch::asm!("ud2") }; //应当触发#UD
+
+    serial_println!("[no invalid opcode]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.invalid_opcode
+            .set_handler_fn(test_invalid_opcode_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    //ud2的编码为0f 0b
+    let rip = stack_frame.instruction_pointer.as_ptr::<[u8; 2]>();
+    let opcode = unsafe { rip.read_volatile() };
+    if opcode == [0x0f, 0x0b] {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("unexpected opcode {:02x?}", opcode);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::memory::{self, lazy, FrameStats};
+use x86_64::structures::paging::Translate;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+//帧分配器已分配的帧数，以及其中页表本身占用的帧数
+fn frame_usage() -> (usize, usize) {
+    memory::with_paging(|paging| {
+        let offset = paging.mapper.phys_offset();
+        (
+            paging.frame_allocator.allocated_frames(),
+            memory::table_frames_allocated(paging.mapper.level_4_table(), offset),
+        )
+    })
+    .expect("paging not installed")
+}
+
+#[test_case]
+fn lazy_pages_mapped_on_touch() {
+    const PAGES: u64 = 64;
+    let start = memory::alloc_lazy(PAGES).expect("alloc_lazy failed");
+    let translated = memory::with_paging(|paging| paging.mapper.translate_addr(start));
+    assert_eq!(translated, Some(None));
+
+    let resident_before = lazy::resident_pages();
+    let (frames_before, tables_before) = frame_usage();
+
+    for i in 0..PAGES {
+        let ptr: *mut u8 = (start + i * 4096 + 123u64).as_mut_ptr();
+        unsafe {
+            assert_eq!(ptr.read_volatile(), 0); //新帧已清零
+            ptr.write_volatile(i as u8);
+        }
+    }
+
+    //再次访问不会消耗新的帧
+    for i in 0..PAGES {
+        let ptr: *const u8 = (start + i * 4096 + 123u64).as_ptr();
+        assert_eq!(unsafe { ptr.read_volatile() }, i as u8);
+    }
+    assert_eq!(lazy::resident_pages() - resident_before, PAGES as usize);
+    //每个页面恰好从帧分配器取得一个数据帧，其余的是首次访问时新建的页表
+    let (frames, tables) = frame_usage();
+    assert_eq!(
+        frames - frames_before,
+        PAGES as usize + (tables - tables_before)
+    );
+}
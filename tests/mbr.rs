@@ -0,0 +1,159 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::storage::mbr::{self, MbrError, PartitionProblem, TYPE_EXTENDED_LBA};
+use os::storage::{self, BlockDevice, BlockError, RamDisk};
+use spin::Mutex;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+    os::allocator::init_heap().expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+const DISK_SECTORS: usize = 256;
+const LINUX: u8 = 0x83;
+
+//构造一个MBR或EBR扇区，表项为(类型, 起始LBA, 扇区数)
+fn table(entries: &[(u8, u32, u32)]) -> [u8; 512] {
+    let mut sector = [0u8; 512];
+    for (i, &(kind, start, sectors)) in entries.iter().enumerate() {
+        let entry = &mut sector[0x1be + i * 16..][..16];
+        entry[4] = kind;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&sectors.to_le_bytes());
+    }
+    sector[510] = 0x55;
+    sector[511] = 0xaa;
+    sector
+}
+
+fn disk_with(sectors: &[(u64, [u8; 512])]) -> RamDisk {
+    let mut disk = RamDisk::new(512, DISK_SECTORS);
+    for (lba, sector) in sectors {
+        disk.write_blocks(*lba, sector).unwrap();
+    }
+    disk
+}
+
+#[test_case]
+fn no_signature() {
+    let mut disk = RamDisk::new(512, DISK_SECTORS);
+    assert_eq!(mbr::parse(&mut disk), Err(MbrError::NoSignature));
+    let mut disk = RamDisk::new(4096, 8);
+    assert_eq!(mbr::parse(&mut disk), Err(MbrError::BadBlockSize(4096)));
+}
+
+#[test_case]
+fn single_partition() {
+    let mut disk = disk_with(&[(0, table(&[(LINUX, 128, 100)]))]);
+    let partitions = mbr::parse(&mut disk).unwrap();
+    assert_eq!(partitions.len(), 1);
+    let partition = partitions[0];
+    assert_eq!(partition.index, 1);
+    assert_eq!(partition.kind, LINUX);
+    assert_eq!(partition.start_lba, 128);
+    assert_eq!(partition.sectors, 100);
+    assert_eq!(partition.problem, None);
+}
+
+#[test_case]
+fn extended_chain_of_three() {
+    //扩展分区从LBA 100开始，每个EBR后面紧跟它的逻辑分区
+    let disk = disk_with(&[
+        (0, table(&[(LINUX, 1, 50), (TYPE_EXTENDED_LBA, 100, 150)])),
+        (100, table(&[(LINUX, 1, 20), (TYPE_EXTENDED_LBA, 30, 40)])),
+        (130, table(&[(LINUX, 1, 20), (TYPE_EXTENDED_LBA, 60, 40)])),
+        (160, table(&[(LINUX, 1, 30)])),
+    ]);
+    let disk = Arc::new(Mutex::new(disk));
+    let partitions = mbr::parse(&mut disk.clone()).unwrap();
+    let layout: alloc::vec::Vec<_> = partitions
+        .iter()
+        .map(|p| (p.index, p.start_lba, p.sectors, p.problem))
+        .collect();
+    assert_eq!(
+        layout,
+        [
+            (1, 1, 50, None),
+            (5, 101, 20, None),
+            (6, 131, 20, None),
+            (7, 161, 30, None),
+        ]
+    );
+
+    //注册为分区设备后，写入分区的数据落在底层设备的对应位置
+    assert_eq!(
+        mbr::register_partitions("ram1", disk.clone(), &partitions),
+        Ok(4)
+    );
+    storage::with_device("ram1p6", |dev| {
+        assert_eq!(dev.block_count(), 20);
+        dev.write_blocks(0, &[0x66; 512])
+    })
+    .unwrap()
+    .unwrap();
+    let mut sector = [0u8; 512];
+    disk.lock().read_blocks(131, &mut sector).unwrap();
+    assert!(sector.iter().all(|&b| b == 0x66));
+    for name in ["ram1p1", "ram1p5", "ram1p6", "ram1p7"] {
+        assert!(storage::unregister(name).is_some());
+    }
+}
+
+#[test_case]
+fn overlapping_and_out_of_range() {
+    let mut disk = disk_with(&[(
+        0,
+        table(&[(LINUX, 10, 50), (LINUX, 40, 50), (LINUX, 200, 100)]),
+    )]);
+    let partitions = mbr::parse(&mut disk).unwrap();
+    assert_eq!(partitions.len(), 3);
+    assert_eq!(partitions[0].problem, None);
+    assert_eq!(partitions[1].problem, Some(PartitionProblem::Overlaps(1)));
+    assert_eq!(partitions[2].problem, Some(PartitionProblem::OutOfRange));
+
+    //超出设备的分区不注册，重叠的分区仍然注册
+    let disk = Arc::new(Mutex::new(disk));
+    assert_eq!(mbr::register_partitions("ram2", disk, &partitions), Ok(2));
+    assert!(storage::unregister("ram2p1").is_some());
+    assert!(storage::unregister("ram2p2").is_some());
+    assert!(storage::unregister("ram2p3").is_none());
+}
+
+#[test_case]
+fn register_partitions_all_or_nothing() {
+    let disk = disk_with(&[(0, table(&[(LINUX, 10, 50), (LINUX, 100, 50)]))]);
+    let disk = Arc::new(Mutex::new(disk));
+    let partitions = mbr::parse(&mut disk.clone()).unwrap();
+
+    //第二个分区的名字已被占用时，第一个分区也不应留在设备表中
+    storage::register("ram3p2", alloc::boxed::Box::new(RamDisk::new(512, 1))).unwrap();
+    assert_eq!(
+        mbr::register_partitions("ram3", disk.clone(), &partitions),
+        Err(BlockError::AlreadyRegistered)
+    );
+    assert!(storage::unregister("ram3p1").is_none());
+
+    assert!(storage::unregister("ram3p2").is_some());
+    assert_eq!(mbr::register_partitions("ram3", disk, &partitions), Ok(2));
+    assert!(storage::unregister("ram3p1").is_some());
+    assert!(storage::unregister("ram3p2").is_some());
+}
@@ -0,0 +1,91 @@
+//测试跳转到设置了NO_EXECUTE的页面会触发取指页错误
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use os::memory;
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::structures::paging::{FrameAllocator, Page, PageTableFlags};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+const CODE_PAGE: u64 = 0x4444_0000_0000;
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("nx_exec::jump_to_no_execute_page...\t");
+
+    //没有NX的CPU上os::init会panic，直接跳过
+    if !os::cpu::features().nx {
+        serial_println!("[skipped: CPUID reports no NX support]");
+        exit_qemu(QemuExitCode::Success);
+        loop {}
+    }
+
+    os::init(boot_info);
+    x86_64::instructions::interrupts::disable();
+    init_test_idt();
+
+    let page = Page::containing_address(VirtAddr::new(CODE_PAGE));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    memory::with_paging(|paging| {
+        let frame = paging
+            .frame_allocator
+            .allocate_frame()
+            .expect("out of frames");
+        memory::map_page(
+            page,
+            frame,
+            flags,
+            &mut paging.mapper,
+            &mut paging.frame_allocator,
+        )
+    })
+    .expect("paging not installed")
+    .expect("mapping failed");
+
+    let code: *mut u8 = page.start_address().as_mut_ptr();
+    unsafe { code.write_volatile(0xc3) }; //ret
+    let function: extern "C" fn() = unsafe { core::mem::transmute(code) };
+    function(); //应当触发页错误
+
+    serial_println!("[no-execute page was executable]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let expected = PageFaultErrorCode::INSTRUCTION_FETCH | PageFaultErrorCode::PROTECTION_VIOLATION;
+    if error_code.contains(expected) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\nunexpected page fault: {:?}", error_code);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
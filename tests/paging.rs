@@ -0,0 +1,374 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use os::memory::{self, MappingSize, MemoryError};
+use os::{serial_println, vga_buffer};
+use x86_64::structures::paging::{Mapper, Page, PageTableFlags, PhysFrame};
+use x86_64::{PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+static PHYS_MEM_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+fn phys_mem_offset() -> VirtAddr {
+    VirtAddr::new(PHYS_MEM_OFFSET.load(Ordering::SeqCst))
+}
+
+fn translate(addr: VirtAddr) -> Option<(PhysAddr, MappingSize, PageTableFlags)> {
+    memory::with_paging(|paging| memory::translate(&paging.mapper, addr))
+        .expect("paging not installed")
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+    PHYS_MEM_OFFSET.store(boot_info.physical_memory_offset, Ordering::SeqCst);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+#[test_case]
+fn translate_4k_mapping() {
+    //控制台在初始化时被重新映射到MMIO窗口中
+    let addr = vga_buffer::buffer_addr();
+    let (phys, size, flags) = translate(addr).expect("VGA buffer not mapped");
+    assert_eq!(
+        (phys, size),
+        (PhysAddr::new(0xb8000), MappingSize::Size4KiB)
+    );
+    assert!(flags.contains(PageTableFlags::PRESENT | PageTableFlags::WRITABLE));
+    //兼容旧接口的结果与新接口一致
+    #[allow(deprecated)]
+    let old = unsafe { memory::translate_addr(addr, phys_mem_offset()) };
+    assert_eq!(old, Some(phys));
+}
+
+#[test_case]
+fn translate_offset_mapped_region() {
+    //物理内存映射区域由bootloader使用大页建立
+    let addr = phys_mem_offset() + 0x20_1234u64;
+    let (phys, size, flags) = translate(addr).expect("offset region not mapped");
+    assert_eq!(phys, PhysAddr::new(0x20_1234));
+    assert_ne!(size, MappingSize::Size4KiB);
+    assert!(flags.contains(PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE));
+}
+
+#[test_case]
+fn audit_after_init() {
+    let report = memory::audit(phys_mem_offset());
+    serial_println!("{}", report);
+    assert!(report.mappings.size_4k > 0);
+    //物理内存映射由bootloader使用大页建立
+    assert!(report.mappings.size_2m + report.mappings.size_1g > 0);
+    assert_eq!(report.out_of_range, 0);
+    assert_eq!(report.user_in_kernel, 0);
+    //内核映像、引导程序的映射和MMIO窗口都已经按W^X设置
+    assert_eq!(report.wx_violations, 0, "{}", report);
+}
+
+fn map_region(phys: u64, size: usize) -> VirtAddr {
+    memory::with_paging(|paging| {
+        memory::map_physical_region(
+            PhysAddr::new(phys),
+            size,
+            &mut paging.mapper,
+            &mut paging.frame_allocator,
+        )
+    })
+    .expect("paging not installed")
+    .expect("map_physical_region failed")
+}
+
+fn unmap_region(virt: VirtAddr, size: usize) {
+    memory::with_paging(|paging| memory::unmap_physical_region(virt, size, &mut paging.mapper))
+        .expect("paging not installed")
+        .expect("unmap_physical_region failed");
+}
+
+#[test_case]
+fn mmio_vga_write_visible() {
+    //通过MMIO映射写入最后一行的最后一个字符，再从控制台使用的映射读回
+    let offset = (25 * 80 - 1) * 2;
+    let virt = map_region(0xb8000 + offset, 2);
+    assert_eq!(virt.as_u64() & 0xfff, offset);
+
+    let cell: *mut u16 = virt.as_mut_ptr();
+    let vga: *mut u16 = (vga_buffer::buffer_addr() + offset).as_mut_ptr();
+    unsafe {
+        let old = vga.read_volatile();
+        cell.write_volatile(0x0f21);
+        assert_eq!(vga.read_volatile(), 0x0f21);
+        vga.write_volatile(old);
+    }
+
+    unmap_region(virt, 2);
+    assert_eq!(translate(virt), None);
+}
+
+#[test_case]
+fn mmio_regions_do_not_overlap() {
+    let size = 3 * 4096 + 17;
+    let a = map_region(0xb8000, size);
+    let b = map_region(0xb8000, size);
+    assert!(a.as_u64() + size as u64 <= b.as_u64() || b.as_u64() + size as u64 <= a.as_u64());
+    unmap_region(a, size);
+    unmap_region(b, size);
+}
+
+#[test_case]
+fn identity_map_low_frame() {
+    //VGA图形窗口位于1MiB以下，bootloader可能已经恒等映射了它
+    let addr = 0xa0000;
+    let frame = PhysFrame::containing_address(PhysAddr::new(addr));
+    let before = translate(VirtAddr::new(addr)).map(|(phys, _, _)| phys);
+
+    let result = memory::with_paging(|paging| {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+        memory::identity_map(
+            frame,
+            flags,
+            &mut paging.mapper,
+            &mut paging.frame_allocator,
+        )
+    })
+    .expect("paging not installed");
+
+    match before {
+        Some(phys) if phys != PhysAddr::new(addr) => match result {
+            Err(MemoryError::AlreadyMapped { current, .. }) => {
+                assert_eq!(current.start_address(), phys)
+            }
+            other => panic!("expected PageAlreadyMapped, got {:?}", other),
+        },
+        _ => {
+            assert!(result.is_ok());
+            let after = translate(VirtAddr::new(addr)).map(|(phys, _, _)| phys);
+            assert_eq!(after, Some(PhysAddr::new(addr)));
+        }
+    }
+
+    //仅撤销本测试新建的映射
+    if before.is_none() {
+        let page = Page::containing_address(VirtAddr::new(addr));
+        memory::with_paging(|paging| memory::unmap_page_keep_frame(page, &mut paging.mapper))
+            .expect("paging not installed")
+            .expect("unmap failed");
+    }
+}
+
+#[test_case]
+fn identity_map_refuses_different_mapping() {
+    //先把页面映射到VGA帧，再尝试以恒等方式映射同一页面
+    let addr = 0xdead_b000;
+    let page = Page::containing_address(VirtAddr::new(addr));
+    let vga = PhysFrame::containing_address(PhysAddr::new(memory::VGA_FRAME));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    memory::with_paging(|paging| {
+        unsafe {
+            paging
+                .mapper
+                .map_to(page, vga, flags, &mut paging.frame_allocator)
+        }
+        .expect("map_to failed")
+        .flush();
+
+        let frame = PhysFrame::containing_address(PhysAddr::new(addr));
+        let result = memory::identity_map(
+            frame,
+            flags,
+            &mut paging.mapper,
+            &mut paging.frame_allocator,
+        );
+        assert_eq!(
+            result,
+            Err(MemoryError::AlreadyMapped {
+                page,
+                current: vga,
+                requested: frame,
+            })
+        );
+
+        memory::unmap_page_keep_frame(page, &mut paging.mapper).expect("unmap failed");
+    })
+    .expect("paging not installed");
+}
+
+#[test_case]
+fn map_page_reports_conflicting_frame() {
+    let page = Page::containing_address(VirtAddr::new(0xdead_a000));
+    let vga = PhysFrame::containing_address(PhysAddr::new(memory::VGA_FRAME));
+    let other = vga + 1;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    memory::with_paging(|paging| {
+        let (mapper, frames) = (&mut paging.mapper, &mut paging.frame_allocator);
+        memory::map_page(page, vga, flags, mapper, frames).expect("map_page failed");
+        //映射到同一帧视为成功，映射到其他帧返回AlreadyMapped
+        assert_eq!(memory::map_page(page, vga, flags, mapper, frames), Ok(()));
+        assert_eq!(
+            memory::map_page(page, other, flags, mapper, frames),
+            Err(MemoryError::AlreadyMapped {
+                page,
+                current: vga,
+                requested: other,
+            })
+        );
+        assert_eq!(
+            memory::map_page(page + 1, vga, PageTableFlags::WRITABLE, mapper, frames),
+            Err(MemoryError::InvalidFlags {
+                page: page + 1,
+                flags: PageTableFlags::WRITABLE,
+            })
+        );
+
+        memory::unmap_page_keep_frame(page, mapper).expect("unmap failed");
+    })
+    .expect("paging not installed");
+}
+
+#[test_case]
+fn dump_vga_translation() {
+    let result = memory::dump_translation(vga_buffer::buffer_addr(), phys_mem_offset());
+    assert_eq!(
+        result,
+        Some((PhysAddr::new(0xb8000), MappingSize::Size4KiB))
+    );
+}
+
+#[test_case]
+fn count_mapped_pages_sees_new_mapping() {
+    let addr = 0xdead_c000;
+    let page = Page::containing_address(VirtAddr::new(addr));
+    let vga = PhysFrame::containing_address(PhysAddr::new(memory::VGA_FRAME));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    memory::with_paging(|paging| {
+        let offset = paging.mapper.phys_offset();
+        let before = memory::count_mapped_pages(paging.mapper.level_4_table(), offset);
+        assert!(before.size_4k > 0);
+
+        unsafe {
+            paging
+                .mapper
+                .map_to(page, vga, flags, &mut paging.frame_allocator)
+        }
+        .expect("map_to failed")
+        .flush();
+        let mapped = memory::count_mapped_pages(paging.mapper.level_4_table(), offset);
+        assert_eq!(mapped.size_4k, before.size_4k + 1);
+
+        memory::unmap_page_keep_frame(page, &mut paging.mapper).expect("unmap failed");
+        let after = memory::count_mapped_pages(paging.mapper.level_4_table(), offset);
+        assert_eq!(after, before);
+    })
+    .expect("paging not installed");
+}
+
+#[test_case]
+fn user_mapping_sets_user_on_every_level() {
+    //使用一个未被内核占用的P4条目，避免给内核的页表加上USER标志
+    let addr = memory::with_paging(|paging| {
+        let p4 = paging.mapper.level_4_table();
+        let index = (1..256)
+            .find(|&i| p4[i].is_unused())
+            .expect("no unused P4 entry");
+        let start = Page::containing_address(VirtAddr::new((index as u64) << 39));
+        memory::map_user_region(
+            start,
+            2,
+            PageTableFlags::WRITABLE,
+            &mut paging.mapper,
+            &mut paging.frame_allocator,
+        )
+        .expect("map_user_region failed");
+        start.start_address() + 4096u64
+    })
+    .expect("paging not installed");
+
+    assert!(memory::dump_translation(addr, phys_mem_offset()).is_some());
+    for (level, flags) in memory::entry_flags(addr, phys_mem_offset())
+        .iter()
+        .enumerate()
+    {
+        let flags = flags.expect("level not present");
+        assert!(
+            flags.contains(PageTableFlags::USER_ACCESSIBLE),
+            "P{} entry is not user accessible",
+            4 - level
+        );
+    }
+}
+
+#[test_case]
+fn kernel_mapping_is_not_user_accessible() {
+    let addr = memory::vspace::reserve("user-flag-test", 4096, 4096).expect("no virtual space");
+    let page: Page = Page::containing_address(addr);
+    let vga = PhysFrame::containing_address(PhysAddr::new(memory::VGA_FRAME));
+    memory::with_paging(|paging| {
+        unsafe {
+            paging.mapper.map_to(
+                page,
+                vga,
+                memory::wx::DATA_FLAGS,
+                &mut paging.frame_allocator,
+            )
+        }
+        .expect("map_to failed")
+        .flush();
+    })
+    .expect("paging not installed");
+
+    let flags = memory::entry_flags(addr, phys_mem_offset());
+    assert!(flags.iter().all(Option::is_some));
+    assert!(flags
+        .iter()
+        .flatten()
+        .all(|flags| !flags.contains(PageTableFlags::USER_ACCESSIBLE)));
+}
+
+#[test_case]
+fn phys_slice_reads_vga_text() {
+    use core::fmt::Write;
+
+    let s = "phys_slice reads this line";
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = os::vga_buffer::WRITER.lock();
+        writeln!(writer, "\n{}", s).expect("writeln failed");
+
+        //文本位于倒数第二行，每个字符占两个字节：ASCII码和颜色
+        let row = PhysAddr::new(memory::VGA_FRAME + 23 * 160);
+        let cells = unsafe { memory::phys_slice(row, s.len() * 2) };
+        for (i, c) in s.bytes().enumerate() {
+            assert_eq!(cells[i * 2], c);
+        }
+    });
+}
+
+#[test_case]
+fn phys_read_write_round_trip() {
+    use x86_64::structures::paging::{FrameAllocator, FrameDeallocator};
+
+    assert_eq!(memory::phys_offset(), phys_mem_offset());
+    memory::with_paging(|paging| {
+        let frame: PhysFrame = paging.frame_allocator.allocate_frame().unwrap();
+        let addr = frame.start_address() + 8u64;
+        unsafe {
+            memory::phys_write(addr, 0x1234_5678_u64);
+            assert_eq!(memory::phys_read::<u64>(addr), 0x1234_5678);
+            paging.frame_allocator.deallocate_frame(frame);
+        }
+    })
+    .expect("paging not installed");
+}
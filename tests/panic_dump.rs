@@ -0,0 +1,78 @@
+//测试panic处理函数能在关中断时保存寄存器，并输出包含CR3的现场信息
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::fmt;
+use core::panic::PanicInfo;
+use os::crash::{self, Registers};
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::instructions::interrupts;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    serial_print!("panic_dump::registers_and_stack...\t");
+
+    interrupts::without_interrupts(|| panic!("deliberate panic"));
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+//逐行检查输出，记录是否出现CR3行
+struct LineChecker {
+    line: [u8; 128],
+    len: usize,
+    saw_cr3: bool,
+}
+
+impl fmt::Write for LineChecker {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if byte == b'\n' {
+                self.saw_cr3 |= self.line[..self.len].starts_with(b"CR3: 0x");
+                self.len = 0;
+            } else if self.len < self.line.len() {
+                self.line[self.len] = byte;
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check(regs: &Registers) -> Result<(), &'static str> {
+    if regs.rflags & (1 << 9) != 0 {
+        return Err("interrupts enabled in snapshot");
+    }
+    let mut checker = LineChecker {
+        line: [0; 128],
+        len: 0,
+        saw_cr3: false,
+    };
+    crash::write_dump(&mut checker, regs).map_err(|_| "dump failed")?;
+    if !checker.saw_cr3 {
+        return Err("no CR3 line in dump");
+    }
+    Ok(())
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    let regs = crash::snapshot();
+    crash::dump_to_serial(&regs);
+    match check(&regs) {
+        Ok(()) => {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success);
+        }
+        Err(reason) => {
+            serial_println!("[failed]");
+            serial_println!("{}", reason);
+            exit_qemu(QemuExitCode::Failed);
+        }
+    }
+    loop {}
+}
@@ -0,0 +1,22 @@
+//测试power::shutdown能让QEMU退出：关机端口使QEMU以0退出，isa-debug-exit以成功码退出，
+//两者都无效时shutdown停机，测试因超时而失败
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::{power, serial_print, serial_println};
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    serial_print!("power_off::shutdown...\t");
+    //shutdown成功时不会返回，只能在调用之前输出结果
+    serial_println!("[ok]");
+    power::shutdown();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
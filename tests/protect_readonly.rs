@@ -0,0 +1,111 @@
+//测试将页面改为只读后写入会触发带CAUSED_BY_WRITE的页错误
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use os::memory::{self, vspace, ProtectError};
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags};
+
+entry_point!(main);
+
+//预期引发页错误的地址
+static TARGET: AtomicU64 = AtomicU64::new(0);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("protect_readonly::write_after_protect...\t");
+
+    os::init_paging(boot_info);
+    os::gdt::init();
+    init_test_idt();
+
+    let start = vspace::reserve("protect-test", 2 * 4096, 4096).expect("no virtual space");
+    let page: Page = Page::containing_address(start);
+    memory::with_paging(|paging| {
+        let frame = paging.frame_allocator.allocate_frame().unwrap();
+        unsafe {
+            paging
+                .mapper
+                .map_to(
+                    page,
+                    frame,
+                    memory::wx::DATA_FLAGS,
+                    &mut paging.frame_allocator,
+                )
+                .expect("map_to failed")
+                .flush();
+        }
+    })
+    .expect("paging not installed");
+
+    let ptr: *mut u64 = start.as_mut_ptr();
+    unsafe { ptr.write_volatile(42) };
+
+    memory::with_paging(|paging| {
+        let read_only = PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE;
+        let user = read_only | PageTableFlags::USER_ACCESSIBLE;
+        //内核地址上设置USER_ACCESSIBLE需要显式允许
+        assert_eq!(
+            memory::protect(page, user, &mut paging.mapper, false),
+            Err(ProtectError::UserOnKernel)
+        );
+        //第二个页面没有映射，错误中报告该页面
+        assert_eq!(
+            memory::protect_range(page, 2, read_only, &mut paging.mapper, false),
+            Err((page + 1, ProtectError::NotMapped))
+        );
+        memory::protect(page, read_only, &mut paging.mapper, false).expect("protect failed");
+    })
+    .expect("paging not installed");
+
+    assert_eq!(unsafe { ptr.read_volatile() }, 42);
+    TARGET.store(start.as_u64(), Ordering::SeqCst);
+    unsafe { ptr.write_volatile(43) }; //应当触发页错误
+
+    serial_println!("[no page fault]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let expected = PageFaultErrorCode::CAUSED_BY_WRITE | PageFaultErrorCode::PROTECTION_VIOLATION;
+    if error_code.contains(expected) && Cr2::read().as_u64() == TARGET.load(Ordering::SeqCst) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!(
+            "unexpected page fault: {:?} at {:?}",
+            error_code,
+            Cr2::read()
+        );
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
@@ -0,0 +1,161 @@
+//测试在时钟中断反复读取页面期间多次替换映射：替换在开中断的状态下进行，时钟中断会落在替换过程中，
+//读取方不会遇到页错误，只会看到旧帧或新帧；替换返回后通过替换前取得的指针能读到新帧的内容
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use os::interrupts::{InterruptIndex, PICS};
+use os::memory::{self, vspace, ReplaceError};
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::registers::control::{Cr2, Cr3};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame, Size4KiB,
+};
+
+entry_point!(main);
+
+//两个帧中写入的值，读取方只应看到其中之一
+const VALUES: [u64; 2] = [0x_aaaa_aaaa, 0x_bbbb_bbbb];
+//落在替换过程中的读取次数下限
+const MIN_PROBES: usize = 20;
+
+//读取方访问的地址，为0时不读取
+static PROBE_ADDR: AtomicU64 = AtomicU64::new(0);
+//主循环正在调用replace_mapping
+static IN_FLIGHT: AtomicBool = AtomicBool::new(false);
+static PROBES_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+static BAD_VALUES: AtomicUsize = AtomicUsize::new(0);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("replace_mapping::probe_during_replace...\t");
+
+    os::init_paging(boot_info);
+    os::gdt::init();
+    init_test_idt();
+
+    let start = vspace::reserve("replace-test", 4096, 4096).expect("no virtual space");
+    let page: Page = Page::containing_address(start);
+    let frames = memory::with_paging(|paging| {
+        let frames: [PhysFrame; 2] = [
+            FrameAllocator::<Size4KiB>::allocate_frame(&mut paging.frame_allocator).unwrap(),
+            FrameAllocator::<Size4KiB>::allocate_frame(&mut paging.frame_allocator).unwrap(),
+        ];
+        let offset = paging.mapper.phys_offset();
+        for (frame, value) in frames.iter().zip(VALUES) {
+            let ptr: *mut u64 = (offset + frame.start_address().as_u64()).as_mut_ptr();
+            unsafe { ptr.write_volatile(value) };
+        }
+        unsafe {
+            paging
+                .mapper
+                .map_to(
+                    page,
+                    frames[0],
+                    memory::wx::DATA_FLAGS,
+                    &mut paging.frame_allocator,
+                )
+                .expect("map_to failed")
+                .flush();
+        }
+        //未映射的页面会被拒绝，而不是建立新映射
+        let unmapped = page + 1;
+        assert_eq!(
+            memory::replace_mapping(
+                unmapped,
+                frames[1],
+                memory::wx::DATA_FLAGS,
+                &mut paging.mapper
+            ),
+            Err(ReplaceError::NotMapped)
+        );
+        frames
+    })
+    .expect("paging not installed");
+
+    PROBE_ADDR.store(start.as_u64(), Ordering::SeqCst);
+    unsafe { PICS.lock().initialize() };
+    x86_64::instructions::interrupts::enable();
+
+    //with_paging在关中断的状态下运行闭包，时钟中断无法落在其中的替换过程里，
+    //因此替换通过不加锁的第二个页表视图进行；测试中没有其他代码修改页表
+    let mut mapper = unlocked_mapper();
+    let ptr: *const u64 = start.as_ptr();
+    let mut current = 0;
+    while PROBES_IN_FLIGHT.load(Ordering::SeqCst) < MIN_PROBES {
+        let next = 1 - current;
+        IN_FLIGHT.store(true, Ordering::SeqCst);
+        let old = memory::replace_mapping(page, frames[next], memory::wx::DATA_FLAGS, &mut mapper)
+            .expect("replace_mapping failed");
+        IN_FLIGHT.store(false, Ordering::SeqCst);
+        assert_eq!(old, frames[current]);
+        //旧的TLB表项已经刷新
+        assert_eq!(unsafe { ptr.read_volatile() }, VALUES[next]);
+        current = next;
+    }
+    x86_64::instructions::interrupts::disable();
+
+    assert_eq!(BAD_VALUES.load(Ordering::SeqCst), 0);
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+//指向当前P4表的页表视图，不持有全局页表锁
+fn unlocked_mapper() -> OffsetPageTable<'static> {
+    let offset = memory::phys_offset();
+    let (p4, _) = Cr3::read();
+    unsafe {
+        let table = &mut *(offset + p4.start_address().as_u64()).as_mut_ptr::<PageTable>();
+        OffsetPageTable::new(table, offset)
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt[InterruptIndex::Timer as usize].set_handler_fn(timer_probe_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn timer_probe_handler(_stack_frame: InterruptStackFrame) {
+    let addr = PROBE_ADDR.load(Ordering::SeqCst);
+    if addr != 0 {
+        let value = unsafe { (addr as *const u64).read_volatile() };
+        if !VALUES.contains(&value) {
+            BAD_VALUES.fetch_add(1, Ordering::SeqCst);
+        }
+        if IN_FLIGHT.load(Ordering::SeqCst) {
+            PROBES_IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Timer as u8);
+    }
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    serial_println!("[failed]");
+    serial_println!("prober faulted: {:?} at {:?}", error_code, Cr2::read());
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
@@ -0,0 +1,71 @@
+//测试启用SSE后可以执行SSE浮点指令并得到正确结果
+//目标规格使用soft-float，编译器不会为f32运算生成SSE指令，因此用内联汇编直接执行
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::arch::asm;
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+//编译器不使用XMM寄存器，汇编中可以直接使用xmm0而不声明
+macro_rules! sse_op {
+    ($op:literal, $a:expr, $b:expr) => {{
+        let (a, b): (f32, f32) = ($a, $b);
+        let mut out = 0f32;
+        unsafe {
+            asm!(
+                "movss xmm0, [{a}]",
+                concat!($op, " xmm0, [{b}]"),
+                "movss [{out}], xmm0",
+                a = in(reg) &a,
+                b = in(reg) &b,
+                out = in(reg) &mut out,
+                options(nostack),
+            );
+        }
+        out
+    }};
+}
+
+#[test_case]
+fn sse_is_enabled() {
+    assert!(os::cpu::sse_enabled());
+}
+
+#[test_case]
+fn f32_arithmetic() {
+    assert_eq!(sse_op!("addss", 1.5, 2.25), 3.75);
+    assert_eq!(sse_op!("subss", 1.0, 4.0), -3.0);
+    assert_eq!(sse_op!("mulss", 3.0, 0.5), 1.5);
+    assert_eq!(sse_op!("divss", 1.0, 4.0), 0.25);
+    //sqrtss使用第二个操作数
+    assert_eq!(sse_op!("sqrtss", 0.0, 2.25), 1.5);
+}
+
+#[test_case]
+fn masked_exception_sets_flag() {
+    use x86_64::registers::mxcsr::{self, MxCsr};
+
+    //默认屏蔽所有异常，除以零只置位标志并返回无穷大
+    mxcsr::write(MxCsr::default());
+    assert_eq!(sse_op!("divss", 1.0, 0.0), f32::INFINITY);
+    assert!(mxcsr::read().contains(MxCsr::DIVIDE_BY_ZERO));
+    mxcsr::write(MxCsr::default());
+}
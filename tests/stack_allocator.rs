@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::memory::{self, vspace, KernelStack, StackAllocator};
+use x86_64::structures::paging::Translate;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+const PAGES: u64 = 4;
+
+fn allocate(stacks: &mut StackAllocator) -> KernelStack {
+    memory::with_paging(|paging| {
+        stacks.allocate(PAGES, &mut paging.mapper, &mut paging.frame_allocator)
+    })
+    .expect("paging not installed")
+    .expect("stack allocation failed")
+}
+
+//栈顶下方的第一个u64
+fn top_word(stack: &KernelStack) -> *mut u64 {
+    (stack.top() - 8u64).as_mut_ptr()
+}
+
+fn is_mapped(addr: x86_64::VirtAddr) -> bool {
+    memory::with_paging(|paging| paging.mapper.translate_addr(addr).is_some()).unwrap()
+}
+
+#[test_case]
+fn three_stacks_with_guards() {
+    let size = 64 * 4096;
+    let start = vspace::reserve("stack-allocator-test", size, 4096).expect("no virtual space");
+    let mut stacks = StackAllocator::new(start, size);
+    let allocated = [
+        allocate(&mut stacks),
+        allocate(&mut stacks),
+        allocate(&mut stacks),
+    ];
+
+    for (i, stack) in allocated.iter().enumerate() {
+        assert!(stack.top().is_aligned(16u64));
+        assert_eq!(stack.top() - stack.bottom(), PAGES * 4096);
+        assert_eq!(stack.guard_page().start_address() + 4096u64, stack.bottom());
+        //保护页不映射，栈本身已映射
+        assert!(!is_mapped(stack.guard_page().start_address()));
+        assert!(is_mapped(stack.bottom()));
+
+        let ptr = top_word(stack);
+        unsafe {
+            ptr.write_volatile(i as u64);
+            assert_eq!(ptr.read_volatile(), i as u64);
+        }
+
+        //各栈（含保护页）互不重叠
+        for other in allocated[..i].iter() {
+            assert!(
+                stack.top() <= other.guard_page().start_address()
+                    || other.top() <= stack.guard_page().start_address()
+            );
+        }
+    }
+
+    let [first, middle, last] = allocated;
+    let middle_guard = middle.guard_page();
+    let middle_bottom = middle.bottom();
+    memory::with_paging(|paging| {
+        stacks.deallocate(middle, &mut paging.mapper, &mut paging.frame_allocator)
+    })
+    .unwrap()
+    .expect("deallocate failed");
+    assert!(!is_mapped(middle_bottom));
+
+    //释放的范围会被再次使用
+    let reused = allocate(&mut stacks);
+    assert_eq!(reused.guard_page(), middle_guard);
+    assert!(is_mapped(reused.bottom()));
+    assert_eq!(unsafe { top_word(&first).read_volatile() }, 0);
+    assert_eq!(unsafe { top_word(&last).read_volatile() }, 2);
+}
@@ -1,14 +1,18 @@
-//测试栈溢出
+//测试栈溢出升级为double fault：IDT中没有页错误处理函数时，保护页上的页错误无法投递，
+//CPU切换到IST0上的double fault处理函数，CR2落在启动栈的保护页内
 #![no_std]
 #![no_main]
 #![feature(abi_x86_interrupt)]
 
+use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use lazy_static::lazy_static;
 use os::serial_print;
 use os::{exit_qemu, serial_println, QemuExitCode};
-use x86_64::structures::idt::InterruptDescriptorTable;
-use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+entry_point!(main);
 
 #[allow(unconditional_recursion)] //关闭编译器对递归安全警告
 fn stack_overflow() {
@@ -16,11 +20,11 @@ fn stack_overflow() {
     volatile::Volatile::new(0).read(); //阻止编译器尾调用优化
 }
 
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
+fn main(boot_info: &'static BootInfo) -> ! {
     serial_print!("stack_overflow::stack_overflow..\t");
 
-    os::gdt::init();
+    //os::init注册启动栈的保护页并加载TSS，之后换成只有double fault处理函数的IDT
+    os::init(boot_info);
     init_test_idt();
 
     //爆栈
@@ -42,12 +46,10 @@ lazy_static! {
                 .set_handler_fn(test_double_fault_handler)
                 .set_stack_index(os::gdt::DOUBLE_FAULT_IST_INDEX);
         }
-
         idt
     };
 }
 
-//由于触发exit_qemu()而非默认逻辑，所以我们自己写个init_idt而不用interrupts::init_idt函数
 pub fn init_test_idt() {
     TEST_IDT.load();
 }
@@ -56,7 +58,20 @@ extern "x86-interrupt" fn test_double_fault_handler(
     _stack_frame: InterruptStackFrame,
     _error_code: u64,
 ) -> ! {
-    serial_print!("[ok]");
-    exit_qemu(QemuExitCode::Success);
+    //能运行到这里说明IST0上的栈可用；CR2应当落在启动栈的保护页内
+    match os::memory::guard::find_guard(Cr2::read()) {
+        Some("boot") => {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success);
+        }
+        other => {
+            serial_println!(
+                "[failed]\nunexpected guard {:?} at {:?}",
+                other,
+                Cr2::read()
+            );
+            exit_qemu(QemuExitCode::Failed);
+        }
+    }
     loop {}
 }
@@ -0,0 +1,55 @@
+//测试栈溢出时页错误处理函数在自己的IST栈上运行并报告溢出，不再升级为double fault
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::interrupts::interrupt_count;
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::InterruptStackFrame;
+
+entry_point!(main);
+
+#[allow(unconditional_recursion)] //关闭编译器对递归安全警告
+fn stack_overflow() {
+    stack_overflow();
+    volatile::Volatile::new(0).read(); //阻止编译器尾调用优化
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("stack_overflow_page_fault::reported_by_page_fault..\t");
+
+    //页错误处理函数报告栈溢出后调用栈溢出钩子，升级为double fault时调用double fault钩子
+    os::interrupts::set_stack_overflow_hook(hook);
+    os::interrupts::set_double_fault_hook(hook);
+    os::init(boot_info);
+
+    stack_overflow();
+
+    panic!("Execution continued after stack overflow");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+fn hook(_stack_frame: &InterruptStackFrame) -> ! {
+    let page_faults = interrupt_count(14);
+    let double_faults = interrupt_count(8);
+    let guard = os::memory::guard::find_guard(Cr2::read());
+    if guard == Some("boot") && page_faults > 0 && double_faults == 0 {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!(
+            "[failed]\nguard {:?}, {} page faults, {} double faults",
+            guard,
+            page_faults,
+            double_faults
+        );
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::storage::{self, BlockDevice, BlockError, PartitionDevice, RamDisk};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+    os::allocator::init_heap().expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+#[test_case]
+fn ramdisk_conformance() {
+    storage::conformance::run(&mut RamDisk::new(512, 64));
+    //块大小不是512时同样成立
+    storage::conformance::run(&mut RamDisk::new(4096, 2));
+    storage::conformance::persistence(&mut RamDisk::new(512, 64), &mut || {});
+}
+
+#[test_case]
+fn partition_conformance() {
+    let mut partition = PartitionDevice::new(RamDisk::new(512, 64), 16, 8).unwrap();
+    assert_eq!(partition.block_count(), 8);
+    storage::conformance::run(&mut partition);
+}
+
+#[test_case]
+fn partition_offsets_lbas() {
+    let mut partition = PartitionDevice::new(RamDisk::new(512, 8), 2, 4).unwrap();
+    partition.write_blocks(0, &[0xaa; 512]).unwrap();
+    partition.write_blocks(3, &[0xbb; 512]).unwrap();
+    let disk = partition.into_inner();
+    let bytes = disk.as_bytes();
+    assert!(bytes[2 * 512..3 * 512].iter().all(|&b| b == 0xaa));
+    assert!(bytes[5 * 512..6 * 512].iter().all(|&b| b == 0xbb));
+    //分区之外的块没有被改写
+    assert!(bytes[..2 * 512].iter().all(|&b| b == 0));
+    assert!(bytes[6 * 512..].iter().all(|&b| b == 0));
+}
+
+#[test_case]
+fn partition_must_fit() {
+    assert_eq!(
+        PartitionDevice::new(RamDisk::new(512, 8), 4, 5).err(),
+        Some(BlockError::BadPartition {
+            start: 4,
+            blocks: 5
+        })
+    );
+    assert!(PartitionDevice::new(RamDisk::new(512, 8), u64::MAX, 2).is_err());
+}
+
+#[test_case]
+fn registry_lookup() {
+    storage::register("ram0", Box::new(RamDisk::new(512, 4))).unwrap();
+    assert_eq!(
+        storage::register("ram0", Box::new(RamDisk::new(512, 4))),
+        Err(BlockError::AlreadyRegistered)
+    );
+    let written = storage::with_device("ram0", |dev| dev.write_blocks(1, &[7; 512]));
+    assert_eq!(written, Some(Ok(())));
+    let mut block = [0u8; 512];
+    storage::with_device("ram0", |dev| dev.read_blocks(1, &mut block))
+        .unwrap()
+        .unwrap();
+    assert!(block.iter().all(|&b| b == 7));
+    assert!(storage::with_device("missing", |dev| dev.block_count()).is_none());
+
+    let mut names = 0;
+    storage::for_each_device(|name, dev| {
+        assert_eq!(name, "ram0");
+        assert_eq!(dev.block_count(), 4);
+        names += 1;
+    });
+    assert_eq!(names, 1);
+    assert!(storage::unregister("ram0").is_some());
+    assert!(storage::with_device("ram0", |dev| dev.block_count()).is_none());
+}
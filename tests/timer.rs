@@ -0,0 +1,143 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use os::time::{self, TimerError};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+#[test_case]
+fn default_frequency_after_init() {
+    assert_eq!(time::frequency(), time::DEFAULT_FREQUENCY);
+}
+
+#[test_case]
+fn ticks_advance_at_1000hz() {
+    const WAITS: u64 = 100;
+
+    time::init(1000).expect("1000 Hz is in range");
+    assert_eq!(time::frequency(), 1000);
+    //每次hlt在下一个中断时返回，这里几乎只有时钟中断
+    let start = time::ticks();
+    for _ in 0..WAITS {
+        x86_64::instructions::hlt();
+    }
+    let elapsed = time::ticks() - start;
+    assert!(
+        (WAITS * 9 / 10..=WAITS + 1).contains(&elapsed),
+        "{} ticks after {} waits",
+        elapsed,
+        WAITS
+    );
+    time::init(time::DEFAULT_FREQUENCY).unwrap();
+}
+
+#[test_case]
+fn out_of_range_frequency_is_clamped() {
+    assert_eq!(
+        time::init(5),
+        Err(TimerError::OutOfRange {
+            requested: 5,
+            applied: time::MIN_FREQUENCY
+        })
+    );
+    assert_eq!(time::frequency(), time::MIN_FREQUENCY);
+    assert_eq!(
+        time::init(5000),
+        Err(TimerError::OutOfRange {
+            requested: 5000,
+            applied: time::MAX_FREQUENCY
+        })
+    );
+    assert_eq!(time::frequency(), time::MAX_FREQUENCY);
+    time::init(time::DEFAULT_FREQUENCY).unwrap();
+}
+
+#[test_case]
+fn ticks_and_uptime_are_monotonic() {
+    let mut last_ticks = time::ticks();
+    let mut last_uptime = time::uptime();
+    for _ in 0..50 {
+        for _ in 0..10_000 {
+            core::hint::spin_loop();
+        }
+        x86_64::instructions::hlt();
+        let (ticks, uptime) = (time::ticks(), time::uptime());
+        assert!(ticks >= last_ticks);
+        assert!(uptime >= last_uptime);
+        last_ticks = ticks;
+        last_uptime = uptime;
+    }
+    assert!(time::uptime_ms() > 0);
+}
+
+#[test_case]
+fn sleep_ms_matches_tsc() {
+    let start = time::rdtsc();
+    time::sleep_ms(50);
+    let elapsed_ms = (time::rdtsc() - start) / time::tsc_per_ms();
+    assert!(
+        (45..=150).contains(&elapsed_ms),
+        "sleep_ms(50) took {} ms",
+        elapsed_ms
+    );
+}
+
+#[test_case]
+fn sleep_busy_us_matches_ticks() {
+    //100Hz下每个滴答10ms，忙等30ms应经过2到4个滴答
+    let start = time::ticks();
+    time::sleep_busy_us(30_000);
+    let elapsed = time::ticks() - start;
+    assert!((2..=4).contains(&elapsed), "{} ticks in 30 ms", elapsed);
+}
+
+static CALLBACK_COUNT: AtomicU64 = AtomicU64::new(0);
+
+fn count_tick() {
+    CALLBACK_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test_case]
+fn callback_stops_after_cancel() {
+    let id = time::every_ticks(1, count_tick).expect("no free timer callback slot");
+    time::sleep_ms(50);
+    time::cancel(id);
+    let count = CALLBACK_COUNT.load(Ordering::SeqCst);
+    assert!(count >= 3, "callback ran {} times", count);
+
+    time::sleep_ms(50);
+    assert_eq!(CALLBACK_COUNT.load(Ordering::SeqCst), count);
+    //取消过的句柄再次取消不会影响之后注册的回调
+    time::cancel(id);
+}
+
+#[test_case]
+fn callback_slots_are_limited() {
+    let ids: [_; time::MAX_CALLBACKS] =
+        core::array::from_fn(|_| time::every_ticks(1000, count_tick).unwrap());
+    assert_eq!(
+        time::every_ticks(1000, count_tick),
+        Err(TimerError::NoFreeSlot)
+    );
+    for id in ids {
+        time::cancel(id);
+    }
+}
@@ -0,0 +1,72 @@
+//测试取消映射后访问页面会触发页错误
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use os::memory;
+use os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::structures::paging::Page;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("unmap_page::access_after_unmap...\t");
+
+    os::init_paging(boot_info);
+    os::gdt::init();
+    init_test_idt();
+
+    //映射到VGA帧并通过新页面写入
+    let page = Page::containing_address(VirtAddr::new(0xdeadbeaf000));
+    memory::with_paging(|paging| {
+        memory::create_example_mapping(page, &mut paging.mapper, &mut paging.frame_allocator)
+    })
+    .expect("paging not installed")
+    .expect("example mapping failed");
+    let page_ptr: *mut u64 = page.start_address().as_mut_ptr();
+    unsafe { page_ptr.offset(400).write_volatile(0x_f021_f077_f065_f04e) };
+
+    memory::with_paging(|paging| {
+        let frame = memory::unmap_page_keep_frame(page, &mut paging.mapper).expect("unmap failed");
+        assert_eq!(frame.start_address().as_u64(), 0xb8000);
+        assert!(memory::unmap_page_keep_frame(page, &mut paging.mapper).is_err());
+    })
+    .expect("paging not installed");
+
+    unsafe { page_ptr.offset(400).read_volatile() }; //应当触发页错误
+
+    serial_println!("[no page fault]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    _error_code: PageFaultErrorCode,
+) {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::println;
+use os::vga_buffer::{self, BUFFER_HEIGHT};
+use os::vga_graphics::{self, GraphicsError, HEIGHT, WIDTH};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+fn last_line() -> [u8; vga_buffer::BUFFER_WIDTH] {
+    vga_buffer::read_row(BUFFER_HEIGHT - 2)
+}
+
+#[test_case]
+fn draw_and_read_back() {
+    vga_graphics::enter_mode_13h().expect("failed to enter mode 13h");
+    assert!(vga_graphics::is_active());
+    assert!(matches!(
+        vga_graphics::enter_mode_13h(),
+        Err(GraphicsError::AlreadyActive)
+    ));
+    //进入时清屏
+    assert_eq!(vga_graphics::get_pixel(0, 0), Some(0));
+    assert_eq!(vga_graphics::get_pixel(WIDTH - 1, HEIGHT - 1), Some(0));
+
+    vga_graphics::put_pixel(3, 4, 15);
+    assert_eq!(vga_graphics::get_pixel(3, 4), Some(15));
+    vga_graphics::put_pixel(WIDTH, 0, 15); //超出屏幕，被忽略
+    assert_eq!(vga_graphics::get_pixel(WIDTH, 0), None);
+
+    vga_graphics::fill_rect(-10, -10, 20, 20, 4);
+    assert_eq!(vga_graphics::get_pixel(0, 0), Some(4));
+    assert_eq!(vga_graphics::get_pixel(9, 9), Some(4));
+    assert_eq!(vga_graphics::get_pixel(10, 10), Some(0));
+
+    let image = [1, 2, 3, 4, 5, 6];
+    vga_graphics::blit(&image, 3, 2, WIDTH as i32 - 2, 100);
+    assert_eq!(vga_graphics::get_pixel(WIDTH - 2, 100), Some(1));
+    assert_eq!(vga_graphics::get_pixel(WIDTH - 1, 101), Some(5));
+
+    vga_graphics::exit_to_text_mode().expect("failed to leave mode 13h");
+    assert!(!vga_graphics::is_active());
+    assert!(matches!(
+        vga_graphics::exit_to_text_mode(),
+        Err(GraphicsError::NotActive)
+    ));
+    //离开图形模式后绘图函数不再访问显存
+    assert_eq!(vga_graphics::get_pixel(3, 4), None);
+}
+
+#[test_case]
+fn text_output_survives_graphics() {
+    let before = "printed before graphics mode";
+    let during = "printed during graphics mode";
+    let after = "printed after graphics mode";
+    println!("{}", before);
+    vga_graphics::enter_mode_13h().expect("failed to enter mode 13h");
+    assert!(vga_buffer::is_suspended());
+    println!("{}", during);
+    vga_graphics::fill_rect(0, 0, WIDTH, HEIGHT, 9);
+    vga_graphics::exit_to_text_mode().expect("failed to leave mode 13h");
+    assert!(!vga_buffer::is_suspended());
+
+    //图形模式期间的输出在回到文本模式时写回屏幕
+    let row = vga_buffer::read_row(BUFFER_HEIGHT - 3);
+    assert_eq!(&row[..before.len()], before.as_bytes());
+    assert_eq!(&last_line()[..during.len()], during.as_bytes());
+    println!("{}", after);
+    assert_eq!(&last_line()[..after.len()], after.as_bytes());
+}
+
+#[test_case]
+fn palette_round_trip() {
+    let text_palette = vga_graphics::get_palette();
+    let mut palette = [(0u8, 0u8, 0u8); 256];
+    for (i, color) in palette.iter_mut().enumerate() {
+        *color = (i as u8, (i as u8) >> 1, 0xff);
+    }
+    vga_graphics::enter_mode_13h().expect("failed to enter mode 13h");
+    vga_graphics::set_palette(&palette);
+    //DAC只保存每个分量的低6位
+    let read_back = vga_graphics::get_palette();
+    for (i, (&written, &read)) in palette.iter().zip(read_back.iter()).enumerate() {
+        let (r, g, b) = written;
+        assert_eq!(read, (r & 0x3f, g & 0x3f, b & 0x3f), "color {}", i);
+    }
+    vga_graphics::exit_to_text_mode().expect("failed to leave mode 13h");
+
+    //离开图形模式时恢复进入前的调色板
+    assert_eq!(vga_graphics::get_palette(), text_palette);
+    println!("text still prints after a palette change");
+}
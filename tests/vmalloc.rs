@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::memory::{self, VmallocError};
+use x86_64::structures::paging::Translate;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+const SIZE: usize = 1024 * 1024;
+
+fn free_frames() -> usize {
+    memory::with_paging(|paging| paging.frame_allocator.inner().free_frames())
+        .expect("paging not installed")
+}
+
+fn is_mapped(addr: VirtAddr) -> bool {
+    memory::with_paging(|paging| paging.mapper.translate_addr(addr).is_some())
+        .expect("paging not installed")
+}
+
+#[test_case]
+fn pattern_across_page_boundaries() {
+    let start = memory::vmalloc(SIZE).expect("vmalloc failed");
+    let base: *mut u8 = start.as_mut_ptr();
+
+    //按7字节跨步写入，很多值跨越页边界
+    for offset in (0..SIZE - 8).step_by(7) {
+        unsafe { (base.add(offset) as *mut u64).write_unaligned(offset as u64) };
+    }
+    for offset in (0..SIZE - 8).step_by(7) {
+        let value = unsafe { (base.add(offset) as *const u64).read_unaligned() };
+        assert_eq!(value, offset as u64);
+    }
+
+    memory::vfree(start).expect("vfree failed");
+    assert!(!is_mapped(start));
+    assert!(matches!(
+        memory::vfree(start),
+        Err(VmallocError::NotAllocated)
+    ));
+}
+
+#[test_case]
+fn freed_frames_are_reused() {
+    let pages = SIZE / 4096;
+    let start = memory::vmalloc(SIZE).expect("vmalloc failed");
+    let before_free = free_frames();
+    memory::vfree(start).expect("vfree failed");
+    assert_eq!(free_frames(), before_free + pages);
+
+    //再次分配时从回收的帧中取出
+    let again = memory::vmalloc(SIZE).expect("vmalloc failed");
+    assert_eq!(again, start);
+    assert_eq!(free_frames(), before_free);
+    memory::vfree(again).expect("vfree failed");
+}
+
+#[test_case]
+fn zero_size_rejected() {
+    assert!(matches!(memory::vmalloc(0), Err(VmallocError::ZeroSize)));
+}
@@ -0,0 +1,75 @@
+//测试关闭中断后时钟中断停止，看门狗能在超时后检测到并panic
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use os::{exit_qemu, serial_print, serial_println, time, watchdog, QemuExitCode};
+use x86_64::instructions::interrupts;
+
+entry_point!(main);
+
+//超时的滴答数，按未编程的PIT约18.2Hz计算约为0.3秒
+const TIMEOUT_TICKS: u64 = 5;
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    serial_print!("watchdog::interrupts_disabled...\t");
+
+    interrupts::disable();
+    watchdog::arm(TIMEOUT_TICKS);
+    //最多等待2秒，看门狗应当在此之前panic
+    let deadline = time::rdtsc() + 2000 * time::tsc_per_ms();
+    while time::rdtsc() < deadline {
+        watchdog::check();
+        core::hint::spin_loop();
+    }
+
+    serial_println!("[watchdog did not fire]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+//保存panic信息的前256字节
+struct Message {
+    buf: [u8; 256],
+    len: usize,
+}
+
+impl Write for Message {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.len < self.buf.len() {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Message {
+    fn contains(&self, needle: &str) -> bool {
+        self.buf[..self.len]
+            .windows(needle.len())
+            .any(|window| window == needle.as_bytes())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut message = Message {
+        buf: [0; 256],
+        len: 0,
+    };
+    let _ = write!(message, "{}", info.message());
+    if message.contains("no timer ticks observed") && message.contains("IF=0") {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("unexpected panic: {}", info);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use os::memory::{self, vspace};
+use os::serial_println;
+use os::time::rdtsc;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    os::init(boot_info);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    os::test_panic_handler(info)
+}
+
+#[test_case]
+fn allocated_frame_reads_zero() {
+    let start = vspace::reserve("zeroing-test", 4096, 4096).expect("no virtual space");
+    let page: Page = Page::containing_address(start);
+    memory::with_paging(|paging| {
+        //先写脏一个帧再归还，空闲栈是后进先出的，下一次分配会拿到同一个帧
+        let dirty = paging.frame_allocator.allocate_frame_uninit().unwrap();
+        let ptr: *mut u8 =
+            (paging.mapper.phys_offset() + dirty.start_address().as_u64()).as_mut_ptr();
+        unsafe {
+            ptr.write_bytes(0xaa, 4096);
+            paging.frame_allocator.deallocate_frame(dirty);
+        }
+
+        let frame: PhysFrame = paging.frame_allocator.allocate_frame().unwrap();
+        assert_eq!(frame, dirty);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        unsafe {
+            paging
+                .mapper
+                .map_to(page, frame, flags, &mut paging.frame_allocator)
+                .expect("map_to failed")
+                .flush();
+        }
+    })
+    .expect("paging not installed");
+
+    let bytes: *const u8 = start.as_ptr();
+    for i in 0..4096 {
+        assert_eq!(unsafe { bytes.add(i).read_volatile() }, 0);
+    }
+}
+
+#[test_case]
+fn uninit_is_faster() {
+    const COUNT: usize = 64;
+
+    let (zeroing_cycles, uninit_cycles) = memory::with_paging(|paging| {
+        let frames = &mut paging.frame_allocator;
+        let mut allocated: [Option<PhysFrame>; COUNT] = [None; COUNT];
+
+        let start = rdtsc();
+        for slot in allocated.iter_mut() {
+            *slot = FrameAllocator::<Size4KiB>::allocate_frame(frames);
+        }
+        let zeroing_cycles = rdtsc() - start;
+        for frame in allocated.iter_mut().filter_map(Option::take) {
+            unsafe { frames.deallocate_frame(frame) };
+        }
+
+        let start = rdtsc();
+        for slot in allocated.iter_mut() {
+            *slot = frames.allocate_frame_uninit();
+        }
+        let uninit_cycles = rdtsc() - start;
+        for frame in allocated.iter_mut().filter_map(Option::take) {
+            unsafe { frames.deallocate_frame(frame) };
+        }
+
+        (zeroing_cycles, uninit_cycles)
+    })
+    .expect("paging not installed");
+
+    serial_println!(
+        "zeroing: {} cycles, uninit: {} cycles for {} frames",
+        zeroing_cycles,
+        uninit_cycles,
+        COUNT
+    );
+    assert!(uninit_cycles < zeroing_cycles);
+}